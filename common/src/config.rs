@@ -0,0 +1,152 @@
+use clap::error::ErrorKind;
+use clap::{Arg, ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::ffi::OsString;
+use std::path::Path;
+
+/// Id of the argument that points to the configuration file.
+pub const CONFIG_FILE: &str = "config_file";
+const PRINT_CONFIG: &str = "print_config";
+/// Replaces the values of secret arguments in `redacted`.
+pub const REDACTED: &str = "<redacted>";
+
+/// Parses `T` from the configuration file, environment variables and command line arguments,
+/// in increasing order of precedence. `T` needs a `config_file` argument.
+/// Exits on errors and prints the effective configuration if `--print-config` is given,
+/// without secrets, see `redacted`.
+pub fn parse<T: CommandFactory + FromArgMatches + Serialize>() -> T {
+    let matches = get_matches::<T, _, _>(std::env::args_os()).unwrap_or_else(|e| e.exit());
+    let config = T::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    if matches.get_flag(PRINT_CONFIG) {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&redacted(&config)).unwrap()
+        );
+        std::process::exit(0);
+    }
+
+    config
+}
+
+/// Returns `config` as JSON with the values of secret arguments replaced by `REDACTED`.
+/// Secret arguments are marked with `hide_env_values`, which also hides them in `--help`.
+pub fn redacted<T: CommandFactory + Serialize>(config: &T) -> Value {
+    let mut value = serde_json::to_value(config).expect("config");
+    if let Value::Object(fields) = &mut value {
+        for arg in T::command().get_arguments() {
+            if !arg.is_hide_env_values_set() {
+                continue;
+            }
+            match fields.get_mut(arg.get_id().as_str()) {
+                Some(field) if !field.is_null() => *field = Value::String(REDACTED.to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    value
+}
+
+/// Like `parse` but from `args` and returning errors.
+pub fn try_parse_from<T, I, S>(args: I) -> Result<T, clap::Error>
+where
+    T: CommandFactory + FromArgMatches,
+    I: IntoIterator<Item = S>,
+    S: Into<OsString> + Clone,
+{
+    T::from_arg_matches(&get_matches::<T, _, _>(args)?)
+}
+
+/// Reads a configuration file in TOML, YAML or JSON format, depending on the file extension.
+pub fn read_config_file(path: &Path) -> Result<Map<String, Value>, String> {
+    let data = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let value: Value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&data).map_err(|e| e.to_string()),
+        Some("yaml" | "yml") => serde_yaml::from_str(&data).map_err(|e| e.to_string()),
+        Some("json") => serde_json::from_str(&data).map_err(|e| e.to_string()),
+        _ => Err("expected a .toml, .yaml or .json file".to_string()),
+    }
+    .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    match value {
+        Value::Object(obj) => Ok(obj),
+        _ => Err(format!("{}: expected a table", path.display())),
+    }
+}
+
+fn get_matches<T, I, S>(args: I) -> Result<ArgMatches, clap::Error>
+where
+    T: CommandFactory,
+    I: IntoIterator<Item = S>,
+    S: Into<OsString> + Clone,
+{
+    let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    let mut cmd = T::command().arg(
+        Arg::new(PRINT_CONFIG)
+            .long("print-config")
+            .action(ArgAction::SetTrue)
+            .help("Prints the effective configuration and exits"),
+    );
+
+    // find the config file first, the other arguments may be incomplete without it
+    let path = cmd
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches_from(&args)
+        .ok()
+        .and_then(|matches| {
+            matches
+                .try_get_raw(CONFIG_FILE)
+                .expect("config_file argument")
+                .and_then(|mut values| values.next().map(|v| v.to_owned()))
+        });
+
+    if let Some(path) = path {
+        let path = Path::new(&path);
+        let res = read_config_file(path).and_then(|values| {
+            with_defaults(cmd.clone(), values).map_err(|e| format!("{}: {}", path.display(), e))
+        });
+        cmd = match res {
+            Ok(cmd) => cmd,
+            Err(err) => return Err(cmd.error(ErrorKind::InvalidValue, err)),
+        };
+    }
+
+    cmd.try_get_matches_from(args)
+}
+
+/// Uses `values` as default values for the arguments of `cmd`.
+/// Environment variables and command line arguments take precedence over default values.
+fn with_defaults(mut cmd: Command, values: Map<String, Value>) -> Result<Command, String> {
+    let scalar = |key: &str, value: &Value| match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(format!("{key}: expected a string, number, boolean or list")),
+    };
+
+    for (key, value) in values {
+        let id = key.replace('-', "_");
+        let known = cmd
+            .get_arguments()
+            .any(|arg| arg.get_id() == id.as_str() && arg.get_long().is_some());
+        if !known || id == CONFIG_FILE || id == PRINT_CONFIG {
+            return Err(format!("unknown configuration key: {key}"));
+        }
+
+        let values = match value {
+            // treated as not set
+            Value::Null => continue,
+            Value::Array(items) => items
+                .iter()
+                .map(|item| scalar(&key, item))
+                .collect::<Result<Vec<_>, _>>()?,
+            value => vec![scalar(&key, &value)?],
+        };
+        cmd = cmd.mut_arg(id, |arg| arg.required(false).default_values(values));
+    }
+
+    Ok(cmd)
+}
@@ -0,0 +1,43 @@
+//! FNV-1a hashing for names and checksums that must be stable across builds,
+//! unlike the hashers of the standard library.
+use std::fmt;
+use std::hash::Hasher;
+
+const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const PRIME: u64 = 0x100000001b3;
+
+/// The 64 bit FNV-1a hash of `data`.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    let mut hasher = Fnv1a::default();
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// Incremental FNV-1a, also hashes formatted output without buffering it.
+#[derive(Clone, Copy, Debug)]
+pub struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(OFFSET_BASIS)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0 = bytes
+            .iter()
+            .fold(self.0, |hash, b| (hash ^ *b as u64).wrapping_mul(PRIME));
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Write for Fnv1a {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
@@ -8,11 +8,160 @@ use hyper::Uri;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Invalid JSON was received by the server.
+pub const PARSE_ERROR: i32 = -32700;
+/// The JSON sent is not a valid request object.
+pub const INVALID_REQUEST: i32 = -32600;
+/// The method does not exist or is not available.
+pub const METHOD_NOT_FOUND: i32 = -32601;
+/// Invalid method parameters.
+pub const INVALID_PARAMS: i32 = -32602;
+/// Internal json-rpc error.
+pub const INTERNAL_ERROR: i32 = -32603;
+/// Generic server error, the message describes the failure.
+pub const SERVER_ERROR: i32 = -32000;
+/// Used by geth for `execution reverted` errors, `data` holds the revert data.
+pub const EXECUTION_REVERTED: i32 = 3;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct JsonRpcError {
     pub code: i32,
     pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl JsonRpcError {
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+
+    pub fn method_not_found(message: impl Into<String>) -> Self {
+        Self::new(METHOD_NOT_FOUND, message)
+    }
+
+    /// Returns `true` if a transaction or call reverted.
+    pub fn is_revert(&self) -> bool {
+        self.code == EXECUTION_REVERTED || self.message.starts_with("execution reverted")
+    }
+
+    /// Returns the hex encoded revert data, if any.
+    pub fn revert_data(&self) -> Option<&str> {
+        match self.is_revert() {
+            true => self.data.as_ref()?.as_str(),
+            false => None,
+        }
+    }
+}
+
+impl fmt::Display for JsonRpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Server side errors are reported as `SERVER_ERROR`.
+impl From<String> for JsonRpcError {
+    fn from(message: String) -> Self {
+        Self::new(SERVER_ERROR, message)
+    }
+}
+
+impl From<&str> for JsonRpcError {
+    fn from(message: &str) -> Self {
+        Self::new(SERVER_ERROR, message)
+    }
+}
+
+/// Errors of `jsonrpc_request_client`.
+/// The variants other than `Response` and `NoResult` carry the uri and method of the request.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RpcError {
+    /// The request could not be sent or the response could not be read.
+    Transport(String),
+    /// There was no response within the timeout.
+    Timeout(String),
+    /// The credentials of the request were rejected.
+    Unauthorized(String),
+    /// The response is not a json-rpc response or the result can not be deserialized.
+    Decode(String),
+    /// The error object of the response.
+    Response(JsonRpcError),
+    /// The response has neither a result nor an error.
+    NoResult,
+}
+
+impl RpcError {
+    /// The json-rpc error code of the response, if any.
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            Self::Response(err) => Some(err.code),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` for failures that may go away by retrying the same request.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Transport(_) | Self::Timeout(_))
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::Timeout(_))
+    }
+
+    /// Returns `true` if a transaction or call reverted.
+    pub fn is_revert(&self) -> bool {
+        match self {
+            Self::Response(err) => err.is_revert(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transport(err) => write!(f, "jsonrpc: {err}"),
+            Self::Timeout(req) => write!(f, "jsonrpc: {req} deadline has elapsed"),
+            Self::Unauthorized(req) => write!(f, "jsonrpc: {req} unauthorized"),
+            Self::Decode(err) => write!(f, "jsonrpc: {err}"),
+            Self::Response(err) => write!(f, "{err}"),
+            Self::NoResult => f.write_str("no result in response"),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+impl From<RpcError> for String {
+    fn from(err: RpcError) -> Self {
+        err.to_string()
+    }
+}
+
+/// Errors of upstream requests are forwarded as is, everything else is an internal error.
+impl From<RpcError> for JsonRpcError {
+    fn from(err: RpcError) -> Self {
+        match err {
+            RpcError::Response(err) => err,
+            err => Self::new(INTERNAL_ERROR, err.to_string()),
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -37,64 +186,401 @@ pub struct JsonRpcRequest<T: Serialize> {
     pub params: T,
 }
 
-/// Invokes a `json-rpc` request with a timeout of `timeout` ms for the network
-/// and deserialize part.
-pub async fn jsonrpc_request_client<T: Serialize + Send + Sync, R: DeserializeOwned>(
+/// Retries of requests that failed with a transient error, see `RpcError::is_transient`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The number of retries after the first attempt.
+    pub max_retries: u32,
+    /// The delay before the first retry in ms, doubled for every further retry.
+    pub backoff: u64,
+    /// The upper bound of the delay in ms.
+    pub max_backoff: u64,
+}
+
+impl RetryPolicy {
+    /// Fail on the first error.
+    pub const NONE: Self = Self {
+        max_retries: 0,
+        backoff: 0,
+        max_backoff: 0,
+    };
+
+    /// Returns the delay before retry number `retry`, starting at zero.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let delay = self.backoff.saturating_mul(1 << retry.min(16));
+
+        Duration::from_millis(delay.min(self.max_backoff))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            backoff: 500,
+            max_backoff: 8000,
+        }
+    }
+}
+
+/// Ids of outgoing requests, unique within the process.
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A json-rpc client for one endpoint. Connections are kept alive and shared by the clones
+/// of a client. Requests that fail with a transient error are retried according to the
+/// `RetryPolicy` of the method.
+#[derive(Clone)]
+pub struct JsonRpcClient {
+    http_client: hyper::Client<HttpConnector>,
+    uri: Uri,
     timeout: u64,
-    client: &hyper::Client<HttpConnector>,
-    uri: &Uri,
-    method: &str,
-    params: T,
-) -> Result<R, String> {
-    #[derive(Debug, Deserialize)]
-    struct JsonRpcResponseInternal<T> {
-        result: Option<T>,
-        error: Option<JsonRpcError>,
+    retry: RetryPolicy,
+    method_retry: Arc<HashMap<String, RetryPolicy>>,
+    /// limits the number of requests in flight, if set
+    permits: Option<Arc<Semaphore>>,
+}
+
+impl JsonRpcClient {
+    /// A client for `uri` with the default settings of `JsonRpcClientBuilder`.
+    pub fn new(uri: Uri) -> Self {
+        Self::builder(uri).build()
     }
 
-    let node_req = Request::post(uri);
-    let req_obj = JsonRpcRequest {
-        jsonrpc: "2.0".to_string(),
-        id: 0.into(),
-        method: method.to_string(),
-        params,
-    };
+    pub fn builder(uri: Uri) -> JsonRpcClientBuilder {
+        JsonRpcClientBuilder {
+            uri,
+            timeout: 30_000,
+            retry: RetryPolicy::default(),
+            method_retry: HashMap::new(),
+            max_idle_connections: usize::MAX,
+            idle_timeout: Duration::from_secs(90),
+            max_concurrent_requests: None,
+        }
+    }
 
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// Returns the retry policy for `method`.
+    pub fn retry_policy(&self, method: &str) -> RetryPolicy {
+        self.method_retry.get(method).copied().unwrap_or(self.retry)
+    }
+
+    /// Invokes `method` with `params`, retrying transient errors.
+    pub async fn request<T: Serialize + Send + Sync, R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: T,
+    ) -> Result<R, RpcError> {
+        let policy = self.retry_policy(method);
+        let mut retry = 0;
+        loop {
+            let id = next_request_id();
+            let req_obj = JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: id.clone(),
+                method: method.to_string(),
+                params: &params,
+            };
+            let res = async {
+                let req = format!("uri={} method={method}", self.uri);
+                let body = self.send(&req, &req_obj).await?;
+                decode_response(&req, body, &id)
+            }
+            .await;
+
+            match res {
+                Err(err) if err.is_transient() && retry < policy.max_retries => {
+                    log::debug!("jsonrpc: retry {} of {}: {}", retry + 1, method, err);
+                    tokio::time::sleep(policy.delay(retry)).await;
+                    retry += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Invokes `calls` as a single batch request, the results are in the order of `calls`.
+    /// The whole batch is retried on transient errors, with the policy of the method
+    /// that allows the least retries.
+    pub async fn batch<R: DeserializeOwned>(
+        &self,
+        calls: &[(&str, serde_json::Value)],
+    ) -> Result<Vec<Result<R, RpcError>>, RpcError> {
+        if calls.is_empty() {
+            return Ok(Vec::new());
+        }
+        let policy = calls
+            .iter()
+            .map(|(method, _)| self.retry_policy(method))
+            .min_by_key(|policy| policy.max_retries)
+            .unwrap();
+
+        let mut retry = 0;
+        loop {
+            match self.batch_once(calls).await {
+                Err(err) if err.is_transient() && retry < policy.max_retries => {
+                    log::debug!("jsonrpc: retry {} of batch: {}", retry + 1, err);
+                    tokio::time::sleep(policy.delay(retry)).await;
+                    retry += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    async fn batch_once<R: DeserializeOwned>(
+        &self,
+        calls: &[(&str, serde_json::Value)],
+    ) -> Result<Vec<Result<R, RpcError>>, RpcError> {
+        let reqs: Vec<_> = calls
+            .iter()
+            .map(|(method, params)| JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: next_request_id(),
+                method: method.to_string(),
+                params,
+            })
+            .collect();
+        let req = format!("uri={} batch={}", self.uri, reqs.len());
+        let body = self.send(&req, &reqs).await?;
+
+        let responses: Vec<JsonRpcResponseInternal<serde_json::Value>> =
+            match serde_json::from_reader(body.reader()) {
+                Ok(serde_json::Value::Array(items)) => items
+                    .into_iter()
+                    .map(serde_json::from_value)
+                    .collect::<Result<_, _>>()
+                    .map_err(|err| RpcError::Decode(format!("{req} error={err}")))?,
+                // the whole batch was rejected
+                Ok(value) => {
+                    let resp: JsonRpcResponseInternal<serde_json::Value> =
+                        serde_json::from_value(value)
+                            .map_err(|err| RpcError::Decode(format!("{req} error={err}")))?;
+                    return Err(resp.error.map_or(RpcError::NoResult, RpcError::Response));
+                }
+                Err(err) => return Err(RpcError::Decode(format!("{req} error={err}"))),
+            };
+
+        // responses may come in any order
+        let mut by_id: HashMap<String, JsonRpcResponseInternal<serde_json::Value>> = responses
+            .into_iter()
+            .map(|resp| (resp.id.to_string(), resp))
+            .collect();
+        Ok(reqs
+            .iter()
+            .map(|req_obj| {
+                let resp = by_id
+                    .remove(&req_obj.id.to_string())
+                    .ok_or(RpcError::NoResult)?;
+                if let Some(err) = resp.error {
+                    return Err(RpcError::Response(err));
+                }
+                serde_json::from_value(resp.result.ok_or(RpcError::NoResult)?).map_err(|err| {
+                    RpcError::Decode(format!("{req} method={} error={err}", req_obj.method))
+                })
+            })
+            .collect())
+    }
+
+    /// Posts `body` within the timeout, waiting for a permit first if the number of requests
+    /// in flight is limited.
+    async fn send<B: Serialize>(&self, req: &str, body: &B) -> Result<impl Buf, RpcError> {
+        let _permit = match &self.permits {
+            Some(permits) => Some(permits.acquire().await.expect("semaphore is never closed")),
+            None => None,
+        };
+
+        post(self.timeout, &self.http_client, &self.uri, None, req, body).await
+    }
+}
+
+/// Settings of a `JsonRpcClient`.
+pub struct JsonRpcClientBuilder {
+    uri: Uri,
+    timeout: u64,
+    retry: RetryPolicy,
+    method_retry: HashMap<String, RetryPolicy>,
+    max_idle_connections: usize,
+    idle_timeout: Duration,
+    max_concurrent_requests: Option<usize>,
+}
+
+impl JsonRpcClientBuilder {
+    /// The timeout of a single attempt in ms, defaults to 30 seconds.
+    pub fn timeout(mut self, timeout: u64) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The retry policy for methods without a policy of their own.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// The retry policy for `method`.
+    pub fn retry_method(mut self, method: &str, policy: RetryPolicy) -> Self {
+        self.method_retry.insert(method.to_string(), policy);
+        self
+    }
+
+    /// The maximum number of idle keep-alive connections, unlimited by default.
+    pub fn max_idle_connections(mut self, max: usize) -> Self {
+        self.max_idle_connections = max;
+        self
+    }
+
+    /// Idle connections are closed after `timeout`, defaults to 90 seconds.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// The maximum number of requests in flight, further requests wait for a free slot.
+    /// Unlimited by default.
+    pub fn max_concurrent_requests(mut self, max: usize) -> Self {
+        self.max_concurrent_requests = Some(max);
+        self
+    }
+
+    pub fn build(self) -> JsonRpcClient {
+        let http_client = hyper::Client::builder()
+            .pool_max_idle_per_host(self.max_idle_connections)
+            .pool_idle_timeout(self.idle_timeout)
+            .build_http();
+
+        JsonRpcClient {
+            http_client,
+            uri: self.uri,
+            timeout: self.timeout,
+            retry: self.retry,
+            method_retry: Arc::new(self.method_retry),
+            permits: self
+                .max_concurrent_requests
+                .map(|max| Arc::new(Semaphore::new(max))),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonRpcResponseInternal<T> {
+    #[serde(default)]
+    id: serde_json::Value,
+    result: Option<T>,
+    error: Option<JsonRpcError>,
+}
+
+fn next_request_id() -> serde_json::Value {
+    NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed).into()
+}
+
+/// Posts `body` to `uri` and returns the response body.
+/// `req` describes the request for error messages.
+async fn post<B: Serialize>(
+    timeout: u64,
+    client: &hyper::Client<HttpConnector>,
+    uri: &Uri,
+    token: Option<&str>,
+    req: &str,
+    body: &B,
+) -> Result<impl Buf, RpcError> {
+    let mut node_req = Request::post(uri);
+    if let Some(token) = token {
+        node_req = node_req.header(hyper::header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let body = serde_json::to_vec(body).map_err(|err| RpcError::Decode(format!("{req} {err}")))?;
     let node_req = node_req
         .header(hyper::header::CONTENT_TYPE, "application/json")
-        .body(Body::from(serde_json::to_vec(&req_obj).unwrap()))
-        .unwrap();
+        .body(Body::from(body))
+        .map_err(|err| RpcError::Transport(format!("{req} error={err}")))?;
 
-    log::trace!("jsonrpc_request_client: {} {}", uri, method);
+    log::trace!("jsonrpc: {}", req);
 
-    let json = tokio::time::timeout(std::time::Duration::from_millis(timeout), async {
-        let err_str = &uri.to_string();
-        let resp = client.request(node_req).await.expect(err_str);
-        let body = hyper::body::aggregate(resp).await.expect(err_str);
-        let json: JsonRpcResponseInternal<R> =
-            serde_json::from_reader(body.reader()).expect(err_str);
+    tokio::time::timeout(Duration::from_millis(timeout), async {
+        let resp = client
+            .request(node_req)
+            .await
+            .map_err(|err| RpcError::Transport(format!("{req} error={err}")))?;
+        let status = resp.status();
+        if status == hyper::StatusCode::UNAUTHORIZED {
+            return Err(RpcError::Unauthorized(req.to_string()));
+        }
+        let body = hyper::body::aggregate(resp)
+            .await
+            .map_err(|err| RpcError::Transport(format!("{req} error={err}")))?;
+        // e.g. a proxy without upstream
+        if status.is_server_error() && !body.has_remaining() {
+            return Err(RpcError::Transport(format!("{req} status={status}")));
+        }
 
-        json
+        Ok(body)
     })
     .await
-    .map_err(|err| format!("jsonrpc: uri={uri} method={method} error={err}"))?;
+    .map_err(|_| RpcError::Timeout(req.to_string()))?
+}
 
-    if json.error.is_some() {
-        return Err(json.error.unwrap().message);
+/// Decodes the response to the request with `id`.
+fn decode_response<R: DeserializeOwned>(
+    req: &str,
+    body: impl Buf,
+    id: &serde_json::Value,
+) -> Result<R, RpcError> {
+    let json: JsonRpcResponseInternal<R> = serde_json::from_reader(body.reader())
+        .map_err(|err| RpcError::Decode(format!("{req} error={err}")))?;
+    if let Some(err) = json.error {
+        return Err(RpcError::Response(err));
     }
-
-    if json.result.is_none() {
-        return Err("no result in response".to_string());
+    if json.id != *id {
+        return Err(RpcError::Decode(format!(
+            "{req} error=expected id {id}, got {}",
+            json.id
+        )));
     }
 
-    Ok(json.result.unwrap())
+    json.result.ok_or(RpcError::NoResult)
+}
+
+/// Invokes a `json-rpc` request with a timeout of `timeout` ms for the network
+/// and deserialize part. Errors are not retried, see `JsonRpcClient`.
+pub async fn jsonrpc_request_client<T: Serialize + Send + Sync, R: DeserializeOwned>(
+    timeout: u64,
+    client: &hyper::Client<HttpConnector>,
+    uri: &Uri,
+    method: &str,
+    params: T,
+) -> Result<R, RpcError> {
+    jsonrpc_request_client_auth(timeout, client, uri, None, method, params).await
+}
+
+/// Like `jsonrpc_request_client`, with `token` as the bearer token of the request if given.
+pub async fn jsonrpc_request_client_auth<T: Serialize + Send + Sync, R: DeserializeOwned>(
+    timeout: u64,
+    client: &hyper::Client<HttpConnector>,
+    uri: &Uri,
+    token: Option<&str>,
+    method: &str,
+    params: T,
+) -> Result<R, RpcError> {
+    let id = next_request_id();
+    let req_obj = JsonRpcRequest {
+        jsonrpc: "2.0".to_string(),
+        id: id.clone(),
+        method: method.to_string(),
+        params,
+    };
+    let req = format!("uri={uri} method={method}");
+    let body = post(timeout, client, uri, token, &req, &req_obj).await?;
+
+    decode_response(&req, body, &id)
 }
 
 pub async fn jsonrpc_request<T: Serialize + Send + Sync, R: DeserializeOwned>(
     uri: &Uri,
     method: &str,
     params: T,
-) -> Result<R, String> {
+) -> Result<R, RpcError> {
     let client = hyper::Client::new();
     jsonrpc_request_client(30_000, &client, uri, method, params).await
 }
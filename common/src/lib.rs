@@ -1,2 +1,7 @@
+pub mod config;
+pub mod hash;
 pub mod json_rpc;
+pub mod openrpc;
 pub mod prover;
+pub mod server;
+pub mod task_store;
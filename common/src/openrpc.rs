@@ -0,0 +1,245 @@
+/// JSON schemas of json-rpc params and results for OpenRPC documents
+use eth_types::{Address, Bytes, H256, U256, U64};
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::{InstanceType, SchemaObject, StringValidation};
+use schemars::JsonSchema;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// The OpenRPC version of the documents.
+pub const OPENRPC_VERSION: &str = "1.2.6";
+
+/// The pattern of hex encoded values.
+const HEX_PATTERN: &str = "^0x[0-9a-fA-F]*$";
+
+/// Types with a JSON schema, used to describe the params and results of json-rpc methods.
+/// Types of this crate derive `JsonSchema` and implement it with `derive_schema!`.
+pub trait Schema {
+    fn schema() -> Value;
+
+    /// `true` if the param may be omitted.
+    fn optional() -> bool {
+        false
+    }
+}
+
+/// Params of json-rpc methods, `()` for none and tuples for positional params.
+pub trait ParamsSchema {
+    /// The schema and `Schema::optional` of each param.
+    fn params() -> Vec<(Value, bool)>;
+}
+
+/// The description of a json-rpc method in an OpenRPC document.
+#[derive(Clone, Debug, Default)]
+pub struct MethodSchema {
+    pub summary: String,
+    pub param_names: Vec<String>,
+    pub params: Vec<(Value, bool)>,
+    pub result: Value,
+}
+
+impl MethodSchema {
+    pub fn new<P: ParamsSchema, R: Schema>() -> Self {
+        Self {
+            params: P::params(),
+            result: R::schema(),
+            ..Default::default()
+        }
+    }
+
+    /// The OpenRPC method object, params without a name in `param_names` are named by position.
+    pub fn to_json(&self, name: &str) -> Value {
+        let params: Vec<Value> = self
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, (schema, optional))| {
+                let name = self
+                    .param_names
+                    .get(i)
+                    .cloned()
+                    .unwrap_or_else(|| format!("param{i}"));
+                json!({ "name": name, "required": !optional, "schema": schema })
+            })
+            .collect();
+        let mut method = json!({
+            "name": name,
+            "params": params,
+            "result": { "name": "result", "schema": self.result },
+        });
+        if !self.summary.is_empty() {
+            method["summary"] = self.summary.clone().into();
+        }
+
+        method
+    }
+}
+
+/// Returns an OpenRPC document with `methods`, sorted by name.
+pub fn document(title: &str, version: &str, methods: &HashMap<String, MethodSchema>) -> Value {
+    let mut names: Vec<&String> = methods.keys().collect();
+    names.sort_unstable();
+
+    json!({
+        "openrpc": OPENRPC_VERSION,
+        "info": { "title": title, "version": version },
+        "methods": names
+            .into_iter()
+            .map(|name| methods[name].to_json(name))
+            .collect::<Vec<Value>>(),
+    })
+}
+
+/// The schema of an object with `properties`, the flag marks optional ones.
+pub fn object(title: &str, properties: &[(&str, Value, bool)]) -> Value {
+    let required: Vec<&str> = properties
+        .iter()
+        .filter(|(_, _, optional)| !optional)
+        .map(|(name, _, _)| *name)
+        .collect();
+    let properties: serde_json::Map<String, Value> = properties
+        .iter()
+        .map(|(name, schema, _)| (name.to_string(), schema.clone()))
+        .collect();
+
+    json!({
+        "title": title,
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// The schema of `T` with all subschemas inlined, the documents have no shared definitions.
+pub fn derived<T: JsonSchema>() -> Value {
+    let root = SchemaSettings::draft07()
+        .with(|settings| {
+            settings.inline_subschemas = true;
+            settings.meta_schema = None;
+        })
+        .into_generator()
+        .into_root_schema_for::<T>();
+
+    serde_json::to_value(root).expect("JSON schema")
+}
+
+/// Implements `Schema` with `derived` for types deriving `JsonSchema`.
+#[macro_export]
+macro_rules! derive_schema {
+    ($($ty:ty),+) => {
+        $(
+            impl $crate::openrpc::Schema for $ty {
+                fn schema() -> serde_json::Value {
+                    $crate::openrpc::derived::<$ty>()
+                }
+            }
+        )+
+    };
+}
+
+/// Stands in for hex encoded fields, e.g. `#[schemars(with = "Hex")]` for `Bytes`.
+pub struct Hex;
+
+impl JsonSchema for Hex {
+    fn schema_name() -> String {
+        "Hex".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> schemars::schema::Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(HEX_PATTERN.to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    fn is_referenceable() -> bool {
+        false
+    }
+}
+
+fn hex(title: &str) -> Value {
+    json!({ "title": title, "type": "string", "pattern": HEX_PATTERN })
+}
+
+macro_rules! impl_schema {
+    ($schema:expr => $($ty:ty),+) => {
+        $(
+            impl Schema for $ty {
+                fn schema() -> Value {
+                    $schema
+                }
+            }
+        )+
+    };
+}
+
+impl_schema!(json!({ "type": "boolean" }) => bool);
+impl_schema!(json!({ "type": "integer" }) => u8, u16, u32, u64, usize, i32, i64);
+impl_schema!(json!({ "type": "string" }) => String, str);
+impl_schema!(json!({ "type": "null" }) => ());
+impl_schema!(json!({}) => Value);
+impl_schema!(hex("Address") => Address);
+impl_schema!(hex("Bytes") => Bytes);
+impl_schema!(hex("H256") => H256);
+impl_schema!(hex("U256") => U256);
+impl_schema!(hex("U64") => U64);
+
+impl<T: Schema + ?Sized> Schema for &T {
+    fn schema() -> Value {
+        T::schema()
+    }
+}
+
+impl<T: Schema> Schema for Option<T> {
+    fn schema() -> Value {
+        json!({ "oneOf": [T::schema(), { "type": "null" }] })
+    }
+
+    fn optional() -> bool {
+        true
+    }
+}
+
+impl<T: Schema> Schema for Vec<T> {
+    fn schema() -> Value {
+        json!({ "type": "array", "items": T::schema() })
+    }
+}
+
+/// serde represents `Result`s as `{ "Ok": .. }` or `{ "Err": .. }`.
+impl<T: Schema, E: Schema> Schema for Result<T, E> {
+    fn schema() -> Value {
+        json!({
+            "oneOf": [
+                object("Ok", &[("Ok", T::schema(), false)]),
+                object("Err", &[("Err", E::schema(), false)]),
+            ]
+        })
+    }
+}
+
+impl ParamsSchema for () {
+    fn params() -> Vec<(Value, bool)> {
+        Vec::new()
+    }
+}
+
+macro_rules! impl_params_schema {
+    ($($ty:ident),+) => {
+        impl<$($ty: Schema),+> ParamsSchema for ($($ty,)+) {
+            fn params() -> Vec<(Value, bool)> {
+                vec![$(($ty::schema(), $ty::optional())),+]
+            }
+        }
+    };
+}
+
+impl_params_schema!(A);
+impl_params_schema!(A, B);
+impl_params_schema!(A, B, C);
+impl_params_schema!(A, B, C, D);
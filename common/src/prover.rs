@@ -1,15 +1,28 @@
+use crate::derive_schema;
+use crate::json_rpc::{JsonRpcClient, RpcError};
+use crate::openrpc::Hex;
 use eth_types::{Bytes, U256};
+use hyper::Uri;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Clone, Default, Serialize, Deserialize)]
+/// Seconds a pending task waits to gain one priority level, so low priority tasks run eventually.
+pub const PRIORITY_AGING_SECS: u64 = 600;
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct ProofResult {
     /// The halo2 transcript
+    #[schemars(with = "Hex")]
     pub proof: Bytes,
     /// Public inputs for the proof
+    #[schemars(with = "Vec<Hex>")]
     pub instance: Vec<U256>,
     /// k of circuit parameters
     pub k: u8,
     /// Randomness used
+    #[schemars(with = "Hex")]
     pub randomness: Bytes,
     /// Circuit name / identifier
     pub label: String,
@@ -30,7 +43,7 @@ impl std::fmt::Debug for ProofResult {
 }
 
 /// Timing information in milliseconds.
-#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct ProofResultInstrumentation {
     /// keygen_vk
     pub vk: u32,
@@ -48,7 +61,25 @@ pub struct ProofResultInstrumentation {
     pub protocol: u32,
 }
 
-#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+impl ProofResultInstrumentation {
+    /// The sum of all timings.
+    pub fn total(&self) -> u64 {
+        [
+            self.vk,
+            self.pk,
+            self.proof,
+            self.verify,
+            self.mock,
+            self.circuit,
+            self.protocol,
+        ]
+        .iter()
+        .map(|v| *v as u64)
+        .sum()
+    }
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Proofs {
     /// Circuit configuration used
     pub config: CircuitConfig,
@@ -60,7 +91,7 @@ pub struct Proofs {
     pub gas: u64,
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ProofRequestOptions {
     /// The name of the circuit.
     /// "super", "pi"
@@ -86,6 +117,14 @@ pub struct ProofRequestOptions {
     /// Verifies the proof after computation.
     #[serde(default = "default_bool")]
     pub verify_proof: bool,
+    /// Tasks with a higher priority are computed first, 0 by default.
+    /// Not part of the task identity, the first request of a task sets it.
+    #[serde(default)]
+    pub priority: i32,
+    /// Seconds since the unix epoch the proofs are needed by, earlier deadlines are
+    /// computed first among tasks of the same priority.
+    #[serde(default)]
+    pub deadline: Option<u64>,
 }
 
 impl PartialEq for ProofRequestOptions {
@@ -99,21 +138,146 @@ impl PartialEq for ProofRequestOptions {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The error of cancelled tasks.
+pub const TASK_CANCELLED: &str = "task cancelled";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ProofRequest {
     pub options: ProofRequestOptions,
     pub result: Option<Result<Proofs, String>>,
     /// A counter to keep track of changes of the `result` field
     pub edition: u64,
+    /// Seconds since the unix epoch the task was enqueued at, 0 if unknown.
+    /// Tasks loaded or merged without it count as enqueued at that time.
+    #[serde(default)]
+    pub enqueued_at: u64,
+}
+
+impl ProofRequest {
+    /// `options.priority` plus one level for every `PRIORITY_AGING_SECS` the task waited.
+    pub fn effective_priority(&self, now: u64) -> i64 {
+        let age = now.saturating_sub(self.enqueued_at) / PRIORITY_AGING_SECS;
+        self.options.priority as i64 + age as i64
+    }
+}
+
+/// The pending tasks in the order they should be computed: by effective priority,
+/// then earliest deadline, then enqueue time and finally their order in `tasks`.
+pub fn schedule(tasks: &[ProofRequest], now: u64) -> Vec<&ProofRequest> {
+    let mut pending: Vec<&ProofRequest> = tasks.iter().filter(|e| e.result.is_none()).collect();
+    pending.sort_by_key(|e| {
+        (
+            Reverse(e.effective_priority(now)),
+            e.options.deadline.unwrap_or(u64::MAX),
+            e.enqueued_at,
+        )
+    });
+
+    pending
+}
+
+/// The queue state of the pending task with `options`, `running` is the task being computed.
+/// The estimated start assumes that all tasks take as long as the completed ones in `tasks`.
+pub fn pending_task(
+    tasks: &[ProofRequest],
+    running: Option<&ProofRequestOptions>,
+    options: &ProofRequestOptions,
+    now: u64,
+) -> PendingTask {
+    if running == Some(options) {
+        return PendingTask {
+            queue_position: 0,
+            estimated_start: Some(now),
+        };
+    }
+
+    let queue = schedule(tasks, now);
+    let ahead = queue
+        .iter()
+        .take_while(|e| e.options != *options)
+        .filter(|e| running != Some(&e.options))
+        .count();
+    let queue_position = ahead + 1;
+
+    let durations: Vec<u64> = tasks
+        .iter()
+        .filter_map(|e| match &e.result {
+            Some(Ok(proofs)) => Some(proofs.duration()),
+            _ => None,
+        })
+        .collect();
+    let estimated_start = match (durations.is_empty(), running.is_some()) {
+        (true, _) => None,
+        (false, running) => {
+            let average = durations.iter().sum::<u64>() / durations.len() as u64;
+            // without a running task the first one starts now
+            let slots = if running { queue_position } else { ahead } as u64;
+            Some(now + slots * average / 1000)
+        }
+    };
+
+    PendingTask {
+        queue_position,
+        estimated_start,
+    }
+}
+
+/// Seconds since the unix epoch.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time after the unix epoch")
+        .as_secs()
+}
+
+/// The response of the `proof` method, the proofs or the queue state of the pending task.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ProofResponse {
+    Completed(Proofs),
+    Pending(PendingTask),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct PendingTask {
+    /// Position in the queue of the node, 0 if the task is being computed
+    pub queue_position: usize,
+    /// Estimated start of the computation in seconds since the unix epoch,
+    /// `None` without completed tasks to estimate from
+    pub estimated_start: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct NodeInformation {
     pub id: String,
     pub tasks: Vec<ProofRequest>,
+    #[serde(default)]
+    pub pk_cache: PkCacheStats,
+}
+
+/// Statistics of the in-memory proving key cache of a node.
+#[derive(Clone, Default, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PkCacheStats {
+    /// The memory budget in bytes, unlimited if `None`
+    pub budget: Option<u64>,
+    /// Bytes used by all cached keys
+    pub size: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub keys: Vec<PkCacheEntry>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PkCacheEntry {
+    pub name: String,
+    /// Approximate size in bytes
+    pub size: u64,
+    /// `true` if a proof computation uses the key, it is not evicted then
+    pub in_use: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct NodeStatus {
     pub id: String,
     /// The current active task this instance wants to obtain or is working on.
@@ -122,7 +286,7 @@ pub struct NodeStatus {
     pub obtained: bool,
 }
 
-#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub struct CircuitConfig {
     pub block_gas_limit: usize,
     pub max_txs: usize,
@@ -137,6 +301,138 @@ pub struct CircuitConfig {
     pub keccak_padding: usize,
 }
 
+/// Selects the tasks that `flush` removes.
+#[derive(Clone, Default, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct FlushRequestOptions {
+    /// The proving key cache
+    pub cache: bool,
+    /// Tasks without a result
+    pub pending: bool,
+    /// Tasks with a result
+    pub completed: bool,
+}
+
 fn default_bool() -> bool {
     false
 }
+
+impl Proofs {
+    /// Milliseconds spent computing the proofs.
+    pub fn duration(&self) -> u64 {
+        self.circuit.aux.total() + self.aggregation.aux.total()
+    }
+}
+
+derive_schema!(
+    ProofResult,
+    ProofResultInstrumentation,
+    Proofs,
+    ProofRequestOptions,
+    ProofRequest,
+    ProofResponse,
+    PendingTask,
+    NodeInformation,
+    PkCacheStats,
+    PkCacheEntry,
+    NodeStatus,
+    CircuitConfig,
+    FlushRequestOptions
+);
+
+/// Client for the json-rpc API of the prover daemon, one method per json-rpc method.
+#[derive(Clone)]
+pub struct ProverClient {
+    client: JsonRpcClient,
+}
+
+impl ProverClient {
+    pub fn new(uri: Uri) -> Self {
+        Self::with_client(JsonRpcClient::new(uri))
+    }
+
+    /// Uses `client`, e.g. with a different timeout or retry policy.
+    pub fn with_client(client: JsonRpcClient) -> Self {
+        Self { client }
+    }
+
+    pub fn uri(&self) -> &Uri {
+        self.client.uri()
+    }
+
+    /// Enqueues a task to compute the proofs for `options`.
+    /// Returns `None` while the proofs are not yet computed.
+    pub async fn proof(&self, options: &ProofRequestOptions) -> Result<Option<Proofs>, RpcError> {
+        match self.proof_response(options).await? {
+            Some(ProofResponse::Completed(proofs)) => Ok(Some(proofs)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Like `proof` but returns the queue state of the task while it is pending.
+    /// `None` if the node does not report it.
+    pub async fn proof_response(
+        &self,
+        options: &ProofRequestOptions,
+    ) -> Result<Option<ProofResponse>, RpcError> {
+        match self.client.request("proof", [options]).await {
+            // ...not an error
+            Err(RpcError::NoResult) => Ok(None),
+            res => res.map(Some),
+        }
+    }
+
+    /// Returns the circuit parameters for the block in `options`.
+    pub async fn circuit_config(
+        &self,
+        options: &ProofRequestOptions,
+    ) -> Result<CircuitConfig, RpcError> {
+        self.client.request("circuit_config", [options]).await
+    }
+
+    /// Returns the node id and its tasks.
+    pub async fn info(&self) -> Result<NodeInformation, RpcError> {
+        self.client.request("info", ()).await
+    }
+
+    /// Returns the task the node works on.
+    pub async fn status(&self) -> Result<NodeStatus, RpcError> {
+        self.client.request("status", ()).await
+    }
+
+    /// Cancels the task with `options` on the node and its peers, `false` if it is unknown
+    /// or already completed. A node working on it stops at the next phase.
+    pub async fn cancel(&self, options: &ProofRequestOptions) -> Result<bool, RpcError> {
+        self.client.request("cancel", [options]).await
+    }
+
+    /// Cancels the task the node works on and returns it, if any.
+    pub async fn abort(&self) -> Result<Option<ProofRequestOptions>, RpcError> {
+        self.client.request("abort", ()).await
+    }
+
+    /// Drops the result of the task with `options` on the node and its peers and enqueues it
+    /// again, `false` if it is unknown or still pending.
+    pub async fn invalidate(&self, options: &ProofRequestOptions) -> Result<bool, RpcError> {
+        self.client.request("invalidate", [options]).await
+    }
+
+    /// Removes the tasks selected by `options` from the node, but not from its peers.
+    pub async fn flush(&self, options: &FlushRequestOptions) -> Result<bool, RpcError> {
+        self.client.request("flush", [options]).await
+    }
+
+    /// Obsolete, same as `flush` of pending and completed tasks.
+    pub async fn flush_all(&self) -> Result<bool, RpcError> {
+        self.client.request("flushAll", ()).await
+    }
+
+    /// Obsolete, same as `flush` of pending tasks.
+    pub async fn flush_pending(&self) -> Result<bool, RpcError> {
+        self.client.request("flushPending", ()).await
+    }
+
+    /// Obsolete, same as `flush` of completed tasks.
+    pub async fn flush_completed(&self) -> Result<bool, RpcError> {
+        self.client.request("flushCompleted", ()).await
+    }
+}
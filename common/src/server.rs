@@ -0,0 +1,575 @@
+//! A json-rpc server with a method registry, middleware and health endpoints
+use crate::json_rpc::JsonRpcError;
+use crate::json_rpc::JsonRpcResponse;
+use crate::json_rpc::JsonRpcResponseError;
+use crate::json_rpc::INTERNAL_ERROR;
+use crate::json_rpc::INVALID_REQUEST;
+use crate::json_rpc::PARSE_ERROR;
+use crate::openrpc::{self, MethodSchema, ParamsSchema, Schema};
+use hyper::body::Buf;
+use hyper::body::HttpBody;
+use hyper::header::HeaderValue;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde::de::{DeserializeOwned, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+type MethodHandler<S> =
+    Arc<dyn Fn(S, Vec<Value>) -> BoxFuture<Result<Value, JsonRpcError>> + Send + Sync>;
+type RouteHandler<S> = Arc<dyn Fn(S, Request<Body>) -> BoxFuture<Response<Body>> + Send + Sync>;
+type ReadyHandler<S> = Arc<dyn Fn(S) -> BoxFuture<bool> + Send + Sync>;
+
+/// Requests with a larger body are rejected, see `JsonRpcServer::max_body_size`.
+pub const DEFAULT_MAX_BODY_SIZE: u64 = 1 << 20;
+
+/// Hooks into the request handling of `JsonRpcServer`.
+pub trait Middleware: Send + Sync + 'static {
+    /// Called before a http request is routed, returning a response ends the request.
+    fn on_request(&self, _req: &Request<Body>) -> Option<Response<Body>> {
+        None
+    }
+
+    /// Called after each call of a registered json-rpc method.
+    fn on_call(&self, _method: &str, _elapsed: Duration, _result: &Result<Value, JsonRpcError>) {}
+
+    /// Called for every http response, `method` is the method of the http request.
+    fn on_response(&self, _method: &Method, _resp: &mut Response<Body>) {}
+}
+
+/// Sets the CORS headers, with the extended headers for preflight requests.
+pub struct Cors;
+
+impl Middleware for Cors {
+    fn on_response(&self, method: &Method, resp: &mut Response<Body>) {
+        let headers = resp.headers_mut();
+        headers.insert("access-control-allow-origin", HeaderValue::from_static("*"));
+
+        if method == Method::OPTIONS {
+            headers.insert(
+                "access-control-allow-methods",
+                HeaderValue::from_static("post, get, options"),
+            );
+            headers.insert(
+                "access-control-allow-headers",
+                HeaderValue::from_static(
+                    "origin, content-type, accept, x-requested-with, authorization",
+                ),
+            );
+            headers.insert("access-control-max-age", HeaderValue::from_static("300"));
+        }
+    }
+}
+
+/// Logs each json-rpc call with its duration.
+pub struct Logger;
+
+impl Middleware for Logger {
+    fn on_call(&self, method: &str, elapsed: Duration, result: &Result<Value, JsonRpcError>) {
+        match result {
+            Ok(_) => log::debug!("{} took {:?}", method, elapsed),
+            Err(err) => log::debug!("{} took {:?} error={}", method, elapsed, err),
+        }
+    }
+}
+
+/// Rejects requests to `paths` without the header `authorization: Bearer <token>`.
+pub struct BearerAuth {
+    expected: String,
+    paths: Vec<String>,
+}
+
+impl BearerAuth {
+    pub fn new(token: &str, paths: &[&str]) -> Self {
+        Self {
+            expected: format!("Bearer {token}"),
+            paths: paths.iter().map(|path| path.to_string()).collect(),
+        }
+    }
+}
+
+impl Middleware for BearerAuth {
+    fn on_request(&self, req: &Request<Body>) -> Option<Response<Body>> {
+        // preflight requests do not carry credentials
+        if req.method() == Method::OPTIONS || !self.paths.iter().any(|p| p == req.uri().path()) {
+            return None;
+        }
+        let authorized = req
+            .headers()
+            .get(hyper::header::AUTHORIZATION)
+            .map_or(false, |value| value.as_bytes() == self.expected.as_bytes());
+        if authorized {
+            return None;
+        }
+
+        let mut resp = Response::new(Body::from("unauthorized"));
+        *resp.status_mut() = StatusCode::UNAUTHORIZED;
+        Some(resp)
+    }
+}
+
+/// Call statistics of a json-rpc method.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MethodMetrics {
+    pub calls: u64,
+    pub errors: u64,
+    pub duration: Duration,
+}
+
+/// Counts the calls, errors and the time spent per json-rpc method.
+/// Served in the Prometheus text format on `/metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    methods: Mutex<HashMap<String, MethodMetrics>>,
+}
+
+impl Metrics {
+    pub fn get(&self, method: &str) -> MethodMetrics {
+        self.methods
+            .lock()
+            .unwrap()
+            .get(method)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let methods = self.methods.lock().unwrap();
+        let mut names: Vec<&String> = methods.keys().collect();
+        names.sort_unstable();
+
+        let mut out = String::new();
+        for (name, help, kind) in [
+            ("rpc_calls_total", "Number of json-rpc calls", "counter"),
+            (
+                "rpc_errors_total",
+                "Number of failed json-rpc calls",
+                "counter",
+            ),
+            (
+                "rpc_duration_seconds_total",
+                "Time spent in json-rpc calls",
+                "counter",
+            ),
+        ] {
+            writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}").unwrap();
+            for method in names.iter() {
+                let metrics = &methods[*method];
+                let value = match name {
+                    "rpc_calls_total" => metrics.calls.to_string(),
+                    "rpc_errors_total" => metrics.errors.to_string(),
+                    _ => metrics.duration.as_secs_f64().to_string(),
+                };
+                writeln!(out, "{name}{{method=\"{method}\"}} {value}").unwrap();
+            }
+        }
+
+        out
+    }
+}
+
+impl Middleware for Metrics {
+    fn on_call(&self, method: &str, elapsed: Duration, result: &Result<Value, JsonRpcError>) {
+        let mut methods = self.methods.lock().unwrap();
+        let metrics = methods.entry(method.to_string()).or_default();
+        metrics.calls += 1;
+        metrics.errors += result.is_err() as u64;
+        metrics.duration += elapsed;
+    }
+}
+
+/// Deserializes the params array of a call into the params type of a method.
+/// Missing trailing params of tuples are `null`, e.g. for optional params,
+/// and `()` accepts any params.
+struct Params(Vec<Value>);
+
+impl<'de> Deserializer<'de> for Params {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        Value::Array(self.0).deserialize_any(visitor)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        mut self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if self.0.len() < len {
+            self.0.resize(len, Value::Null);
+        }
+        Value::Array(self.0).deserialize_tuple(len, visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit_struct newtype_struct seq tuple_struct
+        map struct enum identifier ignored_any
+    }
+}
+
+#[derive(Deserialize)]
+struct Call {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// A json-rpc server for the state `S`, a clone of it is passed to every handler.
+/// Json-rpc calls are served on `rpc_path`, single or batched, next to the custom `route`s,
+/// `GET /health`, `GET /ready` and `GET /metrics`.
+/// `Cors`, `Logger` and `Metrics` are installed by default.
+/// The OpenRPC document of the methods is served with `rpc.discover`.
+pub struct JsonRpcServer<S> {
+    state: S,
+    title: String,
+    version: String,
+    rpc_path: String,
+    max_body_size: u64,
+    methods: HashMap<String, MethodHandler<S>>,
+    schemas: HashMap<String, MethodSchema>,
+    routes: HashMap<(Method, String), RouteHandler<S>>,
+    ready: Option<ReadyHandler<S>>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    metrics: Arc<Metrics>,
+}
+
+impl<S: Clone + Send + Sync + 'static> JsonRpcServer<S> {
+    pub fn new(state: S) -> Self {
+        let metrics = Arc::new(Metrics::default());
+
+        Self {
+            state,
+            title: "json-rpc".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            rpc_path: "/".to_string(),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            methods: HashMap::new(),
+            schemas: HashMap::new(),
+            routes: HashMap::new(),
+            ready: None,
+            middleware: vec![Arc::new(Cors), Arc::new(Logger), metrics.clone()],
+            metrics,
+        }
+    }
+
+    /// The title and version of the OpenRPC document.
+    pub fn info(mut self, title: &str, version: &str) -> Self {
+        self.title = title.to_string();
+        self.version = version.to_string();
+        self
+    }
+
+    /// The path of the json-rpc endpoint, `/` by default.
+    pub fn rpc_path(mut self, path: &str) -> Self {
+        self.rpc_path = path.to_string();
+        self
+    }
+
+    pub fn max_body_size(mut self, max_body_size: u64) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Adds `middleware`, invoked after the middleware added before.
+    pub fn middleware(mut self, middleware: impl Middleware) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Registers the json-rpc method `name`. The params array is deserialized into `P`,
+    /// usually a tuple, and the result is serialized as the result of the call.
+    pub fn method<P, R, F, Fut>(mut self, name: &str, handler: F) -> Self
+    where
+        P: DeserializeOwned + ParamsSchema + 'static,
+        R: Serialize + Schema + 'static,
+        F: Fn(S, P) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<R, JsonRpcError>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        let method: MethodHandler<S> = Arc::new(move |state, params| {
+            let params = P::deserialize(Params(params))
+                .map_err(|err| JsonRpcError::invalid_params(err.to_string()));
+            let call = params.map(|params| handler(state, params));
+
+            Box::pin(async move {
+                let result = call?.await?;
+                serde_json::to_value(result)
+                    .map_err(|err| JsonRpcError::new(INTERNAL_ERROR, err.to_string()))
+            })
+        });
+        self.methods.insert(name.to_string(), method);
+        self.schemas
+            .insert(name.to_string(), MethodSchema::new::<P, R>());
+        self
+    }
+
+    /// Adds the `summary` and the names of the params of the registered method `name`
+    /// to the OpenRPC document.
+    pub fn describe(mut self, name: &str, summary: &str, param_names: &[&str]) -> Self {
+        let schema = self
+            .schemas
+            .get_mut(name)
+            .unwrap_or_else(|| panic!("describe: unknown method {name}"));
+        schema.summary = summary.to_string();
+        schema.param_names = param_names.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// The OpenRPC document of the registered methods.
+    pub fn openrpc(&self) -> Value {
+        openrpc::document(&self.title, &self.version, &self.schemas)
+    }
+
+    /// Serves `handler` for http requests with `method` to `path`,
+    /// routes take precedence over the built-in endpoints.
+    pub fn route<F, Fut>(mut self, method: Method, path: &str, handler: F) -> Self
+    where
+        F: Fn(S, Request<Body>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Response<Body>> + Send + 'static,
+    {
+        let route: RouteHandler<S> = Arc::new(move |state, req| Box::pin(handler(state, req)));
+        self.routes.insert((method, path.to_string()), route);
+        self
+    }
+
+    /// `GET /ready` returns 200 if `check` returns `true` and 503 otherwise.
+    /// Always ready if not set.
+    pub fn ready<F, Fut>(mut self, check: F) -> Self
+    where
+        F: Fn(S) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        self.ready = Some(Arc::new(move |state| Box::pin(check(state))));
+        self
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// The names of the registered json-rpc methods, sorted.
+    pub fn methods(&self) -> Vec<&str> {
+        let mut methods: Vec<&str> = self.methods.keys().map(String::as_str).collect();
+        methods.sort_unstable();
+        methods
+    }
+
+    /// Binds to `addr`, port 0 picks a free port. Returns the bound address and the server,
+    /// which stops accepting connections once `shutdown` resolves and waits for the open ones.
+    pub fn bind(
+        self,
+        addr: &SocketAddr,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<(SocketAddr, BoxFuture<Result<(), hyper::Error>>), hyper::Error> {
+        let server = Arc::new(self);
+        let service = make_service_fn(move |_| {
+            let server = server.clone();
+            let service = service_fn(move |req| {
+                let server = server.clone();
+                async move { Ok::<_, Infallible>(server.handle(req).await) }
+            });
+
+            async move { Ok::<_, Infallible>(service) }
+        });
+        let server = hyper::Server::try_bind(addr)?.serve(service);
+        let addr = server.local_addr();
+
+        Ok((addr, Box::pin(server.with_graceful_shutdown(shutdown))))
+    }
+
+    /// Serves on `addr` until `shutdown` resolves, see `bind`.
+    pub async fn serve(
+        self,
+        addr: &SocketAddr,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<(), hyper::Error> {
+        let (addr, server) = self.bind(addr, shutdown)?;
+        log::info!("Listening on http://{}", addr);
+
+        server.await
+    }
+
+    /// Handles a http request.
+    pub async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        let method = req.method().clone();
+        let mut resp = match self.middleware.iter().find_map(|m| m.on_request(&req)) {
+            Some(resp) => resp,
+            None => self.dispatch(req).await,
+        };
+
+        if !resp.headers().contains_key(hyper::header::CONTENT_TYPE) {
+            resp.headers_mut().insert(
+                hyper::header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+        }
+        for middleware in self.middleware.iter() {
+            middleware.on_response(&method, &mut resp);
+        }
+
+        resp
+    }
+
+    async fn dispatch(&self, req: Request<Body>) -> Response<Body> {
+        // limits the request size
+        let content_length = req
+            .body()
+            .size_hint()
+            .upper()
+            .unwrap_or(self.max_body_size + 1);
+        if content_length > self.max_body_size {
+            let mut resp = Response::new(Body::from("request too large"));
+            *resp.status_mut() = StatusCode::BAD_REQUEST;
+            return resp;
+        }
+
+        let key = (req.method().clone(), req.uri().path().to_string());
+        if let Some(route) = self.routes.get(&key) {
+            return route(self.state.clone(), req).await;
+        }
+
+        match (req.method(), req.uri().path()) {
+            (&Method::POST, path) if path == self.rpc_path => {
+                let body = match hyper::body::aggregate(req.into_body()).await {
+                    Ok(body) => body,
+                    Err(err) => {
+                        let mut resp = Response::new(Body::from(err.to_string()));
+                        *resp.status_mut() = StatusCode::BAD_REQUEST;
+                        return resp;
+                    }
+                };
+                let payload = match serde_json::from_reader(body.reader()) {
+                    Ok(Value::Array(calls)) if !calls.is_empty() => {
+                        let mut responses = Vec::with_capacity(calls.len());
+                        for call in calls {
+                            responses.push(self.handle_call(call).await);
+                        }
+                        Value::Array(responses)
+                    }
+                    Ok(Value::Array(_)) => error_response(
+                        Value::Null,
+                        JsonRpcError::new(INVALID_REQUEST, "empty batch"),
+                    ),
+                    Ok(call) => self.handle_call(call).await,
+                    Err(err) => {
+                        error_response(Value::Null, JsonRpcError::new(PARSE_ERROR, err.to_string()))
+                    }
+                };
+
+                Response::new(Body::from(serde_json::to_vec(&payload).unwrap()))
+            }
+
+            // serve CORS headers
+            (&Method::OPTIONS, _) => Response::default(),
+
+            // nothing to report - healthy as long as requests are served
+            (&Method::GET, "/health") => Response::default(),
+
+            (&Method::GET, "/ready") => {
+                let ready = match &self.ready {
+                    Some(check) => check(self.state.clone()).await,
+                    None => true,
+                };
+                let mut resp = Response::default();
+                if !ready {
+                    *resp.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                }
+                resp
+            }
+
+            (&Method::GET, "/metrics") => {
+                let mut resp = Response::new(Body::from(self.metrics.render()));
+                resp.headers_mut().insert(
+                    hyper::header::CONTENT_TYPE,
+                    HeaderValue::from_static("text/plain; version=0.0.4"),
+                );
+                resp
+            }
+
+            // everything else
+            _ => {
+                let mut not_found = Response::default();
+                *not_found.status_mut() = StatusCode::NOT_FOUND;
+                not_found
+            }
+        }
+    }
+
+    /// Handles a single json-rpc call and returns the response object.
+    pub async fn handle_call(&self, call: Value) -> Value {
+        let id = call.get("id").cloned().unwrap_or_default();
+        let call: Call = match serde_json::from_value(call) {
+            Ok(call) => call,
+            Err(err) => {
+                return error_response(id, JsonRpcError::new(INVALID_REQUEST, err.to_string()))
+            }
+        };
+        let params = match call.params {
+            Value::Array(params) => params,
+            Value::Null => Vec::new(),
+            _ => {
+                let err = JsonRpcError::invalid_params("expected an array of params");
+                return error_response(call.id, err);
+            }
+        };
+        let handler = match self.methods.get(&call.method) {
+            Some(handler) => handler,
+            None if call.method == "rpc.discover" => {
+                return serde_json::to_value(JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: call.id,
+                    result: Some(self.openrpc()),
+                })
+                .unwrap();
+            }
+            None => {
+                let err = JsonRpcError::method_not_found("this method is not available");
+                return error_response(call.id, err);
+            }
+        };
+
+        let start = Instant::now();
+        let result = handler(self.state.clone(), params).await;
+        let elapsed = start.elapsed();
+        for middleware in self.middleware.iter() {
+            middleware.on_call(&call.method, elapsed, &result);
+        }
+
+        match result {
+            Ok(result) => serde_json::to_value(JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                id: call.id,
+                result: Some(result),
+            })
+            .unwrap(),
+            Err(err) => error_response(call.id, err),
+        }
+    }
+}
+
+fn error_response(id: Value, error: JsonRpcError) -> Value {
+    serde_json::to_value(JsonRpcResponseError {
+        jsonrpc: "2.0".to_string(),
+        id,
+        error,
+    })
+    .unwrap()
+}
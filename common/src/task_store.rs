@@ -0,0 +1,223 @@
+use crate::hash::fnv1a;
+use crate::prover::{unix_time, ProofRequest, ProofRequestOptions};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Which completed tasks a `TaskStore` keeps, everything by default.
+/// Pending tasks are never removed by the retention policy.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Retention {
+    /// Keeps at most this many completed tasks, the oldest are removed first.
+    pub max_completed: Option<usize>,
+    /// Removes completed tasks older than this.
+    pub max_age: Option<Duration>,
+}
+
+/// A stored task in `TaskStore.index`.
+struct IndexEntry {
+    options: ProofRequestOptions,
+    edition: u64,
+    /// `None` if pending
+    completed_at: Option<SystemTime>,
+}
+
+/// On-disk store of proof tasks and their results, one JSON file per task.
+/// Files are replaced atomically, a crash leaves either the old or the new version of a task.
+/// The methods do blocking IO, async callers should not hold locks while calling them.
+pub struct TaskStore {
+    dir: PathBuf,
+    retention: Retention,
+    /// The stored tasks by file name, also serializes the writes.
+    index: Mutex<HashMap<String, IndexEntry>>,
+    /// The edition of the tasks removed by `prune` by file name.
+    pruned: Mutex<HashMap<String, u64>>,
+}
+
+impl TaskStore {
+    /// Opens the store in `dir`, creating the directory if needed.
+    pub fn open(dir: impl Into<PathBuf>, retention: Retention) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            retention,
+            index: Mutex::new(HashMap::new()),
+            pruned: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn retention(&self) -> Retention {
+        self.retention
+    }
+
+    /// Reads all stored tasks, files that can not be read or decoded are skipped.
+    pub fn load(&self) -> io::Result<Vec<ProofRequest>> {
+        let mut index = self.index.lock().unwrap();
+        let mut tasks = Vec::new();
+        let now = unix_time();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension() != Some(OsStr::new("json")) {
+                continue;
+            }
+
+            let mut task: ProofRequest = match fs::read(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|data| serde_json::from_slice(&data).map_err(|e| e.to_string()))
+            {
+                Ok(task) => task,
+                Err(err) => {
+                    log::warn!("task store: skipping {}: {}", path.display(), err);
+                    continue;
+                }
+            };
+            // stored by an earlier version, the task should not age from the epoch
+            if task.enqueued_at == 0 {
+                task.enqueued_at = now;
+            }
+            // the modification time of completed tasks is their completion time
+            let completed_at = match task.result {
+                Some(_) => Some(fs::metadata(&path)?.modified()?),
+                None => None,
+            };
+            index.insert(
+                task_key(&task.options),
+                IndexEntry {
+                    options: task.options.clone(),
+                    edition: task.edition,
+                    completed_at,
+                },
+            );
+            tasks.push(task);
+        }
+
+        Ok(tasks)
+    }
+
+    /// Writes `task`, replacing a previous version.
+    /// Does nothing if a later edition of the task is stored already, saves that were
+    /// issued in order may complete out of order.
+    pub fn save(&self, task: &ProofRequest) -> io::Result<()> {
+        let key = task_key(&task.options);
+        let mut index = self.index.lock().unwrap();
+        if matches!(index.get(&key), Some(entry) if entry.edition > task.edition) {
+            return Ok(());
+        }
+
+        let path = self.path(&key);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(task)?)?;
+        fs::rename(&tmp, &path)?;
+
+        index.insert(
+            key.clone(),
+            IndexEntry {
+                options: task.options.clone(),
+                edition: task.edition,
+                completed_at: task.result.as_ref().map(|_| SystemTime::now()),
+            },
+        );
+        self.pruned.lock().unwrap().remove(&key);
+
+        Ok(())
+    }
+
+    /// Removes the task with `options`, if any.
+    pub fn remove(&self, options: &ProofRequestOptions) -> io::Result<()> {
+        let key = task_key(options);
+        let mut index = self.index.lock().unwrap();
+        index.remove(&key);
+
+        match fs::remove_file(self.path(&key)) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            res => res,
+        }
+    }
+
+    /// Removes the completed tasks exceeding the retention policy and returns their options.
+    pub fn prune(&self) -> io::Result<Vec<ProofRequestOptions>> {
+        let now = SystemTime::now();
+        let mut completed: Vec<(SystemTime, ProofRequestOptions, u64)> = self
+            .index
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|entry| {
+                entry
+                    .completed_at
+                    .map(|t| (t, entry.options.clone(), entry.edition))
+            })
+            .collect();
+        // newest first
+        completed.sort_by(|a, b| b.0.cmp(&a.0));
+
+        let expired: Vec<(ProofRequestOptions, u64)> = completed
+            .into_iter()
+            .enumerate()
+            .filter(|(i, (completed_at, _, _))| {
+                self.retention.max_completed.map_or(false, |max| *i >= max)
+                    || self.retention.max_age.map_or(false, |max_age| {
+                        now.duration_since(*completed_at).unwrap_or_default() > max_age
+                    })
+            })
+            .map(|(_, (_, options, edition))| (options, edition))
+            .collect();
+
+        let mut pruned = Vec::with_capacity(expired.len());
+        for (options, edition) in expired {
+            self.remove(&options)?;
+            self.pruned
+                .lock()
+                .unwrap()
+                .insert(task_key(&options), edition);
+            pruned.push(options);
+        }
+
+        Ok(pruned)
+    }
+
+    /// Returns `true` if `prune` removed `task` or a later edition of it.
+    /// Peers still knowing the task should not bring it back.
+    pub fn is_pruned(&self, task: &ProofRequest) -> bool {
+        self.pruned
+            .lock()
+            .unwrap()
+            .get(&task_key(&task.options))
+            .map_or(false, |edition| task.edition <= *edition)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+}
+
+/// The file name of a task, derived from the fields compared by `ProofRequestOptions::eq`.
+fn task_key(options: &ProofRequestOptions) -> String {
+    let fields = serde_json::json!([
+        options.circuit,
+        options.block,
+        options.rpc,
+        options.param,
+        options.mock,
+        options.aggregate,
+    ]);
+    let hash = fnv1a(fields.to_string().as_bytes());
+    let circuit: String = options
+        .circuit
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect();
+
+    format!("{}-{}-{:016x}", circuit, options.block, hash)
+}
@@ -0,0 +1,22 @@
+#![allow(dead_code)]
+use zkevm_common::prover::{ProofRequest, ProofRequestOptions, Proofs};
+
+/// A task for `block` of the super circuit.
+pub fn task(block: u64, result: Option<Result<Proofs, String>>) -> ProofRequest {
+    ProofRequest {
+        options: ProofRequestOptions {
+            circuit: "super".to_string(),
+            block,
+            rpc: "http://localhost:8545".to_string(),
+            ..Default::default()
+        },
+        result,
+        edition: 1,
+        enqueued_at: 0,
+    }
+}
+
+/// The blocks of `tasks` in their order.
+pub fn blocks<'a>(tasks: impl IntoIterator<Item = &'a ProofRequest>) -> Vec<u64> {
+    tasks.into_iter().map(|task| task.options.block).collect()
+}
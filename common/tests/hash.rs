@@ -0,0 +1,15 @@
+use std::fmt::Write;
+use std::hash::Hasher;
+use zkevm_common::hash::{fnv1a, Fnv1a};
+
+#[test]
+fn fnv1a_vectors() {
+    assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+    assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+
+    // formatted output hashes like its bytes
+    let mut hasher = Fnv1a::default();
+    write!(hasher, "foo{}", 1).unwrap();
+    assert_eq!(hasher.finish(), fnv1a(b"foo1"));
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::blocks;
+use zkevm_common::prover::*;
+
+fn task(block: u64, priority: i32, deadline: Option<u64>, enqueued_at: u64) -> ProofRequest {
+    let mut task = common::task(block, None);
+    task.options.priority = priority;
+    task.options.deadline = deadline;
+    task.enqueued_at = enqueued_at;
+    task
+}
+
+fn completed(block: u64, proof_ms: u32) -> ProofRequest {
+    let mut proofs = Proofs::default();
+    proofs.circuit.aux.proof = proof_ms;
+    common::task(block, Some(Ok(proofs)))
+}
+
+#[test]
+fn schedule_order() {
+    let now = 10_000;
+    let tasks = vec![
+        task(1, 0, None, now),
+        task(2, 1, None, now),
+        task(3, 0, Some(now + 60), now),
+        task(4, 0, Some(now + 30), now),
+        completed(5, 1000),
+        task(6, 1, None, now - 1),
+    ];
+
+    // higher priority first, then the earliest deadline, then the oldest
+    assert_eq!(blocks(schedule(&tasks, now)), [6, 2, 4, 3, 1]);
+}
+
+#[test]
+fn schedule_aging() {
+    let now = 10 * PRIORITY_AGING_SECS;
+    let tasks = vec![task(1, 2, None, now), task(2, 0, None, 0)];
+
+    // a low priority task waiting long enough overtakes newer ones
+    assert_eq!(blocks(schedule(&tasks, now)), [2, 1]);
+    assert_eq!(tasks[1].effective_priority(now), 10);
+    assert_eq!(blocks(schedule(&tasks, PRIORITY_AGING_SECS)), [1, 2]);
+}
+
+#[test]
+fn pending_task_position() {
+    let now = 1000;
+    let tasks = vec![
+        task(1, 1, None, now),
+        task(2, 0, None, now),
+        task(3, 0, None, now),
+        completed(4, 10_000),
+        completed(5, 30_000),
+    ];
+
+    // nothing running, the first task starts now
+    assert_eq!(
+        pending_task(&tasks, None, &tasks[2].options, now),
+        PendingTask {
+            queue_position: 3,
+            estimated_start: Some(now + 2 * 20),
+        }
+    );
+    // the running task is not counted as ahead but delays the start
+    assert_eq!(
+        pending_task(&tasks, Some(&tasks[0].options), &tasks[2].options, now),
+        PendingTask {
+            queue_position: 2,
+            estimated_start: Some(now + 2 * 20),
+        }
+    );
+    assert_eq!(
+        pending_task(&tasks, Some(&tasks[0].options), &tasks[0].options, now).queue_position,
+        0
+    );
+    // no completed tasks, no estimate
+    assert_eq!(
+        pending_task(&tasks[..3], None, &tasks[1].options, now),
+        PendingTask {
+            queue_position: 2,
+            estimated_start: None,
+        }
+    );
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use common::task;
+use std::path::PathBuf;
+use std::time::Duration;
+use zkevm_common::prover::{unix_time, ProofRequest, Proofs};
+use zkevm_common::task_store::{Retention, TaskStore};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("task_store-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// The blocks of `tasks` sorted, the task store loads them in any order.
+fn blocks(tasks: &[ProofRequest]) -> Vec<u64> {
+    let mut blocks = common::blocks(tasks);
+    blocks.sort_unstable();
+    blocks
+}
+
+#[test]
+fn task_store_reload() {
+    let dir = temp_dir("reload");
+    let store = TaskStore::open(&dir, Retention::default()).expect("open");
+    store.save(&task(1, None)).expect("save");
+    store
+        .save(&task(2, Some(Err("failed".to_string()))))
+        .expect("save");
+    store
+        .save(&task(3, Some(Ok(Proofs::default()))))
+        .expect("save");
+    // replaces the pending version
+    store
+        .save(&task(1, Some(Ok(Proofs::default()))))
+        .expect("save");
+    store.remove(&task(2, None).options).expect("remove");
+    // removing a missing task is fine
+    store.remove(&task(4, None).options).expect("remove");
+    std::fs::write(dir.join("garbage.json"), "{").unwrap();
+
+    let store = TaskStore::open(&dir, Retention::default()).expect("open");
+    let tasks = store.load().expect("load");
+    assert_eq!(blocks(&tasks), [1, 3]);
+    assert!(tasks
+        .iter()
+        .all(|task| task.result.as_ref().unwrap().is_ok()));
+    assert!(store.prune().expect("prune").is_empty());
+
+    // tasks stored without `enqueued_at` count as enqueued when loaded
+    let mut enqueued = task(5, None);
+    enqueued.enqueued_at = 1000;
+    store.save(&enqueued).expect("save");
+    let before = unix_time();
+    let tasks = store.load().expect("load");
+    let enqueued_at = |block| {
+        tasks
+            .iter()
+            .find(|task| task.options.block == block)
+            .map(|task| task.enqueued_at)
+            .unwrap()
+    };
+    assert!(enqueued_at(1) >= before);
+    assert_eq!(enqueued_at(5), 1000);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn task_store_retention() {
+    let dir = temp_dir("retention");
+    let retention = Retention {
+        max_completed: Some(2),
+        max_age: None,
+    };
+    let store = TaskStore::open(&dir, retention).expect("open");
+    store.save(&task(1, None)).expect("save");
+    for block in 2..6 {
+        store
+            .save(&task(block, Some(Ok(Proofs::default()))))
+            .expect("save");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    // the oldest completed tasks go first, pending tasks stay
+    let mut pruned: Vec<u64> = store
+        .prune()
+        .expect("prune")
+        .iter()
+        .map(|options| options.block)
+        .collect();
+    pruned.sort_unstable();
+    assert_eq!(pruned, [2, 3]);
+    assert_eq!(blocks(&store.load().expect("load")), [1, 4, 5]);
+
+    let retention = Retention {
+        max_completed: None,
+        max_age: Some(Duration::ZERO),
+    };
+    let store = TaskStore::open(&dir, retention).expect("open");
+    store.load().expect("load");
+    store.prune().expect("prune");
+    assert_eq!(blocks(&store.load().expect("load")), [1]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn task_store_editions() {
+    let dir = temp_dir("editions");
+    let retention = Retention {
+        max_completed: Some(0),
+        max_age: None,
+    };
+    let store = TaskStore::open(&dir, retention).expect("open");
+    let mut completed = task(1, Some(Ok(Proofs::default())));
+    completed.edition = 2;
+    store.save(&completed).expect("save");
+    // an earlier edition saved late does not replace the completed task
+    store.save(&task(1, None)).expect("save");
+    assert!(store.load().expect("load")[0].result.is_some());
+
+    assert!(!store.is_pruned(&completed));
+    assert_eq!(store.prune().expect("prune").len(), 1);
+    // peers still knowing the task do not bring it back, a later edition does
+    assert!(store.is_pruned(&completed));
+    assert!(store.is_pruned(&task(1, None)));
+    completed.edition = 3;
+    assert!(!store.is_pruned(&completed));
+    store.save(&completed).expect("save");
+    assert!(!store.is_pruned(&task(1, None)));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
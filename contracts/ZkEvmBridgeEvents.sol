@@ -8,4 +8,6 @@ contract ZkEvmBridgeEvents {
   event MessageDispatched(address from, address to, uint256 value, uint256 fee, uint256 deadline, uint256 nonce, bytes data);
   event MessageDelivered(bytes32 id);
   event MessageDropped(bytes32 id);
+
+  event ForcedTransactionQueued(bytes32 txHash, uint256 deadline, bytes transaction);
 }
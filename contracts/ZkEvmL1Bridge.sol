@@ -233,6 +233,13 @@ contract ZkEvmL1Bridge is
     _dropMessage(from, to, value, fee, deadline, nonce, data);
   }
 
+  /// @notice Queues the signed L2 transaction `transaction` for inclusion on L2
+  /// within `FORCED_INCLUSION_SECONDS`, bypassing the sequencer.
+  function forceTransaction (bytes calldata transaction) external {
+    require(transaction.length != 0, 'FTLEN');
+    emit ForcedTransactionQueued(keccak256(transaction), block.timestamp + FORCED_INCLUSION_SECONDS, transaction);
+  }
+
   /// @dev For testing purposes
   function initGenesis (bytes32 _blockHash, bytes32 _stateRoot) external {
     stateRoots[_blockHash] = _stateRoot;
@@ -3,6 +3,7 @@ pragma solidity <0.9.0;
 
 contract ZkEvmMagicNumbers {
   uint256 constant MIN_MESSAGE_LOCK_SECONDS = 7200;
+  uint256 constant FORCED_INCLUSION_SECONDS = 3600;
   address constant L2_DELIVERER = 0x0000000000000000000000000000000000010000;
   address constant L2_DISPATCHER = 0x0000000000000000000000000000000000020000;
   address constant L1_BRIDGE = 0x936a70C0b28532AA22240dce21f89a8399d6ac60;
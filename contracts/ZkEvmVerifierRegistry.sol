@@ -0,0 +1,36 @@
+// SPDX-License-Identifier: MIT
+pragma solidity <0.9.0;
+
+/// @notice Maps circuit labels and configurations to deployed verifier contracts.
+/// The configuration digest is `keccak256(abi.encode(...))` of the `CircuitConfig` fields
+/// in declaration order, zero registers a verifier for any configuration of the circuit.
+contract ZkEvmVerifierRegistry {
+  event VerifierRegistered(string label, bytes32 configDigest, address verifier);
+
+  address public owner;
+  mapping (bytes32 => mapping (bytes32 => address)) _verifiers;
+
+  constructor () {
+    owner = msg.sender;
+  }
+
+  /// @notice Registers `verifier` for circuit `label` and `configDigest`, replacing any previous verifier.
+  /// `verifier` may be zero to remove the entry.
+  function registerVerifier (string calldata label, bytes32 configDigest, address verifier) external {
+    require(msg.sender == owner, 'OWNER');
+
+    _verifiers[keccak256(bytes(label))][configDigest] = verifier;
+    emit VerifierRegistered(label, configDigest, verifier);
+  }
+
+  /// @notice Returns the verifier for circuit `label` and `configDigest`, the verifier registered
+  /// for any configuration of `label` or zero if there is none.
+  function getVerifier (string calldata label, bytes32 configDigest) external view returns (address) {
+    mapping (bytes32 => address) storage verifiers = _verifiers[keccak256(bytes(label))];
+    address verifier = verifiers[configDigest];
+    if (verifier == address(0)) {
+      verifier = verifiers[bytes32(0)];
+    }
+    return verifier;
+  }
+}
@@ -1,27 +1,25 @@
-use clap::Parser;
 use coordinator::config::Config;
+use coordinator::error::Error;
 use coordinator::faucet::Faucet;
 use coordinator::shared_state::SharedState;
 use coordinator::utils::*;
 use env_logger::Env;
-use ethers_core::types::{Address, U64};
-use hyper::body::Buf;
-use hyper::body::HttpBody;
+use ethers_core::types::{Address, H256, U64};
 use hyper::client::HttpConnector;
-use hyper::header::HeaderValue;
-use hyper::service::{make_service_fn, service_fn};
-use hyper::HeaderMap;
-use hyper::{Body, Method, Request, Response, Server, StatusCode, Uri};
+use hyper::{Body, Method, Request, Response, StatusCode, Uri};
 use std::net::{SocketAddr, ToSocketAddrs};
 use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::task::spawn;
 use tokio::time::sleep;
+use zkevm_common::config::redacted;
 use zkevm_common::json_rpc::JsonRpcError;
-use zkevm_common::json_rpc::JsonRpcRequest;
-use zkevm_common::json_rpc::JsonRpcResponse;
 use zkevm_common::json_rpc::JsonRpcResponseError;
+use zkevm_common::server::{BearerAuth, JsonRpcServer};
 
 const EVENT_LOOP_COOLDOWN: Duration = Duration::from_millis(3000);
+const PROVER_CHECK_INTERVAL: Duration = Duration::from_millis(1000);
+const CONFIG_WATCH_INTERVAL: Duration = Duration::from_millis(1000);
 /// allowed jsonrpc methods
 const PROXY_ALLOWED_METHODS: [&str; 40] = [
     "eth_chainId",
@@ -66,201 +64,190 @@ const PROXY_ALLOWED_METHODS: [&str; 40] = [
     "debug_getModifiedAccountsByHash",
 ];
 
-fn set_headers(headers: &mut HeaderMap, extended: bool) {
-    headers.insert("content-type", HeaderValue::from_static("application/json"));
-    headers.insert("access-control-allow-origin", HeaderValue::from_static("*"));
+/// The coordinator json-rpc methods on `/rpc`, next to the json-rpc proxy for the L2 nodes
+/// on `/`, the chain state and the faucet.
+/// With `rpc_token` set, requests to `/rpc` require it as bearer token.
+fn rpc_server(
+    shared_state: SharedState,
+    faucet: Faucet,
+    client: hyper::Client<HttpConnector>,
+    rpc_token: Option<&str>,
+) -> JsonRpcServer<SharedState> {
+    // TODO: support deflate content encoding
+    let server = JsonRpcServer::new(shared_state)
+        .info("coordinator", env!("CARGO_PKG_VERSION"))
+        .rpc_path("/rpc")
+        .max_body_size(4 << 20)
+        .ready(|ctx: SharedState| async move { !ctx.rw.lock().await.nodes.is_empty() })
+        // serve some information about the chain
+        .route(Method::GET, "/", |ctx: SharedState, _req| async move {
+            Response::new(Body::from(
+                serde_json::to_vec(&ctx.rw.lock().await.chain_state).unwrap(),
+            ))
+        })
+        // geth upstream json-rpc
+        .route(Method::POST, "/", move |ctx: SharedState, req| {
+            proxy_request(ctx, client.clone(), req)
+        })
+        // returns 503 if faucet is disabled else 200 and enqueues a faucet requests
+        // that is processed asyncly.
+        // The faucet transfer can still fail if the `l1_wallet` has not enough ETH.
+        .route(Method::GET, "/faucet", move |ctx: SharedState, req| {
+            let faucet = faucet.clone();
+            async move {
+                let receiver = req
+                    .uri()
+                    .query()
+                    .expect("uri query")
+                    .parse::<Address>()
+                    .expect("receiver address");
+                let mut resp = Response::default();
+
+                let enabled = ctx.config.lock().await.enable_faucet;
+                match enabled {
+                    false => {
+                        *resp.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                    }
+                    true => {
+                        faucet.queue.lock().await.push_back(receiver);
+                        *resp.status_mut() = StatusCode::OK;
+                    }
+                }
 
-    if extended {
-        headers.insert(
-            "access-control-allow-methods",
-            HeaderValue::from_static("post, get, options"),
-        );
-        headers.insert(
-            "access-control-allow-headers",
-            HeaderValue::from_static("origin, content-type, accept, x-requested-with"),
+                resp
+            }
+        })
+        .method(
+            "config",
+            |ctx: SharedState, (options,): (Option<serde_json::Value>,)| async move {
+                require_unsafe_rpc(&ctx).await?;
+
+                match options {
+                    // fields not present keep their current value
+                    Some(options) => {
+                        let config = ctx
+                            .get_config()
+                            .await
+                            .merge(options)
+                            .map_err(JsonRpcError::invalid_params)?;
+                        let update = ctx.set_config(config).await?;
+
+                        // return the configuration in effect and the outcome
+                        Ok(serde_json::json!({
+                            "config": redacted(&ctx.get_config().await),
+                            "applied": update.applied,
+                            "restart_required": update.restart_required,
+                        }))
+                    }
+                    // return the current configuration
+                    None => Ok(redacted(&ctx.get_config().await)),
+                }
+            },
+        )
+        // forced transactions from L1 that are not yet included
+        .method("forced_transactions", |ctx: SharedState, ()| async move {
+            Ok(serde_json::to_value(&ctx.rw.lock().await.forced_transactions).unwrap())
+        })
+        // bridge messages that exhausted their delivery attempts
+        .method("dead_letters", |ctx: SharedState, ()| async move {
+            let rw = ctx.rw.lock().await;
+            Ok(serde_json::json!({
+                "l1": rw.l1_dead_letters,
+                "l2": rw.l2_dead_letters,
+            }))
+        })
+        // requeues a dead letter by message id
+        .method(
+            "retry_dead_letter",
+            |ctx: SharedState, (id,): (H256,)| async move {
+                require_unsafe_rpc(&ctx).await?;
+                ctx.retry_dead_letter(id)
+                    .await
+                    .map_err(JsonRpcError::invalid_params)?;
+
+                Ok(true)
+            },
+        )
+        .describe(
+            "config",
+            "Returns the configuration or merges the given fields into it, requires unsafe_rpc",
+            &["config"],
+        )
+        .describe(
+            "forced_transactions",
+            "Returns the forced transactions from L1 that are not yet included",
+            &[],
+        )
+        .describe(
+            "dead_letters",
+            "Returns the bridge messages that exhausted their delivery attempts",
+            &[],
+        )
+        .describe(
+            "retry_dead_letter",
+            "Requeues a dead letter by message id, requires unsafe_rpc",
+            &["id"],
         );
-        headers.insert("access-control-max-age", HeaderValue::from_static("300"));
+
+    match rpc_token {
+        Some(token) => server.middleware(BearerAuth::new(token, &["/rpc"])),
+        None => server,
+    }
+}
+
+/// Methods that change the state of the coordinator are only available with `unsafe_rpc`.
+async fn require_unsafe_rpc(shared_state: &SharedState) -> Result<(), JsonRpcError> {
+    match shared_state.config.lock().await.unsafe_rpc {
+        true => Ok(()),
+        false => Err(JsonRpcError::method_not_found("this method is disabled")),
     }
 }
 
-async fn handle_request(
+/// Forwards `req` to one of the healthy L2 nodes, for the methods in `PROXY_ALLOWED_METHODS`.
+async fn proxy_request(
     shared_state: SharedState,
-    faucet: Option<Faucet>,
     client: hyper::Client<HttpConnector>,
     req: Request<Body>,
-) -> Result<Response<Body>, hyper::Error> {
-    // TODO: support deflate content encoding
-
+) -> Response<Body> {
     #[derive(serde::Deserialize, serde::Serialize)]
     struct ProxyRequest {
         id: serde_json::Value,
         method: String,
     }
 
-    {
-        // limits the request size
-        const MAX_BODY_SIZE: u64 = 4 << 20;
-        let response_content_length = match req.body().size_hint().upper() {
-            Some(v) => v,
-            None => MAX_BODY_SIZE + 1,
-        };
+    let body_bytes = hyper::body::to_bytes(req.into_body()).await.unwrap();
+    let obj: ProxyRequest = serde_json::from_slice(body_bytes.as_ref()).expect("ProxyRequest");
 
-        if response_content_length > MAX_BODY_SIZE {
-            let mut resp = Response::new(Body::from("request too large"));
-            *resp.status_mut() = StatusCode::BAD_REQUEST;
-            return Ok(resp);
-        }
+    // only allow allow the following methods and nothing else
+    if !PROXY_ALLOWED_METHODS.iter().any(|e| **e == obj.method) {
+        let err = JsonRpcResponseError {
+            jsonrpc: "2.0".to_string(),
+            id: obj.id,
+            error: JsonRpcError::method_not_found("this method is not available"),
+        };
+        return Response::new(Body::from(serde_json::to_vec(&err).unwrap()));
     }
 
-    match (req.method(), req.uri().path()) {
-        // serve some information about the chain
-        (&Method::GET, "/") => {
-            let mut resp = Response::new(Body::from(
-                serde_json::to_vec(&shared_state.rw.lock().await.chain_state).unwrap(),
-            ));
-            set_headers(resp.headers_mut(), false);
-            Ok(resp)
-        }
-
-        // geth upstream json-rpc
-        (&Method::POST, "/") => {
-            let body_bytes = hyper::body::to_bytes(req.into_body()).await.unwrap();
-            let obj: ProxyRequest =
-                serde_json::from_slice(body_bytes.as_ref()).expect("ProxyRequest");
-
-            // only allow allow the following methods and nothing else
-            if !PROXY_ALLOWED_METHODS.iter().any(|e| **e == obj.method) {
-                let err = JsonRpcResponseError {
-                    jsonrpc: "2.0".to_string(),
-                    id: obj.id,
-                    error: JsonRpcError {
-                        code: -32601,
-                        message: "this method is not available".to_string(),
-                    },
-                };
-                let resp = Response::new(Body::from(serde_json::to_vec(&err).unwrap()));
-                return Ok(resp);
-            }
-
-            let mut resp;
-            {
-                // choose a serving node or none
-                let r = rand::random::<usize>();
-                let ctx = shared_state.rw.lock().await;
-                let len = ctx.nodes.len();
-                if len == 0 {
-                    drop(ctx);
-                    resp = Response::default();
-                    *resp.status_mut() = StatusCode::SERVICE_UNAVAILABLE
-                } else {
-                    let node_req = Request::post(&ctx.nodes[r % len]);
-                    drop(ctx);
-                    // reusing the same request doesn't work correctly.
-                    // Feeding the body via a reader() which was already consumed doesn't work either :/
-                    let node_req = node_req
-                        .header(hyper::header::CONTENT_TYPE, "application/json")
-                        .body(Body::from(body_bytes))
-                        .unwrap();
-                    resp = client.request(node_req).await.unwrap();
-                }
-            }
-
-            set_headers(resp.headers_mut(), false);
-            Ok(resp)
-        }
-
-        // serve CORS headers
-        (&Method::OPTIONS, "/") => {
-            let mut resp = Response::default();
-            set_headers(resp.headers_mut(), true);
-            Ok(resp)
-        }
-
-        // returns 503 if faucet is disabled else 200 and enqueues a faucet requests
-        // that is processed asyncly.
-        // The faucet transfer can still fail if the `l1_wallet` has not enough ETH.
-        (&Method::GET, "/faucet") => {
-            let receiver = req
-                .uri()
-                .query()
-                .expect("uri query")
-                .parse::<Address>()
-                .expect("receiver address");
-            let mut resp = Response::default();
-
-            match faucet {
-                None => {
-                    *resp.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
-                }
-                Some(faucet) => {
-                    faucet.queue.lock().await.push_back(receiver);
-                    *resp.status_mut() = StatusCode::OK;
-                }
-            }
-
-            set_headers(resp.headers_mut(), false);
-            Ok(resp)
-        }
-
-        // coordinator rpc
-        // TODO: protect this interface from public consumption
-        (&Method::POST, "/rpc") => {
-            let body_bytes = hyper::body::aggregate(req.into_body())
-                .await
-                .unwrap()
-                .reader();
-            let json_req: Result<JsonRpcRequest<Vec<serde_json::Value>>, serde_json::Error> =
-                serde_json::from_reader(body_bytes);
-
-            if let Err(err) = json_req {
-                let payload = serde_json::to_vec(&JsonRpcResponseError {
-                    jsonrpc: "2.0".to_string(),
-                    id: 0.into(),
-                    error: JsonRpcError {
-                        // parser error
-                        code: -32700,
-                        message: err.to_string(),
-                    },
-                })
-                .unwrap();
-                let mut resp = Response::new(Body::from(payload));
-                set_headers(resp.headers_mut(), false);
-                return Ok(resp);
-            }
-
-            let json_req = json_req.unwrap();
-            let result: Result<serde_json::Value, String> =
-                handle_method(json_req.method.as_str(), &json_req.params, &shared_state).await;
-            let payload = match result {
-                Err(err) => {
-                    serde_json::to_vec(&JsonRpcResponseError {
-                        jsonrpc: "2.0".to_string(),
-                        id: json_req.id,
-                        error: JsonRpcError {
-                            // internal server error
-                            code: -32000,
-                            message: err,
-                        },
-                    })
-                }
-                Ok(val) => serde_json::to_vec(&JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id: json_req.id,
-                    result: Some(val),
-                }),
-            };
-            let mut resp = Response::new(Body::from(payload.unwrap()));
-            set_headers(resp.headers_mut(), false);
-            Ok(resp)
-        }
-
-        // everything else
-        _ => {
-            let mut not_found = Response::default();
-            *not_found.status_mut() = StatusCode::NOT_FOUND;
-            Ok(not_found)
-        }
+    // choose a serving node or none
+    let r = rand::random::<usize>();
+    let ctx = shared_state.rw.lock().await;
+    let len = ctx.nodes.len();
+    if len == 0 {
+        drop(ctx);
+        let mut resp = Response::default();
+        *resp.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        return resp;
     }
+    let node_req = Request::post(&ctx.nodes[r % len]);
+    drop(ctx);
+    // reusing the same request doesn't work correctly.
+    // Feeding the body via a reader() which was already consumed doesn't work either :/
+    let node_req = node_req
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body_bytes))
+        .unwrap();
+
+    client.request(node_req).await.unwrap()
 }
 
 /// Discovers healthy nodes via DNS service discovery.
@@ -308,92 +295,82 @@ async fn check_nodes(ctx: SharedState, client: hyper::Client<HttpConnector>) {
     rw.nodes = nodes;
 }
 
+/// Logs the error of an event loop stage, the stage is run again in the next round.
+fn log_stage_error(stage: &str, err: Error) {
+    match err {
+        err if err.is_transient() => log::warn!("{}: {}", stage, err),
+        err @ (Error::GasLimitReached | Error::AlreadyKnown) => log::warn!("{}: {}", stage, err),
+        Error::Reverted { message, data } => {
+            log::error!("{}: reverted: {} data={:?}", stage, message, data)
+        }
+        err => log::error!("{}: {}", stage, err),
+    }
+}
+
 async fn event_loop(ctx: SharedState, _client: hyper::Client<HttpConnector>) {
     // TODO: split sync,mine into own task
 
-    ctx.sync().await;
-    ctx.mine().await;
-    ctx.submit_blocks().await;
-    ctx.finalize_blocks().await.expect("finalize_blocks");
-    ctx.relay_to_l1().await;
-}
-
-async fn handle_method(
-    method: &str,
-    params: &[serde_json::Value],
-    shared_state: &SharedState,
-) -> Result<serde_json::Value, String> {
-    match method {
-        "config" => {
-            if !shared_state.config.lock().await.unsafe_rpc {
-                return Err("this method is disabled".to_string());
+    // each stage runs to completion, no new stage is started once a shutdown is requested
+    macro_rules! stage {
+        ($name:literal, $e:expr) => {
+            if ctx.is_shutting_down() {
+                return;
             }
+            if let Err(err) = $e {
+                log_stage_error($name, err);
+            }
+        };
+    }
 
-            let config = match params.get(0) {
-                Some(options) => {
-                    let options: Config =
-                        serde_json::from_value(options.to_owned()).map_err(|e| e.to_string())?;
-
-                    shared_state.set_config(options.clone()).await;
-                    options
-                }
-                None => shared_state.get_config().await,
-            };
-
-            // return the current configuration
-            Ok(serde_json::to_value(config).unwrap())
-        }
-
-        _ => Err("this method is not available".to_string()),
+    stage!("sync", ctx.sync().await);
+    stage!("mine", ctx.mine().await);
+    stage!("request_proofs", ctx.request_proofs().await);
+    stage!("submit_blocks", ctx.submit_blocks().await);
+    stage!("finalize_blocks", ctx.finalize_blocks().await);
+    if ctx.is_shutting_down() {
+        return;
     }
+    ctx.relay_to_l1().await;
 }
 
 #[tokio::main]
 async fn main() {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
-    let config = Config::parse();
+    let config: Config = zkevm_common::config::parse();
+    config.validate().expect("config");
     let shared_state = SharedState::new(&config).await;
 
     shared_state.init().await;
+    shared_state.load_state().await.expect("state_file");
 
-    let faucet: Option<Faucet> = if config.enable_faucet {
-        Some(Faucet::default())
-    } else {
-        None
-    };
+    // the faucet can be enabled or disabled with configuration changes
+    let faucet = Faucet::default();
 
-    log::info!("faucet enabled: {}", faucet.is_some());
+    log::info!("faucet enabled: {}", config.enable_faucet);
 
-    {
+    let server = {
         let addr = config.listen;
         let client = hyper::Client::new();
         let shared_state = shared_state.clone();
         let faucet = faucet.clone();
+        let rpc_token = config.rpc_token.clone();
         // start the http server
         spawn(async move {
-            let service = make_service_fn(move |_| {
-                let shared_state = shared_state.clone();
-                let faucet = faucet.clone();
-                let client = client.clone();
-                let service = service_fn(move |req| {
-                    handle_request(shared_state.clone(), faucet.clone(), client.to_owned(), req)
-                });
-
-                async move { Ok::<_, hyper::Error>(service) }
-            });
-            let server = Server::bind(&addr).serve(service);
-            log::info!("Listening on http://{}", addr);
-            server.await.expect("server should be serving");
-            // terminate process?
-        });
-    }
+            let ctx = shared_state.clone();
+            rpc_server(shared_state, faucet, client, rpc_token.as_deref())
+                // stops accepting connections and waits for the open ones to complete
+                .serve(&addr, async move { ctx.shutdown_requested().await })
+                .await
+                .expect("server should be serving");
+        })
+    };
 
     {
         let ctx = shared_state.clone();
-        let h1 = spawn(async move {
+        let mut h1 = spawn(async move {
             let client = hyper::Client::new();
-            loop {
+            while !ctx.is_shutting_down() {
                 log::debug!("spawning event_loop task");
 
                 {
@@ -405,15 +382,19 @@ async fn main() {
                     }
                 }
 
+                if ctx.is_shutting_down() {
+                    break;
+                }
+
                 {
                     // The faucet shares the same l1 wallet with the event_loop
                     // above, therefore it should be invoked in serial.
                     let ctx = ctx.clone();
                     let faucet = faucet.clone();
                     let res = spawn(async move {
-                        if let Some(_faucet) = &faucet {
+                        if ctx.config.lock().await.enable_faucet {
                             // only consume up to 3 items each time
-                            _faucet.drain(ctx, 3).await;
+                            faucet.drain(ctx, 3).await;
                         }
                     })
                     .await;
@@ -423,12 +404,15 @@ async fn main() {
                     }
                 }
 
-                sleep(EVENT_LOOP_COOLDOWN).await;
+                tokio::select! {
+                    _ = sleep(EVENT_LOOP_COOLDOWN) => {}
+                    _ = ctx.shutdown_requested() => {}
+                }
             }
         });
 
         let ctx = shared_state.clone();
-        let h2 = spawn(async move {
+        let mut h2 = spawn(async move {
             let client = hyper::Client::new();
             loop {
                 log::debug!("spawning check_nodes task");
@@ -442,9 +426,104 @@ async fn main() {
             }
         });
 
-        // wait for all tasks
-        if tokio::try_join!(h1, h2).is_err() {
-            panic!("unexpected task error");
+        let ctx = shared_state.clone();
+        let mut h3 = spawn(async move {
+            loop {
+                log::debug!("spawning check_provers task");
+                let ctx = ctx.clone();
+                let res = spawn(async move { ctx.check_provers().await }).await;
+
+                if let Err(err) = res {
+                    log::error!("task: {}", err);
+                }
+
+                sleep(PROVER_CHECK_INTERVAL).await;
+            }
+        });
+
+        let ctx = shared_state.clone();
+        let mut h4 = spawn(async move {
+            let config_file = ctx.config.lock().await.config_file.clone();
+            let modified_at = move || {
+                config_file
+                    .as_ref()
+                    .and_then(|path| std::fs::metadata(path).ok())
+                    .and_then(|meta| meta.modified().ok())
+            };
+            let mut sighup = signal(SignalKind::hangup()).expect("SIGHUP handler");
+            let mut last_modified = modified_at();
+            loop {
+                let reload = tokio::select! {
+                    _ = sighup.recv() => {
+                        log::info!("SIGHUP: reloading configuration");
+                        true
+                    }
+                    _ = sleep(CONFIG_WATCH_INTERVAL) => {
+                        let modified = modified_at();
+                        let changed = modified.is_some() && modified != last_modified;
+                        last_modified = modified;
+                        changed
+                    }
+                };
+
+                if !reload {
+                    continue;
+                }
+                if ctx.config.lock().await.config_file.is_none() {
+                    log::warn!("no config_file to reload");
+                    continue;
+                }
+                if let Err(err) = ctx.reload_config_file().await {
+                    log::error!("config reload: {}", err);
+                }
+            }
+        });
+
+        // the tasks only return on errors or after a shutdown was requested
+        tokio::select! {
+            res = async { tokio::try_join!(&mut h1, &mut h2, &mut h3, &mut h4) } => {
+                if let Err(err) = res {
+                    log::error!("unexpected task error: {}", err);
+                }
+            }
+            _ = shutdown_signal() => {}
+        }
+
+        log::info!("shutting down");
+        shared_state.shutdown();
+        // the remaining tasks do not hold any state
+        h2.abort();
+        h3.abort();
+        h4.abort();
+
+        let timeout = Duration::from_secs(shared_state.config.lock().await.shutdown_timeout);
+        let res = tokio::time::timeout(timeout, async move {
+            if let Err(err) = h1.await {
+                log::error!("event_loop: {}", err);
+            }
+            if let Err(err) = server.await {
+                log::error!("server: {}", err);
+            }
+            // the event loop is done, write out the final message queues
+            shared_state.save_state().await;
+        })
+        .await;
+
+        if res.is_err() {
+            log::error!("shutdown timeout of {:?} exceeded", timeout);
+            std::process::exit(1);
         }
+        log::info!("shutdown complete");
+    }
+}
+
+/// Resolves on SIGTERM or SIGINT.
+async fn shutdown_signal() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("SIGTERM handler");
+    let mut sigint = signal(SignalKind::interrupt()).expect("SIGINT handler");
+
+    tokio::select! {
+        _ = sigterm.recv() => log::info!("received SIGTERM"),
+        _ = sigint.recv() => log::info!("received SIGINT"),
     }
 }
@@ -0,0 +1,665 @@
+use crate::config::Config;
+use crate::config::L2BlockBuilder;
+use crate::engine_api::EngineApi;
+use crate::error::Error;
+use crate::structs::*;
+use crate::utils::*;
+use async_trait::async_trait;
+use ethers_core::abi::{Abi, AbiParser, Token, Tokenizable};
+use ethers_core::types::{
+    Address, Block, Bytes, Filter, Log, Transaction, TransactionReceipt, TransactionRequest,
+    TxpoolContent, TxpoolStatus, H256, U256, U64,
+};
+use ethers_core::utils::keccak256;
+use ethers_signers::LocalWallet;
+use ethers_signers::Signer;
+use hyper::Uri;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use zkevm_common::json_rpc::{JsonRpcClient, RetryPolicy, RpcError};
+
+/// Access to the L1 node and the L1 bridge.
+/// Only `reconnect`, `wallet`, `request` and `send_transaction` have to be implemented,
+/// the other methods default to the respective json-rpc requests via `request`.
+#[async_trait]
+pub trait L1Client: Sized + Send + Sync + 'static {
+    /// Returns a client for `config`, called if the node or wallet configuration changes.
+    async fn reconnect(&self, config: &Config) -> Result<Self, Error>;
+
+    /// The wallet that signs transactions on L1.
+    fn wallet(&self) -> &LocalWallet;
+
+    /// Sends a json-rpc request to the node.
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error>;
+
+    /// Sends a transaction signed by `wallet` and waits for the receipt.
+    async fn send_transaction(
+        &self,
+        to: Option<Address>,
+        value: U256,
+        calldata: Vec<u8>,
+    ) -> Result<TransactionReceipt, Error>;
+
+    async fn block_number(&self) -> Result<U64, Error> {
+        decode(self.request("eth_blockNumber", json!([])).await?)
+    }
+
+    async fn get_balance(&self, address: Address) -> Result<U256, Error> {
+        decode(
+            self.request("eth_getBalance", json!([address, "latest"]))
+                .await?,
+        )
+    }
+
+    async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, Error> {
+        decode(self.request("eth_getLogs", json!([filter])).await?)
+    }
+
+    async fn get_transaction(&self, tx_hash: H256) -> Result<Transaction, Error> {
+        decode(
+            self.request("eth_getTransactionByHash", json!([tx_hash]))
+                .await?,
+        )
+    }
+
+    async fn latest_header(&self) -> Result<BlockHeader, Error> {
+        decode(
+            self.request("eth_getHeaderByNumber", json!(["latest"]))
+                .await?,
+        )
+    }
+
+    /// Returns the rlp encoded header of block `number`.
+    async fn header_rlp(&self, number: U64) -> Result<Bytes, Error> {
+        decode(
+            self.request("debug_getHeaderRlp", json!([number.as_u64()]))
+                .await?,
+        )
+    }
+
+    /// Returns the account proof for `address` and the storage proofs for `keys`.
+    async fn get_proof(
+        &self,
+        address: Address,
+        keys: &[H256],
+        block_hash: H256,
+    ) -> Result<MerkleProofRequest, Error> {
+        decode(
+            self.request("eth_getProof", json!([address, keys, block_hash]))
+                .await?,
+        )
+    }
+
+    /// Executes a call against the latest block, with the optional state `overrides`.
+    async fn call(
+        &self,
+        to: Address,
+        data: Bytes,
+        overrides: Option<Value>,
+    ) -> Result<Bytes, Error> {
+        let tx = json!({ "to": to, "data": data });
+        let params = match overrides {
+            Some(overrides) => json!([tx, "latest", overrides]),
+            None => json!([tx, "latest"]),
+        };
+
+        decode(self.request("eth_call", params).await?)
+    }
+
+    /// Calls `stateRoots(block_hash)` on the `bridge`.
+    async fn state_root(&self, bridge: Address, block_hash: H256) -> Result<H256, Error> {
+        let calldata = encode_call("stateRoots", &[block_hash.into_token()]);
+
+        decode_word(&self.call(bridge, calldata, None).await?)
+    }
+
+    /// Calls `getTimestampForStorageRoot(storage_root)` on the `bridge`.
+    async fn timestamp_for_storage_root(
+        &self,
+        bridge: Address,
+        storage_root: H256,
+    ) -> Result<H256, Error> {
+        let calldata = encode_call("getTimestampForStorageRoot", &[storage_root.into_token()]);
+
+        decode_word(&self.call(bridge, calldata, None).await?)
+    }
+
+    /// Returns the public inputs that the `bridge` computes for the block `witness`.
+    async fn build_commitment(&self, bridge: Address, witness: Bytes) -> Result<Vec<U256>, Error> {
+        let abi = bridge_abi();
+        let func = abi.function("buildCommitment").unwrap();
+        let calldata = encode_call("buildCommitment", &[witness.into_token()]);
+        let result = self.call(bridge, calldata, None).await?;
+
+        func.decode_output(&result)
+            .map_err(|e| format!("buildCommitment: {e}"))?
+            .get(0)
+            .and_then(|token| token.to_owned().into_array())
+            .ok_or_else(|| "buildCommitment: expected an array".to_string())?
+            .into_iter()
+            .map(|item| {
+                item.into_uint()
+                    .ok_or_else(|| "buildCommitment: expected uint256".into())
+            })
+            .collect()
+    }
+
+    /// Calls `getVerifier(label, config_digest)` on the verifier `registry`, zero if there is none.
+    async fn get_verifier(
+        &self,
+        registry: Address,
+        label: &str,
+        config_digest: H256,
+    ) -> Result<Address, Error> {
+        let abi = bridge_abi();
+        let func = abi.function("getVerifier").unwrap();
+        let calldata = encode_call(
+            "getVerifier",
+            &[label.to_string().into_token(), config_digest.into_token()],
+        );
+        let result = self.call(registry, calldata, None).await?;
+
+        Ok(func
+            .decode_output(&result)
+            .map_err(|e| format!("getVerifier({label}): {e}"))?
+            .get(0)
+            .and_then(|token| token.to_owned().into_address())
+            .unwrap_or_default())
+    }
+
+    /// Initializes the `bridge` with the L2 genesis block.
+    async fn init_genesis(
+        &self,
+        bridge: Address,
+        block_hash: H256,
+        state_root: H256,
+    ) -> Result<TransactionReceipt, Error> {
+        let calldata = encode_call(
+            "initGenesis",
+            &[block_hash.into_token(), state_root.into_token()],
+        );
+
+        self.send_transaction(Some(bridge), U256::zero(), calldata.to_vec())
+            .await
+    }
+
+    /// Submits the L2 block `witness` to the `bridge`.
+    async fn submit_block(
+        &self,
+        bridge: Address,
+        witness: Bytes,
+    ) -> Result<TransactionReceipt, Error> {
+        let calldata = encode_call("submitBlock", &[witness.into_token()]);
+
+        self.send_transaction(Some(bridge), U256::zero(), calldata.to_vec())
+            .await
+    }
+
+    /// Finalizes a L2 block on the `bridge` with `proof_data`.
+    async fn finalize_block(
+        &self,
+        bridge: Address,
+        proof_data: Bytes,
+    ) -> Result<TransactionReceipt, Error> {
+        let calldata = encode_call("finalizeBlock", &[proof_data.into_token()]);
+
+        self.send_transaction(Some(bridge), U256::zero(), calldata.to_vec())
+            .await
+    }
+}
+
+/// Access to the L2 node and its block production.
+/// Only `reconnect`, `wallet`, `request` and `send_transaction` have to be implemented,
+/// the other methods default to the respective json-rpc requests via `request`.
+#[async_trait]
+pub trait L2Engine: Sized + Send + Sync + 'static {
+    /// Returns a client for `config`, called if the node or wallet configuration changes.
+    async fn reconnect(&self, config: &Config) -> Result<Self, Error>;
+
+    /// The wallet that signs transactions on L2.
+    fn wallet(&self) -> &LocalWallet;
+
+    /// Sends a json-rpc request to the node.
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error>;
+
+    /// Sends the json-rpc requests `calls` to the node, the results are in the order of `calls`.
+    /// Defaults to one request after another.
+    async fn request_batch(
+        &self,
+        calls: &[(&str, Value)],
+    ) -> Result<Vec<Result<Value, Error>>, Error> {
+        let mut results = Vec::with_capacity(calls.len());
+        for (method, params) in calls {
+            results.push(self.request(method, params.clone()).await);
+        }
+
+        Ok(results)
+    }
+
+    /// Sends a transaction signed by `wallet` without waiting for it to be mined.
+    /// Uses `gas_limit` or estimates the gas if it is `None`.
+    async fn send_transaction(
+        &self,
+        to: Option<Address>,
+        value: U256,
+        calldata: Vec<u8>,
+        gas_limit: Option<U256>,
+    ) -> Result<H256, Error>;
+
+    async fn block_number(&self) -> Result<U64, Error> {
+        decode(self.request("eth_blockNumber", json!([])).await?)
+    }
+
+    async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, Error> {
+        decode(self.request("eth_getLogs", json!([filter])).await?)
+    }
+
+    async fn get_block(&self, number: U64) -> Result<Block<H256>, Error> {
+        decode(
+            self.request("eth_getBlockByNumber", json!([number, false]))
+                .await?,
+        )
+    }
+
+    async fn get_block_by_hash(&self, block_hash: H256) -> Result<Block<H256>, Error> {
+        decode(
+            self.request("eth_getBlockByHash", json!([block_hash, false]))
+                .await?,
+        )
+    }
+
+    /// Returns the blocks `numbers` of the canonical chain in the same order,
+    /// `None` for blocks the node does not have.
+    async fn get_blocks(&self, numbers: &[U64]) -> Result<Vec<Option<Block<H256>>>, Error> {
+        let calls: Vec<_> = numbers
+            .iter()
+            .map(|number| ("eth_getBlockByNumber", json!([number, false])))
+            .collect();
+
+        self.request_batch(&calls)
+            .await?
+            .into_iter()
+            .map(|res| decode(res?))
+            .collect()
+    }
+
+    async fn get_block_with_transactions(&self, number: U64) -> Result<Block<Transaction>, Error> {
+        decode(
+            self.request("eth_getBlockByNumber", json!([number, true]))
+                .await?,
+        )
+    }
+
+    async fn get_header(&self, block_hash: H256) -> Result<BlockHeader, Error> {
+        decode(
+            self.request("eth_getHeaderByHash", json!([block_hash]))
+                .await?,
+        )
+    }
+
+    /// Returns the headers of the blocks `numbers` of the canonical chain, in the same order.
+    async fn get_headers(&self, numbers: &[U64]) -> Result<Vec<BlockHeader>, Error> {
+        let calls: Vec<_> = numbers
+            .iter()
+            .map(|number| ("eth_getHeaderByNumber", json!([number])))
+            .collect();
+
+        self.request_batch(&calls)
+            .await?
+            .into_iter()
+            .map(|res| decode(res?))
+            .collect()
+    }
+
+    async fn latest_header(&self) -> Result<BlockHeader, Error> {
+        decode(
+            self.request("eth_getHeaderByNumber", json!(["latest"]))
+                .await?,
+        )
+    }
+
+    /// Returns the rlp encoded header of block `number`.
+    async fn header_rlp(&self, number: U64) -> Result<Bytes, Error> {
+        decode(
+            self.request("debug_getHeaderRlp", json!([number.as_u64()]))
+                .await?,
+        )
+    }
+
+    /// Returns the account proof for `address` and the storage proofs for `keys`.
+    async fn get_proof(
+        &self,
+        address: Address,
+        keys: &[H256],
+        block_hash: H256,
+    ) -> Result<MerkleProofRequest, Error> {
+        decode(
+            self.request("eth_getProof", json!([address, keys, block_hash]))
+                .await?,
+        )
+    }
+
+    /// Returns `None` if the transaction is not included in the canonical chain.
+    async fn transaction_receipt(
+        &self,
+        tx_hash: H256,
+    ) -> Result<Option<TransactionReceipt>, Error> {
+        decode(
+            self.request("eth_getTransactionReceipt", json!([tx_hash]))
+                .await?,
+        )
+    }
+
+    /// Returns the nonce of `address` at the latest block.
+    async fn transaction_count(&self, address: Address) -> Result<U256, Error> {
+        decode(
+            self.request("eth_getTransactionCount", json!([address, "latest"]))
+                .await?,
+        )
+    }
+
+    async fn gas_price(&self) -> Result<U256, Error> {
+        decode(self.request("eth_gasPrice", json!([])).await?)
+    }
+
+    /// Estimates the gas for `tx` against `block_tag`.
+    async fn estimate_gas(&self, tx: &TransactionRequest, block_tag: &str) -> Result<U256, Error> {
+        decode(
+            self.request("eth_estimateGas", json!([tx, block_tag]))
+                .await?,
+        )
+    }
+
+    async fn txpool_status(&self) -> Result<TxpoolStatus, Error> {
+        decode(self.request("txpool_status", json!([])).await?)
+    }
+
+    async fn txpool_content(&self) -> Result<TxpoolContent, Error> {
+        decode(self.request("txpool_content", json!([])).await?)
+    }
+
+    /// Prepares the node for block production, e.g. enables the transaction pool.
+    /// Called before each round of mining to account for node restarts.
+    async fn init(&self) -> Result<(), Error> {
+        miner_init(self).await
+    }
+
+    /// Builds a block on top of `parent` with `transactions` or from the transaction pool
+    /// if `None`. The block does not become the chain head until `set_head`.
+    async fn seal_block(
+        &self,
+        parent: H256,
+        attributes: &BlockAttributes,
+        transactions: Option<&Vec<Bytes>>,
+    ) -> Result<Block<Transaction>, Error> {
+        miner_seal_block(self, parent, attributes, transactions).await
+    }
+
+    /// Makes `block_hash` the canonical chain head, returns `false` if that is not possible.
+    async fn set_head(&self, block_hash: H256) -> Result<bool, Error> {
+        miner_set_head(self, block_hash).await
+    }
+}
+
+async fn miner_init<E: L2Engine>(engine: &E) -> Result<(), Error> {
+    engine.request("miner_init", json!([])).await.map(|_| ())
+}
+
+async fn miner_seal_block<E: L2Engine>(
+    engine: &E,
+    parent: H256,
+    attributes: &BlockAttributes,
+    transactions: Option<&Vec<Bytes>>,
+) -> Result<Block<Transaction>, Error> {
+    let req = SealBlockRequest {
+        parent: &parent,
+        random: &attributes.random,
+        timestamp: &attributes.timestamp.into(),
+        coinbase: &attributes.coinbase,
+        transactions,
+    };
+
+    decode(engine.request("miner_sealBlock", json!([req])).await?)
+}
+
+async fn miner_set_head<E: L2Engine>(engine: &E, block_hash: H256) -> Result<bool, Error> {
+    decode(engine.request("miner_setHead", json!([block_hash])).await?)
+}
+
+/// `L1Client` for a json-rpc node.
+#[derive(Clone)]
+pub struct RpcL1Client {
+    pub client: JsonRpcClient,
+    pub wallet: LocalWallet,
+}
+
+impl RpcL1Client {
+    pub async fn connect(config: &Config) -> Result<Self, Error> {
+        let client = rpc_client(config, &config.l1_rpc_url);
+        let wallet = get_wallet(&client, &config.l1_priv).await?;
+
+        Ok(Self { client, wallet })
+    }
+}
+
+#[async_trait]
+impl L1Client for RpcL1Client {
+    async fn reconnect(&self, config: &Config) -> Result<Self, Error> {
+        Self::connect(config).await
+    }
+
+    fn wallet(&self) -> &LocalWallet {
+        &self.wallet
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+        Ok(self.client.request(method, params).await?)
+    }
+
+    async fn send_transaction(
+        &self,
+        to: Option<Address>,
+        value: U256,
+        calldata: Vec<u8>,
+    ) -> Result<TransactionReceipt, Error> {
+        send_transaction_to_l1(&self.client, &self.wallet, to, value, calldata).await
+    }
+}
+
+/// `L2Engine` for a json-rpc node. Blocks are built with the `miner_*` namespace
+/// or with the Engine API, depending on `l2_block_builder`.
+#[derive(Clone)]
+pub struct RpcL2Engine {
+    pub client: JsonRpcClient,
+    pub wallet: LocalWallet,
+    /// `None` for the `miner` block builder
+    pub engine_api: Option<EngineApi>,
+}
+
+impl RpcL2Engine {
+    pub async fn connect(config: &Config) -> Result<Self, Error> {
+        let client = rpc_client(config, &config.l2_rpc_url);
+        // TODO: support different keys for L1 and L2
+        let wallet = get_wallet(&client, &config.l1_priv).await?;
+        let engine_api = match config.l2_block_builder {
+            L2BlockBuilder::Miner => None,
+            L2BlockBuilder::Engine => Some(EngineApi::from_config(config)?),
+        };
+
+        Ok(Self {
+            client,
+            wallet,
+            engine_api,
+        })
+    }
+}
+
+#[async_trait]
+impl L2Engine for RpcL2Engine {
+    async fn reconnect(&self, config: &Config) -> Result<Self, Error> {
+        Self::connect(config).await
+    }
+
+    fn wallet(&self) -> &LocalWallet {
+        &self.wallet
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+        Ok(self.client.request(method, params).await?)
+    }
+
+    async fn request_batch(
+        &self,
+        calls: &[(&str, Value)],
+    ) -> Result<Vec<Result<Value, Error>>, Error> {
+        Ok(self
+            .client
+            .batch(calls)
+            .await?
+            .into_iter()
+            .map(|res| res.map_err(Error::from))
+            .collect())
+    }
+
+    async fn send_transaction(
+        &self,
+        to: Option<Address>,
+        value: U256,
+        calldata: Vec<u8>,
+        gas_limit: Option<U256>,
+    ) -> Result<H256, Error> {
+        send_transaction_to_l2(&self.client, &self.wallet, to, value, calldata, gas_limit).await
+    }
+
+    async fn init(&self) -> Result<(), Error> {
+        match self.engine_api {
+            Some(_) => Ok(()),
+            None => miner_init(self).await,
+        }
+    }
+
+    /// With the Engine API, blocks can only be built from the transaction pool.
+    /// `transactions` are added to the pool first and it is an error if any of them
+    /// is not included in the block. The block may contain other transactions from the pool.
+    async fn seal_block(
+        &self,
+        parent: H256,
+        attributes: &BlockAttributes,
+        transactions: Option<&Vec<Bytes>>,
+    ) -> Result<Block<Transaction>, Error> {
+        let engine_api = match &self.engine_api {
+            Some(engine_api) => engine_api,
+            None => return miner_seal_block(self, parent, attributes, transactions).await,
+        };
+
+        for raw_tx in transactions.into_iter().flatten() {
+            match self
+                .request("eth_sendRawTransaction", json!([raw_tx]))
+                .await
+            {
+                // the transaction may be in the pool from a previous attempt
+                Ok(_) | Err(Error::AlreadyKnown) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let payload = engine_api.build_block(parent, attributes).await?;
+        if let Some(raw_tx) = transactions
+            .into_iter()
+            .flatten()
+            .find(|raw_tx| !payload.transactions.contains(raw_tx))
+        {
+            return Err(format!(
+                "transaction {:?} not included in block",
+                H256::from(keccak256(raw_tx))
+            )
+            .into());
+        }
+
+        decode(
+            self.request("eth_getBlockByHash", json!([payload.block_hash, true]))
+                .await?,
+        )
+    }
+
+    async fn set_head(&self, block_hash: H256) -> Result<bool, Error> {
+        match &self.engine_api {
+            Some(engine_api) => engine_api.set_head(block_hash).await,
+            None => miner_set_head(self, block_hash).await,
+        }
+    }
+}
+
+/// The interface of the L1 bridge and the verifier registry.
+pub fn bridge_abi() -> Abi {
+    AbiParser::default()
+        .parse(&[
+            "event BlockSubmitted()",
+            "event BlockFinalized(bytes32 blockHash)",
+            "event MessageDispatched(address from, address to, uint256 value, uint256 fee, uint256 deadline, uint256 nonce, bytes data)",
+            "event MessageDelivered(bytes32 id)",
+            "event ForcedTransactionQueued(bytes32 txHash, uint256 deadline, bytes transaction)",
+            "function submitBlock(bytes)",
+            "function finalizeBlock(bytes proof)",
+            "function deliverMessageWithProof(address from, address to, uint256 value, uint256 fee, uint256 deadline, uint256 nonce, bytes data, bytes proof)",
+            "function stateRoots(bytes32 blockHash) returns (bytes32)",
+            "function importForeignBlock(uint256 blockNumber, bytes32 blockHash)",
+            "function initGenesis(bytes32 blockHash, bytes32 stateRoot)",
+            "function buildCommitment(bytes) returns (uint256[])",
+            "function importForeignBridgeState(bytes, bytes)",
+            "function multicall()",
+            "function getTimestampForStorageRoot(bytes32 storageRoot) returns (uint256)",
+            "function getVerifier(string label, bytes32 configDigest) returns (address)",
+        ])
+        .expect("parse abi")
+}
+
+fn encode_call(function_name: &str, args: &[Token]) -> Bytes {
+    bridge_abi()
+        .function(function_name)
+        .unwrap()
+        .encode_input(args)
+        .expect("calldata")
+        .into()
+}
+
+fn decode<R: DeserializeOwned>(value: Value) -> Result<R, Error> {
+    serde_json::from_value(value).map_err(|e| Error::Rpc(RpcError::Decode(e.to_string())))
+}
+
+/// The first 32 bytes of a call result.
+fn decode_word(result: &Bytes) -> Result<H256, Error> {
+    match result.len() {
+        len if len < 32 => Err(format!("expected at least 32 bytes, got {len}").into()),
+        _ => Ok(H256::from_slice(&result[0..32])),
+    }
+}
+
+/// Returns a client for the node at `uri` with the retry and connection settings of `config`.
+/// Requests that change the state of the node are not retried.
+pub fn rpc_client(config: &Config, uri: &Uri) -> JsonRpcClient {
+    let retry = RetryPolicy {
+        max_retries: config.rpc_max_retries,
+        backoff: config.rpc_retry_backoff,
+        max_backoff: config.rpc_retry_backoff.saturating_mul(16),
+    };
+
+    JsonRpcClient::builder(uri.clone())
+        .timeout(RPC_REQUEST_TIMEOUT)
+        .retry(retry)
+        .retry_method("eth_sendRawTransaction", RetryPolicy::NONE)
+        .retry_method("miner_sealBlock", RetryPolicy::NONE)
+        .max_concurrent_requests(config.rpc_max_concurrent_requests)
+        .build()
+}
+
+async fn get_wallet(client: &JsonRpcClient, sign_key: &str) -> Result<LocalWallet, Error> {
+    let chain_id: U64 = client
+        .request("eth_chainId", ())
+        .await
+        .map_err(|e| format!("eth_chainId {}: {e}", client.uri()))?;
+
+    Ok(sign_key
+        .parse::<LocalWallet>()
+        .map_err(|_| "cannot create LocalWallet from private key".to_string())?
+        .with_chain_id(chain_id.as_u64()))
+}
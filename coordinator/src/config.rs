@@ -1,9 +1,155 @@
+use crate::engine_api::read_jwt_secret;
 use clap::Parser;
-use ethers_core::types::Address;
+use ethers_core::types::{Address, H256};
+use ethers_signers::LocalWallet;
 use hyper::Uri;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
+use std::fmt;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// A prover endpoint in the form of `[route=]url`.
+/// Endpoints without a route serve any request. Otherwise the endpoint is dedicated to
+/// requests for the circuit named `route` or to aggregation requests if `route` is `aggregate`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProverEndpoint {
+    pub route: Option<String>,
+    pub uri: Uri,
+}
+
+impl ProverEndpoint {
+    /// Returns `true` if this endpoint accepts requests for `circuit`.
+    pub fn serves(&self, circuit: &str, aggregate: bool) -> bool {
+        match &self.route {
+            None => true,
+            Some(route) => route == circuit || (aggregate && route == "aggregate"),
+        }
+    }
+}
+
+impl FromStr for ProverEndpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (route, uri) = match s.split_once('=') {
+            Some((route, uri)) => (Some(route.trim().to_string()), uri),
+            None => (None, s),
+        };
+        let uri = uri.trim().parse::<Uri>().map_err(|e| format!("{s}: {e}"))?;
+
+        Ok(Self { route, uri })
+    }
+}
+
+impl fmt::Display for ProverEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.route {
+            Some(route) => write!(f, "{}={}", route, self.uri),
+            None => write!(f, "{}", self.uri),
+        }
+    }
+}
+
+/// How the coordinator verifies proofs before submitting them to `finalizeBlock`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProofVerification {
+    /// `eth_call` the verifier contract deployed on L1.
+    Call,
+    /// `eth_call` the verifier runtime code loaded from this directory via state overrides.
+    /// The files are expected to be named `verifier-<label>-<address>`, with the address from the
+    /// verifier registry, and contain hex encoded runtime code, the layout of
+    /// `build/contracts/plonk-verifier`. Verification fails if the file is missing.
+    /// Like `Call` this runs the verifier contract, the verifying key is part of its code.
+    VerifierCode(PathBuf),
+}
+
+impl FromStr for ProofVerification {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err("expected `call` or a directory".to_string()),
+            "call" => Ok(Self::Call),
+            path => Ok(Self::VerifierCode(PathBuf::from(path))),
+        }
+    }
+}
+
+impl fmt::Display for ProofVerification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Call => write!(f, "call"),
+            Self::VerifierCode(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Where the coordinator looks up verifier contract addresses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifierRegistrySource {
+    /// A JSON file with a list of registry entries or a directory of JSON files,
+    /// each containing a single entry.
+    File(PathBuf),
+    /// A `ZkEvmVerifierRegistry` contract deployed on L1.
+    Contract(Address),
+}
+
+impl FromStr for VerifierRegistrySource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err("expected a path or a contract address".to_string()),
+            s if s.starts_with("0x") => s
+                .parse::<Address>()
+                .map(Self::Contract)
+                .map_err(|e| format!("{s}: {e}")),
+            path => Ok(Self::File(PathBuf::from(path))),
+        }
+    }
+}
+
+impl fmt::Display for VerifierRegistrySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Contract(addr) => write!(f, "{addr:?}"),
+        }
+    }
+}
+
+/// How the coordinator builds L2 blocks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum L2BlockBuilder {
+    /// `miner_sealBlock` and `miner_setHead` of the patched L2 node.
+    Miner,
+    /// `engine_forkchoiceUpdatedV1`, `engine_getPayloadV1` and `engine_newPayloadV1`
+    /// of any execution client.
+    Engine,
+}
+
+impl FromStr for L2BlockBuilder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "miner" => Ok(Self::Miner),
+            "engine" => Ok(Self::Engine),
+            _ => Err(format!("expected `miner` or `engine`, got {s}")),
+        }
+    }
+}
+
+impl fmt::Display for L2BlockBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Miner => write!(f, "miner"),
+            Self::Engine => write!(f, "engine"),
+        }
+    }
+}
 
 #[serde_as]
 #[derive(Parser, Deserialize, Serialize, Clone, Debug)]
@@ -38,6 +184,34 @@ pub struct Config {
     /// Enable verification of the proof.
     pub verify_proof: bool,
 
+    #[clap(long, env = "COORDINATOR_PROOF_PRIORITY", default_value_t = 0)]
+    /// Priority of the proof requests, provers compute tasks with a higher priority first.
+    pub proof_priority: i32,
+
+    #[clap(long, env = "COORDINATOR_PRE_VERIFY_PROOF")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    /// Checks proofs with the verifier contract before submitting them to the L1 bridge.
+    /// Either `call` to `eth_call` the verifier contract on L1 or a directory with
+    /// verifier runtime code to `eth_call` via state overrides.
+    pub pre_verify_proof: Option<ProofVerification>,
+
+    #[clap(long, env = "COORDINATOR_VERIFIER_REGISTRY")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    /// Registry of the verifier contracts used for `finalizeBlock`.
+    /// Either a JSON file or directory with registry entries or the L1 address of a
+    /// `ZkEvmVerifierRegistry` contract.
+    /// Required unless `verifier_label_fallback` or `dummy_prover` is set.
+    pub verifier_registry: Option<VerifierRegistrySource>,
+
+    #[clap(
+        long,
+        env = "COORDINATOR_VERIFIER_LABEL_FALLBACK",
+        default_value_t = false
+    )]
+    /// Derives verifier addresses from the circuit label if no `verifier_registry` is set,
+    /// for setups with verifiers deployed at these addresses.
+    pub verifier_label_fallback: bool,
+
     #[clap(long, env = "COORDINATOR_L1_RPC_URL")]
     #[serde_as(as = "DisplayFromStr")]
     /// L1 RPC node URL format.
@@ -47,7 +221,7 @@ pub struct Config {
     /// Ethereum address of the L1 bridge contract.
     pub l1_bridge: Address,
 
-    #[clap(long, env = "COORDINATOR_L1_PRIV")]
+    #[clap(long, env = "COORDINATOR_L1_PRIV", hide_env_values = true)]
     /// Private key for Ethereum L1 wallet.
     pub l1_priv: String,
 
@@ -56,10 +230,74 @@ pub struct Config {
     /// L2 RPC node in http URL format.
     pub l2_rpc_url: Uri,
 
-    #[clap(long, env = "COORDINATOR_PROVER_RPCD_URL")]
+    #[clap(long, env = "COORDINATOR_L2_BLOCK_BUILDER", default_value = "miner")]
     #[serde_as(as = "DisplayFromStr")]
-    /// Prover RPC node URL.
-    pub prover_rpcd_url: Uri,
+    /// How L2 blocks are built. Either `miner` for the `miner_*` namespace of the L2 node
+    /// or `engine` for the Engine API at `l2_engine_url`.
+    pub l2_block_builder: L2BlockBuilder,
+
+    #[clap(long, env = "COORDINATOR_L2_ENGINE_URL")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    /// Authenticated Engine API endpoint of the L2 node, required for the `engine` block builder.
+    pub l2_engine_url: Option<Uri>,
+
+    #[clap(long, env = "COORDINATOR_L2_JWT_SECRET", hide_env_values = true)]
+    /// File with the hex encoded 32 byte JWT secret for `l2_engine_url`.
+    pub l2_jwt_secret: Option<PathBuf>,
+
+    #[clap(long, env = "COORDINATOR_MIN_BLOCK_INTERVAL", default_value_t = 0)]
+    /// Minimum number of seconds between L2 blocks with transactions from the pool.
+    pub min_block_interval: u64,
+
+    #[clap(long, env = "COORDINATOR_MAX_BLOCK_INTERVAL", default_value_t = 0)]
+    /// Maximum number of seconds that pending transactions wait for `target_block_gas`
+    /// and the interval of `heartbeat_blocks`. 0 disables both.
+    pub max_block_interval: u64,
+
+    #[clap(long, env = "COORDINATOR_TARGET_BLOCK_GAS", default_value_t = 0)]
+    /// Gas limit of the pending transactions at which a block is sealed before
+    /// `max_block_interval`. 0 seals a block as soon as a transaction is pending.
+    pub target_block_gas: u64,
+
+    #[clap(long, env = "COORDINATOR_HEARTBEAT_BLOCKS", default_value_t = false)]
+    /// Seal empty blocks if there was no block for `max_block_interval` seconds.
+    pub heartbeat_blocks: bool,
+
+    #[clap(
+        long,
+        env = "COORDINATOR_L1_ANCHORED_TIMESTAMPS",
+        default_value_t = false
+    )]
+    /// Derive L2 block timestamps from the latest L1 block instead of the local clock.
+    pub l1_anchored_timestamps: bool,
+
+    #[clap(long, env = "COORDINATOR_MAX_TIMESTAMP_DRIFT", default_value_t = 60)]
+    /// Maximum number of seconds a L2 block timestamp may be ahead of the local clock.
+    /// Blocks are not sealed until the clock catches up.
+    pub max_timestamp_drift: u64,
+
+    #[clap(
+        long,
+        env = "COORDINATOR_L2_COINBASE",
+        default_value = "0x0000000000000000000000000000000000000000"
+    )]
+    /// Coinbase, or fee recipient, of L2 blocks.
+    pub l2_coinbase: Address,
+
+    #[clap(
+        long,
+        env = "COORDINATOR_L2_BLOCK_RANDOM",
+        default_value = "0x0000000000000000000000000000000000000000000000000000000000000000"
+    )]
+    /// The `random` (`prevRandao`) value of L2 blocks.
+    pub l2_block_random: H256,
+
+    #[clap(long, env = "COORDINATOR_PROVER_RPCD_URL", value_delimiter = ',')]
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    /// Comma separated list of prover RPC node URLs in the form of `[route=]url`.
+    /// Requests are distributed over all healthy provers.
+    /// `route` dedicates a prover to a circuit name or to aggregation requests (`aggregate`).
+    pub prover_rpcd_url: Vec<ProverEndpoint>,
 
     #[clap(long, env = "COORDINATOR_PARAMS_PATH")]
     /// Parameters file or directory to use for the prover requests.
@@ -71,6 +309,51 @@ pub struct Config {
     /// Either "pi", "super", "evm", "state", "tx", "bytecode", "copy", "exp", "keccak"
     pub circuit_name: String,
 
+    #[clap(long, env = "COORDINATOR_MAX_PENDING_PROOFS", default_value_t = 4)]
+    /// The maximum number of proof requests in flight.
+    /// Proofs are requested as soon as blocks are sealed, up to this limit.
+    pub max_pending_proofs: u32,
+
+    #[clap(
+        long,
+        env = "COORDINATOR_FORCED_INCLUSION_ALERT_MARGIN",
+        default_value_t = 600
+    )]
+    /// Seconds before the inclusion deadline of a forced transaction from L1
+    /// at which an alert is raised if it is not yet included in a L2 block.
+    pub forced_inclusion_alert_margin: u64,
+
+    #[clap(long, env = "COORDINATOR_MESSAGE_RETRY_BACKOFF", default_value_t = 10)]
+    /// Seconds to wait before a failed bridge message delivery is tried again,
+    /// doubled with every further failure.
+    pub message_retry_backoff: u64,
+
+    #[clap(long, env = "COORDINATOR_MESSAGE_MAX_ATTEMPTS", default_value_t = 8)]
+    /// Bridge messages that failed to be delivered this many times are moved to the dead letters.
+    pub message_max_attempts: u32,
+
+    #[clap(long, env = "COORDINATOR_STATE_FILE")]
+    /// JSON file that keeps the bridge message queues and dead letters across restarts.
+    pub state_file: Option<PathBuf>,
+
+    #[clap(long, env = "COORDINATOR_RPC_MAX_RETRIES", default_value_t = 3)]
+    /// Requests to the L1 and L2 nodes that fail with a network error or a timeout
+    /// are retried this many times.
+    pub rpc_max_retries: u32,
+
+    #[clap(long, env = "COORDINATOR_RPC_RETRY_BACKOFF", default_value_t = 500)]
+    /// Milliseconds to wait before a failed request to the L1 or L2 node is retried,
+    /// doubled with every further retry.
+    pub rpc_retry_backoff: u64,
+
+    #[clap(
+        long,
+        env = "COORDINATOR_RPC_MAX_CONCURRENT_REQUESTS",
+        default_value_t = 16
+    )]
+    /// The maximum number of requests in flight to each of the L1 and L2 nodes.
+    pub rpc_max_concurrent_requests: usize,
+
     #[clap(long, env = "COORDINATOR_AGGREGATE_PROOF", default_value_t = false)]
     /// Signals the prover to aggregate the circuit proof
     pub aggregate_proof: bool,
@@ -78,10 +361,148 @@ pub struct Config {
     #[clap(long, env = "COORDINATOR_UNSAFE_RPC", default_value_t = false)]
     /// Allow unsafe rpc methods of the coordinator if true
     pub unsafe_rpc: bool,
+
+    #[clap(long, env = "COORDINATOR_RPC_TOKEN", hide_env_values = true)]
+    /// Requests to `/rpc` must carry the header `authorization: Bearer <rpc_token>` if set.
+    pub rpc_token: Option<String>,
+
+    #[clap(long, env = "COORDINATOR_SHUTDOWN_TIMEOUT", default_value_t = 60)]
+    /// Seconds to wait for the current tasks to finish on SIGTERM or SIGINT.
+    pub shutdown_timeout: u64,
+
+    #[clap(long, env = "COORDINATOR_CONFIG_FILE")]
+    /// TOML, YAML or JSON file with values for any of the options, keyed by their long name.
+    /// Environment variables and command line arguments take precedence.
+    /// The file is watched for changes and reloaded on SIGHUP.
+    pub config_file: Option<PathBuf>,
+}
+
+/// Changes that can't be applied to a running coordinator.
+pub const RESTART_REQUIRED: [&str; 4] = ["listen", "l1_bridge", "rpc_token", "config_file"];
+
+/// The outcome of a configuration update.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigUpdate {
+    /// Changed fields that are in effect
+    pub applied: Vec<String>,
+    /// Changed fields that are ignored until the coordinator is restarted
+    pub restart_required: Vec<String>,
 }
 
 impl Config {
     pub fn from_env() -> Self {
-        Self::parse_from(std::env::args().skip(usize::MAX))
+        zkevm_common::config::try_parse_from::<Self, _, _>(["coordinator"])
+            .unwrap_or_else(|e| e.exit())
+    }
+
+    /// Returns a copy of this configuration with the fields of the JSON object `overlay` replaced.
+    pub fn merge(&self, overlay: serde_json::Value) -> Result<Self, String> {
+        let overlay = match overlay {
+            serde_json::Value::Object(obj) => obj,
+            _ => return Err("expected a configuration object".to_string()),
+        };
+        let mut config = serde_json::to_value(self).map_err(|e| e.to_string())?;
+        let fields = config.as_object_mut().unwrap();
+        for (key, value) in overlay {
+            if !fields.contains_key(&key) {
+                return Err(format!("unknown configuration field: {key}"));
+            }
+            fields.insert(key, value);
+        }
+
+        serde_json::from_value(config).map_err(|e| e.to_string())
+    }
+
+    /// Parses the configuration again from the config file, environment variables and
+    /// command line arguments of this process.
+    pub fn reload() -> Result<Self, String> {
+        zkevm_common::config::try_parse_from::<Self, _, _>(std::env::args_os())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Checks the configuration values that are not covered by parsing.
+    pub fn validate(&self) -> Result<(), String> {
+        match self.rpc_server_nodes.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {}
+            _ => {
+                return Err(format!(
+                    "rpc_server_nodes: expected host:port, got {}",
+                    self.rpc_server_nodes
+                ))
+            }
+        }
+        if self.l1_priv.parse::<LocalWallet>().is_err() {
+            return Err("l1_priv: invalid private key".to_string());
+        }
+        if self.prover_rpcd_url.is_empty() && !self.dummy_prover {
+            return Err("prover_rpcd_url: no prover configured".to_string());
+        }
+        if self.verifier_registry.is_none() && !self.verifier_label_fallback && !self.dummy_prover {
+            return Err(
+                "verifier_registry: required unless verifier_label_fallback is set".to_string(),
+            );
+        }
+        if self.rpc_max_concurrent_requests == 0 {
+            return Err("rpc_max_concurrent_requests: must be at least 1".to_string());
+        }
+        if self.message_max_attempts == 0 {
+            return Err("message_max_attempts: must be at least 1".to_string());
+        }
+        if self.max_pending_proofs == 0 {
+            return Err("max_pending_proofs: must be at least 1".to_string());
+        }
+        if self.max_block_interval != 0 && self.max_block_interval < self.min_block_interval {
+            return Err("max_block_interval: must not be less than min_block_interval".to_string());
+        }
+        if self.max_block_interval == 0 && (self.target_block_gas != 0 || self.heartbeat_blocks) {
+            return Err(
+                "max_block_interval: required for target_block_gas and heartbeat_blocks"
+                    .to_string(),
+            );
+        }
+        if self.l2_block_builder == L2BlockBuilder::Engine {
+            if self.l2_engine_url.is_none() {
+                return Err("l2_engine_url: required for the engine block builder".to_string());
+            }
+            match &self.l2_jwt_secret {
+                Some(path) => {
+                    read_jwt_secret(path).map_err(|e| format!("l2_jwt_secret: {e}"))?;
+                }
+                None => {
+                    return Err("l2_jwt_secret: required for the engine block builder".to_string())
+                }
+            }
+        }
+        if let Some(ProofVerification::VerifierCode(path)) = &self.pre_verify_proof {
+            if !path.is_dir() {
+                return Err(format!(
+                    "pre_verify_proof: {} is not a directory",
+                    path.display()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Keeps the `RESTART_REQUIRED` fields of `current`, changes of these are not applied
+    /// to a running coordinator.
+    pub fn keep_restart_required(&mut self, current: &Self) {
+        self.listen = current.listen;
+        self.l1_bridge = current.l1_bridge;
+        self.rpc_token = current.rpc_token.clone();
+        self.config_file = current.config_file.clone();
+    }
+
+    /// Returns the names of the fields that differ between `self` and `other`.
+    pub fn changed_fields(&self, other: &Self) -> Vec<String> {
+        let a = serde_json::to_value(self).unwrap();
+        let b = serde_json::to_value(other).unwrap();
+        let (a, b) = (a.as_object().unwrap(), b.as_object().unwrap());
+
+        a.iter()
+            .filter(|(key, value)| b.get(*key) != Some(value))
+            .map(|(key, _)| key.clone())
+            .collect()
     }
 }
@@ -0,0 +1,179 @@
+use crate::config::Config;
+use crate::error::Error;
+use crate::structs::*;
+use crate::utils::RPC_REQUEST_TIMEOUT;
+use ethers_core::types::{Bytes, H256, U64};
+use hmac::{Hmac, Mac};
+use hyper::client::HttpConnector;
+use hyper::Uri;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::Sha256;
+use std::path::Path;
+use std::time::SystemTime;
+use zkevm_common::json_rpc::jsonrpc_request_client_auth;
+
+/// Client for the authenticated Engine API of an execution client.
+/// Blocks are built with `engine_forkchoiceUpdatedV1` and `engine_getPayloadV1`,
+/// imported with `engine_newPayloadV1` and made canonical with `engine_forkchoiceUpdatedV1`.
+#[derive(Clone)]
+pub struct EngineApi {
+    pub http_client: hyper::Client<HttpConnector>,
+    pub uri: Uri,
+    jwt_secret: Vec<u8>,
+}
+
+impl EngineApi {
+    /// Returns the client for `l2_engine_url` and `l2_jwt_secret` of `config`.
+    pub fn from_config(config: &Config) -> Result<Self, Error> {
+        let uri = config
+            .l2_engine_url
+            .clone()
+            .ok_or("l2_engine_url: required for the engine block builder")?;
+        let path = config
+            .l2_jwt_secret
+            .as_ref()
+            .ok_or("l2_jwt_secret: required for the engine block builder")?;
+
+        Ok(Self::new(uri, read_jwt_secret(path)?))
+    }
+
+    pub fn new(uri: Uri, jwt_secret: Vec<u8>) -> Self {
+        Self {
+            http_client: hyper::Client::new(),
+            uri,
+            jwt_secret,
+        }
+    }
+
+    /// Sends a json-rpc request with a fresh JWT.
+    pub async fn request<T: Serialize + Send + Sync, R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: T,
+    ) -> Result<R, Error> {
+        let token = jwt_token(&self.jwt_secret, timestamp());
+
+        Ok(jsonrpc_request_client_auth(
+            RPC_REQUEST_TIMEOUT,
+            &self.http_client,
+            &self.uri,
+            Some(&token),
+            method,
+            params,
+        )
+        .await?)
+    }
+
+    pub async fn forkchoice_updated(
+        &self,
+        state: &ForkchoiceStateV1,
+        attributes: Option<&PayloadAttributesV1>,
+    ) -> Result<ForkchoiceUpdatedResponse, Error> {
+        self.request("engine_forkchoiceUpdatedV1", (state, attributes))
+            .await
+    }
+
+    pub async fn get_payload(&self, payload_id: &Bytes) -> Result<ExecutionPayloadV1, Error> {
+        self.request("engine_getPayloadV1", [payload_id]).await
+    }
+
+    pub async fn new_payload(
+        &self,
+        payload: &ExecutionPayloadV1,
+    ) -> Result<PayloadStatusV1, Error> {
+        self.request("engine_newPayloadV1", [payload]).await
+    }
+
+    /// Builds a block on top of `parent` from the transaction pool of the execution client and
+    /// imports it without making it canonical. Returns the payload of the block.
+    /// `parent` becomes the chain head in the process.
+    pub async fn build_block(
+        &self,
+        parent: H256,
+        attributes: &BlockAttributes,
+    ) -> Result<ExecutionPayloadV1, Error> {
+        let attributes = PayloadAttributesV1 {
+            timestamp: U64::from(attributes.timestamp),
+            prev_randao: attributes.random,
+            suggested_fee_recipient: attributes.coinbase,
+        };
+        let resp = self
+            .forkchoice_updated(&head_state(parent), Some(&attributes))
+            .await?;
+        check_status(&resp.payload_status, &["VALID"])?;
+        let payload_id = resp
+            .payload_id
+            .ok_or_else(|| format!("no payload id for parent {parent:?}"))?;
+
+        let payload = self.get_payload(&payload_id).await?;
+        let status = self.new_payload(&payload).await?;
+        check_status(&status, &["VALID", "ACCEPTED"])?;
+
+        Ok(payload)
+    }
+
+    /// Makes `block_hash` the chain head. Returns `false` if the execution client
+    /// does not consider the block valid (yet).
+    pub async fn set_head(&self, block_hash: H256) -> Result<bool, Error> {
+        let resp = self
+            .forkchoice_updated(&head_state(block_hash), None)
+            .await?;
+
+        Ok(check_status(&resp.payload_status, &["VALID"]).is_ok())
+    }
+}
+
+/// The safe and finalized blocks are left unknown, they are tracked by the L1 bridge.
+fn head_state(head: H256) -> ForkchoiceStateV1 {
+    ForkchoiceStateV1 {
+        head_block_hash: head,
+        safe_block_hash: H256::zero(),
+        finalized_block_hash: H256::zero(),
+    }
+}
+
+fn check_status(status: &PayloadStatusV1, expected: &[&str]) -> Result<(), Error> {
+    match expected.contains(&status.status.as_str()) {
+        true => Ok(()),
+        false => Err(format!(
+            "payload status {}: {}",
+            status.status,
+            status.validation_error.as_deref().unwrap_or_default()
+        )
+        .into()),
+    }
+}
+
+/// Reads a hex encoded 32 byte secret, the format used by `--authrpc.jwtsecret` of geth.
+pub fn read_jwt_secret(path: &Path) -> Result<Vec<u8>, Error> {
+    let data = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let secret = hex::decode(data.trim().trim_start_matches("0x"))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    if secret.len() != 32 {
+        return Err(format!("{}: expected 32 bytes", path.display()).into());
+    }
+
+    Ok(secret)
+}
+
+/// Returns a HS256 JSON Web Token with the issued-at claim `iat`, as required by the Engine API.
+pub fn jwt_token(secret: &[u8], iat: u64) -> String {
+    let encode = |data: &[u8]| base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+    let header = encode(br#"{"alg":"HS256","typ":"JWT"}"#);
+    let claims = encode(format!(r#"{{"iat":{iat}}}"#).as_bytes());
+    let message = format!("{header}.{claims}");
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC can take key of any size");
+    mac.update(message.as_bytes());
+    let signature = encode(&mac.finalize().into_bytes());
+
+    format!("{message}.{signature}")
+}
+
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("time")
+        .as_secs()
+}
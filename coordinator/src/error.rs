@@ -0,0 +1,108 @@
+use ethers_core::types::Bytes;
+use std::fmt;
+use zkevm_common::json_rpc::{JsonRpcError, RpcError, EXECUTION_REVERTED, INVALID_PARAMS};
+
+/// Errors of the L1 and L2 clients and the coordinator tasks.
+/// Json-rpc errors of the nodes that the coordinator acts upon get their own variant,
+/// see `From<RpcError>`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// A json-rpc request failed.
+    Rpc(RpcError),
+    /// A transaction or call reverted, with the revert data if the node returned it.
+    Reverted {
+        message: String,
+        data: Option<Bytes>,
+    },
+    /// The transactions do not fit into the block.
+    GasLimitReached,
+    /// The transaction is already in the transaction pool.
+    AlreadyKnown,
+    /// The configuration is invalid.
+    Config(String),
+    /// A file could not be read or written, the message starts with its path.
+    Io(String),
+    Other(String),
+}
+
+impl Error {
+    /// Returns `true` for failures that may go away by retrying the same request.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Rpc(err) if err.is_transient())
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::Rpc(err) if err.is_timeout())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rpc(err) => write!(f, "{err}"),
+            Self::Reverted { message, .. } => f.write_str(message),
+            Self::GasLimitReached => f.write_str("gas limit reached"),
+            Self::AlreadyKnown => f.write_str("already known"),
+            Self::Config(err) | Self::Io(err) | Self::Other(err) => f.write_str(err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Classifies the error responses of the nodes.
+impl From<RpcError> for Error {
+    fn from(err: RpcError) -> Self {
+        let response = match &err {
+            RpcError::Response(response) => response,
+            _ => return Self::Rpc(err),
+        };
+        if response.is_revert() {
+            return Self::Reverted {
+                message: response.message.clone(),
+                data: response.revert_data().and_then(|data| data.parse().ok()),
+            };
+        }
+
+        // geth does not use dedicated error codes for these
+        match response.message.as_str() {
+            "gas limit reached" => Self::GasLimitReached,
+            "already known" => Self::AlreadyKnown,
+            _ => Self::Rpc(err),
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(err: String) -> Self {
+        Self::Other(err)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(err: &str) -> Self {
+        Self::Other(err.to_string())
+    }
+}
+
+impl From<Error> for String {
+    fn from(err: Error) -> Self {
+        err.to_string()
+    }
+}
+
+/// Upstream json-rpc errors keep their code.
+impl From<Error> for JsonRpcError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Rpc(err) => err.into(),
+            Error::Reverted { message, data } => JsonRpcError {
+                code: EXECUTION_REVERTED,
+                message,
+                data: data.map(|data| serde_json::to_value(data).unwrap()),
+            },
+            Error::Config(message) => JsonRpcError::new(INVALID_PARAMS, message),
+            err => err.to_string().into(),
+        }
+    }
+}
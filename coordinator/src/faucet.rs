@@ -8,6 +8,7 @@ use ethers_signers::Signer;
 use tokio::spawn;
 use tokio::sync::Mutex;
 
+use crate::client::L1Client;
 use crate::shared_state::SharedState;
 
 #[derive(Clone)]
@@ -24,17 +25,16 @@ impl Default for Faucet {
 }
 
 impl Faucet {
-    /// Iterates over `queue` and sends ETH with the `shared_state.ro.l1_wallet`.
+    /// Iterates over `queue` and sends ETH with the wallet of the L1 client.
     /// To avoid replacing transactions or invoking other race conditions,
     /// this function should not be run in parallel with any other `SharedState` tasks.
     /// Only consumes up to `max_items` items from the queue each time.
     pub async fn drain(&self, shared_state: SharedState, max_items: usize) {
         let mut queue = self.queue.lock().await;
-        let mut remaining_balance: U256 = shared_state
-            .request_l1(
-                "eth_getBalance",
-                (shared_state.ro.l1_wallet.address(), "latest"),
-            )
+        let ro = shared_state.ro();
+        let mut remaining_balance = ro
+            .l1
+            .get_balance(ro.l1.wallet().address())
             .await
             .expect("l1 balance");
 
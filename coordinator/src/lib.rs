@@ -1,6 +1,10 @@
+pub mod client;
 pub mod config;
+pub mod engine_api;
+pub mod error;
 pub mod faucet;
 pub mod macros;
 pub mod shared_state;
 pub mod structs;
 pub mod utils;
+pub mod verifier_registry;
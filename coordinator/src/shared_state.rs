@@ -1,21 +1,26 @@
+use crate::client::*;
 use crate::config::Config;
+use crate::config::ConfigUpdate;
+use crate::config::ProofVerification;
+use crate::config::RESTART_REQUIRED;
+use crate::error::Error;
 use crate::structs::*;
 use crate::utils::*;
+use crate::verifier_registry::{config_digest, verifier_code, VerifierRegistry};
 use ethers_core::abi::Abi;
-use ethers_core::abi::AbiParser;
 use ethers_core::abi::RawLog;
 use ethers_core::abi::Token;
 use ethers_core::abi::Tokenizable;
 use ethers_core::types::TransactionReceipt;
 use ethers_core::types::{
-    Address, Block, Bytes, Filter, Log, Transaction, TransactionRequest, TxpoolStatus,
-    ValueOrArray, H256, U256, U64,
+    Address, Block, Bytes, Filter, Log, Transaction, TransactionRequest, ValueOrArray, H256, U256,
+    U64,
 };
 use ethers_core::utils::keccak256;
 use ethers_core::utils::rlp;
-use ethers_signers::LocalWallet;
 use ethers_signers::Signer;
 use hyper::client::HttpConnector;
+use hyper::StatusCode;
 use hyper::Uri;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -24,13 +29,14 @@ use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::sync::Arc;
 use std::time::SystemTime;
+use tokio::sync::watch;
 use tokio::sync::Mutex;
-use zkevm_common::json_rpc::jsonrpc_request;
-use zkevm_common::json_rpc::jsonrpc_request_client;
+use zkevm_common::json_rpc::{JsonRpcClient, RetryPolicy, RpcError};
 use zkevm_common::prover::ProofRequestOptions;
 use zkevm_common::prover::Proofs;
+use zkevm_common::prover::ProverClient;
 
-pub struct RoState {
+pub struct RoState<L1 = RpcL1Client, L2 = RpcL2Engine> {
     pub l2_message_deliverer_addr: Address,
     pub l2_message_dispatcher_addr: Address,
 
@@ -38,28 +44,35 @@ pub struct RoState {
     pub block_finalized_topic: H256,
     pub message_dispatched_topic: H256,
     pub message_delivered_topic: H256,
+    pub forced_transaction_topic: H256,
 
     pub http_client: hyper::Client<HttpConnector>,
-    pub l1_wallet: LocalWallet,
-    pub l2_wallet: LocalWallet,
+    pub l1: L1,
+    pub l2: L2,
 
     pub bridge_abi: Abi,
+    pub verifier_registry: VerifierRegistry,
 }
 
-impl RoState {
-    pub async fn new(config: &Config) -> Self {
-        let l1_wallet = get_wallet(&config.l1_rpc_url, &config.l1_priv).await;
-        // TODO: support different keys for L1 and L2
-        let l2_wallet = get_wallet(&config.l2_rpc_url, &config.l1_priv).await;
-
-        let abi = get_abi();
+impl<L1: L1Client, L2: L2Engine> RoState<L1, L2> {
+    /// Builds the verifier registry for `config`, with the `l1` and `l2` clients.
+    pub fn try_new(config: &Config, l1: L1, l2: L2) -> Result<Self, String> {
+        let abi = bridge_abi();
+        let verifier_registry = VerifierRegistry::load(
+            config.verifier_registry.as_ref(),
+            config.verifier_label_fallback || config.dummy_prover,
+        )?;
+        if let VerifierRegistry::Label = verifier_registry {
+            log::warn!("no verifier registry configured, deriving verifier addresses from labels");
+        }
 
         let beacon_topic = abi.event("BlockSubmitted").unwrap().signature();
         let block_finalized_topic = abi.event("BlockFinalized").unwrap().signature();
         let message_dispatched_topic = abi.event("MessageDispatched").unwrap().signature();
         let message_delivered_topic = abi.event("MessageDelivered").unwrap().signature();
+        let forced_transaction_topic = abi.event("ForcedTransactionQueued").unwrap().signature();
 
-        RoState {
+        Ok(RoState {
             l2_message_deliverer_addr: "0x0000000000000000000000000000000000010000"
                 .parse()
                 .unwrap(),
@@ -71,29 +84,49 @@ impl RoState {
             block_finalized_topic,
             message_dispatched_topic,
             message_delivered_topic,
+            forced_transaction_topic,
 
             http_client: hyper::Client::new(),
-            l1_wallet,
-            l2_wallet,
+            l1,
+            l2,
             bridge_abi: abi,
-        }
+            verifier_registry,
+        })
     }
 }
 
+/// Number of L2 headers kept for the history hashes, a few windows of 256 blocks.
+const HEADER_CACHE_SIZE: usize = 1024;
+/// Number of blocks per batch request in `blocks_between`.
+const BLOCK_BATCH_SIZE: u64 = 64;
+
 pub struct RwState {
     pub chain_state: ForkchoiceStateV1,
     pub nodes: Vec<Uri>,
-    pub prover_requests: HashMap<U64, Option<Proofs>>,
+    /// health information about the configured provers
+    pub provers: Vec<ProverNode>,
+    /// proof requests for blocks that are not yet finalized
+    pub prover_requests: HashMap<U64, ProverRequest>,
+    /// number of proof requests in flight
     pub pending_proofs: u32,
     pub l1_last_sync_block: U64,
     pub l2_last_sync_block: U64,
-    pub l1_message_queue: VecDeque<MessageBeacon>,
+    pub l1_message_queue: VecDeque<MessageDelivery>,
     pub l2_delivered_messages: Vec<H256>,
-    pub l2_message_queue: Vec<MessageBeacon>,
+    pub l2_message_queue: Vec<MessageDelivery>,
     pub l1_delivered_messages: Vec<H256>,
-
-    /// keeps track of the timestamp used for preparing the last block
-    _prev_timestamp: u64,
+    /// L1 > L2 messages that failed `message_max_attempts` times
+    pub l1_dead_letters: Vec<MessageDelivery>,
+    /// L2 > L1 messages that failed `message_max_attempts` times
+    pub l2_dead_letters: Vec<MessageDelivery>,
+    /// L2 transactions from L1 that are not yet included, in the order of submission
+    pub forced_transactions: VecDeque<ForcedTransaction>,
+    /// block hash and witness of the blocks submitted on L1, starting at the finalized block
+    pub submitted_blocks: Vec<(H256, Bytes)>,
+    /// L2 headers for the history hashes of the witnesses
+    pub l2_headers: HeaderCache,
+    /// history hashes of the last witness, see `history_hashes`
+    pub history_hashes: Vec<H256>,
 }
 
 impl Default for RwState {
@@ -105,6 +138,7 @@ impl Default for RwState {
                 finalized_block_hash: H256::zero(),
             },
             nodes: Vec::new(),
+            provers: Vec::new(),
             prover_requests: HashMap::new(),
             pending_proofs: 0,
             l1_last_sync_block: U64::zero(),
@@ -113,27 +147,45 @@ impl Default for RwState {
             l2_delivered_messages: Vec::new(),
             l2_message_queue: Vec::new(),
             l1_delivered_messages: Vec::new(),
-
-            _prev_timestamp: 0,
+            l1_dead_letters: Vec::new(),
+            l2_dead_letters: Vec::new(),
+            forced_transactions: VecDeque::new(),
+            submitted_blocks: Vec::new(),
+            l2_headers: HeaderCache::new(HEADER_CACHE_SIZE),
+            history_hashes: Vec::new(),
         }
     }
 }
 
-#[derive(Clone)]
-pub struct SharedState {
+/// The coordinator state, generic over the L1 and L2 clients.
+pub struct SharedState<L1 = RpcL1Client, L2 = RpcL2Engine> {
     pub config: Arc<Mutex<Config>>,
-    pub ro: Arc<RoState>,
+    /// replaced as a whole if the configuration changes, see `ro()`
+    pub ro: Arc<std::sync::RwLock<Arc<RoState<L1, L2>>>>,
     pub rw: Arc<Mutex<RwState>>,
+    /// `true` once a shutdown was requested
+    pub shutdown: Arc<watch::Sender<bool>>,
 }
 
-impl SharedState {
-    pub async fn new(config: &Config) -> Self {
+impl<L1, L2> Clone for SharedState<L1, L2> {
+    fn clone(&self) -> Self {
         Self {
-            config: Arc::new(Mutex::new(config.clone())),
-            ro: Arc::new(RoState::new(config).await),
-            rw: Arc::new(Mutex::new(RwState::default())),
+            config: self.config.clone(),
+            ro: self.ro.clone(),
+            rw: self.rw.clone(),
+            shutdown: self.shutdown.clone(),
         }
     }
+}
+
+impl SharedState {
+    /// Connects to the json-rpc nodes in `config`.
+    pub async fn new(config: &Config) -> Self {
+        let l1 = RpcL1Client::connect(config).await.expect("L1 client");
+        let l2 = RpcL2Engine::connect(config).await.expect("L2 client");
+
+        Self::with_clients(config, l1, l2).expect("SharedState")
+    }
 
     /// Initiates configuration from environment variables only.
     pub async fn from_env() -> Self {
@@ -141,14 +193,55 @@ impl SharedState {
 
         Self::new(&config).await
     }
+}
+
+impl<L1: L1Client, L2: L2Engine> SharedState<L1, L2> {
+    /// Uses the clients `l1` and `l2` instead of connecting to the nodes in `config`.
+    pub fn with_clients(config: &Config, l1: L1, l2: L2) -> Result<Self, String> {
+        Ok(Self {
+            config: Arc::new(Mutex::new(config.clone())),
+            ro: Arc::new(std::sync::RwLock::new(Arc::new(RoState::try_new(
+                config, l1, l2,
+            )?))),
+            rw: Arc::new(Mutex::new(RwState::default())),
+            shutdown: Arc::new(watch::channel(false).0),
+        })
+    }
+
+    /// Requests a shutdown. Tasks stop taking new work and finish their current step.
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    /// Returns `true` if a shutdown was requested.
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// Resolves once a shutdown was requested.
+    pub async fn shutdown_requested(&self) {
+        let mut rx = self.shutdown.subscribe();
+        while !*rx.borrow_and_update() {
+            if rx.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Returns the `RoState` for the current configuration.
+    pub fn ro(&self) -> Arc<RoState<L1, L2>> {
+        self.ro.read().unwrap().clone()
+    }
 
     pub async fn init(&self) {
         if !self.rw.lock().await.chain_state.head_block_hash.is_zero() {
             panic!("init");
         }
 
-        let genesis: Block<H256> = self
-            .request_l2("eth_getBlockByNumber", ("0x0", false))
+        let genesis = self
+            .ro()
+            .l2
+            .get_block(U64::zero())
             .await
             .expect("genesis block");
         let h = genesis.hash.unwrap();
@@ -160,41 +253,37 @@ impl SharedState {
         chain_state.finalized_block_hash = h;
 
         // initialize l1 bridge if necessary
+        let l1_bridge_addr = self.config.lock().await.l1_bridge;
         let bridge_state_root = self
-            .call_fn_l1("stateRoots", &[genesis.hash.unwrap().into_token()])
+            .ro()
+            .l1
+            .state_root(l1_bridge_addr, h)
             .await
             .expect("l1.stateRoots");
         if bridge_state_root == H256::zero() {
             log::info!("init l1 bridge");
-            let block_hash = genesis.hash.unwrap();
-            let state_root = genesis.state_root;
-            let calldata = get_abi()
-                .function("initGenesis")
-                .unwrap()
-                .encode_input(&[block_hash.into_token(), state_root.into_token()])
-                .expect("calldata");
-            let l1_bridge_addr = self.config.lock().await.l1_bridge;
-            self.transaction_to_l1(Some(l1_bridge_addr), U256::zero(), calldata)
+            self.ro()
+                .l1
+                .init_genesis(l1_bridge_addr, h, genesis.state_root)
                 .await
                 .expect("init genesis");
         }
     }
 
-    pub async fn sync(&self) {
+    /// Syncs the events of the L1 bridge and the L2 message deliverer.
+    /// The progress is kept per range of blocks, a range that fails is synced again.
+    pub async fn sync(&self) -> Result<(), Error> {
         // sync events
-        let latest_block: U64 = self
-            .request_l1("eth_blockNumber", ())
-            .await
-            .expect("eth_blockNumber");
-        let mut last_to_block: U64 = U64::zero();
+        let latest_block = self.ro().l1.block_number().await?;
         let mut from: U64 = self.rw.lock().await.l1_last_sync_block + 1;
         let mut filter = Filter::new()
             .address(ValueOrArray::Value(self.config.lock().await.l1_bridge))
             .topic0(ValueOrArray::Array(vec![
-                self.ro.block_beacon_topic,
-                self.ro.block_finalized_topic,
-                self.ro.message_dispatched_topic,
-                self.ro.message_delivered_topic,
+                self.ro().block_beacon_topic,
+                self.ro().block_finalized_topic,
+                self.ro().message_dispatched_topic,
+                self.ro().message_delivered_topic,
+                self.ro().forced_transaction_topic,
             ]));
 
         while from <= latest_block {
@@ -203,24 +292,18 @@ impl SharedState {
             log::trace!("fetching l1 logs from={} to={}", from, to);
             filter = filter.from_block(from).to_block(to);
 
-            let logs: Vec<Log> = self
-                .request_l1("eth_getLogs", [&filter])
-                .await
-                .expect("eth_getLogs");
+            let logs = self.ro().l1.get_logs(&filter).await?;
             // TODO: ugly hack to fix geth inconstency issues
-            if !logs.is_empty() {
-                last_to_block = to;
-            }
+            let synced = !logs.is_empty();
 
             for log in logs {
                 let topic = log.topics[0];
 
-                if topic == self.ro.block_beacon_topic {
-                    let tx_hash = log.transaction_hash.expect("log txhash");
-                    let tx: Transaction = self
-                        .request_l1("eth_getTransactionByHash", [tx_hash])
-                        .await
-                        .expect("tx");
+                if topic == self.ro().block_beacon_topic {
+                    let tx_hash = log
+                        .transaction_hash
+                        .ok_or("BlockSubmitted: log without transaction hash")?;
+                    let tx = self.ro().l1.get_transaction(tx_hash).await?;
 
                     let tx_data = tx.input.as_ref();
 
@@ -232,27 +315,35 @@ impl SharedState {
                         log::warn!("TODO: zeropad block data");
                     }
                     let rlp = rlp::Rlp::new(&tx_data[start..end]);
-                    let info = rlp.payload_info().expect("payload_info");
+                    let info = rlp
+                        .payload_info()
+                        .map_err(|e| format!("BlockSubmitted: {tx_hash:?}: {e}"))?;
                     let block_header = &rlp.as_raw()[0..info.header_len + info.value_len];
                     let block_hash = H256::from(keccak256(block_header));
                     log::info!("BlockSubmitted: {:?} via {:?}", block_hash, tx_hash);
 
-                    let resp: Result<serde_json::Value, String> =
-                        self.request_l2("eth_getHeaderByHash", [block_hash]).await;
+                    let resp = self.ro().l2.get_header(block_hash).await;
 
                     if resp.is_err() {
-                        log::error!(
-                            "TODO: block not found {} {}",
+                        // imported again by `check_l2_head`
+                        log::warn!(
+                            "BlockSubmitted: {:?} not found on L2: {}",
                             block_hash,
                             resp.err().unwrap()
                         );
                     }
 
-                    self.rw.lock().await.chain_state.safe_block_hash = block_hash;
+                    let witness = Bytes::from(tx_data[start..end.min(tx_data.len())].to_vec());
+                    let mut rw = self.rw.lock().await;
+                    rw.chain_state.safe_block_hash = block_hash;
+                    // already known if the range is synced again
+                    if !rw.submitted_blocks.iter().any(|(h, _)| *h == block_hash) {
+                        rw.submitted_blocks.push((block_hash, witness));
+                    }
                     continue;
                 }
 
-                if topic == self.ro.block_finalized_topic {
+                if topic == self.ro().block_finalized_topic {
                     let block_hash = H256::from_slice(log.data.as_ref());
                     log::info!(
                         "BlockFinalized: {:?} via {:?}",
@@ -260,417 +351,955 @@ impl SharedState {
                         log.transaction_hash
                     );
 
-                    self.rw.lock().await.chain_state.finalized_block_hash = block_hash;
-                    self.record_l2_messages(block_hash).await;
+                    {
+                        let mut rw = self.rw.lock().await;
+                        rw.chain_state.finalized_block_hash = block_hash;
+                        if let Some(pos) = rw
+                            .submitted_blocks
+                            .iter()
+                            .position(|(h, _)| *h == block_hash)
+                        {
+                            rw.submitted_blocks.drain(0..pos);
+                        }
+                    }
+                    self.record_l2_messages(block_hash).await?;
                     continue;
                 }
 
-                if topic == self.ro.message_dispatched_topic {
+                if topic == self.ro().message_dispatched_topic {
                     let beacon = self._parse_message_beacon(log);
                     log::info!("L1:MessageDispatched:{:?}", beacon.id);
                     log::debug!("{:?}", beacon);
-                    self.rw.lock().await.l1_message_queue.push_back(beacon);
+                    let mut rw = self.rw.lock().await;
+                    // already known if loaded from `state_file`
+                    if !rw
+                        .l1_message_queue
+                        .iter()
+                        .chain(rw.l1_dead_letters.iter())
+                        .any(|e| e.message.id == beacon.id)
+                    {
+                        rw.l1_message_queue.push_back(MessageDelivery::new(beacon));
+                    }
                     continue;
                 }
 
-                if topic == self.ro.message_delivered_topic {
+                if topic == self.ro().message_delivered_topic {
                     let id = H256::from_slice(log.data.as_ref());
                     log::info!("L1:MessageDelivered:{:?}", id);
-                    self.rw.lock().await.l1_delivered_messages.push(id);
+                    let mut rw = self.rw.lock().await;
+                    if !rw.l1_delivered_messages.contains(&id) {
+                        rw.l1_delivered_messages.push(id);
+                    }
+                    continue;
+                }
+
+                if topic == self.ro().forced_transaction_topic {
+                    let forced_tx = self._parse_forced_transaction(log);
+                    log::info!(
+                        "L1:ForcedTransactionQueued:{:?} deadline={}",
+                        forced_tx.tx_hash,
+                        forced_tx.deadline
+                    );
+                    let mut rw = self.rw.lock().await;
+                    if !rw
+                        .forced_transactions
+                        .iter()
+                        .any(|e| e.tx_hash == forced_tx.tx_hash)
+                    {
+                        rw.forced_transactions.push_back(forced_tx);
+                    }
                     continue;
                 }
             }
 
+            if synced {
+                self.rw.lock().await.l1_last_sync_block = to;
+            }
             from = to + 1u64;
         }
 
-        if last_to_block != U64::zero() {
-            self.rw.lock().await.l1_last_sync_block = last_to_block;
-        }
-        self.sync_l2().await;
+        self.sync_l2().await?;
+        self.save_state().await;
+
+        Ok(())
     }
 
-    pub async fn mine(&self) {
-        // TODO: verify that head_hash is correct
-        let head_hash = get_chain_head(&self.ro.http_client, &self.config.lock().await.l2_rpc_url)
-            .await
-            .hash;
-        self.rw.lock().await.chain_state.head_block_hash = head_hash;
+    pub async fn mine(&self) -> Result<(), Error> {
+        self.check_l2_head().await?;
 
         {
             // always send a miner_init request to enable transaction pool etc.
             // just to account for the case that the node was restarted
-            let _: Option<Address> = self.request_l2("miner_init", ()).await.unwrap_or_default();
+            if let Err(err) = self.ro().l2.init().await {
+                log::debug!("miner_init: {}", err);
+            }
         }
 
+        // forced transactions from L1 take precedence over everything else
+        self.include_forced_transactions().await;
+
         {
             // check l1 > l2 message queue
-            let len = self.rw.lock().await.l1_message_queue.len();
-            if len > 0 {
-                let mut nonce: U256 = self
-                    .request_l2(
-                        "eth_getTransactionCount",
-                        (self.ro.l2_wallet.address(), "latest"),
-                    )
-                    .await
-                    .expect("nonce");
-
+            let now = timestamp();
+            let due = self
+                .rw
+                .lock()
+                .await
+                .l1_message_queue
+                .iter()
+                .any(|e| e.next_attempt <= now);
+            if due {
                 const LOG_TAG: &str = "L2:deliverMessage:";
 
-                // anchors a L1 block into L2
-                let l1_block_header: BlockHeader = self
-                    .request_l1("eth_getHeaderByNumber", ["latest"])
-                    .await
-                    .expect("l1 block header");
-                // TODO: figure out how to get by hash - gonna be safer
-                // Or just hash it and compare against l1_block_header.hash.
-                let block_data: Bytes = self
-                    .request_l1("debug_getHeaderRlp", [l1_block_header.number.as_u64()])
-                    .await
-                    .expect("block_data");
-                let account_proof: Bytes = {
-                    let l1_bridge_addr = self.config.lock().await.l1_bridge;
-                    let proof_obj: MerkleProofRequest = self
-                        .request_l1("eth_getProof", (l1_bridge_addr, (), l1_block_header.hash))
-                        .await
-                        .expect("eth_getProof");
-                    Bytes::from(marshal_proof_single(&proof_obj.account_proof))
+                let (drop_idxs, failures) = match self.deliver_l1_messages(now).await {
+                    Ok(res) => res,
+                    Err(err) => {
+                        // the round failed as a whole, every due message failed an attempt
+                        log::warn!("{} {}", LOG_TAG, err);
+                        let failures = self
+                            .rw
+                            .lock()
+                            .await
+                            .l1_message_queue
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, e)| e.next_attempt <= now)
+                            .map(|(i, _)| (i, err.to_string()))
+                            .collect();
+                        (Vec::new(), failures)
+                    }
                 };
-                let mut messages = Vec::new();
-                // authorize l1 block
-                {
-                    let calldata = self
-                        .ro
-                        .bridge_abi
-                        .function("importForeignBlock")
-                        .unwrap()
-                        .encode_input(&[
-                            U256::from(l1_block_header.number.as_u64()).into_token(),
-                            l1_block_header.hash.into_token(),
-                        ])
-                        .expect("calldata");
-                    let tx = self
-                        .sign_l2(
-                            Some(self.ro.l2_message_deliverer_addr),
-                            U256::zero(),
-                            nonce,
-                            calldata,
-                        )
-                        .await;
-                    messages.push(tx);
-                    nonce = nonce + 1;
+
+                let (backoff, max_attempts) = self.message_retry_policy().await;
+                let mut guard = self.rw.lock().await;
+                let rw = &mut *guard;
+                for (i, err) in failures {
+                    rw.l1_message_queue[i].failed(err, now, backoff);
                 }
-                // Use this block to run the messages against.
-                // This is required for proper gas calculation.
-                let block_timestamp = self.next_timestamp().await;
-                let temporary_block = self
-                    .prepare_block(block_timestamp, Some(&messages))
-                    .await
-                    .expect("prepare block with import tx");
-                // import block header
-                {
-                    let calldata = self
-                        .ro
-                        .bridge_abi
-                        .function("importForeignBridgeState")
-                        .unwrap()
-                        .encode_input(&[block_data.into_token(), account_proof.into_token()])
-                        .expect("calldata");
-                    let tx = self
-                        .sign_l2_given_block_tag(
-                            Some(self.ro.l2_message_deliverer_addr),
-                            U256::zero(),
-                            nonce,
-                            calldata,
-                            Some(format!("{:#066x}", temporary_block.hash.unwrap())),
-                        )
-                        .await
-                        .expect("importForeignBridgeState on temporary_block");
-                    messages.push(tx);
-                    nonce = nonce + 1;
+                for (i, original_pos) in drop_idxs.into_iter().enumerate() {
+                    rw.l1_message_queue.remove(original_pos - i);
                 }
-                let mut temporary_block = self
-                    .prepare_block(block_timestamp, Some(&messages))
-                    .await
-                    .expect("prepare block with import tx");
-                let ts = U256::from(block_timestamp);
-                let mut drop_idxs = Vec::new();
-                let mut i = 0;
-                let l1_bridge_addr = self.config.lock().await.l1_bridge;
-                loop {
-                    let rw = self.rw.lock().await;
-                    let msg = rw.l1_message_queue.get(i);
-                    if msg.is_none() {
-                        break;
+                let dead_letters = &mut rw.l1_dead_letters;
+                rw.l1_message_queue.retain(|e| {
+                    if e.attempts < max_attempts {
+                        return true;
                     }
-                    let msg = msg.unwrap().clone();
-                    drop(rw);
+                    log::error!(
+                        "{} {:?} failed {} times, moved to dead letters: {}",
+                        LOG_TAG,
+                        e.message.id,
+                        e.attempts,
+                        e.last_error.as_deref().unwrap_or_default()
+                    );
+                    dead_letters.push(e.clone());
+                    false
+                });
+                drop(guard);
+                self.save_state().await;
+            }
+        }
+
+        // check if we can mine a block
+        match self.block_due().await {
+            Ok(false) => {}
+            Ok(true) => {
+                // empty if it is a heartbeat block
+                if let Err(err) = self.mine_block(None).await {
+                    log::warn!("mine_block: {}", err);
+                }
+            }
+            Err(err) => log::error!("block_due: {}", err),
+        }
+
+        Ok(())
+    }
+
+    /// Delivers the due messages of `l1_message_queue` in a block of their own, anchored to the
+    /// latest L1 block. Returns the positions of the messages that are done with and of the
+    /// messages that failed, with their error. An error fails the round for every due message.
+    async fn deliver_l1_messages(
+        &self,
+        now: u64,
+    ) -> Result<(Vec<usize>, Vec<(usize, String)>), Error> {
+        const LOG_TAG: &str = "L2:deliverMessage:";
+
+        let ro = self.ro();
+        let mut nonce = ro.l2.transaction_count(ro.l2.wallet().address()).await?;
+
+        // anchors a L1 block into L2
+        let l1_block_header = ro.l1.latest_header().await?;
+        // TODO: figure out how to get by hash - gonna be safer
+        // Or just hash it and compare against l1_block_header.hash.
+        let block_data = ro.l1.header_rlp(l1_block_header.number).await?;
+        let account_proof: Bytes = {
+            let l1_bridge_addr = self.config.lock().await.l1_bridge;
+            let proof_obj = ro
+                .l1
+                .get_proof(l1_bridge_addr, &[], l1_block_header.hash)
+                .await?;
+            Bytes::from(marshal_proof_single(&proof_obj.account_proof))
+        };
+        let mut messages = Vec::new();
+        // authorize l1 block
+        {
+            let calldata = self
+                .ro()
+                .bridge_abi
+                .function("importForeignBlock")
+                .unwrap()
+                .encode_input(&[
+                    U256::from(l1_block_header.number.as_u64()).into_token(),
+                    l1_block_header.hash.into_token(),
+                ])
+                .expect("calldata");
+            let tx = self
+                .sign_l2(
+                    Some(self.ro().l2_message_deliverer_addr),
+                    U256::zero(),
+                    nonce,
+                    calldata,
+                )
+                .await?;
+            messages.push(tx);
+            nonce = nonce + 1;
+        }
+        // Use this block to run the messages against.
+        // This is required for proper gas calculation.
+        let attributes = self.next_block_attributes().await?;
+        let temporary_block = self.prepare_block(&attributes, Some(&messages)).await?;
+        // import block header
+        {
+            let calldata = self
+                .ro()
+                .bridge_abi
+                .function("importForeignBridgeState")
+                .unwrap()
+                .encode_input(&[block_data.into_token(), account_proof.into_token()])
+                .expect("calldata");
+            let tx = self
+                .sign_l2_given_block_tag(
+                    Some(self.ro().l2_message_deliverer_addr),
+                    U256::zero(),
+                    nonce,
+                    calldata,
+                    Some(format!("{:#066x}", temporary_block.hash.unwrap())),
+                )
+                .await?;
+            messages.push(tx);
+            nonce = nonce + 1;
+        }
+        let mut temporary_block = self.prepare_block(&attributes, Some(&messages)).await?;
+        let ts = U256::from(attributes.timestamp);
+        let mut drop_idxs = Vec::new();
+        let mut failures = Vec::new();
+        let mut i = 0;
+        let l1_bridge_addr = self.config.lock().await.l1_bridge;
+        loop {
+            let rw = self.rw.lock().await;
+            let delivery = rw.l1_message_queue.get(i);
+            if delivery.is_none() {
+                break;
+            }
+            let delivery = delivery.unwrap().clone();
+            drop(rw);
 
-                    if msg.deadline < ts {
-                        log::info!("{} {:?} deadline exceeded", LOG_TAG, msg.id);
-                        log::debug!("{:?}", msg);
-                        drop_idxs.push(i);
+            if delivery.next_attempt > now {
+                i += 1;
+                continue;
+            }
+            let msg = delivery.message;
+
+            if msg.deadline < ts {
+                log::info!("{} {:?} deadline exceeded", LOG_TAG, msg.id);
+                log::debug!("{:?}", msg);
+                drop_idxs.push(i);
+                i += 1;
+                continue;
+            }
+
+            {
+                let found = self
+                    .rw
+                    .lock()
+                    .await
+                    .l2_delivered_messages
+                    .iter()
+                    .any(|&e| e == msg.id);
+
+                log::info!("{} skip={} {:?}", LOG_TAG, found, msg.id);
+                log::debug!("{:?}", msg);
+
+                if found {
+                    drop_idxs.push(i);
+                    i += 1;
+                    continue;
+                }
+            }
+
+            let storage_proof: Bytes = {
+                // calculate the storage slot for this message
+                let storage_slot = msg.storage_slot();
+                // request proof
+                let proof_obj = match ro
+                    .l1
+                    .get_proof(l1_bridge_addr, &[storage_slot], l1_block_header.hash)
+                    .await
+                {
+                    Ok(proof_obj) => proof_obj,
+                    Err(err) => {
+                        log::debug!("{} eth_getProof {}", LOG_TAG, err);
+                        failures.push((i, format!("eth_getProof: {err}")));
                         i += 1;
                         continue;
                     }
+                };
+                // encode proof
+                Bytes::from(marshal_proof_single(&proof_obj.storage_proof[0].proof))
+            };
+            let calldata = self
+                .ro()
+                .bridge_abi
+                .function("deliverMessageWithProof")
+                .unwrap()
+                .encode_input(&[
+                    msg.from.into_token(),
+                    msg.to.into_token(),
+                    msg.value.into_token(),
+                    msg.fee.into_token(),
+                    msg.deadline.into_token(),
+                    msg.nonce.into_token(),
+                    Token::Bytes(msg.calldata),
+                    storage_proof.into_token(),
+                ])
+                .expect("calldata");
 
-                    {
-                        let found = self
-                            .rw
-                            .lock()
-                            .await
-                            .l2_delivered_messages
-                            .iter()
-                            .any(|&e| e == msg.id);
-
-                        log::info!("{} skip={} {:?}", LOG_TAG, found, msg.id);
-                        log::debug!("{:?}", msg);
+            // simulate against temporary block
+            let tx = self
+                .sign_l2_given_block_tag(
+                    Some(self.ro().l2_message_deliverer_addr),
+                    U256::zero(),
+                    nonce,
+                    calldata,
+                    Some(format!("{:#066x}", temporary_block.hash.unwrap())),
+                )
+                .await;
+            if let Err(err) = tx {
+                log::debug!("{} simulate tx {}", LOG_TAG, err);
+                failures.push((i, format!("simulate tx: {err}")));
+                i += 1;
+                continue;
+            }
 
-                        if found {
-                            drop_idxs.push(i);
-                            i += 1;
-                            continue;
-                        }
+            // try to build that block
+            messages.push(tx.unwrap());
+            let tmp = self.prepare_block(&attributes, Some(&messages)).await;
+            if let Err(err) = tmp {
+                log::debug!("{} {}", LOG_TAG, err);
+                // bad tx
+                messages.pop();
+
+                match err {
+                    Error::GasLimitReached => {
+                        // block is full
+                        break;
                     }
-
-                    let storage_proof: Bytes = {
-                        // calculate the storage slot for this message
-                        let storage_slot = msg.storage_slot();
-                        // request proof
-                        let proof_obj: MerkleProofRequest = self
-                            .request_l1(
-                                "eth_getProof",
-                                (l1_bridge_addr, [storage_slot], l1_block_header.hash),
-                            )
-                            .await
-                            .expect("eth_getProof");
-                        // encode proof
-                        Bytes::from(marshal_proof_single(&proof_obj.storage_proof[0].proof))
-                    };
-                    let calldata = self
-                        .ro
-                        .bridge_abi
-                        .function("deliverMessageWithProof")
-                        .unwrap()
-                        .encode_input(&[
-                            msg.from.into_token(),
-                            msg.to.into_token(),
-                            msg.value.into_token(),
-                            msg.fee.into_token(),
-                            msg.deadline.into_token(),
-                            msg.nonce.into_token(),
-                            Token::Bytes(msg.calldata),
-                            storage_proof.into_token(),
-                        ])
-                        .expect("calldata");
-
-                    // simulate against temporary block
-                    let tx = self
-                        .sign_l2_given_block_tag(
-                            Some(self.ro.l2_message_deliverer_addr),
-                            U256::zero(),
-                            nonce,
-                            calldata,
-                            Some(format!("{:#066x}", temporary_block.hash.unwrap())),
-                        )
-                        .await;
-                    if let Err(err) = tx {
-                        log::debug!("{} simulate tx {}", LOG_TAG, err);
-                        drop_idxs.push(i);
+                    _ => {
+                        // another error, probably a revert
+                        failures.push((i, err.to_string()));
                         i += 1;
                         continue;
                     }
+                }
+            }
 
-                    // try to build that block
-                    messages.push(tx.unwrap());
-                    let tmp = self.prepare_block(block_timestamp, Some(&messages)).await;
-                    if let Err(err) = tmp {
-                        log::debug!("{} {}", LOG_TAG, err);
-                        // bad tx
-                        messages.pop();
-
-                        match err.as_str() {
-                            "gas limit reached" => {
-                                // block is full
-                                break;
-                            }
-                            _ => {
-                                // another error, probably a revert
-                                drop_idxs.push(i);
-                                i += 1;
-                                continue;
-                            }
-                        }
-                    }
+            // block looks good
+            temporary_block = tmp.unwrap();
+            log::debug!(
+                "{} used={} limit={}",
+                LOG_TAG,
+                temporary_block.gas_used,
+                temporary_block.gas_limit
+            );
+            nonce = nonce + 1;
+            drop_idxs.push(i);
+            i += 1;
+        }
 
-                    // block looks good
-                    temporary_block = tmp.unwrap();
-                    log::debug!(
-                        "{} used={} limit={}",
-                        LOG_TAG,
-                        temporary_block.gas_used,
-                        temporary_block.gas_limit
-                    );
-                    nonce = nonce + 1;
-                    drop_idxs.push(i);
-                    i += 1;
-                }
+        // final step
+        if temporary_block.transactions.len() > 1 {
+            self.set_chain_head(temporary_block.hash.unwrap()).await?;
+        }
 
-                // final step
-                if temporary_block.transactions.len() > 1 {
-                    self.set_chain_head(temporary_block.hash.unwrap())
-                        .await
-                        .expect("set_chain_head relay");
-                }
+        Ok((drop_idxs, failures))
+    }
 
-                // everything went well
-                let mut rw = self.rw.lock().await;
-                for (i, original_pos) in drop_idxs.into_iter().enumerate() {
-                    rw.l1_message_queue.remove(original_pos - i);
-                }
+    /// Decides if a block should be sealed from the transaction pool
+    /// according to the block production policy.
+    async fn block_due(&self) -> Result<bool, Error> {
+        let (min_interval, max_interval, target_gas, heartbeat) = {
+            let config = self.config.lock().await;
+            (
+                config.min_block_interval,
+                config.max_block_interval,
+                config.target_block_gas,
+                config.heartbeat_blocks,
+            )
+        };
+        let head_hash = self.rw.lock().await.chain_state.head_block_hash;
+        let head = self.ro().l2.get_header(head_hash).await?;
+        let elapsed = timestamp().saturating_sub(head.timestamp.as_u64());
+        if elapsed < min_interval {
+            return Ok(false);
+        }
+
+        let pending = self.ro().l2.txpool_status().await?.pending.as_u64();
+        if pending == 0 {
+            return Ok(heartbeat && elapsed >= max_interval);
+        }
+        if target_gas == 0 || elapsed >= max_interval {
+            return Ok(true);
+        }
+
+        // the gas limit of the pending transactions is an upper bound of the gas used
+        let content = self.ro().l2.txpool_content().await?;
+        let pending_gas = content
+            .pending
+            .values()
+            .flat_map(|txs| txs.values())
+            .fold(U256::zero(), |acc, tx| acc + tx.gas);
+        Ok(pending_gas >= U256::from(target_gas))
+    }
+
+    /// Makes sure that the canonical chain of the L2 node extends `finalized_block_hash` and
+    /// `safe_block_hash` and updates `head_block_hash` to the head of the node.
+    /// Otherwise, e.g. after the node lost blocks in a restart, the missing blocks submitted on L1
+    /// are imported again and the head is reset to the previous head if it extends
+    /// `safe_block_hash`, or to `safe_block_hash` itself.
+    pub async fn check_l2_head(&self) -> Result<(), Error> {
+        let latest = self.ro().l2.latest_header().await?;
+        let chain_state = self.rw.lock().await.chain_state;
+        let safe_hash = chain_state.safe_block_hash;
+        if self.is_canonical(chain_state.finalized_block_hash).await?
+            && self.is_canonical(safe_hash).await?
+        {
+            self.rw.lock().await.chain_state.head_block_hash = latest.hash;
+            return Ok(());
+        }
+
+        log::warn!(
+            "L2 head {:?} does not extend the safe block {:?}, recovering",
+            latest.hash,
+            safe_hash
+        );
+        self.import_submitted_blocks().await?;
+
+        let prev_head = chain_state.head_block_hash;
+        let head = match self.extends(prev_head, safe_hash).await {
+            true => prev_head,
+            false => safe_hash,
+        };
+        self.set_chain_head(head).await?;
+        log::info!("L2 head reset to {:?}", head);
+
+        Ok(())
+    }
+
+    /// `true` if the L2 node knows `block_hash` and it is part of the canonical chain.
+    async fn is_canonical(&self, block_hash: H256) -> Result<bool, Error> {
+        let header = match self.ro().l2.get_header(block_hash).await {
+            Ok(header) => header,
+            // unknown block
+            Err(_) => return Ok(false),
+        };
+        let canonical = self.ro().l2.get_block(header.number).await;
+
+        Ok(canonical.map_or(false, |block| block.hash == Some(block_hash)))
+    }
+
+    /// `true` if the L2 node knows `block_hash` and `ancestor` is one of its ancestors,
+    /// or `block_hash` itself.
+    async fn extends(&self, block_hash: H256, ancestor: H256) -> bool {
+        let ro = self.ro();
+        let ancestor = match ro.l2.get_header(ancestor).await {
+            Ok(header) => header,
+            Err(_) => return false,
+        };
+        let mut hash = block_hash;
+        loop {
+            let header = match ro.l2.get_header(hash).await {
+                Ok(header) => header,
+                Err(_) => return false,
+            };
+            if header.number <= ancestor.number {
+                return header.hash == ancestor.hash;
             }
+            hash = header.parent_hash;
         }
+    }
 
-        // check if we can mine a block
-        let resp: TxpoolStatus = self.request_l2("txpool_status", ()).await.unwrap();
-        let pending_txs = resp.pending.as_u64();
+    /// Imports the blocks submitted on L1 that are unknown to the L2 node,
+    /// with the transactions and block attributes of the submission.
+    async fn import_submitted_blocks(&self) -> Result<(), Error> {
+        let ro = self.ro();
+        let chain_id = ro.l2.wallet().chain_id();
+        let submitted = self.rw.lock().await.submitted_blocks.clone();
+        for (block_hash, witness) in submitted {
+            if ro.l2.get_header(block_hash).await.is_ok() {
+                continue;
+            }
+
+            let block = decode_verifier_witness(&witness, chain_id)?;
+            let imported = ro
+                .l2
+                .seal_block(
+                    block.parent_hash,
+                    &block.attributes,
+                    Some(&block.transactions),
+                )
+                .await
+                .map_err(|e| format!("import {block_hash:?}: {e}"))?;
+            if imported.hash != Some(block_hash) {
+                return Err(format!(
+                    "import {:?}: got block {:?} instead",
+                    block_hash, imported.hash
+                )
+                .into());
+            }
+            log::info!("imported submitted block {} {:?}", block.number, block_hash);
+        }
+
+        Ok(())
+    }
+
+    /// Mines the queued forced transactions into a block of their own, in the order of
+    /// submission on L1. If the L2 node rejects the block, each transaction is tried on its own.
+    /// Transactions that can't be included stay queued and raise an alert once their deadline
+    /// is within `forced_inclusion_alert_margin`, they are dropped after the deadline.
+    pub async fn include_forced_transactions(&self) {
+        const LOG_TAG: &str = "L2:forcedTransaction:";
+
+        let queue: Vec<ForcedTransaction> = self
+            .rw
+            .lock()
+            .await
+            .forced_transactions
+            .iter()
+            .cloned()
+            .collect();
+        if queue.is_empty() {
+            return;
+        }
+
+        let mut included = Vec::new();
+        let mut pending = Vec::new();
+        for forced_tx in queue {
+            // may be included already, e.g. if it was also sent to the transaction pool
+            match self.ro().l2.transaction_receipt(forced_tx.tx_hash).await {
+                Ok(Some(_)) => included.push(forced_tx.tx_hash),
+                Ok(None) => pending.push(forced_tx),
+                Err(err) => {
+                    log::warn!("{} {:?} receipt: {}", LOG_TAG, forced_tx.tx_hash, err);
+                    pending.push(forced_tx);
+                }
+            }
+        }
 
-        if pending_txs != 0 {
-            self.mine_block(None).await.expect("mine_block regular");
+        if !pending.is_empty() {
+            let txs: Vec<Bytes> = pending.iter().map(|e| e.raw_tx.clone()).collect();
+            match self.mine_block(Some(&txs)).await {
+                Ok(block) => {
+                    log::info!("{} {} in {:?}", LOG_TAG, txs.len(), block.hash);
+                    included.extend(pending.iter().map(|e| e.tx_hash));
+                }
+                Err(err) => {
+                    log::warn!("{} batch of {}: {}", LOG_TAG, txs.len(), err);
+                    for forced_tx in pending.iter() {
+                        let txs = vec![forced_tx.raw_tx.clone()];
+                        match self.mine_block(Some(&txs)).await {
+                            Ok(block) => {
+                                log::info!(
+                                    "{} {:?} in {:?}",
+                                    LOG_TAG,
+                                    forced_tx.tx_hash,
+                                    block.hash
+                                );
+                                included.push(forced_tx.tx_hash);
+                            }
+                            Err(err) => {
+                                log::warn!("{} {:?}: {}", LOG_TAG, forced_tx.tx_hash, err)
+                            }
+                        }
+                    }
+                }
+            }
         }
+
+        let margin = self.config.lock().await.forced_inclusion_alert_margin;
+        let now = U256::from(timestamp());
+        let mut rw = self.rw.lock().await;
+        rw.forced_transactions
+            .retain(|e| !included.contains(&e.tx_hash));
+        rw.forced_transactions.retain_mut(|forced_tx| {
+            if now >= forced_tx.deadline {
+                log::error!(
+                    "ALERT {} {:?} missed its inclusion deadline {}, dropping it",
+                    LOG_TAG,
+                    forced_tx.tx_hash,
+                    forced_tx.deadline
+                );
+                return false;
+            }
+            if now + margin >= forced_tx.deadline {
+                forced_tx.at_risk = true;
+                log::error!(
+                    "ALERT {} {:?} not included, deadline {} in {}s",
+                    LOG_TAG,
+                    forced_tx.tx_hash,
+                    forced_tx.deadline,
+                    forced_tx.deadline - now
+                );
+            }
+            true
+        });
     }
 
-    pub async fn submit_blocks(&self) {
+    /// Submits the blocks between `safe_block_hash` and `head_block_hash` to the L1 bridge
+    /// in ascending order, stops at the first error.
+    pub async fn submit_blocks(&self) -> Result<(), Error> {
         // block submission
         let safe_hash = self.rw.lock().await.chain_state.safe_block_hash;
         let head_hash = self.rw.lock().await.chain_state.head_block_hash;
         if safe_hash != head_hash {
             // find all the blocks since `safe_hash`
-            let blocks = get_blocks_between(
-                &self.ro.http_client,
-                &self.config.lock().await.l2_rpc_url,
-                &safe_hash,
-                &head_hash,
-            )
-            .await;
-            let l1_bridge_addr = Some(self.config.lock().await.l1_bridge);
+            let blocks = self.blocks_between(&safe_hash, &head_hash).await?;
+            let l1_bridge_addr = self.config.lock().await.l1_bridge;
 
             log::trace!("blocks to be submitted: {:?}", blocks.len());
             for block in blocks.iter().rev() {
                 log::info!("submit_block: {}", format_block(block));
-                {
-                    let witness = self
-                        .request_witness(&block.number.unwrap())
-                        .await
-                        .expect("witness");
-                    let block_data = witness.input;
-                    let calldata = self
-                        .ro
-                        .bridge_abi
-                        .function("submitBlock")
-                        .unwrap()
-                        .encode_input(&[block_data.into_token()])
-                        .expect("calldata");
-
-                    self.transaction_to_l1(l1_bridge_addr, U256::zero(), calldata)
-                        .await
-                        .expect("receipt");
+                let witness = self.request_witness(&block.number.unwrap()).await?;
+                self.ro()
+                    .l1
+                    .submit_block(l1_bridge_addr, witness.input)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Requests proofs for all blocks between `finalized_block_hash` and `head_block_hash`
+    /// in ascending order, keeping up to `max_pending_proofs` requests in flight.
+    /// The status and result of each request is tracked in `prover_requests`.
+    /// Requests that failed are sent again with `retry` set.
+    /// Returns the first error encountered, if any.
+    pub async fn request_proofs(&self) -> Result<(), Error> {
+        const LOG_TAG: &str = "request_proofs:";
+
+        let final_hash = self.rw.lock().await.chain_state.finalized_block_hash;
+        let head_hash = self.rw.lock().await.chain_state.head_block_hash;
+        let blocks = match final_hash == head_hash {
+            true => Vec::new(),
+            false => self.blocks_between(&final_hash, &head_hash).await?,
+        };
+
+        {
+            // forget about requests for finalized or orphaned blocks
+            let mut rw = self.rw.lock().await;
+            rw.prover_requests.retain(|block_num, req| {
+                blocks
+                    .iter()
+                    .any(|e| e.number.unwrap() == *block_num && e.hash.unwrap() == req.block_hash)
+            });
+        }
+
+        // the message deadlines of the blocks without a request yet
+        let missing: Vec<U64> = {
+            let rw = self.rw.lock().await;
+            blocks
+                .iter()
+                .map(|block| block.number.unwrap())
+                .filter(|block_num| !rw.prover_requests.contains_key(block_num))
+                .collect()
+        };
+        let fetched = match (missing.iter().min(), missing.iter().max()) {
+            (Some(from), Some(to)) => self.l2_message_deadlines(*from, *to).await?,
+            _ => HashMap::new(),
+        };
+        // finalization is in order, a block has to be finalized by the earliest deadline
+        // of the messages in it and in all later blocks
+        let mut deadlines: HashMap<U64, (Option<u64>, Option<u64>)> = HashMap::new();
+        {
+            let rw = self.rw.lock().await;
+            let mut deadline = None;
+            // newest first
+            for block in blocks.iter() {
+                let block_num = block.number.unwrap();
+                let message_deadline = match rw.prover_requests.get(&block_num) {
+                    Some(req) => req.message_deadline,
+                    None => fetched.get(&block.hash.unwrap()).copied(),
+                };
+                deadline = min_deadline(deadline, message_deadline);
+                deadlines.insert(block_num, (message_deadline, deadline));
+            }
+        }
+
+        let max_pending_proofs = self.config.lock().await.max_pending_proofs;
+        let mut first_err = None;
+        for block in blocks.iter().rev() {
+            let block_num = block.number.unwrap();
+            let block_hash = block.hash.unwrap();
+            let (message_deadline, deadline) = deadlines[&block_num];
+            let (started, retry, assigned) = {
+                let rw = self.rw.lock().await;
+                match rw.prover_requests.get(&block_num) {
+                    Some(req) => match req.status {
+                        ProofRequestStatus::Completed => continue,
+                        ProofRequestStatus::Pending => (req.started, false, req.prover.clone()),
+                        ProofRequestStatus::Failed => (req.started, true, req.prover.clone()),
+                    },
+                    None => {
+                        if rw.pending_proofs >= max_pending_proofs {
+                            // look-ahead window is full
+                            break;
+                        }
+                        (timestamp(), false, None)
+                    }
+                }
+            };
+
+            log::trace!("{} block={} retry={}", LOG_TAG, block_num, retry);
+            let dummy_prover = self.config.lock().await.dummy_prover;
+            let (prover, resp) = match dummy_prover {
+                true => (None, self.dummy_proof(&block_num).await.map(Some)),
+                false => {
+                    let proof_options = self
+                        .proof_request_options(&block_num, retry, deadline)
+                        .await;
+                    let prover = match assigned {
+                        // keep pending requests on the same prover as long as it is available
+                        Some(uri) if !retry && self.is_prover_available(&uri).await => Ok(uri),
+                        // failed requests are routed to another prover if possible
+                        prev => {
+                            let uri = self.choose_prover(&proof_options, prev.as_ref()).await;
+                            if let (Some(prev), Ok(uri)) = (&prev, &uri) {
+                                if prev != uri {
+                                    log::info!(
+                                        "{} re-routing block={} from {} to {}",
+                                        LOG_TAG,
+                                        block_num,
+                                        prev,
+                                        uri
+                                    );
+                                }
+                            }
+                            uri
+                        }
+                    };
+                    match prover {
+                        Ok(uri) => (
+                            Some(uri.clone()),
+                            self.request_proof_from(&uri, &proof_options).await,
+                        ),
+                        Err(err) => (None, Err(err.into())),
+                    }
+                }
+            };
+            let mut verification_failures = self
+                .rw
+                .lock()
+                .await
+                .prover_requests
+                .get(&block_num)
+                .map_or(0, |e| e.verification_failures);
+            let resp = match resp {
+                Ok(Some(proofs)) => match self.verify_proofs(&proofs).await {
+                    Ok(()) => Ok(Some(proofs)),
+                    Err(err) => {
+                        verification_failures += 1;
+                        log::error!(
+                            "{} verification failed for block={} prover={:?}: {}",
+                            LOG_TAG,
+                            block_num,
+                            prover,
+                            err
+                        );
+                        // otherwise the prover keeps returning the same proofs
+                        if let Some(prover) = &prover {
+                            self.invalidate_proof(prover, &block_num).await;
+                        }
+                        Err(format!("proof verification failed: {err}").into())
+                    }
+                },
+                other => other,
+            };
+            let (status, result) = match resp {
+                Ok(None) => (ProofRequestStatus::Pending, None),
+                Ok(Some(proofs)) => {
+                    log::info!(
+                        "{} proof for {} completed after {}s",
+                        LOG_TAG,
+                        block_num,
+                        timestamp().saturating_sub(started)
+                    );
+                    (ProofRequestStatus::Completed, Some(Ok(proofs)))
+                }
+                Err(err) => {
+                    log::error!("{} block={} {}", LOG_TAG, block_num, err);
+                    let result = Some(Err(err.to_string()));
+                    first_err.get_or_insert(err);
+                    (ProofRequestStatus::Failed, result)
+                }
+            };
+
+            let mut rw = self.rw.lock().await;
+            rw.prover_requests.insert(
+                block_num,
+                ProverRequest {
+                    block_hash,
+                    status,
+                    prover,
+                    started,
+                    verification_failures,
+                    result,
+                    message_deadline,
+                },
+            );
+            rw.pending_proofs = rw
+                .prover_requests
+                .values()
+                .filter(|e| e.status != ProofRequestStatus::Completed)
+                .count() as u32;
+        }
+
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Submits `finalizeBlock` transactions for all blocks between `finalized_block_hash` and
+    /// `safe_block_hash` in ascending order, as long as proofs are available in
+    /// `prover_requests`.
+    pub async fn finalize_blocks(&self) -> Result<(), Error> {
+        // block finalization
+        let safe_hash = self.rw.lock().await.chain_state.safe_block_hash;
+        let final_hash = self.rw.lock().await.chain_state.finalized_block_hash;
+        if final_hash != safe_hash {
+            let blocks = self.blocks_between(&final_hash, &safe_hash).await?;
+
+            log::trace!("blocks for finalization: {:?}", blocks.len());
+            for block in blocks.iter().rev() {
+                let block_num = block.number.unwrap();
+                let proofs = match self.rw.lock().await.prover_requests.get(&block_num) {
+                    Some(ProverRequest {
+                        block_hash,
+                        result: Some(Ok(proofs)),
+                        ..
+                    }) if *block_hash == block.hash.unwrap() => Some(proofs.clone()),
+                    _ => None,
+                };
+
+                match proofs {
+                    Some(proofs) => self.finalize_block(block, proofs).await?,
+                    None => {
+                        // blocks have to be finalized in order
+                        log::trace!("proof not yet computed for: {}", block_num);
+                        break;
+                    }
                 }
             }
         }
+
+        Ok(())
+    }
+
+    /// Verifies `proofs` according to `pre_verify_proof` before they are used for
+    /// `finalizeBlock`. Succeeds if verification is disabled.
+    pub async fn verify_proofs(&self, proofs: &Proofs) -> Result<(), Error> {
+        let mode = match self.config.lock().await.pre_verify_proof.clone() {
+            Some(mode) => mode,
+            None => return Ok(()),
+        };
+        let (_, proof_result) = select_proof(proofs);
+        let verifier_addr = self.verifier_address(proofs).await?;
+        let calldata = Bytes::from(encode_verifier_calldata(proof_result));
+
+        let resp = match mode {
+            ProofVerification::Call => self.ro().l1.call(verifier_addr, calldata, None).await,
+            ProofVerification::VerifierCode(path) => {
+                let code = verifier_code(&path, &proof_result.label, verifier_addr)?;
+                let overrides =
+                    serde_json::json!({ (format!("{verifier_addr:?}")): { "code": code } });
+
+                self.ro()
+                    .l1
+                    .call(verifier_addr, calldata, Some(overrides))
+                    .await
+            }
+        };
+
+        resp.map(|_| ())
     }
 
-    pub async fn finalize_blocks(&self) -> Result<(), String> {
-        // block finalization
-        let safe_hash = self.rw.lock().await.chain_state.safe_block_hash;
-        let final_hash = self.rw.lock().await.chain_state.finalized_block_hash;
-        if final_hash != safe_hash {
-            let blocks = get_blocks_between(
-                &self.ro.http_client,
-                &self.config.lock().await.l2_rpc_url,
-                &final_hash,
-                &safe_hash,
-            )
-            .await;
+    /// Returns the verifier contract for `proofs` from the verifier registry.
+    /// Fails if there is no verifier registered for the circuit label and configuration.
+    pub async fn verifier_address(&self, proofs: &Proofs) -> Result<Address, Error> {
+        let (_, proof_result) = select_proof(proofs);
+        let label = &proof_result.label;
+        let registry_addr = match self.ro().verifier_registry {
+            VerifierRegistry::Contract(addr) => addr,
+            ref registry => return Ok(registry.lookup(label, &proofs.config)?),
+        };
 
-            log::trace!("blocks for finalization: {:?}", blocks.len());
-            for block in blocks.iter().rev() {
-                self.finalize_block(block).await?;
-            }
+        let verifier_addr = self
+            .ro()
+            .l1
+            .get_verifier(registry_addr, label, config_digest(&proofs.config))
+            .await?;
+
+        if verifier_addr.is_zero() {
+            return Err(format!("no verifier registered for {label} in {registry_addr:?}").into());
         }
 
-        Ok(())
+        Ok(verifier_addr)
     }
 
-    pub async fn finalize_block(&self, block: &Block<H256>) -> Result<(), String> {
+    /// Submits a `finalizeBlock` transaction for `block` with `proof` to the L1 bridge.
+    pub async fn finalize_block(&self, block: &Block<H256>, proof: Proofs) -> Result<(), Error> {
         const LOG_TAG: &str = "L1:finalize_block:";
         log::trace!("{} {}", LOG_TAG, format_block(block));
 
         let block_num = block.number.unwrap();
-        let proofs: Result<Option<Proofs>, String> = self.request_proof(&block_num).await;
+        log::info!("{} found proof: {:#?} for {}", LOG_TAG, proof, block_num);
 
-        if let Err(err) = proofs {
-            log::error!("{}:{} {:?}", LOG_TAG, block_num, err);
-
-            return Err(err);
-        }
-
-        match proofs.unwrap() {
-            None => log::trace!("{} proof not yet computed for: {}", LOG_TAG, block_num),
-            Some(proof) => {
-                log::info!("{} found proof: {:#?} for {}", LOG_TAG, proof, block_num);
-
-                // choose the aggregation proof if not empty
-                let (is_aggregated, proof_result) = {
-                    if proof.aggregation.proof.len() != 0 {
-                        (true, proof.aggregation)
-                    } else {
-                        (false, proof.circuit)
-                    }
-                };
+        // choose the aggregation proof if not empty
+        let (is_aggregated, proof_result) = select_proof(&proof);
+        let verifier_calldata = encode_verifier_calldata(proof_result);
+        let verifier_addr = self.verifier_address(&proof).await?;
 
-                let mut verifier_calldata = vec![];
-                let mut tmp_buf = vec![0u8; 32];
+        let mut proof_data = vec![];
+        let mut tmp_buf = vec![0u8; 32];
+        proof_data.extend_from_slice(block.hash.unwrap().as_ref());
+        proof_data.extend_from_slice(H256::from(verifier_addr).as_ref());
 
-                proof_result.instance.iter().for_each(|v| {
-                    v.to_big_endian(&mut tmp_buf);
-                    verifier_calldata.extend_from_slice(&tmp_buf);
-                });
-                verifier_calldata.extend_from_slice(proof_result.proof.as_ref());
+        let is_aggregated = match is_aggregated {
+            true => U256::one(),
+            false => U256::zero(),
+        };
+        is_aggregated.to_big_endian(&mut tmp_buf);
+        proof_data.extend_from_slice(&tmp_buf);
 
-                let mut proof_data = vec![];
-                proof_data.extend_from_slice(block.hash.unwrap().as_ref());
+        proof_data.extend_from_slice(&verifier_calldata);
 
-                // this is temporary until proper contract setup
-                let verifier_addr = U256::from(proof_result.label.as_bytes());
-                verifier_addr.to_big_endian(&mut tmp_buf);
-                proof_data.extend_from_slice(&tmp_buf);
+        let proof_data = Bytes::from(proof_data);
+        log::debug!("proof_data: {}", proof_data);
 
-                let is_aggregated = match is_aggregated {
-                    true => U256::one(),
-                    false => U256::zero(),
-                };
-                is_aggregated.to_big_endian(&mut tmp_buf);
-                proof_data.extend_from_slice(&tmp_buf);
-
-                proof_data.extend_from_slice(&verifier_calldata);
-
-                let proof_data = Bytes::from(proof_data);
-                log::debug!("proof_data: {}", proof_data);
-                let calldata = self
-                    .ro
-                    .bridge_abi
-                    .function("finalizeBlock")
-                    .unwrap()
-                    .encode_input(&[proof_data.into_token()])
-                    .expect("calldata");
-
-                let l1_bridge_addr = Some(self.config.lock().await.l1_bridge);
-                self.transaction_to_l1(l1_bridge_addr, U256::zero(), calldata)
-                    .await
-                    .expect("receipt");
-            }
-        }
+        let l1_bridge_addr = self.config.lock().await.l1_bridge;
+        self.ro()
+            .l1
+            .finalize_block(l1_bridge_addr, proof_data)
+            .await?;
 
         Ok(())
     }
@@ -680,16 +1309,8 @@ impl SharedState {
         to: Option<Address>,
         value: U256,
         calldata: Vec<u8>,
-    ) -> Result<TransactionReceipt, String> {
-        send_transaction_to_l1(
-            &self.ro.http_client,
-            &self.config.lock().await.l1_rpc_url,
-            &self.ro.l1_wallet,
-            to,
-            value,
-            calldata,
-        )
-        .await
+    ) -> Result<TransactionReceipt, Error> {
+        self.ro().l1.send_transaction(to, value, calldata).await
     }
 
     pub async fn transaction_to_l2(
@@ -698,31 +1319,23 @@ impl SharedState {
         value: U256,
         calldata: Vec<u8>,
         gas_limit: Option<U256>,
-    ) -> Result<H256, String> {
-        send_transaction_to_l2(
-            &self.ro.http_client,
-            &self.config.lock().await.l2_rpc_url,
-            &self.ro.l2_wallet,
-            to,
-            value,
-            calldata,
-            gas_limit,
-        )
-        .await
+    ) -> Result<H256, Error> {
+        self.ro()
+            .l2
+            .send_transaction(to, value, calldata, gas_limit)
+            .await
     }
 
     /// Estimates gas against "latest" block and returns a raw signed transaction.
-    /// Throws on error.
     pub async fn sign_l2(
         &self,
         to: Option<Address>,
         value: U256,
         nonce: U256,
         calldata: Vec<u8>,
-    ) -> Bytes {
+    ) -> Result<Bytes, Error> {
         self.sign_l2_given_block_tag(to, value, nonce, calldata, None)
             .await
-            .expect("sign_l2")
     }
 
     /// Estimates gas against `option_block` or "latest" block and returns a raw signed
@@ -734,10 +1347,11 @@ impl SharedState {
         nonce: U256,
         calldata: Vec<u8>,
         option_block: Option<String>,
-    ) -> Result<Bytes, String> {
-        let wallet = &self.ro.l2_wallet;
+    ) -> Result<Bytes, Error> {
+        let ro = self.ro();
+        let wallet = ro.l2.wallet();
         let wallet_addr: Address = wallet.address();
-        let gas_price: U256 = self.request_l2("eth_gasPrice", ()).await?;
+        let gas_price = ro.l2.gas_price().await?;
         let mut tx = TransactionRequest::new()
             .chain_id(wallet.chain_id())
             .from(wallet_addr)
@@ -749,7 +1363,7 @@ impl SharedState {
             tx = tx.to(to);
         };
         let block_tag = option_block.unwrap_or_else(|| "latest".into());
-        let estimate: U256 = self.request_l2("eth_estimateGas", (&tx, block_tag)).await?;
+        let estimate = ro.l2.estimate_gas(&tx, &block_tag).await?;
         let tx = tx.gas(estimate).into();
         let sig = wallet
             .sign_transaction(&tx)
@@ -759,49 +1373,65 @@ impl SharedState {
         Ok(tx.rlp_signed(&sig))
     }
 
+    /// Sends a json-rpc request to the L1 node, for methods that are not covered by `L1Client`.
     pub async fn request_l1<T: Serialize + Send + Sync, R: DeserializeOwned>(
         &self,
         method: &str,
         args: T,
-    ) -> Result<R, String> {
-        jsonrpc_request_client(
-            RPC_REQUEST_TIMEOUT,
-            &self.ro.http_client,
-            &self.config.lock().await.l1_rpc_url,
-            method,
-            args,
-        )
-        .await
+    ) -> Result<R, Error> {
+        let params = serde_json::to_value(args).map_err(|e| e.to_string())?;
+        let result = self.ro().l1.request(method, params).await?;
+
+        serde_json::from_value(result).map_err(|e| Error::Rpc(RpcError::Decode(e.to_string())))
     }
 
+    /// Sends a json-rpc request to the L2 node, for methods that are not covered by `L2Engine`.
     pub async fn request_l2<T: Serialize + Send + Sync, R: DeserializeOwned>(
         &self,
         method: &str,
         args: T,
-    ) -> Result<R, String> {
-        jsonrpc_request_client(
-            RPC_REQUEST_TIMEOUT,
-            &self.ro.http_client,
-            &self.config.lock().await.l2_rpc_url,
-            method,
-            args,
-        )
-        .await
-    }
-
-    /// Returns a timestamp that takes care of being greater than the previous one.
-    /// This can potentially lead to a timestamp too far into the future
-    /// if used too fast.
-    async fn next_timestamp(&self) -> u64 {
-        let mut ts = timestamp();
-        let mut rw = self.rw.lock().await;
+    ) -> Result<R, Error> {
+        let params = serde_json::to_value(args).map_err(|e| e.to_string())?;
+        let result = self.ro().l2.request(method, params).await?;
+
+        serde_json::from_value(result).map_err(|e| Error::Rpc(RpcError::Decode(e.to_string())))
+    }
 
-        if ts <= rw._prev_timestamp {
-            ts = rw._prev_timestamp + 1;
+    /// Returns the attributes for a block on top of the chain head.
+    /// The timestamp is taken from the local clock or the latest L1 block if
+    /// `l1_anchored_timestamps` is set, and is at least one second after the chain head.
+    /// Returns `Err` if the timestamp would be more than `max_timestamp_drift` seconds ahead
+    /// of the local clock, e.g. if blocks are sealed too fast.
+    async fn next_block_attributes(&self) -> Result<BlockAttributes, Error> {
+        let (l1_anchored, max_drift, random, coinbase) = {
+            let config = self.config.lock().await;
+            (
+                config.l1_anchored_timestamps,
+                config.max_timestamp_drift,
+                config.l2_block_random,
+                config.l2_coinbase,
+            )
+        };
+        let now = timestamp();
+        let base = match l1_anchored {
+            true => self.ro().l1.latest_header().await?.timestamp.as_u64(),
+            false => now,
+        };
+        let head_hash = self.rw.lock().await.chain_state.head_block_hash;
+        let head = self.ro().l2.get_header(head_hash).await?;
+        let ts = cmp::max(base, head.timestamp.as_u64() + 1);
+        if ts > now + max_drift {
+            return Err(format!(
+                "block timestamp {ts} is more than {max_drift}s ahead of the local clock"
+            )
+            .into());
         }
-        rw._prev_timestamp = ts;
 
-        ts
+        Ok(BlockAttributes {
+            timestamp: ts,
+            random,
+            coinbase,
+        })
     }
 
     /// Creates a new block with `transactions` on `l2_node`.
@@ -810,20 +1440,15 @@ impl SharedState {
     /// transactions are not considered to be errors.
     async fn prepare_block(
         &self,
-        timestamp: u64,
+        attributes: &BlockAttributes,
         transactions: Option<&Vec<Bytes>>,
-    ) -> Result<Block<Transaction>, String> {
+    ) -> Result<Block<Transaction>, Error> {
         // request new block
-        let prepared_block: Block<Transaction> = self
-            .request_l2(
-                "miner_sealBlock",
-                [SealBlockRequest {
-                    parent: &self.rw.lock().await.chain_state.head_block_hash,
-                    random: &H256::zero(),
-                    timestamp: &timestamp.into(),
-                    transactions,
-                }],
-            )
+        let parent = self.rw.lock().await.chain_state.head_block_hash;
+        let prepared_block = self
+            .ro()
+            .l2
+            .seal_block(parent, attributes, transactions)
             .await?;
         log::info!(
             "submitted block assembly request to l2 node - txs: {}",
@@ -834,11 +1459,11 @@ impl SharedState {
     }
 
     /// Set canonical chain head on `l2_node` and update `chain_state`.
-    pub async fn set_chain_head(&self, block_hash: H256) -> Result<(), String> {
-        let res: bool = self.request_l2("miner_setHead", [block_hash]).await?;
+    pub async fn set_chain_head(&self, block_hash: H256) -> Result<(), Error> {
+        let res = self.ro().l2.set_head(block_hash).await?;
 
         if !res {
-            return Err(format!("unable to set chain head to {block_hash:?}"));
+            return Err(format!("unable to set chain head to {block_hash:?}").into());
         }
 
         self.rw.lock().await.chain_state.head_block_hash = block_hash;
@@ -850,27 +1475,71 @@ impl SharedState {
     pub async fn mine_block(
         &self,
         transactions: Option<&Vec<Bytes>>,
-    ) -> Result<Block<Transaction>, String> {
-        let block = self
-            .prepare_block(self.next_timestamp().await, transactions)
-            .await?;
+    ) -> Result<Block<Transaction>, Error> {
+        let attributes = self.next_block_attributes().await?;
+        let block = self.prepare_block(&attributes, transactions).await?;
 
         self.set_chain_head(block.hash.unwrap()).await?;
         Ok(block)
     }
 
+    /// Returns the blocks after `from` up to and including `to`, in descending order.
+    /// The blocks are fetched by number in batches of `BLOCK_BATCH_SIZE`, blocks that are not
+    /// canonical are fetched by the parent hash of their child.
+    /// Fails if `to` does not descend from `from`, e.g. after a reorg.
+    pub async fn blocks_between(&self, from: &H256, to: &H256) -> Result<Vec<Block<H256>>, Error> {
+        if from == to {
+            return Ok(Vec::new());
+        }
+        let from_number = self.l2_header(*from).await?.number.as_u64();
+        let mut number = self.l2_header(*to).await?.number.as_u64();
+
+        let mut ret: Vec<Block<H256>> = Vec::new();
+        let mut hash = *to;
+        while hash != *from {
+            if number <= from_number {
+                return Err(format!("block {to:?} does not descend from {from:?}").into());
+            }
+            let start = cmp::max(
+                from_number + 1,
+                (number + 1).saturating_sub(BLOCK_BATCH_SIZE),
+            );
+            let numbers: Vec<U64> = (start..=number).rev().map(U64::from).collect();
+            for block in self.ro().l2.get_blocks(&numbers).await? {
+                let block = match block {
+                    Some(block) if block.hash == Some(hash) => block,
+                    // not canonical, follow the parent hash
+                    _ => self.ro().l2.get_block_by_hash(hash).await?,
+                };
+                hash = block.parent_hash;
+                ret.push(block);
+            }
+            number = start - 1;
+        }
+
+        Ok(ret)
+    }
+
+    /// Returns the L2 header of `block_hash` from `l2_headers` or the L2 node.
+    async fn l2_header(&self, block_hash: H256) -> Result<BlockHeader, Error> {
+        if let Some(header) = self.rw.lock().await.l2_headers.get(&block_hash) {
+            return Ok(header.clone());
+        }
+        let header = self.ro().l2.get_header(block_hash).await?;
+        self.rw.lock().await.l2_headers.insert(header.clone());
+
+        Ok(header)
+    }
+
     /// keeps track of l2 bridge message events
-    async fn sync_l2(&self) {
+    async fn sync_l2(&self) -> Result<(), Error> {
         // TODO: DRY syncing mechanics w/ l1
-        let latest_block: U64 = self
-            .request_l2("eth_blockNumber", ())
-            .await
-            .expect("eth_blockNumber");
+        let latest_block = self.ro().l2.block_number().await?;
         let mut last_to_block: U64 = U64::zero();
         let mut from: U64 = self.rw.lock().await.l2_last_sync_block + 1;
         let mut filter = Filter::new()
-            .address(ValueOrArray::Value(self.ro.l2_message_deliverer_addr))
-            .topic0(ValueOrArray::Value(self.ro.message_delivered_topic));
+            .address(ValueOrArray::Value(self.ro().l2_message_deliverer_addr))
+            .topic0(ValueOrArray::Value(self.ro().message_delivered_topic));
         let mut executed_msgs = vec![];
 
         while from <= latest_block {
@@ -879,10 +1548,7 @@ impl SharedState {
             log::trace!("fetching logs from={} to={}", from, to);
             filter = filter.from_block(from).to_block(to);
 
-            let logs: Vec<Log> = self
-                .request_l2("eth_getLogs", [&filter])
-                .await
-                .expect("eth_getLogs");
+            let logs = self.ro().l2.get_logs(&filter).await?;
             // TODO: ugly hack to fix geth inconstency issues
             if !logs.is_empty() {
                 last_to_block = to;
@@ -901,160 +1567,340 @@ impl SharedState {
             rw.l2_last_sync_block = last_to_block;
             rw.l2_delivered_messages.extend_from_slice(&executed_msgs);
         }
+
+        Ok(())
     }
 
-    /// keeps track of L2 > L1 message events
-    async fn record_l2_messages(&self, block_hash: H256) {
+    /// The L2 > L1 messages dispatched in the L2 block `block_hash`.
+    async fn l2_messages(&self, block_hash: H256) -> Result<Vec<MessageBeacon>, Error> {
         let filter = Filter::new()
-            .address(ValueOrArray::Value(self.ro.l2_message_dispatcher_addr))
-            .topic0(ValueOrArray::Value(self.ro.message_dispatched_topic))
+            .address(ValueOrArray::Value(self.ro().l2_message_dispatcher_addr))
+            .topic0(ValueOrArray::Value(self.ro().message_dispatched_topic))
             .at_block_hash(block_hash);
-        let logs: Vec<Log> = self
-            .request_l2("eth_getLogs", [&filter])
-            .await
-            .expect("eth_getLogs");
+        let logs = self.ro().l2.get_logs(&filter).await?;
+
+        Ok(logs
+            .into_iter()
+            .map(|log| self._parse_message_beacon(log))
+            .collect())
+    }
+
+    /// The earliest deadline of the L2 > L1 messages dispatched in each of the L2 blocks
+    /// `from..=to` by block hash, blocks without messages are missing.
+    async fn l2_message_deadlines(&self, from: U64, to: U64) -> Result<HashMap<H256, u64>, Error> {
+        let filter = Filter::new()
+            .address(ValueOrArray::Value(self.ro().l2_message_dispatcher_addr))
+            .topic0(ValueOrArray::Value(self.ro().message_dispatched_topic))
+            .from_block(from)
+            .to_block(to);
+        let logs = self.ro().l2.get_logs(&filter).await?;
 
-        log::trace!("L2: {} relay events for {}", logs.len(), block_hash);
-        let mut pending = vec![];
+        let mut deadlines: HashMap<H256, u64> = HashMap::new();
         for log in logs {
-            let beacon = self._parse_message_beacon(log);
+            let block_hash = match log.block_hash {
+                Some(block_hash) => block_hash,
+                None => continue,
+            };
+            let msg = self._parse_message_beacon(log);
+            let deadline = msg.deadline.min(U256::from(u64::MAX)).as_u64();
+            deadlines
+                .entry(block_hash)
+                .and_modify(|e| *e = cmp::min(*e, deadline))
+                .or_insert(deadline);
+        }
+
+        Ok(deadlines)
+    }
+
+    /// keeps track of L2 > L1 message events
+    async fn record_l2_messages(&self, block_hash: H256) -> Result<(), Error> {
+        let pending = self.l2_messages(block_hash).await?;
+
+        log::trace!("L2: {} relay events for {}", pending.len(), block_hash);
+        for beacon in &pending {
             log::info!("L1Relay: {:?}", beacon.id);
             log::debug!("{:?}", beacon);
-            pending.push(beacon);
         }
 
         let mut rw = self.rw.lock().await;
-        rw.l2_message_queue.extend(pending);
+        let rw = &mut *rw;
+        for beacon in pending {
+            // already known if loaded from `state_file`
+            if !rw
+                .l2_message_queue
+                .iter()
+                .chain(rw.l2_dead_letters.iter())
+                .any(|e| e.message.id == beacon.id)
+            {
+                rw.l2_message_queue.push(MessageDelivery::new(beacon));
+            }
+        }
+
+        Ok(())
     }
 
+    /// Relays up to 32 messages of `l2_message_queue` that are due to L1.
+    /// Failed deliveries are retried with exponential backoff and moved to `l2_dead_letters`
+    /// after `message_max_attempts`.
     pub async fn relay_to_l1(&self) {
-        let mut rw = self.rw.lock().await;
-        let len = rw.l2_message_queue.len();
-
-        if len == 0 {
-            return;
-        }
-
-        // TODO: we are going to lose messages if we panic below
-        let todo: Vec<MessageBeacon> = rw.l2_message_queue.drain(0..cmp::min(32, len)).collect();
-        drop(rw);
+        // messages are only removed from the queue once they are handled,
+        // so that they are retried if this task is interrupted
+        let now = timestamp();
+        let todo: Vec<MessageBeacon> = self
+            .rw
+            .lock()
+            .await
+            .l2_message_queue
+            .iter()
+            .filter(|e| e.next_attempt <= now)
+            .take(32)
+            .map(|e| e.message.clone())
+            .collect();
 
         const LOG_TAG: &str = "L1:deliverMessageWithProof:";
-        let l1_bridge_addr = Some(self.config.lock().await.l1_bridge);
+        let l1_bridge_addr = self.config.lock().await.l1_bridge;
+        let (backoff, max_attempts) = self.message_retry_policy().await;
         for msg in todo {
+            if self.is_shutting_down() {
+                log::info!("{} shutdown requested, stopping", LOG_TAG);
+                break;
+            }
+
+            let msg_id = msg.id;
+            let res = self.relay_message_to_l1(l1_bridge_addr, msg).await;
+            let mut rw = self.rw.lock().await;
+            let pos = match rw
+                .l2_message_queue
+                .iter()
+                .position(|e| e.message.id == msg_id)
             {
-                // check deadline
-                let ts_with_padding = U256::from(timestamp() + 900);
-                if msg.deadline < ts_with_padding {
-                    log::info!("{} {:?} deadline exceeded", LOG_TAG, msg.id);
-                    log::debug!("{:?}", msg);
-                    continue;
+                Some(pos) => pos,
+                None => continue,
+            };
+            match res {
+                Ok(()) => {
+                    rw.l2_message_queue.remove(pos);
+                }
+                Err(err) => {
+                    log::warn!("{} {:?} {}", LOG_TAG, msg_id, err);
+                    let delivery = &mut rw.l2_message_queue[pos];
+                    delivery.failed(err.to_string(), timestamp(), backoff);
+                    if delivery.attempts >= max_attempts {
+                        log::error!(
+                            "{} {:?} failed {} times, moved to dead letters",
+                            LOG_TAG,
+                            msg_id,
+                            delivery.attempts
+                        );
+                        let delivery = rw.l2_message_queue.remove(pos);
+                        rw.l2_dead_letters.push(delivery);
+                    }
                 }
             }
+            drop(rw);
+            self.save_state().await;
+        }
+    }
 
-            let found = self
-                .rw
-                .lock()
-                .await
-                .l1_delivered_messages
-                .iter()
-                .any(|&e| e == msg.id);
+    /// Returns `message_retry_backoff` and `message_max_attempts`.
+    async fn message_retry_policy(&self) -> (u64, u32) {
+        let config = self.config.lock().await;
+        (config.message_retry_backoff, config.message_max_attempts)
+    }
 
-            log::trace!("{} skip={} {:?}", LOG_TAG, found, msg.id);
-            log::debug!("{:?}", msg);
-            if found {
-                continue;
+    /// Moves the dead letter with `id` back to its message queue, for another round of
+    /// delivery attempts.
+    pub async fn retry_dead_letter(&self, id: H256) -> Result<(), Error> {
+        {
+            let mut rw = self.rw.lock().await;
+            if let Some(pos) = rw.l1_dead_letters.iter().position(|e| e.message.id == id) {
+                let delivery = rw.l1_dead_letters.remove(pos);
+                rw.l1_message_queue
+                    .push_back(MessageDelivery::new(delivery.message));
+            } else if let Some(pos) = rw.l2_dead_letters.iter().position(|e| e.message.id == id) {
+                let delivery = rw.l2_dead_letters.remove(pos);
+                rw.l2_message_queue
+                    .push(MessageDelivery::new(delivery.message));
+            } else {
+                return Err(format!("no dead letter with id {id:?}").into());
             }
+        }
+        self.save_state().await;
 
-            // latest finalized block hash
-            let block_hash = self.rw.lock().await.chain_state.finalized_block_hash;
-            // calculate the storage slot for this message
-            let storage_slot = msg.storage_slot();
-            // request proof
-            let proof_obj: MerkleProofRequest = self
-                .request_l2(
-                    "eth_getProof",
-                    (
-                        self.ro.l2_message_dispatcher_addr,
-                        [storage_slot],
-                        block_hash,
-                    ),
-                )
-                .await
-                .expect("eth_getProof");
-            let l2_block_header: BlockHeader = self
-                .request_l2("eth_getHeaderByHash", [block_hash])
-                .await
-                .expect("eth_getHeaderByHash");
-            let mut tmp = vec![0u8; 32];
-            let mut bytes = self
-                .ro
-                .bridge_abi
-                .function("multicall")
-                .unwrap()
-                .encode_input(&[])
-                .unwrap();
-            let storage_root = keccak256(proof_obj.storage_proof[0].proof[0].as_ref());
-            let origin_timestamp = self
-                .call_fn_l1("getTimestampForStorageRoot", &[storage_root.into_token()])
-                .await
-                .expect("getTimestampForStorageRoot");
+        Ok(())
+    }
 
-            // block data
-            if origin_timestamp.is_zero() {
-                let block_data: Bytes = self
-                    .request_l2("debug_getHeaderRlp", [l2_block_header.number.as_u64()])
-                    .await
-                    .expect("block_data");
-                let account_proof: Bytes =
-                    Bytes::from(marshal_proof_single(&proof_obj.account_proof));
-                let calldata = self
-                    .ro
-                    .bridge_abi
-                    .function("importForeignBridgeState")
-                    .unwrap()
-                    .encode_input(&[block_data.into_token(), account_proof.into_token()])
-                    .expect("importForeignBridgeState");
-                U256::from(calldata.len()).to_big_endian(&mut tmp);
-                bytes.extend(&tmp[28..32]);
-                bytes.extend(calldata);
-            }
+    /// Restores the message queues and dead letters from `state_file`, if it exists.
+    pub async fn load_state(&self) -> Result<(), Error> {
+        let path = match self.config.lock().await.state_file.clone() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(Error::Io(format!("{}: {}", path.display(), err))),
+        };
+        let queues: MessageQueues = serde_json::from_slice(&data)
+            .map_err(|e| Error::Io(format!("{}: {}", path.display(), e)))?;
+        log::info!(
+            "state_file: loaded {} L1 and {} L2 messages, {} dead letters from {}",
+            queues.l1_message_queue.len(),
+            queues.l2_message_queue.len(),
+            queues.l1_dead_letters.len() + queues.l2_dead_letters.len(),
+            path.display()
+        );
 
-            // relay message
-            {
-                let proof: Bytes =
-                    Bytes::from(marshal_proof_single(&proof_obj.storage_proof[0].proof));
-                let calldata = self
-                    .ro
-                    .bridge_abi
-                    .function("deliverMessageWithProof")
-                    .unwrap()
-                    .encode_input(&[
-                        msg.from.into_token(),
-                        msg.to.into_token(),
-                        msg.value.into_token(),
-                        msg.fee.into_token(),
-                        msg.deadline.into_token(),
-                        msg.nonce.into_token(),
-                        Token::Bytes(msg.calldata),
-                        proof.into_token(),
-                    ])
-                    .expect("calldata");
-                U256::from(calldata.len()).to_big_endian(&mut tmp);
-                bytes.extend(&tmp[28..32]);
-                bytes.extend(calldata);
+        let mut rw = self.rw.lock().await;
+        rw.l1_message_queue = queues.l1_message_queue;
+        rw.l2_message_queue = queues.l2_message_queue;
+        rw.l1_dead_letters = queues.l1_dead_letters;
+        rw.l2_dead_letters = queues.l2_dead_letters;
+
+        Ok(())
+    }
+
+    /// Writes the message queues and dead letters to `state_file`, if set.
+    /// The file is replaced atomically, errors are only logged.
+    pub async fn save_state(&self) {
+        let path = match self.config.lock().await.state_file.clone() {
+            Some(path) => path,
+            None => return,
+        };
+        let data = {
+            let rw = self.rw.lock().await;
+            serde_json::to_vec(&MessageQueues {
+                l1_message_queue: rw.l1_message_queue.clone(),
+                l2_message_queue: rw.l2_message_queue.clone(),
+                l1_dead_letters: rw.l1_dead_letters.clone(),
+                l2_dead_letters: rw.l2_dead_letters.clone(),
+            })
+            .expect("MessageQueues")
+        };
+
+        let tmp = path.with_extension("tmp");
+        if let Err(err) = std::fs::write(&tmp, data).and_then(|_| std::fs::rename(&tmp, &path)) {
+            log::error!("state_file: {}: {}", path.display(), err);
+        }
+    }
+
+    /// Delivers `msg` to L1 unless it is already delivered or its deadline is too close.
+    async fn relay_message_to_l1(
+        &self,
+        l1_bridge_addr: Address,
+        msg: MessageBeacon,
+    ) -> Result<(), Error> {
+        const LOG_TAG: &str = "L1:deliverMessageWithProof:";
+        {
+            // check deadline
+            let ts_with_padding = U256::from(timestamp() + 900);
+            if msg.deadline < ts_with_padding {
+                log::info!("{} {:?} deadline exceeded", LOG_TAG, msg.id);
+                log::debug!("{:?}", msg);
+                return Ok(());
             }
+        }
+
+        let found = self
+            .rw
+            .lock()
+            .await
+            .l1_delivered_messages
+            .iter()
+            .any(|&e| e == msg.id);
+
+        log::trace!("{} skip={} {:?}", LOG_TAG, found, msg.id);
+        log::debug!("{:?}", msg);
+        if found {
+            return Ok(());
+        }
+
+        // latest finalized block hash
+        let block_hash = self.rw.lock().await.chain_state.finalized_block_hash;
+        // calculate the storage slot for this message
+        let storage_slot = msg.storage_slot();
+        // request proof
+        let ro = self.ro();
+        let proof_obj = ro
+            .l2
+            .get_proof(ro.l2_message_dispatcher_addr, &[storage_slot], block_hash)
+            .await
+            .map_err(|e| format!("eth_getProof: {e}"))?;
+        let l2_block_header = ro
+            .l2
+            .get_header(block_hash)
+            .await
+            .map_err(|e| format!("eth_getHeaderByHash: {e}"))?;
+        let mut tmp = vec![0u8; 32];
+        let mut bytes = self
+            .ro()
+            .bridge_abi
+            .function("multicall")
+            .unwrap()
+            .encode_input(&[])
+            .unwrap();
+        let storage_root = keccak256(proof_obj.storage_proof[0].proof[0].as_ref());
+        let origin_timestamp = ro
+            .l1
+            .timestamp_for_storage_root(l1_bridge_addr, storage_root.into())
+            .await
+            .map_err(|e| format!("getTimestampForStorageRoot: {e}"))?;
 
-            // TODO: support relaying multiple messages at once
-            self.transaction_to_l1(l1_bridge_addr, U256::zero(), bytes)
+        // block data
+        if origin_timestamp.is_zero() {
+            let block_data = ro
+                .l2
+                .header_rlp(l2_block_header.number)
                 .await
-                .expect("receipt");
+                .map_err(|e| format!("block_data: {e}"))?;
+            let account_proof: Bytes = Bytes::from(marshal_proof_single(&proof_obj.account_proof));
+            let calldata = self
+                .ro()
+                .bridge_abi
+                .function("importForeignBridgeState")
+                .unwrap()
+                .encode_input(&[block_data.into_token(), account_proof.into_token()])
+                .expect("importForeignBridgeState");
+            U256::from(calldata.len()).to_big_endian(&mut tmp);
+            bytes.extend(&tmp[28..32]);
+            bytes.extend(calldata);
+        }
+
+        // relay message
+        {
+            let proof: Bytes = Bytes::from(marshal_proof_single(&proof_obj.storage_proof[0].proof));
+            let calldata = self
+                .ro()
+                .bridge_abi
+                .function("deliverMessageWithProof")
+                .unwrap()
+                .encode_input(&[
+                    msg.from.into_token(),
+                    msg.to.into_token(),
+                    msg.value.into_token(),
+                    msg.fee.into_token(),
+                    msg.deadline.into_token(),
+                    msg.nonce.into_token(),
+                    Token::Bytes(msg.calldata),
+                    proof.into_token(),
+                ])
+                .expect("calldata");
+            U256::from(calldata.len()).to_big_endian(&mut tmp);
+            bytes.extend(&tmp[28..32]);
+            bytes.extend(calldata);
         }
+
+        // TODO: support relaying multiple messages at once
+        self.transaction_to_l1(Some(l1_bridge_addr), U256::zero(), bytes)
+            .await?;
+
+        Ok(())
     }
 
     fn _parse_message_beacon(&self, log: Log) -> MessageBeacon {
         // TODO: this is really ugly. consider finding a alternative
-        let evt = self.ro.bridge_abi.event("MessageDispatched").unwrap();
+        let ro = self.ro();
+        let evt = ro.bridge_abi.event("MessageDispatched").unwrap();
         let evt = evt
             .parse_log(RawLog::from((log.topics, log.data.to_vec())))
             .unwrap();
@@ -1080,49 +1926,29 @@ impl SharedState {
         }
     }
 
-    async fn call_fn_l1(
-        &self,
-        function_name: &str,
-        function_args: &[Token],
-    ) -> Result<H256, String> {
-        let calldata = Bytes::from(
-            self.ro
-                .bridge_abi
-                .function(function_name)
-                .unwrap()
-                .encode_input(function_args)
-                .expect("calldata"),
-        );
-        let l1_bridge_addr = self.config.lock().await.l1_bridge;
-        let resp: Result<H256, String> = self
-            .request_l1(
-                "eth_call",
-                serde_json::json!([{ "to": l1_bridge_addr, "data": calldata }, "latest"]),
-            )
-            .await;
+    fn _parse_forced_transaction(&self, log: Log) -> ForcedTransaction {
+        let ro = self.ro();
+        let evt = ro.bridge_abi.event("ForcedTransactionQueued").unwrap();
+        let evt = evt
+            .parse_log(RawLog::from((log.topics, log.data.to_vec())))
+            .unwrap();
 
-        resp
+        let raw_tx = evt.params[2].value.to_owned().into_bytes().unwrap();
+        ForcedTransaction {
+            tx_hash: keccak256(&raw_tx).into(),
+            deadline: evt.params[1].value.to_owned().into_uint().unwrap(),
+            raw_tx: raw_tx.into(),
+            at_risk: false,
+        }
     }
 
     /// TODO: WIP - moved from prover/inputs
     /// Generates a witness suitable for the L1 Verifier contract(s) for block `block_num`.
-    pub async fn request_witness(&self, block_num: &U64) -> Result<Witness, String> {
-        let block: Block<Transaction> = self
-            .request_l2("eth_getBlockByNumber", (block_num, true))
-            .await
-            .expect("block");
-        let mut history_hashes = vec![H256::zero(); 256];
-        let mut block_hash = block.parent_hash;
-        history_hashes[255] = block_hash;
-        for i in 0..255 {
-            if block_hash != H256::zero() {
-                let header: BlockHeader =
-                    self.request_l2("eth_getHeaderByHash", [block_hash]).await?;
-                block_hash = header.parent_hash;
-            }
-            history_hashes[254 - i] = block_hash;
-        }
-        let chain_id = self.ro.l2_wallet.chain_id();
+    pub async fn request_witness(&self, block_num: &U64) -> Result<Witness, Error> {
+        let ro = self.ro();
+        let block = ro.l2.get_block_with_transactions(*block_num).await?;
+        let history_hashes = self.history_hashes(&block).await?;
+        let chain_id = ro.l2.wallet().chain_id();
         let witness: Vec<u8> = encode_verifier_witness(&block, &history_hashes, &chain_id)?;
         let witness = Witness {
             randomness: U256::zero(),
@@ -1132,87 +1958,304 @@ impl SharedState {
         Ok(witness)
     }
 
-    pub async fn request_proof(&self, block_num: &U64) -> Result<Option<Proofs>, String> {
-        if self.config.lock().await.dummy_prover {
-            log::warn!("COORDINATOR_DUMMY_PROVER");
-            let instance: Vec<U256> = {
-                let block_data = self
-                    .request_witness(block_num)
-                    .await
-                    .expect("witness")
-                    .input;
-                let func = self.ro.bridge_abi.function("buildCommitment").unwrap();
-                let calldata = Bytes::from(
-                    func.encode_input(&[block_data.into_token()])
-                        .expect("calldata"),
-                );
-                let l1_bridge_addr = self.config.lock().await.l1_bridge;
-                let result: Bytes = self
-                    .request_l1(
-                        "eth_call",
-                        serde_json::json!([{ "to": l1_bridge_addr, "data": calldata }, "latest"]),
-                    )
-                    .await
-                    .expect("eth_call buildCommitment");
-                let result: Vec<Token> = func
-                    .decode_output(&result)
-                    .expect("decode_output")
-                    .get(0)
-                    .unwrap()
-                    .to_owned()
-                    .into_array()
-                    .expect("into_array");
-                let result: Vec<U256> = result
-                    .iter()
-                    .map(|item| item.to_owned().into_uint().expect("into_uint"))
-                    .collect();
+    /// Returns the hashes of the 256 blocks before `block`, oldest first and zero before genesis.
+    /// The window of the previous witness is shifted by one block if `block` follows it,
+    /// otherwise the headers missing in `l2_headers` are fetched by number in one batch.
+    /// Headers that do not link to their child, e.g. after a reorg, are fetched by hash instead.
+    async fn history_hashes<TX: Send + Sync>(&self, block: &Block<TX>) -> Result<Vec<H256>, Error> {
+        {
+            let mut rw = self.rw.lock().await;
+            rw.l2_headers.insert(BlockHeader::from(block));
+            let prev = rw.history_hashes.last().cloned();
+            if prev == Some(block.parent_hash) {
+                return Ok(rw.history_hashes.clone());
+            }
+            let grandparent = rw.l2_headers.get(&block.parent_hash).map(|h| h.parent_hash);
+            if prev.is_some() && prev == grandparent {
+                rw.history_hashes.remove(0);
+                rw.history_hashes.push(block.parent_hash);
+                return Ok(rw.history_hashes.clone());
+            }
+        }
 
-                result
+        let number = block
+            .number
+            .ok_or("history_hashes: pending block")?
+            .as_u64();
+        let mut history_hashes = vec![H256::zero(); 256];
+        // walks the parents from `block_hash` at `block_number` down to `first`
+        let first = number.saturating_sub(256);
+        let mut block_hash = block.parent_hash;
+        let mut block_number = number;
+        let mut headers: HashMap<U64, BlockHeader> = HashMap::new();
+        while block_number > first {
+            block_number -= 1;
+            history_hashes[(block_number + 256 - number) as usize] = block_hash;
+            if block_number == first {
+                break;
+            }
+            let cached = self.rw.lock().await.l2_headers.get(&block_hash).cloned();
+            let header = match cached {
+                Some(header) => header,
+                None => {
+                    if headers.is_empty() {
+                        let numbers: Vec<U64> = (first + 1..=block_number).map(U64::from).collect();
+                        for header in self.ro().l2.get_headers(&numbers).await? {
+                            headers.insert(header.number, header);
+                        }
+                    }
+                    match headers.remove(&block_number.into()) {
+                        Some(header) if header.hash == block_hash => header,
+                        _ => self.ro().l2.get_header(block_hash).await?,
+                    }
+                }
             };
-            let mut proofs = Proofs::default();
-            proofs.circuit.proof = vec![0u8; 256].into();
-            proofs.circuit.instance = instance;
-            proofs.circuit.label = "DUMMY_VERIFIER".into();
+            block_hash = header.parent_hash;
+            self.rw.lock().await.l2_headers.insert(header);
+        }
+        self.rw.lock().await.history_hashes = history_hashes.clone();
+
+        Ok(history_hashes)
+    }
 
-            return Ok(Some(proofs));
+    /// Requests the proofs for `block_num` from the prover.
+    /// Returns `None` if the proofs are not yet computed.
+    /// `retry` signals the prover to compute the proofs again if the previous attempt failed.
+    pub async fn request_proof(
+        &self,
+        block_num: &U64,
+        retry: bool,
+    ) -> Result<Option<Proofs>, Error> {
+        if self.config.lock().await.dummy_prover {
+            return self.dummy_proof(block_num).await.map(Some);
         }
 
+        // the messages of this and the later blocks with a request
+        let deadline = self
+            .rw
+            .lock()
+            .await
+            .prover_requests
+            .iter()
+            .filter(|(num, _)| *num >= block_num)
+            .filter_map(|(_, req)| req.message_deadline)
+            .min();
+        let proof_options = self.proof_request_options(block_num, retry, deadline).await;
+        let prover = self.choose_prover(&proof_options, None).await?;
+
+        self.request_proof_from(&prover, &proof_options).await
+    }
+
+    /// Builds the proof request for `block_num` from the current configuration.
+    /// `deadline` is the time the block has to be finalized on L1 by, if any.
+    async fn proof_request_options(
+        &self,
+        block_num: &U64,
+        retry: bool,
+        deadline: Option<u64>,
+    ) -> ProofRequestOptions {
         let config = self.config.lock().await;
-        let prover_rpcd_url = config.prover_rpcd_url.clone();
-        let proof_options = ProofRequestOptions {
+
+        ProofRequestOptions {
             circuit: config.circuit_name.clone(),
             block: block_num.as_u64(),
             rpc: config.l2_rpc_url.to_string(),
-            retry: false,
+            retry,
             param: config.params_path.clone(),
             mock: config.mock_prover,
             aggregate: config.aggregate_proof,
             mock_feedback: config.mock_prover_if_error,
             verify_proof: config.verify_proof,
+            priority: config.proof_priority,
+            deadline,
+        }
+    }
+
+    /// Sends `proof_options` to `prover`.
+    /// Returns `None` if the proofs are not yet computed.
+    async fn request_proof_from(
+        &self,
+        prover: &Uri,
+        proof_options: &ProofRequestOptions,
+    ) -> Result<Option<Proofs>, Error> {
+        // the proof requests are repeated by the event loop
+        let client = JsonRpcClient::builder(prover.clone())
+            .timeout(RPC_REQUEST_TIMEOUT)
+            .retry(RetryPolicy::NONE)
+            .build();
+        let resp = ProverClient::with_client(client).proof(proof_options).await;
+
+        if let Err(err) = &resp {
+            let mut rw = self.rw.lock().await;
+            if let Some(node) = rw.provers.iter_mut().find(|e| e.endpoint.uri == *prover) {
+                node.last_error = Some(err.to_string());
+            }
+        }
+
+        Ok(resp?)
+    }
+
+    /// Drops the proofs for `block_num` on `prover` so that they are computed again.
+    /// Errors are only logged, the request is retried anyway.
+    async fn invalidate_proof(&self, prover: &Uri, block_num: &U64) {
+        // the deadline is not part of the task identity
+        let proof_options = self.proof_request_options(block_num, false, None).await;
+        let client = JsonRpcClient::builder(prover.clone())
+            .timeout(RPC_REQUEST_TIMEOUT)
+            .retry(RetryPolicy::NONE)
+            .build();
+
+        match ProverClient::with_client(client)
+            .invalidate(&proof_options)
+            .await
+        {
+            Ok(invalidated) => log::info!(
+                "invalidate_proof: block={} prover={} invalidated={}",
+                block_num,
+                prover,
+                invalidated
+            ),
+            Err(err) => log::error!(
+                "invalidate_proof: block={} prover={}: {}",
+                block_num,
+                prover,
+                err
+            ),
+        }
+    }
+
+    /// Returns a proof with the public inputs computed by the L1 bridge and an empty transcript
+    /// for the `DUMMY_VERIFIER`.
+    async fn dummy_proof(&self, block_num: &U64) -> Result<Proofs, Error> {
+        log::warn!("COORDINATOR_DUMMY_PROVER");
+        let instance: Vec<U256> = {
+            let block_data = self.request_witness(block_num).await?.input;
+            let l1_bridge_addr = self.config.lock().await.l1_bridge;
+
+            self.ro()
+                .l1
+                .build_commitment(l1_bridge_addr, block_data)
+                .await?
         };
-        drop(config);
-
-        let resp = jsonrpc_request_client(
-            RPC_REQUEST_TIMEOUT,
-            &self.ro.http_client,
-            &prover_rpcd_url,
-            "proof",
-            [proof_options],
-        )
-        .await;
-
-        match resp {
-            Err(err) => {
-                match err.as_ref() {
-                    "no result in response" => {
-                        // ...not an error
-                        Ok(None)
-                    }
-                    _ => Err(err),
+        let mut proofs = Proofs::default();
+        proofs.circuit.proof = vec![0u8; 256].into();
+        proofs.circuit.instance = instance;
+        proofs.circuit.label = "DUMMY_VERIFIER".into();
+
+        Ok(proofs)
+    }
+
+    /// Chooses a healthy prover for `proof_options`.
+    /// Provers dedicated to the circuit or to aggregation are preferred over general purpose
+    /// provers, then the prover with the least requests in flight and idle provers.
+    /// `exclude` is only chosen if there is no other candidate.
+    async fn choose_prover(
+        &self,
+        proof_options: &ProofRequestOptions,
+        exclude: Option<&Uri>,
+    ) -> Result<Uri, String> {
+        let endpoints = self.config.lock().await.prover_rpcd_url.clone();
+        let rw = self.rw.lock().await;
+
+        endpoints
+            .iter()
+            .filter(|endpoint| endpoint.serves(&proof_options.circuit, proof_options.aggregate))
+            .filter_map(|endpoint| {
+                // provers that were not checked yet are assumed to be healthy
+                let (healthy, busy) = rw
+                    .provers
+                    .iter()
+                    .find(|e| e.endpoint.uri == endpoint.uri)
+                    .map_or((true, false), |e| (e.healthy, e.busy));
+                if !healthy {
+                    return None;
                 }
+
+                let in_flight = rw
+                    .prover_requests
+                    .values()
+                    .filter(|e| {
+                        e.status == ProofRequestStatus::Pending
+                            && e.prover.as_ref() == Some(&endpoint.uri)
+                    })
+                    .count();
+                let excluded = exclude == Some(&endpoint.uri);
+
+                Some((
+                    (excluded, endpoint.route.is_none(), in_flight, busy),
+                    endpoint.uri.clone(),
+                ))
+            })
+            .min_by_key(|(rank, _)| *rank)
+            .map(|(_, uri)| uri)
+            .ok_or_else(|| format!("no healthy prover available for {}", proof_options.circuit))
+    }
+
+    /// Returns `false` if `prover` is not configured anymore or failed the last health check.
+    async fn is_prover_available(&self, prover: &Uri) -> bool {
+        let configured = self
+            .config
+            .lock()
+            .await
+            .prover_rpcd_url
+            .iter()
+            .any(|e| e.uri == *prover);
+        let healthy = self
+            .rw
+            .lock()
+            .await
+            .provers
+            .iter()
+            .find(|e| e.endpoint.uri == *prover)
+            .map_or(true, |e| e.healthy);
+
+        configured && healthy
+    }
+
+    /// Queries `/health` and `/status` of all configured provers and updates `provers`.
+    pub async fn check_provers(&self) {
+        let endpoints = self.config.lock().await.prover_rpcd_url.clone();
+        let mut provers = Vec::with_capacity(endpoints.len());
+
+        for endpoint in endpoints {
+            let health = match with_path(&endpoint.uri, "/health") {
+                Ok(uri) => get_http_status(5000, &self.ro().http_client, &uri).await,
+                Err(err) => Err(err),
+            };
+            let healthy = matches!(health, Ok(StatusCode::OK));
+            // returns 200 if busy else 204
+            let busy = match with_path(&endpoint.uri, "/status") {
+                Ok(uri) if healthy => matches!(
+                    get_http_status(5000, &self.ro().http_client, &uri).await,
+                    Ok(StatusCode::OK)
+                ),
+                _ => false,
+            };
+
+            let prev = self
+                .rw
+                .lock()
+                .await
+                .provers
+                .iter()
+                .find(|e| e.endpoint.uri == endpoint.uri)
+                .cloned();
+            let was_healthy = prev.as_ref().map_or(true, |e| e.healthy);
+            if was_healthy && !healthy {
+                log::warn!("prover {} is unhealthy: {:?}", endpoint, health);
+            } else if !was_healthy && healthy {
+                log::info!("prover {} is healthy again", endpoint);
             }
-            Ok(val) => Ok(Some(val)),
+
+            provers.push(ProverNode {
+                endpoint,
+                healthy,
+                busy,
+                last_error: prev.and_then(|e| e.last_error),
+            });
         }
+
+        self.rw.lock().await.provers = provers;
     }
 
     /// Returns the current coordinator configuration.
@@ -1220,33 +2263,69 @@ impl SharedState {
         self.config.lock().await.to_owned()
     }
 
-    /// Sets the coordinator configuration.
-    /// Not all changes to the config may be reflected.
-    pub async fn set_config(&self, config: Config) {
-        // TODO: doesn't update all config values at the moment.
-        *self.config.lock().await = config;
+    /// Validates and applies `config`.
+    /// Clients and the verifier registry are rebuilt if their configuration changed
+    /// and node discovery starts over for new `rpc_server_nodes`.
+    /// Fields in `RESTART_REQUIRED` keep their current value and are reported instead.
+    pub async fn set_config(&self, mut config: Config) -> Result<ConfigUpdate, Error> {
+        config.validate().map_err(Error::Config)?;
+
+        let mut current = self.config.lock().await;
+        let mut update = ConfigUpdate::default();
+        for field in current.changed_fields(&config) {
+            match RESTART_REQUIRED.contains(&field.as_str()) {
+                true => update.restart_required.push(field),
+                false => update.applied.push(field),
+            }
+        }
+        config.keep_restart_required(&current);
+
+        let changed = |field: &str| update.applied.iter().any(|e| e == field);
+        if [
+            "l1_rpc_url",
+            "l2_rpc_url",
+            "l2_block_builder",
+            "l2_engine_url",
+            "l2_jwt_secret",
+            "l1_priv",
+            "verifier_registry",
+            "verifier_label_fallback",
+            "dummy_prover",
+            "rpc_max_retries",
+            "rpc_retry_backoff",
+            "rpc_max_concurrent_requests",
+        ]
+        .iter()
+        .any(|field| changed(field))
+        {
+            let current_ro = self.ro();
+            let l1 = current_ro.l1.reconnect(&config).await?;
+            let l2 = current_ro.l2.reconnect(&config).await?;
+            let ro = RoState::try_new(&config, l1, l2)?;
+            *self.ro.write().unwrap() = Arc::new(ro);
+        }
+        *current = config;
+        drop(current);
+        if changed("rpc_server_nodes") {
+            self.rw.lock().await.nodes.clear();
+        }
+
+        if !update.applied.is_empty() {
+            log::info!("config: applied {:?}", update.applied);
+        }
+        if !update.restart_required.is_empty() {
+            log::warn!("config: restart required for {:?}", update.restart_required);
+        }
+
+        Ok(update)
     }
-}
 
-fn get_abi() -> Abi {
-    AbiParser::default()
-        .parse(&[
-            "event BlockSubmitted()",
-            "event BlockFinalized(bytes32 blockHash)",
-            "event MessageDispatched(address from, address to, uint256 value, uint256 fee, uint256 deadline, uint256 nonce, bytes data)",
-            "event MessageDelivered(bytes32 id)",
-            "function submitBlock(bytes)",
-            "function finalizeBlock(bytes proof)",
-            "function deliverMessageWithProof(address from, address to, uint256 value, uint256 fee, uint256 deadline, uint256 nonce, bytes data, bytes proof)",
-            "function stateRoots(bytes32 blockHash) returns (bytes32)",
-            "function importForeignBlock(uint256 blockNumber, bytes32 blockHash)",
-            "function initGenesis(bytes32 blockHash, bytes32 stateRoot)",
-            "function buildCommitment(bytes) returns (uint256[])",
-            "function importForeignBridgeState(bytes, bytes)",
-            "function multicall()",
-            "function getTimestampForStorageRoot(bytes32 storageRoot) returns (uint256)",
-        ])
-        .expect("parse abi")
+    /// Reloads the configuration from `config_file`, environment variables and command line.
+    pub async fn reload_config_file(&self) -> Result<ConfigUpdate, Error> {
+        let config = Config::reload().map_err(Error::Config)?;
+
+        self.set_config(config).await
+    }
 }
 
 fn timestamp() -> u64 {
@@ -1256,13 +2335,10 @@ fn timestamp() -> u64 {
         .as_secs()
 }
 
-async fn get_wallet(rcp_url: &Uri, sign_key: &str) -> LocalWallet {
-    let chain_id: U64 = jsonrpc_request(rcp_url, "eth_chainId", ())
-        .await
-        .expect("chain id L1");
-
-    sign_key
-        .parse::<LocalWallet>()
-        .expect("cannot create LocalWallet from private key")
-        .with_chain_id(chain_id.as_u64())
+/// The earlier of two deadlines, if any.
+fn min_deadline(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
 }
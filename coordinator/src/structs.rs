@@ -1,7 +1,13 @@
 use ethers_core::abi::encode;
 use ethers_core::abi::Tokenizable;
-use ethers_core::types::{Address, Bytes, H256, U256, U64};
+use ethers_core::types::{Address, Block, Bytes, H256, U256, U64};
 use ethers_core::utils::keccak256;
+use hyper::Uri;
+use serde_with::{serde_as, DisplayFromStr};
+use std::collections::{HashMap, VecDeque};
+use zkevm_common::prover::Proofs;
+
+use crate::config::ProverEndpoint;
 
 #[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
 pub struct ForkchoiceStateV1 {
@@ -13,7 +19,64 @@ pub struct ForkchoiceStateV1 {
     pub finalized_block_hash: H256,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct PayloadAttributesV1 {
+    pub timestamp: U64,
+    #[serde(rename = "prevRandao")]
+    pub prev_randao: H256,
+    #[serde(rename = "suggestedFeeRecipient")]
+    pub suggested_fee_recipient: Address,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ExecutionPayloadV1 {
+    #[serde(rename = "parentHash")]
+    pub parent_hash: H256,
+    #[serde(rename = "feeRecipient")]
+    pub fee_recipient: Address,
+    #[serde(rename = "stateRoot")]
+    pub state_root: H256,
+    #[serde(rename = "receiptsRoot")]
+    pub receipts_root: H256,
+    #[serde(rename = "logsBloom")]
+    pub logs_bloom: Bytes,
+    #[serde(rename = "prevRandao")]
+    pub prev_randao: H256,
+    #[serde(rename = "blockNumber")]
+    pub block_number: U64,
+    #[serde(rename = "gasLimit")]
+    pub gas_limit: U64,
+    #[serde(rename = "gasUsed")]
+    pub gas_used: U64,
+    pub timestamp: U64,
+    #[serde(rename = "extraData")]
+    pub extra_data: Bytes,
+    #[serde(rename = "baseFeePerGas")]
+    pub base_fee_per_gas: U256,
+    #[serde(rename = "blockHash")]
+    pub block_hash: H256,
+    pub transactions: Vec<Bytes>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct PayloadStatusV1 {
+    /// VALID, INVALID, SYNCING, ACCEPTED or INVALID_BLOCK_HASH
+    pub status: String,
+    #[serde(rename = "latestValidHash")]
+    pub latest_valid_hash: Option<H256>,
+    #[serde(rename = "validationError")]
+    pub validation_error: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ForkchoiceUpdatedResponse {
+    #[serde(rename = "payloadStatus")]
+    pub payload_status: PayloadStatusV1,
+    #[serde(rename = "payloadId")]
+    pub payload_id: Option<Bytes>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct MessageBeacon {
     pub id: H256,
     pub from: Address,
@@ -22,9 +85,64 @@ pub struct MessageBeacon {
     pub fee: U256,
     pub deadline: U256,
     pub nonce: U256,
+    #[serde(
+        serialize_with = "serialize_bytes",
+        deserialize_with = "deserialize_bytes"
+    )]
     pub calldata: Vec<u8>,
 }
 
+fn serialize_bytes<S: serde::Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serde::Serialize::serialize(&Bytes::from(data.to_vec()), serializer)
+}
+
+fn deserialize_bytes<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<u8>, D::Error> {
+    <Bytes as serde::Deserialize>::deserialize(deserializer).map(|data| data.to_vec())
+}
+
+/// Keeps track of the delivery attempts of a bridge message.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct MessageDelivery {
+    pub message: MessageBeacon,
+    /// The number of failed attempts.
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// Unix timestamp (seconds) before which the message is not tried again.
+    pub next_attempt: u64,
+}
+
+impl MessageDelivery {
+    pub fn new(message: MessageBeacon) -> Self {
+        Self {
+            message,
+            attempts: 0,
+            last_error: None,
+            next_attempt: 0,
+        }
+    }
+
+    /// Records a failed attempt at `now` and schedules the next one with exponential backoff,
+    /// starting at `backoff` seconds.
+    pub fn failed(&mut self, err: String, now: u64, backoff: u64) {
+        self.attempts += 1;
+        self.last_error = Some(err);
+        // at most about a day for the default settings
+        let delay = backoff.saturating_mul(1 << (self.attempts - 1).min(13));
+        self.next_attempt = now.saturating_add(delay);
+    }
+}
+
+/// The message deliveries of `RwState` persisted in `state_file`.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct MessageQueues {
+    pub l1_message_queue: VecDeque<MessageDelivery>,
+    pub l2_message_queue: Vec<MessageDelivery>,
+    pub l1_dead_letters: Vec<MessageDelivery>,
+    pub l2_dead_letters: Vec<MessageDelivery>,
+}
+
 impl MessageBeacon {
     /// calculates the storage address for `self`
     pub fn storage_slot(&self) -> H256 {
@@ -55,15 +173,47 @@ impl MessageBeacon {
     }
 }
 
+/// A L2 transaction submitted on L1 with `forceTransaction`,
+/// to be included in a L2 block before `deadline`.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct ForcedTransaction {
+    pub tx_hash: H256,
+    /// Unix timestamp (seconds)
+    pub deadline: U256,
+    pub raw_tx: Bytes,
+    /// Set once the deadline is within `forced_inclusion_alert_margin`.
+    pub at_risk: bool,
+}
+
+/// Header fields of a L2 block that are chosen by the coordinator.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockAttributes {
+    pub timestamp: u64,
+    pub random: H256,
+    pub coinbase: Address,
+}
+
+/// A L2 block as submitted on L1 with `submitBlock`.
+#[derive(Clone, Debug)]
+pub struct SubmittedBlock {
+    pub hash: H256,
+    pub parent_hash: H256,
+    pub number: U64,
+    pub attributes: BlockAttributes,
+    /// signed raw transactions
+    pub transactions: Vec<Bytes>,
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct SealBlockRequest<'a> {
     pub parent: &'a H256,
     pub random: &'a H256,
     pub timestamp: &'a U64,
+    pub coinbase: &'a Address,
     pub transactions: Option<&'a Vec<Bytes>>,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Deserialize)]
 pub struct BlockHeader {
     #[serde(rename = "parentHash")]
     pub parent_hash: H256,
@@ -71,9 +221,63 @@ pub struct BlockHeader {
     pub number: U64,
     #[serde(rename = "stateRoot")]
     pub state_root: H256,
+    pub timestamp: U256,
     // add missing fields if required
 }
 
+impl<TX> From<&Block<TX>> for BlockHeader {
+    fn from(block: &Block<TX>) -> Self {
+        Self {
+            parent_hash: block.parent_hash,
+            hash: block.hash.unwrap_or_default(),
+            number: block.number.unwrap_or_default(),
+            state_root: block.state_root,
+            timestamp: block.timestamp,
+        }
+    }
+}
+
+/// Block headers by hash, the oldest entries are evicted once `capacity` is reached.
+/// A block hash commits to the header, entries stay valid across reorgs.
+pub struct HeaderCache {
+    capacity: usize,
+    headers: HashMap<H256, BlockHeader>,
+    order: VecDeque<H256>,
+}
+
+impl HeaderCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            headers: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub fn get(&self, hash: &H256) -> Option<&BlockHeader> {
+        self.headers.get(hash)
+    }
+
+    pub fn insert(&mut self, header: BlockHeader) {
+        if self.headers.insert(header.hash, header.clone()).is_some() {
+            return;
+        }
+        self.order.push_back(header.hash);
+        while self.order.len() > self.capacity {
+            let hash = self.order.pop_front().unwrap();
+            self.headers.remove(&hash);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+}
+
 // https://eips.ethereum.org/EIPS/eip-1186
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct MerkleProofRequest {
@@ -102,3 +306,47 @@ pub struct Witness {
     pub randomness: U256,
     pub input: Bytes,
 }
+
+/// Lifecycle of a proof request sent to the prover.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub enum ProofRequestStatus {
+    /// Waiting for the prover to return a result.
+    Pending,
+    /// The prover returned a proof.
+    Completed,
+    /// The prover returned an error, the request will be retried.
+    Failed,
+}
+
+/// Keeps track of a proof request for a L2 block.
+#[serde_as]
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct ProverRequest {
+    /// The block hash at the time of the request.
+    pub block_hash: H256,
+    pub status: ProofRequestStatus,
+    /// The prover this request was routed to.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub prover: Option<Uri>,
+    /// Unix timestamp (seconds) of the first request.
+    pub started: u64,
+    /// The number of proofs returned for this block that failed local verification.
+    pub verification_failures: u32,
+    /// The result of the last response, if any.
+    pub result: Option<Result<Proofs, String>>,
+    /// The earliest deadline (unix timestamp in seconds) of the L2 > L1 messages dispatched
+    /// in this block.
+    pub message_deadline: Option<u64>,
+}
+
+/// Health and load information of a prover endpoint.
+#[derive(Clone, Debug)]
+pub struct ProverNode {
+    pub endpoint: ProverEndpoint,
+    /// `false` if the prover failed to respond to `/health`
+    pub healthy: bool,
+    /// `true` if `/status` reports that the prover is working on a task
+    pub busy: bool,
+    /// The last error returned by this prover
+    pub last_error: Option<String>,
+}
@@ -1,60 +1,65 @@
+use crate::error::Error;
 use crate::structs::*;
-use crate::timeout;
+use ethers_core::types::transaction::eip2718::TypedTransaction;
 use ethers_core::types::transaction::eip2930::AccessListWithGasUsed;
 use ethers_core::types::Transaction;
 use ethers_core::types::{
-    Address, Block, Bytes, Eip1559TransactionRequest, TransactionReceipt, TransactionRequest, H256,
-    U256,
+    Address, Block, Bytes, Eip1559TransactionRequest, Signature, TransactionReceipt,
+    TransactionRequest, H256, U256,
 };
 use ethers_core::utils::keccak256;
-use ethers_core::utils::rlp::RlpStream;
+use ethers_core::utils::rlp::{DecoderError, Rlp, RlpStream};
 use ethers_signers::{LocalWallet, Signer};
 use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Request;
+use hyper::StatusCode;
 use hyper::Uri;
-use zkevm_common::json_rpc::jsonrpc_request_client;
+use std::time::Duration;
+use zkevm_common::json_rpc::{jsonrpc_request_client, JsonRpcClient, RpcError};
+use zkevm_common::prover::ProofResult;
+use zkevm_common::prover::Proofs;
 
 pub const RPC_REQUEST_TIMEOUT: u64 = 30000;
+/// Time in ms to wait for the receipt of a L1 transaction.
+const L1_TX_TIMEOUT: u64 = 120_000;
 
 /// may override any pending transactions
 pub async fn send_transaction_to_l1(
-    client: &hyper::Client<HttpConnector>,
-    node_uri: &Uri,
+    client: &JsonRpcClient,
     wallet: &LocalWallet,
     to: Option<Address>,
     value: U256,
     calldata: Vec<u8>,
-) -> Result<TransactionReceipt, String> {
-    let nonce: U256 = jsonrpc_request_client(
-        RPC_REQUEST_TIMEOUT,
-        client,
-        node_uri,
-        "eth_getTransactionCount",
-        (wallet.address(), "latest"),
+) -> Result<TransactionReceipt, Error> {
+    let nonce: U256 = client
+        .request("eth_getTransactionCount", (wallet.address(), "latest"))
+        .await?;
+
+    let raw_tx = sign_transaction_l1(client, wallet, to, value, calldata, nonce).await?;
+    tokio::time::timeout(
+        Duration::from_millis(L1_TX_TIMEOUT),
+        wait_for_tx(client, &raw_tx),
     )
     .await
-    .expect("nonce");
-
-    let raw_tx = sign_transaction_l1(client, node_uri, wallet, to, value, calldata, nonce).await?;
-    // wait up to 120 seconds
-    timeout!(120_000, wait_for_tx(client, node_uri, &raw_tx).await)
+    .map_err(|_| {
+        let tx_hash = H256::from_slice(&keccak256(&raw_tx));
+        Error::Rpc(RpcError::Timeout(format!("l1 transaction {tx_hash:?}")))
+    })?
 }
 
 /// may override any pending transactions
 pub async fn sign_transaction_l1(
-    client: &hyper::Client<HttpConnector>,
-    node_uri: &Uri,
+    client: &JsonRpcClient,
     wallet: &LocalWallet,
     to: Option<Address>,
     value: U256,
     calldata: Vec<u8>,
     nonce: U256,
-) -> Result<Bytes, String> {
+) -> Result<Bytes, Error> {
     let wallet_addr: Address = wallet.address();
 
-    let gas_price: U256 =
-        jsonrpc_request_client(RPC_REQUEST_TIMEOUT, client, node_uri, "eth_gasPrice", ())
-            .await
-            .expect("gasPrice");
+    let gas_price: U256 = client.request("eth_gasPrice", ()).await?;
 
     let mut tx: Eip1559TransactionRequest = Eip1559TransactionRequest::new()
         .chain_id(wallet.chain_id())
@@ -69,24 +74,9 @@ pub async fn sign_transaction_l1(
         tx = tx.to(to.unwrap());
     }
 
-    let access_list: AccessListWithGasUsed = jsonrpc_request_client(
-        RPC_REQUEST_TIMEOUT,
-        client,
-        node_uri,
-        "eth_createAccessList",
-        [&tx],
-    )
-    .await
-    .expect("eth_createAccessList");
+    let access_list: AccessListWithGasUsed = client.request("eth_createAccessList", [&tx]).await?;
     let tx = tx.access_list(access_list.access_list);
-    let estimate: U256 = jsonrpc_request_client(
-        RPC_REQUEST_TIMEOUT,
-        client,
-        node_uri,
-        "eth_estimateGas",
-        [&tx],
-    )
-    .await?;
+    let estimate: U256 = client.request("eth_estimateGas", [&tx]).await?;
     let tx = tx.gas(estimate).into();
 
     log::debug!("sending l1 tx: {:?}", tx);
@@ -94,36 +84,26 @@ pub async fn sign_transaction_l1(
     let sig = wallet
         .sign_transaction(&tx)
         .await
-        .expect("sign_transaction");
+        .map_err(|e| e.to_string())?;
 
     Ok(tx.rlp_signed(&sig))
 }
 
 /// may override any pending transactions
 pub async fn send_transaction_to_l2(
-    client: &hyper::Client<HttpConnector>,
-    node_uri: &Uri,
+    client: &JsonRpcClient,
     wallet: &LocalWallet,
     to: Option<Address>,
     value: U256,
     calldata: Vec<u8>,
     gas_limit: Option<U256>,
-) -> Result<H256, String> {
+) -> Result<H256, Error> {
     let wallet_addr: Address = wallet.address();
-    let nonce: U256 = jsonrpc_request_client(
-        RPC_REQUEST_TIMEOUT,
-        client,
-        node_uri,
-        "eth_getTransactionCount",
-        (wallet_addr, "latest"),
-    )
-    .await
-    .expect("nonce");
+    let nonce: U256 = client
+        .request("eth_getTransactionCount", (wallet_addr, "latest"))
+        .await?;
 
-    let gas_price: U256 =
-        jsonrpc_request_client(RPC_REQUEST_TIMEOUT, client, node_uri, "eth_gasPrice", ())
-            .await
-            .expect("gasPrice");
+    let gas_price: U256 = client.request("eth_gasPrice", ()).await?;
 
     let mut tx = TransactionRequest::new()
         .chain_id(wallet.chain_id())
@@ -139,64 +119,38 @@ pub async fn send_transaction_to_l2(
 
     let estimate: U256 = match gas_limit {
         Some(limit) => limit,
-        None => {
-            jsonrpc_request_client(
-                RPC_REQUEST_TIMEOUT,
-                client,
-                node_uri,
-                "eth_estimateGas",
-                [&tx],
-            )
-            .await?
-        }
+        None => client.request("eth_estimateGas", [&tx]).await?,
     };
     let tx = tx.gas(estimate).into();
 
-    let sig = wallet.sign_transaction(&tx).await.unwrap();
+    let sig = wallet
+        .sign_transaction(&tx)
+        .await
+        .map_err(|e| e.to_string())?;
     let raw_tx = tx.rlp_signed(&sig);
 
     // TODO: will be obsolete once execution api is used
-    jsonrpc_request_client(
-        RPC_REQUEST_TIMEOUT,
-        client,
-        node_uri,
-        "eth_sendRawTransaction",
-        [raw_tx],
-    )
-    .await
+    Ok(client.request("eth_sendRawTransaction", [raw_tx]).await?)
 }
 
 /// Can loop forever, thus should be wrapped inside timeout handler
 pub async fn wait_for_tx(
-    client: &hyper::Client<HttpConnector>,
-    node_uri: &Uri,
+    client: &JsonRpcClient,
     raw_tx: &Bytes,
-) -> Result<TransactionReceipt, String> {
+) -> Result<TransactionReceipt, Error> {
     let tx_hash = H256::from_slice(&keccak256(raw_tx));
 
     // ignore
-    let resp: Result<H256, String> = jsonrpc_request_client(
-        RPC_REQUEST_TIMEOUT,
-        client,
-        node_uri,
-        "eth_sendRawTransaction",
-        [raw_tx],
-    )
-    .await;
+    let resp: Result<H256, RpcError> = client.request("eth_sendRawTransaction", [raw_tx]).await;
 
     log::debug!("{:?}", resp);
 
     loop {
         tokio::time::sleep(std::time::Duration::from_millis(1000)).await;
 
-        let receipt: Result<TransactionReceipt, String> = jsonrpc_request_client(
-            RPC_REQUEST_TIMEOUT,
-            client,
-            node_uri,
-            "eth_getTransactionReceipt",
-            [&tx_hash],
-        )
-        .await;
+        let receipt: Result<TransactionReceipt, RpcError> = client
+            .request("eth_getTransactionReceipt", [&tx_hash])
+            .await;
 
         log::debug!("{:?}", receipt);
 
@@ -207,7 +161,10 @@ pub async fn wait_for_tx(
         let receipt = receipt.expect("TransactionReceipt");
 
         if receipt.status.expect("tx.status").as_u64() != 1 {
-            return Err("transaction reverted".to_string());
+            return Err(Error::Reverted {
+                message: "transaction reverted".to_string(),
+                data: None,
+            });
         }
 
         return Ok(receipt);
@@ -237,35 +194,33 @@ pub async fn get_chain_head(client: &hyper::Client<HttpConnector>, uri: &Uri) ->
     header
 }
 
-pub async fn get_blocks_between(
+/// Returns `uri` with the path replaced by `path`.
+pub fn with_path(uri: &Uri, path: &str) -> Result<Uri, String> {
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path.parse().map_err(|e| format!("{path}: {e}"))?);
+
+    Uri::from_parts(parts).map_err(|e| e.to_string())
+}
+
+/// Sends a GET request to `uri` with a timeout of `timeout` ms and returns the status code of
+/// the response.
+pub async fn get_http_status(
+    timeout: u64,
     client: &hyper::Client<HttpConnector>,
     uri: &Uri,
-    from: &H256,
-    to: &H256,
-) -> Vec<Block<H256>> {
-    let mut ret: Vec<Block<H256>> = Vec::new();
-    let mut hash = *to;
-    loop {
-        let block: Block<H256> = jsonrpc_request_client(
-            RPC_REQUEST_TIMEOUT,
-            client,
-            uri,
-            "eth_getBlockByHash",
-            (hash, false),
-        )
-        .await
-        .expect("eth_getBlockByHash");
-        hash = block.parent_hash;
-
-        if block.hash.unwrap() != *from {
-            ret.push(block);
-        }
-        if hash == *from {
-            break;
-        }
-    }
+) -> Result<StatusCode, String> {
+    let req = Request::get(uri)
+        .body(Body::empty())
+        .map_err(|e| e.to_string())?;
+    let resp = tokio::time::timeout(
+        std::time::Duration::from_millis(timeout),
+        client.request(req),
+    )
+    .await
+    .map_err(|e| format!("{uri}: {e}"))?
+    .map_err(|e| format!("{uri}: {e}"))?;
 
-    ret
+    Ok(resp.status())
 }
 
 /// encodes the proof from `eth_getCode` suitable for the Patricia{Account,Storage}Validator contract.
@@ -301,6 +256,31 @@ pub fn marshal_proof(account_proof: &[Bytes], storage_proof: &[Bytes]) -> Vec<u8
     ret
 }
 
+/// Returns the proof that is used for finalization, the aggregation proof if not empty and
+/// `true` if it is the aggregation proof.
+pub fn select_proof(proofs: &Proofs) -> (bool, &ProofResult) {
+    if proofs.aggregation.proof.len() != 0 {
+        (true, &proofs.aggregation)
+    } else {
+        (false, &proofs.circuit)
+    }
+}
+
+/// Encodes the public inputs followed by the transcript of `proof_result` as expected by the
+/// verifier contracts.
+pub fn encode_verifier_calldata(proof_result: &ProofResult) -> Vec<u8> {
+    let mut verifier_calldata = vec![];
+    let mut tmp_buf = vec![0u8; 32];
+
+    proof_result.instance.iter().for_each(|v| {
+        v.to_big_endian(&mut tmp_buf);
+        verifier_calldata.extend_from_slice(&tmp_buf);
+    });
+    verifier_calldata.extend_from_slice(proof_result.proof.as_ref());
+
+    verifier_calldata
+}
+
 /// Generates a witness suitable for the L1 Verifier contract(s) for block `block_num`.
 pub fn encode_verifier_witness(
     block: &Block<Transaction>,
@@ -378,3 +358,72 @@ pub fn encode_verifier_witness(
 
     Ok(witness)
 }
+
+/// Reverses `encode_verifier_witness`, the signed transactions are reconstructed
+/// from the sender and the signature values.
+pub fn decode_verifier_witness(witness: &[u8], chain_id: u64) -> Result<SubmittedBlock, String> {
+    fn word(data: &[u8], offset: usize) -> Result<&[u8], String> {
+        data.get(offset..offset + 32)
+            .ok_or_else(|| "witness: unexpected end of data".to_string())
+    }
+
+    let header = Rlp::new(witness);
+    let info = header.payload_info().map_err(|e| e.to_string())?;
+    let header_len = info.header_len + info.value_len;
+    let field_err = |e: DecoderError| format!("witness header: {e}");
+    let mut block = SubmittedBlock {
+        hash: H256::from(keccak256(&witness[0..header_len])),
+        parent_hash: header.val_at(0).map_err(field_err)?,
+        number: header.val_at(8).map_err(field_err)?,
+        attributes: BlockAttributes {
+            timestamp: header.val_at::<U256>(11).map_err(field_err)?.as_u64(),
+            random: header.val_at(13).map_err(field_err)?,
+            coinbase: header.val_at(2).map_err(field_err)?,
+        },
+        transactions: Vec::new(),
+    };
+
+    // skip the history hashes
+    let mut offset = header_len + 256 * 32;
+    word(witness, offset - 32)?;
+    while offset < witness.len() {
+        let rlp = Rlp::new(&witness[offset..]);
+        let info = rlp.payload_info().map_err(|e| e.to_string())?;
+        let tx_len = info.header_len + info.value_len;
+        let field_err = |e: DecoderError| format!("witness transaction: {e}");
+        let to: Vec<u8> = rlp.val_at(3).map_err(field_err)?;
+        let mut tx = TransactionRequest::new()
+            .nonce(rlp.val_at::<U256>(0).map_err(field_err)?)
+            .gas_price(rlp.val_at::<U256>(1).map_err(field_err)?)
+            .gas(rlp.val_at::<U256>(2).map_err(field_err)?)
+            .value(rlp.val_at::<U256>(4).map_err(field_err)?)
+            .data(rlp.val_at::<Vec<u8>>(5).map_err(field_err)?)
+            .chain_id(chain_id);
+        if !to.is_empty() {
+            tx = tx.to(Address::from_slice(&to));
+        }
+        offset += tx_len;
+
+        let from = Address::from_slice(&word(witness, offset)?[12..]);
+        let r = U256::from(word(witness, offset + 32)?);
+        let s = U256::from(word(witness, offset + 64)?);
+        offset += 96;
+
+        // the recovery bit is not part of the witness
+        let tx: TypedTransaction = tx.into();
+        let sighash = tx.sighash();
+        let raw_tx = [0, 1]
+            .into_iter()
+            .map(|recovery_id| Signature {
+                r,
+                s,
+                v: chain_id * 2 + 35 + recovery_id,
+            })
+            .find(|signature| signature.recover(sighash).ok() == Some(from))
+            .map(|signature| tx.rlp_signed(&signature))
+            .ok_or_else(|| format!("witness: invalid signature for transaction from {from:?}"))?;
+        block.transactions.push(raw_tx);
+    }
+
+    Ok(block)
+}
@@ -0,0 +1,156 @@
+use crate::config::VerifierRegistrySource;
+use crate::error::Error;
+use ethers_core::abi::{encode, Token};
+use ethers_core::types::{Address, Bytes, H256, U256};
+use ethers_core::utils::keccak256;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use zkevm_common::prover::CircuitConfig;
+
+/// A verifier contract deployed for a circuit.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifierEntry {
+    /// Circuit name / identifier, as used in `ProofResult.label`
+    pub label: String,
+    /// Circuit configuration the verifier was generated for.
+    /// Matches any configuration if not set.
+    #[serde(default)]
+    pub config: Option<CircuitConfig>,
+    /// Address of the verifier contract on L1
+    pub address: Address,
+}
+
+/// Maps circuit labels and configurations to verifier contract addresses.
+#[derive(Clone, Debug)]
+pub enum VerifierRegistry {
+    /// No registry configured, addresses are derived from the circuit label.
+    /// Only used if `verifier_label_fallback` is set.
+    Label,
+    /// Entries loaded from `COORDINATOR_VERIFIER_REGISTRY`.
+    Entries(Vec<VerifierEntry>),
+    /// Addresses are looked up via `getVerifier(label, config_digest)` on this contract.
+    Contract(Address),
+}
+
+impl VerifierRegistry {
+    /// Without `source` addresses are derived from labels if `label_fallback` is set,
+    /// otherwise this fails.
+    pub fn load(
+        source: Option<&VerifierRegistrySource>,
+        label_fallback: bool,
+    ) -> Result<Self, String> {
+        match source {
+            None if label_fallback => Ok(Self::Label),
+            None => Err("no verifier registry configured".to_string()),
+            Some(VerifierRegistrySource::Contract(addr)) => Ok(Self::Contract(*addr)),
+            Some(VerifierRegistrySource::File(path)) => {
+                let entries = read_entries(path)?;
+                if entries.is_empty() {
+                    return Err(format!("{}: no verifiers registered", path.display()));
+                }
+
+                Ok(Self::Entries(entries))
+            }
+        }
+    }
+
+    /// Returns the verifier address for `label` and `config` from the registered entries.
+    /// Entries for the exact configuration are preferred over entries without configuration.
+    /// `Contract` registries have to be queried on L1 instead.
+    pub fn lookup(&self, label: &str, config: &CircuitConfig) -> Result<Address, String> {
+        let entries = match self {
+            Self::Label => return Ok(label_address(label)),
+            Self::Contract(addr) => {
+                return Err(format!(
+                    "verifier registry {addr:?} has to be queried on L1"
+                ))
+            }
+            Self::Entries(entries) => entries,
+        };
+        let candidates: Vec<&VerifierEntry> = entries.iter().filter(|e| e.label == label).collect();
+        let entry = candidates
+            .iter()
+            .find(|e| e.config.as_ref() == Some(config))
+            .or_else(|| candidates.iter().find(|e| e.config.is_none()));
+
+        match entry {
+            Some(entry) => Ok(entry.address),
+            None if candidates.is_empty() => Err(format!("no verifier registered for {label}")),
+            None => Err(format!(
+                "no verifier registered for {label} with circuit config {config:?}"
+            )),
+        }
+    }
+}
+
+/// The key of `config` in `ZkEvmVerifierRegistry`, `keccak256(abi.encode(...))` of its fields.
+pub fn config_digest(config: &CircuitConfig) -> H256 {
+    let fields = [
+        config.block_gas_limit,
+        config.max_txs,
+        config.max_calldata,
+        config.max_bytecode,
+        config.max_rws,
+        config.max_copy_rows,
+        config.max_exp_steps,
+        config.min_k,
+        config.pad_to,
+        config.min_k_aggregation,
+        config.keccak_padding,
+    ];
+    let tokens: Vec<Token> = fields
+        .iter()
+        .map(|field| Token::Uint(U256::from(*field)))
+        .collect();
+
+    H256::from(keccak256(encode(&tokens)))
+}
+
+/// Derives the verifier contract address from the circuit `label`.
+/// Used for setups without a verifier registry if `verifier_label_fallback` is set.
+pub fn label_address(label: &str) -> Address {
+    let mut tmp_buf = vec![0u8; 32];
+    U256::from(label.as_bytes()).to_big_endian(&mut tmp_buf);
+
+    Address::from_slice(&tmp_buf[12..])
+}
+
+/// Reads the hex encoded runtime code of the verifier for `label` at `address` from the file
+/// `verifier-<label>-<address>` in `dir`, see `ProofVerification::VerifierCode`.
+pub fn verifier_code(dir: &Path, label: &str, address: Address) -> Result<Bytes, Error> {
+    let path = dir.join(format!("verifier-{label}-{address:?}"));
+    let code =
+        fs::read_to_string(&path).map_err(|e| Error::Io(format!("{}: {}", path.display(), e)))?;
+
+    format!("0x{}", code.trim().trim_start_matches("0x"))
+        .parse()
+        .map_err(|e| Error::Other(format!("{}: {:?}", path.display(), e)))
+}
+
+fn read_entries(path: &Path) -> Result<Vec<VerifierEntry>, String> {
+    let read_file = |path: &Path| -> Result<Vec<u8>, String> {
+        fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))
+    };
+
+    if !path.is_dir() {
+        return serde_json::from_slice(&read_file(path)?)
+            .map_err(|e| format!("{}: {}", path.display(), e));
+    }
+
+    let mut files: Vec<_> = fs::read_dir(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().map_or(false, |ext| ext == "json"))
+        .collect();
+    files.sort();
+
+    files
+        .iter()
+        .map(|p| {
+            serde_json::from_slice::<VerifierEntry>(&read_file(p)?)
+                .map_err(|e| format!("{}: {}", p.display(), e))
+        })
+        .collect()
+}
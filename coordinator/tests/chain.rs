@@ -24,14 +24,14 @@ async fn zero_eth_transfer() {
     let shared_state = await_state!();
     let tx_hash = shared_state
         .transaction_to_l2(
-            Some(shared_state.ro.l2_wallet.address()),
+            Some(shared_state.ro().l2.wallet.address()),
             U256::zero(),
             vec![],
             None,
         )
         .await
         .expect("tx_hash");
-    shared_state.mine().await;
+    shared_state.mine().await.expect("mine");
     wait_for_tx!(tx_hash, &shared_state.config.lock().await.l2_rpc_url);
 
     finalize_chain!(shared_state);
@@ -49,7 +49,7 @@ async fn keccak() {
         .transaction_to_l2(None, U256::zero(), bytecode, None)
         .await
         .expect("tx_hash");
-    shared_state.mine().await;
+    shared_state.mine().await.expect("mine");
     shared_state.config.lock().await.dummy_prover = true;
     finalize_chain!(shared_state);
 
@@ -59,7 +59,7 @@ async fn keccak() {
         .transaction_to_l2(contract_addr, U256::zero(), vec![], None)
         .await
         .expect("tx_hash");
-    shared_state.mine().await;
+    shared_state.mine().await.expect("mine");
     wait_for_tx!(tx_hash, &shared_state.config.lock().await.l2_rpc_url);
     shared_state.config.lock().await.dummy_prover = false;
     shared_state.config.lock().await.mock_prover = true;
@@ -76,7 +76,7 @@ async fn l1_l2_sync_test() {
     for _ in 0..2 {
         let l1_bridge_addr = Some(shared_state.config.lock().await.l1_bridge);
         // create deposits
-        let from = shared_state.ro.l1_wallet.address();
+        let from = shared_state.ro().l1.wallet.address();
         let to = Address::zero();
         let value = U256::from(1u64);
         let fee = U256::zero();
@@ -110,7 +110,7 @@ async fn l1_l2_sync_test() {
         // create a block with zero logs before the bridge deposit
         let _ = shared_state
             .transaction_to_l1(
-                Some(shared_state.ro.l2_wallet.address()),
+                Some(shared_state.ro().l2.wallet.address()),
                 U256::zero(),
                 vec![],
             )
@@ -150,7 +150,7 @@ async fn l1_l2_sync_test() {
 async fn finalize_chain() {
     let shared_state = await_state!();
     sync!(shared_state);
-    shared_state.mine().await;
+    shared_state.mine().await.expect("mine");
     finalize_chain!(shared_state);
 }
 
@@ -160,19 +160,19 @@ async fn finalize_chain() {
 async fn test_pi_commitment() {
     let shared_state = await_state!();
     sync!(shared_state);
-    shared_state.mine().await;
+    shared_state.mine().await.expect("mine");
 
     let mut tx_nonce: U256 = jsonrpc_request(
         &shared_state.config.lock().await.l2_rpc_url,
         "eth_getTransactionCount",
-        (shared_state.ro.l2_wallet.address(), "latest"),
+        (shared_state.ro().l2.wallet.address(), "latest"),
     )
     .await
     .expect("nonce");
     let mut txs = vec![];
     for i in 0..3 {
         let to = if i % 2 == 0 {
-            Some(shared_state.ro.l2_wallet.address())
+            Some(shared_state.ro().l2.wallet.address())
         } else {
             None
         };
@@ -185,7 +185,12 @@ async fn test_pi_commitment() {
                 input.push(OsRng.gen::<u8>());
             }
         }
-        txs.push(shared_state.sign_l2(to, value, tx_nonce, input).await);
+        txs.push(
+            shared_state
+                .sign_l2(to, value, tx_nonce, input)
+                .await
+                .expect("sign_l2"),
+        );
         tx_nonce = tx_nonce + 1;
     }
 
@@ -198,7 +203,7 @@ async fn test_pi_commitment() {
 
     loop {
         let proofs: Option<Proofs> = shared_state
-            .request_proof(&block_num)
+            .request_proof(&block_num, false)
             .await
             .expect("proofs");
         match proofs {
@@ -0,0 +1,353 @@
+use async_trait::async_trait;
+use clap::Parser;
+use coordinator::client::{L1Client, L2Engine, RpcL1Client};
+use coordinator::config::Config;
+use coordinator::error::Error;
+use coordinator::shared_state::SharedState;
+use coordinator::structs::{BlockAttributes, BlockHeader};
+use ethers_core::types::{
+    Address, Block, Bytes, Filter, Log, Transaction, TransactionReceipt, TxpoolStatus, H256, U256,
+    U64,
+};
+use ethers_signers::LocalWallet;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use zkevm_common::json_rpc::{JsonRpcClient, RetryPolicy, RpcError, SERVER_ERROR};
+use zkevm_fake_node::{Chain, FakeNode};
+
+const PRIV_KEY: &str = "2bdd21761a483f71054e14f5b827213567971c676928d9a1808cbfa4b7501201";
+
+/// Records the json-rpc methods requested by the typed `L1Client` methods.
+struct RecordingL1 {
+    inner: RpcL1Client,
+    methods: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl L1Client for RecordingL1 {
+    async fn reconnect(&self, config: &Config) -> Result<Self, Error> {
+        Ok(Self {
+            inner: self.inner.reconnect(config).await?,
+            methods: self.methods.clone(),
+        })
+    }
+
+    fn wallet(&self) -> &LocalWallet {
+        self.inner.wallet()
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, Error> {
+        self.methods.lock().unwrap().push(method.to_string());
+        self.inner.request(method, params).await
+    }
+
+    async fn send_transaction(
+        &self,
+        to: Option<Address>,
+        value: U256,
+        calldata: Vec<u8>,
+    ) -> Result<TransactionReceipt, Error> {
+        self.methods
+            .lock()
+            .unwrap()
+            .push("send_transaction".to_string());
+        self.inner.send_transaction(to, value, calldata).await
+    }
+}
+
+/// A `L2Engine` without a node.
+struct MockL2 {
+    wallet: LocalWallet,
+    chain: Arc<Mutex<Chain>>,
+}
+
+impl MockL2 {
+    fn block<T: serde::de::DeserializeOwned>(&self, hash: H256, full: bool) -> Result<T, Error> {
+        let chain = self.chain.lock().unwrap();
+        let block = chain.blocks.get(&hash).ok_or("unknown block")?;
+
+        serde_json::from_value(chain.block_json(block, full)).map_err(|e| e.to_string().into())
+    }
+}
+
+#[async_trait]
+impl L2Engine for MockL2 {
+    async fn reconnect(&self, _config: &Config) -> Result<Self, Error> {
+        Ok(Self {
+            wallet: self.wallet.clone(),
+            chain: self.chain.clone(),
+        })
+    }
+
+    fn wallet(&self) -> &LocalWallet {
+        &self.wallet
+    }
+
+    async fn request(&self, method: &str, _params: Value) -> Result<Value, Error> {
+        Err(format!("{method} not supported").into())
+    }
+
+    async fn send_transaction(
+        &self,
+        _to: Option<Address>,
+        _value: U256,
+        calldata: Vec<u8>,
+        _gas_limit: Option<U256>,
+    ) -> Result<H256, Error> {
+        let raw_tx = Bytes::from(calldata);
+        let hash = H256::from(ethers_core::utils::keccak256(&raw_tx));
+        self.chain.lock().unwrap().txpool.push(raw_tx);
+
+        Ok(hash)
+    }
+
+    async fn block_number(&self) -> Result<U64, Error> {
+        Ok(self.chain.lock().unwrap().head().number)
+    }
+
+    async fn get_logs(&self, _filter: &Filter) -> Result<Vec<Log>, Error> {
+        Ok(Vec::new())
+    }
+
+    async fn get_block(&self, number: U64) -> Result<Block<H256>, Error> {
+        let hash = self.chain.lock().unwrap().canonical[number.as_usize()];
+        self.block(hash, false)
+    }
+
+    async fn get_block_by_hash(&self, block_hash: H256) -> Result<Block<H256>, Error> {
+        self.block(block_hash, false)
+    }
+
+    async fn get_header(&self, block_hash: H256) -> Result<BlockHeader, Error> {
+        let header = self.chain.lock().unwrap().blocks[&block_hash].header();
+        serde_json::from_value(header).map_err(|e| e.to_string().into())
+    }
+
+    async fn latest_header(&self) -> Result<BlockHeader, Error> {
+        let header = self.chain.lock().unwrap().head().header();
+        serde_json::from_value(header).map_err(|e| e.to_string().into())
+    }
+
+    async fn txpool_status(&self) -> Result<TxpoolStatus, Error> {
+        Ok(TxpoolStatus {
+            pending: self.chain.lock().unwrap().txpool.len().into(),
+            queued: U64::zero(),
+        })
+    }
+
+    async fn init(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn seal_block(
+        &self,
+        parent: H256,
+        attributes: &BlockAttributes,
+        transactions: Option<&Vec<Bytes>>,
+    ) -> Result<Block<Transaction>, Error> {
+        let hash = {
+            let mut chain = self.chain.lock().unwrap();
+            let raw_txs = match transactions {
+                Some(txs) => txs.clone(),
+                None => std::mem::take(&mut chain.txpool),
+            };
+            chain.seal_block(parent, attributes.timestamp.into(), raw_txs)
+        };
+
+        self.block(hash, true)
+    }
+
+    async fn set_head(&self, block_hash: H256) -> Result<bool, Error> {
+        Ok(self.chain.lock().unwrap().set_head(block_hash))
+    }
+}
+
+#[tokio::test]
+async fn client_mock_l2_engine() {
+    let l1 = FakeNode::start(1, true).await;
+    let config = Config::parse_from([
+        "coordinator",
+        "--rpc-server-nodes",
+        "localhost:8545",
+        "--listen",
+        "[::]:8545",
+        "--l1-rpc-url",
+        &l1.uri.to_string(),
+        "--l1-bridge",
+        "0x936a70c0b28532aa22240dce21f89a8399d6ac60",
+        "--l1-priv",
+        PRIV_KEY,
+        "--l2-rpc-url",
+        "http://localhost:1",
+        "--dummy-prover",
+        "--circuit-name",
+        "super",
+    ]);
+
+    let methods = Arc::new(Mutex::new(Vec::new()));
+    let recording_l1 = RecordingL1 {
+        inner: RpcL1Client::connect(&config).await.expect("connect"),
+        methods: methods.clone(),
+    };
+    let chain = Arc::new(Mutex::new(Chain::new(99, false)));
+    let mock_l2 = MockL2 {
+        wallet: PRIV_KEY.parse().unwrap(),
+        chain: chain.clone(),
+    };
+    let state = SharedState::with_clients(&config, recording_l1, mock_l2).expect("state");
+
+    state.init().await;
+    assert_eq!(
+        *methods.lock().unwrap(),
+        ["eth_call", "send_transaction"],
+        "stateRoots and initGenesis"
+    );
+
+    state
+        .transaction_to_l2(None, U256::zero(), vec![1, 2, 3], None)
+        .await
+        .expect("transaction_to_l2");
+    state.mine().await.expect("mine");
+    let head = chain.lock().unwrap().head().clone();
+    assert_eq!(head.number.as_u64(), 1);
+    assert_eq!(head.transactions.len(), 1);
+    assert_eq!(state.rw.lock().await.chain_state.head_block_hash, head.hash);
+
+    // the clients are kept across configuration changes
+    let mut changed = config.clone();
+    changed.l1_priv = "2bdd21761a483f71054e14f5b827213567971c676928d9a1808cbfa4b7501202".into();
+    state.set_config(changed.clone()).await.expect("set_config");
+    changed.max_pending_proofs = 0;
+    assert!(matches!(
+        state.set_config(changed).await,
+        Err(Error::Config(_))
+    ));
+    methods.lock().unwrap().clear();
+    state.sync().await.expect("sync");
+    assert!(methods.lock().unwrap().contains(&"eth_getLogs".to_string()));
+}
+
+#[tokio::test]
+async fn client_errors() {
+    let l1 = FakeNode::start(1, true).await;
+    let client = RpcL1Client {
+        client: JsonRpcClient::builder(l1.uri.clone())
+            .retry(RetryPolicy::NONE)
+            .build(),
+        wallet: PRIV_KEY.parse().unwrap(),
+    };
+
+    l1.on("eth_call", |_| {
+        Some(Err("execution reverted: DMH".to_string()))
+    });
+    l1.on("eth_estimateGas", |_| {
+        Some(Err("gas limit reached".to_string()))
+    });
+    l1.on("eth_getTransactionByHash", |_| Some(Ok(Value::Null)));
+
+    let err = client
+        .call(Address::zero(), Bytes::default(), None)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, Error::Reverted { message, data: None } if message == "execution reverted: DMH"),
+        "{err}"
+    );
+    let err = client
+        .request("eth_estimateGas", serde_json::json!([]))
+        .await
+        .unwrap_err();
+    assert_eq!(err, Error::GasLimitReached);
+    let err = client
+        .request("eth_unknown", serde_json::json!([]))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "the method eth_unknown does not exist/is not available"
+    );
+    assert!(
+        matches!(&err, Error::Rpc(RpcError::Response(res)) if res.code == SERVER_ERROR),
+        "{err}"
+    );
+    let err = client.get_transaction(H256::zero()).await.unwrap_err();
+    assert_eq!(err, Error::Rpc(RpcError::NoResult));
+    assert!(!err.is_transient());
+
+    // network failures are errors, not panics
+    let unreachable = RpcL1Client {
+        client: JsonRpcClient::builder("http://localhost:1".parse().unwrap())
+            .retry(RetryPolicy::NONE)
+            .build(),
+        ..client
+    };
+    let err = unreachable.block_number().await.unwrap_err();
+    assert!(matches!(err, Error::Rpc(RpcError::Transport(_))), "{err}");
+    assert!(err.is_transient());
+}
+
+#[tokio::test]
+async fn client_retries_and_batches() {
+    let l1 = FakeNode::start(1, true).await;
+    let retry = RetryPolicy {
+        max_retries: 2,
+        backoff: 10,
+        max_backoff: 20,
+    };
+    let client = JsonRpcClient::builder(l1.uri.clone())
+        .retry(retry)
+        .retry_method("eth_sendRawTransaction", RetryPolicy::NONE)
+        .max_concurrent_requests(1)
+        .build();
+    assert_eq!(retry.delay(0).as_millis(), 10);
+    assert_eq!(retry.delay(5).as_millis(), 20);
+
+    // transient errors are retried up to `max_retries` times
+    l1.fail_requests(2);
+    let number: U64 = client
+        .request("eth_blockNumber", ())
+        .await
+        .expect("eth_blockNumber");
+    assert_eq!(number.as_u64(), 0);
+    l1.fail_requests(3);
+    let err = client
+        .request::<_, U64>("eth_blockNumber", ())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, RpcError::Transport(_)), "{err}");
+    l1.fail_requests(1);
+    let err = client
+        .request::<_, H256>("eth_sendRawTransaction", ["0x00"])
+        .await
+        .unwrap_err();
+    assert!(err.is_transient(), "{err}");
+    assert_eq!(l1.count("eth_blockNumber"), 1);
+    assert_eq!(l1.count("eth_sendRawTransaction"), 0);
+
+    // errors of single calls do not fail the batch
+    let results: Vec<Result<Value, RpcError>> = client
+        .batch(&[
+            ("eth_chainId", serde_json::json!([])),
+            ("eth_unknown", serde_json::json!([])),
+            ("eth_blockNumber", serde_json::json!([])),
+        ])
+        .await
+        .expect("batch");
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0], Ok(serde_json::json!("0x1")));
+    assert!(matches!(&results[1], Err(RpcError::Response(_))));
+    assert_eq!(results[2], Ok(serde_json::json!("0x0")));
+    assert!(client
+        .batch::<Value>(&[])
+        .await
+        .expect("empty batch")
+        .is_empty());
+
+    // the whole batch is retried
+    l1.fail_requests(1);
+    let results: Vec<Result<U64, RpcError>> = client
+        .batch(&[("eth_blockNumber", serde_json::json!([]))])
+        .await
+        .expect("batch");
+    assert_eq!(results, [Ok(U64::zero())]);
+}
@@ -31,7 +31,7 @@ pub(crate) async fn test_public_commitment(
         .expect("prev_block");
     let witness = state.request_witness(block_num).await.expect("witness");
     let state_root_prev = U256::from(prev_block.state_root.as_ref());
-    let chain_id = state.ro.l2_wallet.chain_id();
+    let chain_id = state.ro().l2.wallet.chain_id();
     let max_calldata = U256::from(circuit_config.max_calldata);
     let max_txs = U256::from(circuit_config.max_txs);
 
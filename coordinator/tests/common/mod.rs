@@ -143,10 +143,10 @@ macro_rules! await_state {
 macro_rules! sync {
     ($shared_state:expr) => {
         // sync bridge and process events
-        $shared_state.sync().await;
+        $shared_state.sync().await.expect("sync");
         while $shared_state.rw.lock().await.l1_message_queue.len() > 0 {
-            $shared_state.mine().await;
-            $shared_state.sync().await;
+            $shared_state.mine().await.expect("mine");
+            $shared_state.sync().await.expect("sync");
             sleep!(300);
         }
     };
@@ -203,15 +203,18 @@ macro_rules! finalize_chain {
             drop(rw);
 
             sync!($shared_state);
-            $shared_state.submit_blocks().await;
+            $shared_state.submit_blocks().await.expect("submit_blocks");
             let dummy_prover = $shared_state.config.lock().await.dummy_prover;
             if $use_dummy {
                 $shared_state.config.lock().await.dummy_prover = true;
             }
-            let result = $shared_state.finalize_blocks().await;
+            let mut result = $shared_state.request_proofs().await;
+            if result.is_ok() {
+                result = $shared_state.finalize_blocks().await;
+            }
             if result.is_err() {
                 let msg = result.err().unwrap();
-                if msg.find("deadline has elapsed").is_some() {
+                if msg.is_timeout() {
                     deadline_count += 1;
                     if deadline_count > MAX_DEADLINE_ERRORS {
                         panic!("finalize_chain: exceeded MAX_DEADLINE_ERRORS in finalize_blocks");
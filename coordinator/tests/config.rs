@@ -0,0 +1,165 @@
+use clap::Parser;
+use coordinator::config::{Config, RESTART_REQUIRED};
+use zkevm_common::config::{redacted, try_parse_from, REDACTED};
+
+fn config() -> Config {
+    Config::parse_from([
+        "coordinator",
+        "--rpc-server-nodes",
+        "server-testnet-geth:8545",
+        "--listen",
+        "[::]:8545",
+        "--l1-rpc-url",
+        "http://l1-testnet-geth:8545",
+        "--l1-bridge",
+        "0x936a70c0b28532aa22240dce21f89a8399d6ac60",
+        "--l1-priv",
+        "2bdd21761a483f71054e14f5b827213567971c676928d9a1808cbfa4b7501201",
+        "--l2-rpc-url",
+        "http://leader-testnet-geth:8545",
+        "--prover-rpcd-url",
+        "http://prover-rpcd:8545",
+        "--circuit-name",
+        "super",
+        "--verifier-label-fallback",
+    ])
+}
+
+#[test]
+fn config_merge() {
+    let config = config();
+    config.validate().expect("validate");
+
+    let merged = config
+        .merge(serde_json::json!({
+            "max_pending_proofs": 8,
+            "listen": "127.0.0.1:8000",
+        }))
+        .expect("merge");
+    assert_eq!(merged.max_pending_proofs, 8);
+    assert_eq!(merged.circuit_name, config.circuit_name);
+
+    let mut changed = config.changed_fields(&merged);
+    changed.sort();
+    assert_eq!(changed, ["listen", "max_pending_proofs"]);
+
+    assert!(config.merge(serde_json::json!({ "unknown": 1 })).is_err());
+    assert!(config.merge(serde_json::json!([])).is_err());
+}
+
+#[test]
+fn config_restart_required() {
+    let config = config();
+    let mut merged = config
+        .merge(serde_json::json!({
+            "listen": "127.0.0.1:8000",
+            "l1_bridge": "0x0000000000000000000000000000000000000001",
+            "rpc_token": "secret",
+            "config_file": "coordinator.toml",
+            "max_pending_proofs": 8,
+        }))
+        .expect("merge");
+    let mut changed = config.changed_fields(&merged);
+    changed.retain(|field| field != "max_pending_proofs");
+    changed.sort();
+    let mut restart_required = RESTART_REQUIRED.to_vec();
+    restart_required.sort();
+    assert_eq!(changed, restart_required);
+
+    // the values in effect are kept
+    merged.keep_restart_required(&config);
+    assert_eq!(config.changed_fields(&merged), ["max_pending_proofs"]);
+    assert!(merged.rpc_token.is_none());
+}
+
+#[test]
+fn config_redacted() {
+    let config = config();
+    let value = redacted(&config);
+    assert_eq!(value["l1_priv"], REDACTED);
+    // not set
+    assert!(value["l2_jwt_secret"].is_null());
+    assert!(value["rpc_token"].is_null());
+    assert_eq!(value["circuit_name"], "super");
+    assert_eq!(value["l1_bridge"], serde_json::json!(config.l1_bridge));
+
+    let config = config
+        .merge(serde_json::json!({ "rpc_token": "secret" }))
+        .unwrap();
+    assert_eq!(redacted(&config)["rpc_token"], REDACTED);
+}
+
+#[test]
+fn config_validate() {
+    let config = config();
+
+    let invalid = [
+        serde_json::json!({ "rpc_server_nodes": "server-testnet-geth" }),
+        serde_json::json!({ "l1_priv": "0x1234" }),
+        serde_json::json!({ "max_pending_proofs": 0 }),
+        serde_json::json!({ "rpc_max_concurrent_requests": 0 }),
+        serde_json::json!({ "prover_rpcd_url": [] }),
+        serde_json::json!({ "target_block_gas": 1000000 }),
+        serde_json::json!({ "heartbeat_blocks": true }),
+        serde_json::json!({ "min_block_interval": 10, "max_block_interval": 5 }),
+        serde_json::json!({ "verifier_label_fallback": false }),
+    ];
+    for overlay in invalid {
+        let merged = config.merge(overlay.clone()).expect("merge");
+        assert!(merged.validate().is_err(), "{overlay}");
+    }
+
+    let dummy = config
+        .merge(serde_json::json!({ "prover_rpcd_url": [], "dummy_prover": true }))
+        .expect("merge");
+    dummy.validate().expect("validate");
+}
+
+#[test]
+fn config_layered() {
+    let dir = std::env::temp_dir().join(format!("coordinator-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("coordinator.toml");
+    std::fs::write(
+        &path,
+        r#"
+rpc_server_nodes = "server-testnet-geth:8545"
+listen = "[::]:8545"
+l1-rpc-url = "http://l1-testnet-geth:8545"
+l1_bridge = "0x936a70c0b28532aa22240dce21f89a8399d6ac60"
+l1_priv = "2bdd21761a483f71054e14f5b827213567971c676928d9a1808cbfa4b7501201"
+l2_rpc_url = "http://leader-testnet-geth:8545"
+prover_rpcd_url = ["super=http://prover-a:8545", "http://prover-b:8545"]
+circuit_name = "super"
+verifier_label_fallback = true
+max_pending_proofs = 8
+enable_faucet = true
+"#,
+    )
+    .unwrap();
+    let path = path.to_str().unwrap();
+
+    let config: Config = try_parse_from(["coordinator", "--config-file", path]).expect("parse");
+    assert_eq!(config.max_pending_proofs, 8);
+    assert_eq!(config.prover_rpcd_url.len(), 2);
+    assert!(config.enable_faucet);
+    config.validate().expect("validate");
+
+    // command line arguments take precedence
+    let config: Config =
+        try_parse_from(["coordinator", "--config-file", path, "--circuit-name", "pi"])
+            .expect("parse");
+    assert_eq!(config.circuit_name, "pi");
+    assert_eq!(config.max_pending_proofs, 8);
+
+    std::fs::write(dir.join("unknown.yaml"), "circuit_name: pi\nunknown: 1\n").unwrap();
+    let path = dir.join("unknown.yaml");
+    assert!(try_parse_from::<Config, _, _>([
+        "coordinator",
+        "--config-file",
+        path.to_str().unwrap()
+    ])
+    .is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
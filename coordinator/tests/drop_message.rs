@@ -2,6 +2,7 @@ mod common;
 
 use crate::common::get_shared_state;
 use crate::common::zkevm_abi;
+use coordinator::error::Error;
 use coordinator::shared_state::SharedState;
 use coordinator::structs::BlockHeader;
 use coordinator::structs::MerkleProofRequest;
@@ -38,8 +39,8 @@ async fn trigger_l1_block_import(shared_state: &SharedState) {
         .transaction_to_l1(Some(l1_bridge_addr), U256::zero(), calldata)
         .await
         .expect("dispatchMessage");
-    shared_state.sync().await;
-    shared_state.mine().await;
+    shared_state.sync().await.expect("sync");
+    shared_state.mine().await.expect("mine");
     finalize_chain!(shared_state, true);
 }
 
@@ -57,13 +58,13 @@ async fn trigger_l2_block_import(shared_state: &SharedState) {
             Bytes::from([]).into_token(),
         ])
         .unwrap();
-    let l2_bridge_addr = shared_state.ro.l2_message_dispatcher_addr;
+    let l2_bridge_addr = shared_state.ro().l2_message_dispatcher_addr;
     shared_state
         .transaction_to_l2(Some(l2_bridge_addr), U256::zero(), calldata, None)
         .await
         .expect("dispatchMessage");
-    shared_state.sync().await;
-    shared_state.mine().await;
+    shared_state.sync().await.expect("sync");
+    shared_state.mine().await.expect("mine");
     finalize_chain!(shared_state, true);
 }
 
@@ -71,9 +72,9 @@ async fn drop_message_l1(
     shared_state: &SharedState,
     msg: &MessageBeacon,
     proof_bytes: Option<Bytes>,
-) -> Result<TransactionReceipt, String> {
+) -> Result<TransactionReceipt, Error> {
     let l1_bridge_addr = shared_state.config.lock().await.l1_bridge;
-    let l2_bridge_addr = shared_state.ro.l2_message_dispatcher_addr;
+    let l2_bridge_addr = shared_state.ro().l2_message_dispatcher_addr;
     let abi = zkevm_abi();
     let mut account_proof = Bytes::from([]);
     let block_hash = shared_state
@@ -156,9 +157,9 @@ async fn drop_message_l2(
     shared_state: &SharedState,
     msg: &MessageBeacon,
     proof_bytes: Option<Bytes>,
-) -> Result<H256, String> {
+) -> Result<H256, Error> {
     let l1_bridge_addr = shared_state.config.lock().await.l1_bridge;
-    let l2_bridge_addr = shared_state.ro.l2_message_dispatcher_addr;
+    let l2_bridge_addr = shared_state.ro().l2_message_dispatcher_addr;
     let abi = zkevm_abi();
     let proof = match proof_bytes {
         Some(value) => value,
@@ -184,7 +185,7 @@ async fn drop_message_l2(
                     .from_block(from_block)
                     .to_block(l2_block_num)
                     .address(ValueOrArray::Value(
-                        shared_state.ro.l2_message_deliverer_addr,
+                        shared_state.ro().l2_message_deliverer_addr,
                     ))
                     .topic0(ValueOrArray::Array(vec![evt.signature()]));
 
@@ -231,7 +232,7 @@ async fn drop_message_l2(
 }
 
 fn gen_l1_message(shared_state: &SharedState) -> MessageBeacon {
-    let from = shared_state.ro.l1_wallet.address();
+    let from = shared_state.ro().l1.wallet.address();
     let to = Address::zero();
     let value = U256::from(1u64);
     let fee = U256::zero();
@@ -255,7 +256,7 @@ fn gen_l1_message(shared_state: &SharedState) -> MessageBeacon {
 }
 
 fn gen_l2_message(shared_state: &SharedState) -> MessageBeacon {
-    let from = shared_state.ro.l2_wallet.address();
+    let from = shared_state.ro().l2.wallet.address();
     let to = Address::zero();
     let value = U256::from(1u64);
     let fee = U256::zero();
@@ -281,7 +282,7 @@ fn gen_l2_message(shared_state: &SharedState) -> MessageBeacon {
 async fn dispatch_msg_l1(
     shared_state: &SharedState,
     msg: &MessageBeacon,
-) -> Result<TransactionReceipt, String> {
+) -> Result<TransactionReceipt, Error> {
     let abi = zkevm_abi();
     let calldata = abi
         .function("dispatchMessage")
@@ -300,7 +301,7 @@ async fn dispatch_msg_l1(
         .await
 }
 
-async fn dispatch_msg_l2(shared_state: &SharedState, msg: &MessageBeacon) -> Result<H256, String> {
+async fn dispatch_msg_l2(shared_state: &SharedState, msg: &MessageBeacon) -> Result<H256, Error> {
     let abi = zkevm_abi();
     let calldata = abi
         .function("dispatchMessage")
@@ -313,7 +314,7 @@ async fn dispatch_msg_l2(shared_state: &SharedState, msg: &MessageBeacon) -> Res
             Bytes::from(msg.calldata.clone()).into_token(),
         ])
         .expect("dispatchMessage");
-    let l2_bridge_addr = Some(shared_state.ro.l2_message_dispatcher_addr);
+    let l2_bridge_addr = Some(shared_state.ro().l2_message_dispatcher_addr);
     shared_state
         .transaction_to_l2(l2_bridge_addr, msg.value + msg.fee, calldata, None)
         .await
@@ -330,7 +331,10 @@ async fn l1_drop_message() {
             .await
             .expect("dispatch_message success");
         assert_eq!(
-            dispatch_msg_l1(&shared_state, &msg).await.unwrap_err(),
+            dispatch_msg_l1(&shared_state, &msg)
+                .await
+                .unwrap_err()
+                .to_string(),
             "execution reverted: DMH"
         );
     }
@@ -345,7 +349,8 @@ async fn l1_drop_message() {
         assert_eq!(
             drop_message_l1(&shared_state, &msg, None)
                 .await
-                .unwrap_err(),
+                .unwrap_err()
+                .to_string(),
             "execution reverted: DMH"
         );
     }
@@ -360,14 +365,17 @@ async fn l2_drop_message() {
 
     // dispatch
     {
-        shared_state.mine().await;
+        shared_state.mine().await.expect("mine");
         dispatch_msg_l2(&shared_state, &msg)
             .await
             .expect("dispatch_message success");
 
-        shared_state.mine().await;
+        shared_state.mine().await.expect("mine");
         assert_eq!(
-            dispatch_msg_l2(&shared_state, &msg).await.unwrap_err(),
+            dispatch_msg_l2(&shared_state, &msg)
+                .await
+                .unwrap_err()
+                .to_string(),
             "execution reverted: DMH"
         );
     }
@@ -376,21 +384,22 @@ async fn l2_drop_message() {
 
     // drop
     {
-        shared_state.mine().await;
+        shared_state.mine().await.expect("mine");
         drop_message_l2(&shared_state, &msg, None)
             .await
             .expect("drop_message success");
 
-        shared_state.mine().await;
+        shared_state.mine().await.expect("mine");
         assert_eq!(
             drop_message_l2(&shared_state, &msg, None)
                 .await
-                .unwrap_err(),
+                .unwrap_err()
+                .to_string(),
             "execution reverted: DMH"
         );
     }
 
-    shared_state.mine().await;
+    shared_state.mine().await.expect("mine");
     finalize_chain!(shared_state);
 }
 
@@ -401,14 +410,17 @@ async fn l2_drop_message_zero_length_proof() {
 
     // dispatch
     {
-        shared_state.mine().await;
+        shared_state.mine().await.expect("mine");
         dispatch_msg_l2(&shared_state, &msg)
             .await
             .expect("dispatch_message success");
 
-        shared_state.mine().await;
+        shared_state.mine().await.expect("mine");
         assert_eq!(
-            dispatch_msg_l2(&shared_state, &msg).await.unwrap_err(),
+            dispatch_msg_l2(&shared_state, &msg)
+                .await
+                .unwrap_err()
+                .to_string(),
             "execution reverted: DMH"
         );
     }
@@ -417,16 +429,17 @@ async fn l2_drop_message_zero_length_proof() {
 
     // drop
     {
-        shared_state.mine().await;
+        shared_state.mine().await.expect("mine");
         assert_eq!(
             drop_message_l2(&shared_state, &msg, Some(Bytes::from([])))
                 .await
-                .unwrap_err(),
+                .unwrap_err()
+                .to_string(),
             "execution reverted: BOUNDS"
         );
     }
 
-    shared_state.mine().await;
+    shared_state.mine().await.expect("mine");
     finalize_chain!(shared_state);
 }
 
@@ -443,7 +456,10 @@ async fn l1_drop_message_deadline_not_reached() {
             .await
             .expect("dispatch_message success");
         assert_eq!(
-            dispatch_msg_l1(&shared_state, &msg).await.unwrap_err(),
+            dispatch_msg_l1(&shared_state, &msg)
+                .await
+                .unwrap_err()
+                .to_string(),
             "execution reverted: DMH"
         );
     }
@@ -455,7 +471,8 @@ async fn l1_drop_message_deadline_not_reached() {
         assert_eq!(
             drop_message_l1(&shared_state, &msg, None)
                 .await
-                .unwrap_err(),
+                .unwrap_err()
+                .to_string(),
             "execution reverted: DMTS"
         );
     }
@@ -0,0 +1,128 @@
+use clap::Parser;
+use coordinator::config::Config;
+use coordinator::engine_api::{jwt_token, EngineApi};
+use coordinator::error::Error;
+use coordinator::shared_state::SharedState;
+use ethers_core::abi::{encode, Token};
+use ethers_core::types::{Address, U256};
+use std::time::SystemTime;
+use zkevm_common::json_rpc::RpcError;
+use zkevm_fake_node::{FakeEvent, FakeNode};
+
+const L1_BRIDGE: &str = "0x936a70c0b28532aa22240dce21f89a8399d6ac60";
+const JWT_SECRET: [u8; 32] = [0x11; 32];
+
+async fn setup() -> (FakeNode, FakeNode, SharedState) {
+    let l1 = FakeNode::start(1, true).await;
+    let l2 = FakeNode::start(99, false).await;
+    l2.require_jwt(JWT_SECRET.to_vec());
+
+    let path = std::env::temp_dir().join(format!("coordinator-jwt-{}", std::process::id()));
+    std::fs::write(&path, format!("0x{}\n", hex::encode(JWT_SECRET))).unwrap();
+    let config = Config::parse_from([
+        "coordinator",
+        "--rpc-server-nodes",
+        "localhost:8545",
+        "--listen",
+        "[::]:8545",
+        "--l1-rpc-url",
+        &l1.uri.to_string(),
+        "--l1-bridge",
+        L1_BRIDGE,
+        "--l1-priv",
+        "2bdd21761a483f71054e14f5b827213567971c676928d9a1808cbfa4b7501201",
+        "--l2-rpc-url",
+        &l2.uri.to_string(),
+        "--l2-block-builder",
+        "engine",
+        "--l2-engine-url",
+        &l2.uri.to_string(),
+        "--l2-jwt-secret",
+        path.to_str().unwrap(),
+        "--dummy-prover",
+        "--circuit-name",
+        "super",
+    ]);
+    config.validate().expect("validate");
+    let state = SharedState::new(&config).await;
+
+    (l1, l2, state)
+}
+
+fn message_dispatched(state: &SharedState, address: Address) -> FakeEvent {
+    let deadline = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 3600;
+    let data = encode(&[
+        Token::Address(Address::from_low_u64_be(1)),
+        Token::Address(Address::from_low_u64_be(2)),
+        Token::Uint(U256::from(3)),
+        Token::Uint(U256::zero()),
+        Token::Uint(U256::from(deadline)),
+        Token::Uint(U256::from(4)),
+        Token::Bytes(Vec::new()),
+    ]);
+
+    FakeEvent {
+        address,
+        topics: vec![state.ro().message_dispatched_topic],
+        data: data.into(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn engine_api_mine() {
+    let (l1, l2, state) = setup().await;
+    state.init().await;
+
+    // transactions from the pool
+    state
+        .transaction_to_l2(Some(Address::zero()), U256::one(), Vec::new(), None)
+        .await
+        .expect("transaction_to_l2");
+    state.mine().await.expect("mine");
+    let head = l2.head();
+    assert_eq!(head.number.as_u64(), 1);
+    assert_eq!(head.transactions.len(), 1);
+    assert_eq!(state.rw.lock().await.chain_state.head_block_hash, head.hash);
+
+    // L1 > L2 messages go through the transaction pool
+    l1.mine(vec![message_dispatched(&state, L1_BRIDGE.parse().unwrap())]);
+    state.sync().await.expect("sync");
+    state.mine().await.expect("mine");
+    let head = l2.head();
+    assert_eq!(head.number.as_u64(), 2);
+    assert_eq!(head.transactions.len(), 3);
+    assert!(state.rw.lock().await.l1_message_queue.is_empty());
+
+    assert_eq!(l2.count("miner_sealBlock"), 0);
+    assert_eq!(l2.count("miner_setHead"), 0);
+    // including the temporary blocks for gas estimation of the L1 > L2 messages
+    assert_eq!(l2.count("engine_getPayloadV1"), 4);
+    assert_eq!(l2.count("engine_newPayloadV1"), 4);
+}
+
+#[tokio::test]
+async fn engine_api_jwt() {
+    let l2 = FakeNode::start(99, false).await;
+    l2.require_jwt(JWT_SECRET.to_vec());
+    let head = l2.head().hash;
+
+    let engine_api = EngineApi::new(l2.uri.clone(), JWT_SECRET.to_vec());
+    assert!(engine_api.set_head(head).await.expect("set_head"));
+
+    let engine_api = EngineApi::new(l2.uri.clone(), vec![0x22; 32]);
+    let err = engine_api.set_head(head).await.unwrap_err();
+    assert!(
+        matches!(err, Error::Rpc(RpcError::Unauthorized(_))),
+        "{err}"
+    );
+
+    // the token is deterministic for a given secret and issued-at time
+    assert_eq!(jwt_token(&JWT_SECRET, 1), jwt_token(&JWT_SECRET, 1));
+    assert_ne!(jwt_token(&JWT_SECRET, 1), jwt_token(&JWT_SECRET, 2));
+    assert_eq!(jwt_token(&JWT_SECRET, 1).split('.').count(), 3);
+}
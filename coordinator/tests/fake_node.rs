@@ -0,0 +1,736 @@
+use clap::Parser;
+use coordinator::client::L2Engine;
+use coordinator::config::Config;
+use coordinator::shared_state::SharedState;
+use coordinator::utils::encode_verifier_witness;
+use ethers_core::abi::{encode, Token};
+use ethers_core::types::{Address, Bytes, Transaction, H256, U256, U64};
+use ethers_core::utils::keccak256;
+use ethers_core::utils::rlp;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+use zkevm_fake_node::{FakeEvent, FakeNode};
+
+const L1_BRIDGE: &str = "0x936a70c0b28532aa22240dce21f89a8399d6ac60";
+
+async fn setup() -> (FakeNode, FakeNode, SharedState) {
+    let l1 = FakeNode::start(1, true).await;
+    let l2 = FakeNode::start(99, false).await;
+    let config = Config::parse_from([
+        "coordinator",
+        "--rpc-server-nodes",
+        "localhost:8545",
+        "--listen",
+        "[::]:8545",
+        "--l1-rpc-url",
+        &l1.uri.to_string(),
+        "--l1-bridge",
+        L1_BRIDGE,
+        "--l1-priv",
+        "2bdd21761a483f71054e14f5b827213567971c676928d9a1808cbfa4b7501201",
+        "--l2-rpc-url",
+        &l2.uri.to_string(),
+        "--dummy-prover",
+        "--circuit-name",
+        "super",
+    ]);
+    let state = SharedState::new(&config).await;
+
+    (l1, l2, state)
+}
+
+fn message_dispatched(state: &SharedState, address: Address) -> FakeEvent {
+    let deadline = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 3600;
+    message_dispatched_by(state, address, deadline)
+}
+
+fn message_dispatched_by(state: &SharedState, address: Address, deadline: u64) -> FakeEvent {
+    let data = encode(&[
+        Token::Address(Address::from_low_u64_be(1)),
+        Token::Address(Address::from_low_u64_be(2)),
+        Token::Uint(U256::from(3)),
+        Token::Uint(U256::zero()),
+        Token::Uint(U256::from(deadline)),
+        Token::Uint(U256::from(4)),
+        Token::Bytes(Vec::new()),
+    ]);
+
+    FakeEvent {
+        address,
+        topics: vec![state.ro().message_dispatched_topic],
+        data: data.into(),
+        ..Default::default()
+    }
+}
+
+fn calldata_selector(raw_tx: &Bytes, selector: &str) -> bool {
+    let selector = &keccak256(selector)[0..4];
+    raw_tx.as_ref().windows(4).any(|window| window == selector)
+}
+
+#[tokio::test]
+async fn fake_node_init_and_sync() {
+    let (l1, l2, state) = setup().await;
+
+    state.init().await;
+    let genesis = l2.head().hash;
+    assert_eq!(state.rw.lock().await.chain_state.head_block_hash, genesis);
+    // the bridge is not initialized yet, `stateRoots` returns zero
+    let sent = l1.sent_transactions();
+    assert_eq!(sent.len(), 1);
+    assert!(calldata_selector(&sent[0], "initGenesis(bytes32,bytes32)"));
+
+    // a L2 > L1 message in a block that gets finalized on L1
+    let dispatcher = state.ro().l2_message_dispatcher_addr;
+    let l2_block = l2.mine(vec![message_dispatched(&state, dispatcher)]);
+    let l1_bridge = L1_BRIDGE.parse().unwrap();
+    l1.mine(vec![
+        message_dispatched(&state, l1_bridge),
+        FakeEvent {
+            address: l1_bridge,
+            topics: vec![state.ro().block_finalized_topic],
+            data: l2_block.as_bytes().to_vec().into(),
+            ..Default::default()
+        },
+        // ignored, emitted by another contract
+        FakeEvent {
+            address: Address::from_low_u64_be(1),
+            topics: vec![state.ro().block_finalized_topic],
+            data: H256::zero().as_bytes().to_vec().into(),
+            ..Default::default()
+        },
+    ]);
+
+    state.sync().await.expect("sync");
+    {
+        let rw = state.rw.lock().await;
+        assert_eq!(rw.chain_state.finalized_block_hash, l2_block);
+        assert_eq!(rw.l1_message_queue.len(), 1);
+        assert_eq!(rw.l2_message_queue.len(), 1);
+        assert_eq!(rw.l1_last_sync_block, l1.head().number);
+    }
+
+    // syncing again is a no-op
+    state.sync().await.expect("sync");
+    assert_eq!(state.rw.lock().await.l1_message_queue.len(), 1);
+
+    state.relay_to_l1().await;
+    assert!(state.rw.lock().await.l2_message_queue.is_empty());
+    let sent = l1.sent_transactions();
+    assert_eq!(sent.len(), 2);
+    assert!(calldata_selector(
+        &sent[1],
+        "deliverMessageWithProof(address,address,uint256,uint256,uint256,uint256,bytes,bytes)"
+    ));
+}
+
+#[tokio::test]
+async fn fake_node_sync_errors() {
+    let (l1, _l2, state) = setup().await;
+    state.init().await;
+
+    let failing = Arc::new(AtomicBool::new(true));
+    {
+        let failing = failing.clone();
+        l1.on("eth_getLogs", move |_| {
+            match failing.load(Ordering::SeqCst) {
+                true => Some(Err("unavailable".to_string())),
+                false => None,
+            }
+        });
+    }
+
+    // the range is synced again in the next round
+    l1.mine(vec![message_dispatched(&state, L1_BRIDGE.parse().unwrap())]);
+    assert!(state.sync().await.is_err());
+    {
+        let rw = state.rw.lock().await;
+        assert!(rw.l1_message_queue.is_empty());
+        assert_eq!(rw.l1_last_sync_block, U64::zero());
+    }
+
+    failing.store(false, Ordering::SeqCst);
+    state.sync().await.expect("sync");
+    let rw = state.rw.lock().await;
+    assert_eq!(rw.l1_message_queue.len(), 1);
+    assert_eq!(rw.l1_last_sync_block, l1.head().number);
+}
+
+#[tokio::test]
+async fn fake_node_mine() {
+    let (l1, l2, state) = setup().await;
+    state.init().await;
+
+    // nothing to do
+    state.mine().await.expect("mine");
+    assert_eq!(l2.count("miner_sealBlock"), 0);
+    assert_eq!(l2.head().number.as_u64(), 0);
+
+    // transactions from the pool
+    state
+        .transaction_to_l2(Some(Address::zero()), U256::one(), Vec::new(), None)
+        .await
+        .expect("transaction_to_l2");
+    assert_eq!(l2.head().number.as_u64(), 0);
+    state.mine().await.expect("mine");
+    let head = l2.head();
+    assert_eq!(head.number.as_u64(), 1);
+    assert_eq!(head.transactions.len(), 1);
+    assert_eq!(state.rw.lock().await.chain_state.head_block_hash, head.hash);
+
+    // L1 > L2 messages are delivered in a block of their own
+    l1.mine(vec![message_dispatched(&state, L1_BRIDGE.parse().unwrap())]);
+    state.sync().await.expect("sync");
+    state.mine().await.expect("mine");
+    let head = l2.head();
+    assert_eq!(head.number.as_u64(), 2);
+    // importForeignBlock, importForeignBridgeState, deliverMessageWithProof
+    assert_eq!(head.transactions.len(), 3);
+    assert!(state.rw.lock().await.l1_message_queue.is_empty());
+}
+
+fn forced_transaction_queued(state: &SharedState, raw_tx: &Bytes, deadline: u64) -> FakeEvent {
+    let data = encode(&[
+        Token::FixedBytes(keccak256(raw_tx).to_vec()),
+        Token::Uint(U256::from(deadline)),
+        Token::Bytes(raw_tx.to_vec()),
+    ]);
+
+    FakeEvent {
+        address: L1_BRIDGE.parse().unwrap(),
+        topics: vec![state.ro().forced_transaction_topic],
+        data: data.into(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn fake_node_forced_transactions() {
+    let (l1, l2, state) = setup().await;
+    state.init().await;
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    // included in a block of its own, even with an empty transaction pool
+    let raw_tx = state
+        .sign_l2(Some(Address::zero()), U256::one(), U256::zero(), Vec::new())
+        .await
+        .expect("sign_l2");
+    l1.mine(vec![forced_transaction_queued(&state, &raw_tx, now + 3600)]);
+    state.sync().await.expect("sync");
+    assert_eq!(state.rw.lock().await.forced_transactions.len(), 1);
+    state.mine().await.expect("mine");
+    let head = l2.head();
+    assert_eq!(head.number.as_u64(), 1);
+    assert_eq!(head.transactions, [H256::from(keccak256(&raw_tx))]);
+    assert!(state.rw.lock().await.forced_transactions.is_empty());
+
+    // the L2 node rejects the transaction, the deadline is within the alert margin
+    l2.on("miner_sealBlock", |_| Some(Err("rejected".to_string())));
+    let raw_tx = state
+        .sign_l2(Some(Address::zero()), U256::one(), U256::one(), Vec::new())
+        .await
+        .expect("sign_l2");
+    l1.mine(vec![forced_transaction_queued(&state, &raw_tx, now + 60)]);
+    state.sync().await.expect("sync");
+    state.mine().await.expect("mine");
+    assert_eq!(l2.head().number.as_u64(), 1);
+    {
+        let rw = state.rw.lock().await;
+        assert_eq!(rw.forced_transactions.len(), 1);
+        assert!(rw.forced_transactions[0].at_risk);
+    }
+
+    // dropped once the deadline passed
+    state.rw.lock().await.forced_transactions[0].deadline = U256::from(now - 1);
+    state.mine().await.expect("mine");
+    assert!(state.rw.lock().await.forced_transactions.is_empty());
+}
+
+#[tokio::test]
+async fn fake_node_block_policy() {
+    let (l1, l2, state) = setup().await;
+    state.init().await;
+    // the nonce is explicit, transactions may be pending for a while
+    let send = |nonce: u64| {
+        let state = state.clone();
+        async move {
+            let raw_tx = state
+                .sign_l2(Some(Address::zero()), U256::one(), nonce.into(), Vec::new())
+                .await
+                .expect("sign_l2");
+            state
+                .request_l2::<_, H256>("eth_sendRawTransaction", [raw_tx])
+                .await
+                .expect("eth_sendRawTransaction");
+        }
+    };
+
+    // the genesis block is older than the minimum interval, the next block is not
+    {
+        let mut config = state.config.lock().await;
+        config.min_block_interval = 3600;
+        config.l2_coinbase = Address::from_low_u64_be(7);
+        config.l2_block_random = H256::from_low_u64_be(8);
+    }
+    send(0).await;
+    state.mine().await.expect("mine");
+    assert_eq!(l2.head().number.as_u64(), 1);
+    let (_, params) = l2
+        .requests()
+        .into_iter()
+        .rfind(|(method, _)| method == "miner_sealBlock")
+        .unwrap();
+    assert_eq!(
+        params[0]["coinbase"],
+        serde_json::json!(Address::from_low_u64_be(7))
+    );
+    assert_eq!(
+        params[0]["random"],
+        serde_json::json!(H256::from_low_u64_be(8))
+    );
+    send(1).await;
+    state.mine().await.expect("mine");
+    assert_eq!(l2.head().number.as_u64(), 1);
+
+    // waits for the target gas, the fake node estimates 1M gas per transaction
+    {
+        let mut config = state.config.lock().await;
+        config.min_block_interval = 0;
+        config.max_block_interval = 3600;
+        config.target_block_gas = 1_500_000;
+    }
+    state.mine().await.expect("mine");
+    assert_eq!(l2.head().number.as_u64(), 1);
+    send(2).await;
+    state.mine().await.expect("mine");
+    assert_eq!(l2.head().number.as_u64(), 2);
+    assert_eq!(l2.head().transactions.len(), 2);
+
+    // empty heartbeat blocks
+    {
+        let mut config = state.config.lock().await;
+        config.max_block_interval = 1;
+        config.heartbeat_blocks = true;
+    }
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    state.mine().await.expect("mine");
+    assert_eq!(l2.head().number.as_u64(), 3);
+    assert!(l2.head().transactions.is_empty());
+
+    // timestamps follow L1, up to the maximum drift
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let anchor = |timestamp: u64| {
+        l1.with_chain(|chain| {
+            let head = chain.head().hash;
+            let hash = chain.seal_block(head, U256::from(timestamp), Vec::new());
+            chain.set_head(hash);
+        })
+    };
+    {
+        let mut config = state.config.lock().await;
+        config.heartbeat_blocks = false;
+        config.target_block_gas = 0;
+        config.l1_anchored_timestamps = true;
+        config.max_timestamp_drift = 60;
+    }
+    anchor(now + 30);
+    send(3).await;
+    state.mine().await.expect("mine");
+    let head = l2.head();
+    assert_eq!(head.number.as_u64(), 4);
+    assert_eq!(head.timestamp, U256::from(now + 30));
+
+    anchor(now + 3600);
+    send(4).await;
+    state.mine().await.expect("mine");
+    assert_eq!(l2.head().number.as_u64(), 4);
+}
+
+#[tokio::test]
+async fn fake_node_l2_recovery() {
+    let (l1, l2, state) = setup().await;
+    state.init().await;
+    state
+        .transaction_to_l2(Some(Address::zero()), U256::one(), Vec::new(), None)
+        .await
+        .expect("transaction_to_l2");
+    state.mine().await.expect("mine");
+    let block = l2.head();
+
+    // the L1 bridge is a plain account on the fake node, emit the event of `submitBlock`
+    state.submit_blocks().await.expect("submit_blocks");
+    let tx: Transaction = rlp::decode(l1.sent_transactions().last().unwrap()).unwrap();
+    assert!(calldata_selector(&tx.input, "submitBlock(bytes)"));
+    l1.mine(vec![FakeEvent {
+        address: L1_BRIDGE.parse().unwrap(),
+        topics: vec![state.ro().block_beacon_topic],
+        input: tx.input,
+        ..Default::default()
+    }]);
+    state.sync().await.expect("sync");
+    assert_eq!(
+        state.rw.lock().await.chain_state.safe_block_hash,
+        block.hash
+    );
+
+    // the node lost the submitted block
+    l2.with_chain(|chain| {
+        chain.canonical.truncate(1);
+        chain.blocks.remove(&block.hash);
+    });
+    state.mine().await.expect("mine");
+    assert_eq!(l2.head().hash, block.hash);
+    assert_eq!(l2.head().transactions, block.transactions);
+
+    // the node switched to another chain
+    l2.with_chain(|chain| {
+        let fork = chain.seal_block(chain.canonical[0], U256::from(1), Vec::new());
+        chain.set_head(fork);
+    });
+    state.mine().await.expect("mine");
+    assert_eq!(l2.head().hash, block.hash);
+    assert_eq!(
+        state.rw.lock().await.chain_state.head_block_hash,
+        block.hash
+    );
+}
+
+#[tokio::test]
+async fn fake_node_message_dead_letters() {
+    let (l1, l2, state) = setup().await;
+    state.init().await;
+    {
+        let mut config = state.config.lock().await;
+        config.message_retry_backoff = 0;
+        config.message_max_attempts = 2;
+    }
+
+    // the delivery of L1 > L2 messages fails until `failing` is cleared
+    let failing = Arc::new(AtomicBool::new(true));
+    let selector = Bytes::from(
+        keccak256(
+            "deliverMessageWithProof(address,address,uint256,uint256,uint256,uint256,bytes,bytes)",
+        )[0..4]
+            .to_vec(),
+    );
+    {
+        let failing = failing.clone();
+        l2.on("eth_estimateGas", move |params| {
+            let data = params[0]["data"].as_str().unwrap_or_default();
+            match failing.load(Ordering::SeqCst) && data.starts_with(&format!("{selector}")) {
+                true => Some(Err("execution reverted".to_string())),
+                false => None,
+            }
+        });
+    }
+
+    l1.mine(vec![message_dispatched(&state, L1_BRIDGE.parse().unwrap())]);
+    state.sync().await.expect("sync");
+    state.mine().await.expect("mine");
+    {
+        let rw = state.rw.lock().await;
+        assert_eq!(rw.l1_message_queue.len(), 1);
+        assert_eq!(rw.l1_message_queue[0].attempts, 1);
+        assert!(rw.l1_message_queue[0].last_error.is_some());
+        assert!(rw.l1_dead_letters.is_empty());
+    }
+
+    // moved to the dead letters after `message_max_attempts`
+    state.mine().await.expect("mine");
+    let id = {
+        let rw = state.rw.lock().await;
+        assert!(rw.l1_message_queue.is_empty());
+        assert_eq!(rw.l1_dead_letters.len(), 1);
+        assert_eq!(rw.l1_dead_letters[0].attempts, 2);
+        rw.l1_dead_letters[0].message.id
+    };
+    // nothing is due anymore
+    let head = l2.head().number.as_u64();
+    state.mine().await.expect("mine");
+    assert_eq!(l2.head().number.as_u64(), head);
+
+    // requeued dead letters start over
+    assert!(state.retry_dead_letter(H256::zero()).await.is_err());
+    state
+        .retry_dead_letter(id)
+        .await
+        .expect("retry_dead_letter");
+    {
+        let rw = state.rw.lock().await;
+        assert!(rw.l1_dead_letters.is_empty());
+        assert_eq!(rw.l1_message_queue[0].attempts, 0);
+    }
+    failing.store(false, Ordering::SeqCst);
+    state.mine().await.expect("mine");
+    assert_eq!(l2.head().number.as_u64(), head + 1);
+    let last_tx = l2.with_chain(|chain| {
+        let tx_hash = chain.head().transactions.last().cloned().unwrap();
+        chain.raw_transactions[&tx_hash].clone()
+    });
+    assert!(calldata_selector(
+        &last_tx,
+        "deliverMessageWithProof(address,address,uint256,uint256,uint256,uint256,bytes,bytes)"
+    ));
+    assert!(state.rw.lock().await.l1_message_queue.is_empty());
+}
+
+#[tokio::test]
+async fn fake_node_proof_deadlines() {
+    let (_l1, l2, state) = setup().await;
+    state.init().await;
+
+    // a prover that keeps every task pending
+    let prover = FakeNode::start(0, false).await;
+    prover.on("proof", |_| {
+        Some(Ok(serde_json::json!({
+            "queue_position": 0,
+            "estimated_start": null,
+        })))
+    });
+    {
+        let mut config = state.config.lock().await;
+        config.dummy_prover = false;
+        config.prover_rpcd_url = vec![prover.uri.to_string().parse().unwrap()];
+    }
+
+    // blocks 1 and 3 dispatch L2 > L1 messages, the later one is due first
+    let deadline = 1_900_000_000;
+    let dispatcher = state.ro().l2_message_dispatcher_addr;
+    l2.mine(vec![message_dispatched_by(
+        &state,
+        dispatcher,
+        deadline + 100,
+    )]);
+    l2.mine(vec![]);
+    l2.mine(vec![message_dispatched_by(&state, dispatcher, deadline)]);
+    l2.mine(vec![]);
+    state.check_l2_head().await.expect("check_l2_head");
+
+    let deadlines = |prover: &FakeNode| -> Vec<(u64, Option<u64>)> {
+        prover
+            .requests()
+            .into_iter()
+            .filter(|(method, _)| method == "proof")
+            .map(|(_, params)| {
+                (
+                    params[0]["block"].as_u64().unwrap(),
+                    params[0]["deadline"].as_u64(),
+                )
+            })
+            .collect()
+    };
+    let expected = vec![
+        (1, Some(deadline)),
+        (2, Some(deadline)),
+        (3, Some(deadline)),
+        (4, None),
+    ];
+    state.request_proofs().await.expect("request_proofs");
+    assert_eq!(deadlines(&prover), expected);
+
+    // the deadlines are the same for the pending requests
+    state.request_proofs().await.expect("request_proofs");
+    assert_eq!(deadlines(&prover)[4..], expected);
+}
+
+#[tokio::test]
+async fn fake_node_message_round_failures() {
+    let (l1, l2, state) = setup().await;
+    state.init().await;
+    {
+        let mut config = state.config.lock().await;
+        config.message_retry_backoff = 0;
+        config.message_max_attempts = 2;
+    }
+
+    // 0: the account proof of the bridge fails, 1: only the storage proofs of the messages
+    let failing = Arc::new(AtomicUsize::new(0));
+    {
+        let failing = failing.clone();
+        l1.on("eth_getProof", move |params| {
+            let storage_keys = params[1].as_array().map_or(0, |keys| keys.len());
+            match failing.load(Ordering::SeqCst) {
+                0 => Some(Err("unavailable".to_string())),
+                1 if storage_keys > 0 => Some(Err("missing trie node".to_string())),
+                _ => None,
+            }
+        });
+    }
+
+    let l1_bridge = L1_BRIDGE.parse().unwrap();
+    let deadline = 1_900_000_000;
+    l1.mine(vec![
+        message_dispatched_by(&state, l1_bridge, deadline),
+        message_dispatched_by(&state, l1_bridge, deadline + 1),
+    ]);
+    state.sync().await.expect("sync");
+
+    // every due message fails an attempt
+    state.mine().await.expect("mine");
+    assert_eq!(l2.head().number.as_u64(), 0);
+    {
+        let rw = state.rw.lock().await;
+        assert_eq!(rw.l1_message_queue.len(), 2);
+        for delivery in rw.l1_message_queue.iter() {
+            assert_eq!(delivery.attempts, 1);
+            assert!(delivery
+                .last_error
+                .as_ref()
+                .unwrap()
+                .contains("unavailable"));
+        }
+    }
+
+    // each message fails on its own, they are moved to the dead letters
+    failing.store(1, Ordering::SeqCst);
+    state.mine().await.expect("mine");
+    let rw = state.rw.lock().await;
+    assert!(rw.l1_message_queue.is_empty());
+    assert_eq!(rw.l1_dead_letters.len(), 2);
+    for delivery in rw.l1_dead_letters.iter() {
+        assert_eq!(delivery.attempts, 2);
+        assert!(delivery
+            .last_error
+            .as_ref()
+            .unwrap()
+            .contains("missing trie node"));
+    }
+}
+
+#[tokio::test]
+async fn fake_node_state_file() {
+    let (l1, _l2, state) = setup().await;
+    let path = std::env::temp_dir().join(format!("coordinator-state-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    state.config.lock().await.state_file = Some(path.clone());
+    // nothing stored yet
+    state.load_state().await.expect("load_state");
+    state.init().await;
+
+    l1.mine(vec![message_dispatched(&state, L1_BRIDGE.parse().unwrap())]);
+    state.sync().await.expect("sync");
+    let id = state.rw.lock().await.l1_message_queue[0].message.id;
+
+    // a restarted coordinator continues with the stored queue
+    let restarted = SharedState::new(&state.get_config().await).await;
+    restarted.init().await;
+    restarted.load_state().await.expect("load_state");
+    assert_eq!(restarted.rw.lock().await.l1_message_queue[0].message.id, id);
+    // and does not queue the message again while syncing from the start
+    restarted.sync().await.expect("sync");
+    assert_eq!(restarted.rw.lock().await.l1_message_queue.len(), 1);
+
+    std::fs::write(&path, "{").unwrap();
+    assert!(restarted.load_state().await.is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Extends the canonical chain of `node` by `n` blocks on top of `parent`.
+fn extend_chain(node: &FakeNode, parent: H256, n: u64, timestamp: u64) -> H256 {
+    node.with_chain(|chain| {
+        let mut hash = parent;
+        for i in 0..n {
+            hash = chain.seal_block(hash, U256::from(timestamp + i), Vec::new());
+        }
+        chain.set_head(hash);
+        hash
+    })
+}
+
+/// The expected history hashes of the witness for block `number`.
+fn expected_history(node: &FakeNode, number: usize) -> Vec<H256> {
+    let canonical = node.with_chain(|chain| chain.canonical.clone());
+    let mut hashes = vec![H256::zero(); 256];
+    for (i, hash) in canonical[number.saturating_sub(256)..number]
+        .iter()
+        .rev()
+        .enumerate()
+    {
+        hashes[255 - i] = *hash;
+    }
+
+    hashes
+}
+
+async fn assert_witness(state: &SharedState, node: &FakeNode, number: usize) {
+    let witness = state
+        .request_witness(&U64::from(number))
+        .await
+        .expect("request_witness");
+    let block = state
+        .ro()
+        .l2
+        .get_block_with_transactions(U64::from(number))
+        .await
+        .expect("block");
+    let expected = encode_verifier_witness(&block, &expected_history(node, number), &99).unwrap();
+    assert_eq!(witness.input.as_ref(), &expected[..], "block {number}");
+}
+
+#[tokio::test]
+async fn fake_node_history_hashes() {
+    let (_l1, l2, state) = setup().await;
+    let genesis = l2.head().hash;
+    extend_chain(&l2, genesis, 300, 1);
+
+    assert_witness(&state, &l2, 1).await;
+    assert_witness(&state, &l2, 300).await;
+    // one batch for the parents that are not cached
+    assert_eq!(l2.count("eth_getHeaderByHash"), 0);
+    assert_eq!(l2.count("eth_getHeaderByNumber"), 255);
+
+    // consecutive blocks shift the previous window
+    let head = l2.head().hash;
+    extend_chain(&l2, head, 2, 1000);
+    assert_witness(&state, &l2, 301).await;
+    assert_witness(&state, &l2, 302).await;
+    assert_witness(&state, &l2, 302).await;
+    assert_eq!(l2.count("eth_getHeaderByNumber"), 255);
+    assert_eq!(l2.count("eth_getHeaderByHash"), 0);
+
+    // after a reorg, the window is fetched again
+    let fork = l2.with_chain(|chain| chain.canonical[290]);
+    extend_chain(&l2, fork, 12, 2000);
+    assert_witness(&state, &l2, 302).await;
+    assert_eq!(l2.count("eth_getHeaderByNumber"), 2 * 255);
+    assert_eq!(l2.count("eth_getHeaderByHash"), 0);
+
+    // blocks that are not canonical are fetched by hash
+    let blocks = state
+        .blocks_between(&genesis, &head)
+        .await
+        .expect("blocks_between");
+    assert_eq!(blocks.len(), 300);
+    assert_eq!(blocks[0].hash, Some(head));
+    assert_eq!(blocks[299].parent_hash, genesis);
+    assert!(blocks
+        .windows(2)
+        .all(|w| w[0].parent_hash == w[1].hash.unwrap()));
+    assert_eq!(l2.count("eth_getBlockByHash"), 10);
+    assert!(state.blocks_between(&head, &genesis).await.is_err());
+    // `from` is not an ancestor after the reorg
+    let orphaned = blocks[7].hash.unwrap();
+    assert!(state
+        .blocks_between(&orphaned, &l2.head().hash)
+        .await
+        .is_err());
+    assert_eq!(
+        state
+            .blocks_between(&blocks[299].hash.unwrap(), &l2.head().hash)
+            .await
+            .expect("blocks_between")
+            .len(),
+        301
+    );
+}
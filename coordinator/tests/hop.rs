@@ -148,7 +148,7 @@ async fn hop_cross_chain_message() {
             .transaction_to_l2(Some(hop), amount, calldata, None)
             .await
             .expect("tx_hash");
-        shared_state.mine().await;
+        shared_state.mine().await.expect("mine");
         wait_for_tx!(tx_hash, &shared_state.config.lock().await.l2_rpc_url);
     }
 
@@ -166,7 +166,7 @@ async fn hop_cross_chain_message() {
             .transaction_to_l2(Some(hop), U256::zero(), calldata, None)
             .await
             .expect("tx_hash_commit");
-        shared_state.mine().await;
+        shared_state.mine().await.expect("mine");
         wait_for_tx!(tx_hash_commit, &shared_state.config.lock().await.l2_rpc_url);
     }
 
@@ -15,6 +15,7 @@ use ethers_core::utils::keccak256;
 use ethers_signers::Signer;
 use zkevm_common::json_rpc::jsonrpc_request;
 use zkevm_common::json_rpc::jsonrpc_request_client;
+use zkevm_common::json_rpc::RpcError;
 
 #[tokio::test]
 async fn native_deposit() {
@@ -34,7 +35,7 @@ async fn native_deposit() {
     {
         // create deposits
         for _ in 0..9 {
-            let from = shared_state.ro.l1_wallet.address();
+            let from = shared_state.ro().l1.wallet.address();
             let to = receiver;
             let value = U256::from(1u64);
             let fee = U256::zero();
@@ -122,7 +123,7 @@ async fn native_deposit_revert() {
         let mut tx_nonce: U256 = jsonrpc_request(
             &shared_state.config.lock().await.l1_rpc_url,
             "eth_getTransactionCount",
-            (shared_state.ro.l1_wallet.address(), "latest"),
+            (shared_state.ro().l1.wallet.address(), "latest"),
         )
         .await
         .expect("nonce");
@@ -131,9 +132,9 @@ async fn native_deposit_revert() {
         let mut txs: Vec<Bytes> = Vec::new();
         for i in 0..30 {
             let should_revert = i % 2 == 0;
-            let from = shared_state.ro.l1_wallet.address();
+            let from = shared_state.ro().l1.wallet.address();
             let to = match should_revert {
-                true => shared_state.ro.l2_message_deliverer_addr,
+                true => shared_state.ro().l2_message_deliverer_addr,
                 false => receiver,
             };
             let value = U256::from(1u64);
@@ -172,9 +173,8 @@ async fn native_deposit_revert() {
 
             txs.push(
                 sign_transaction_l1(
-                    &shared_state.ro.http_client,
-                    &shared_state.config.lock().await.l1_rpc_url,
-                    &shared_state.ro.l1_wallet,
+                    &shared_state.ro().l1.client,
+                    &shared_state.ro().l1.wallet,
                     l1_bridge_addr,
                     value,
                     calldata,
@@ -189,9 +189,9 @@ async fn native_deposit_revert() {
 
         let mut tx_hashes = Vec::new();
         for raw_tx in &txs {
-            let resp: Result<H256, String> = jsonrpc_request_client(
+            let resp: Result<H256, RpcError> = jsonrpc_request_client(
                 RPC_REQUEST_TIMEOUT,
-                &shared_state.ro.http_client,
+                &shared_state.ro().http_client,
                 &shared_state.config.lock().await.l1_rpc_url,
                 "eth_sendRawTransaction",
                 [raw_tx],
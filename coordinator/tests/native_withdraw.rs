@@ -26,20 +26,20 @@ async fn native_withdraw() {
     .await
     .expect("eth_getBalance");
 
-    shared_state.sync().await;
-    shared_state.mine().await;
+    shared_state.sync().await.expect("sync");
+    shared_state.mine().await.expect("mine");
 
     {
         let mut tx_nonce: U256 = jsonrpc_request(
             &shared_state.config.lock().await.l2_rpc_url,
             "eth_getTransactionCount",
-            (shared_state.ro.l2_wallet.address(), "latest"),
+            (shared_state.ro().l2.wallet.address(), "latest"),
         )
         .await
         .expect("nonce");
         let mut txs = vec![];
         for _ in 0..4 {
-            let from = shared_state.ro.l2_wallet.address();
+            let from = shared_state.ro().l2.wallet.address();
             let to = receiver;
             let value = U256::from(1u64);
             let fee = U256::zero();
@@ -74,12 +74,13 @@ async fn native_withdraw() {
             txs.push(
                 shared_state
                     .sign_l2(
-                        Some(shared_state.ro.l2_message_dispatcher_addr),
+                        Some(shared_state.ro().l2_message_dispatcher_addr),
                         value,
                         tx_nonce,
                         calldata,
                     )
-                    .await,
+                    .await
+                    .expect("sign_l2"),
             );
             tx_nonce = tx_nonce + 1;
         }
@@ -94,7 +95,7 @@ async fn native_withdraw() {
 
     // verify that all messages are picked up
     {
-        shared_state.sync().await;
+        shared_state.sync().await.expect("sync");
         for id in messages {
             let found = shared_state
                 .rw
@@ -0,0 +1,326 @@
+use hyper::{Body, Method, Request, StatusCode};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use zkevm_common::json_rpc::{
+    JsonRpcError, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR, SERVER_ERROR,
+};
+use zkevm_common::prover::{
+    CircuitConfig, FlushRequestOptions, NodeInformation, NodeStatus, ProofRequestOptions, Proofs,
+    ProverClient,
+};
+use zkevm_common::server::{BearerAuth, JsonRpcServer};
+
+const TOKEN: &str = "secret";
+
+async fn send(
+    method: Method,
+    uri: &str,
+    token: Option<&str>,
+    body: &str,
+) -> (StatusCode, hyper::HeaderMap, String) {
+    let mut req = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        req = req.header("authorization", format!("Bearer {token}"));
+    }
+    let resp = hyper::Client::new()
+        .request(req.body(Body::from(body.to_string())).unwrap())
+        .await
+        .expect("request");
+    let status = resp.status();
+    let headers = resp.headers().clone();
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+
+    (status, headers, String::from_utf8(body.to_vec()).unwrap())
+}
+
+async fn call(uri: &str, body: Value) -> Value {
+    let (status, _, body) = send(Method::POST, uri, Some(TOKEN), &body.to_string()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    serde_json::from_str(&body).unwrap()
+}
+
+fn request(id: u64, method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+}
+
+fn error_code(resp: &Value) -> i64 {
+    resp["error"]["code"].as_i64().expect("error code")
+}
+
+#[tokio::test]
+async fn server_methods_and_endpoints() {
+    let counter = Arc::new(AtomicU64::new(0));
+    let server = JsonRpcServer::new(counter.clone())
+        .rpc_path("/rpc")
+        .max_body_size(1024)
+        .middleware(BearerAuth::new(TOKEN, &["/rpc"]))
+        .ready(|counter: Arc<AtomicU64>| async move { counter.load(Ordering::SeqCst) > 0 })
+        .route(Method::GET, "/custom", |_, _| async move {
+            hyper::Response::new(Body::from("custom"))
+        })
+        .method("add", |_, (a, b): (u64, u64)| async move {
+            Ok::<_, JsonRpcError>(a + b)
+        })
+        .method("echo", |_, (value,): (Option<String>,)| async move {
+            Ok::<_, JsonRpcError>(value)
+        })
+        .method("count", |counter: Arc<AtomicU64>, ()| async move {
+            Ok::<_, JsonRpcError>(counter.fetch_add(1, Ordering::SeqCst) + 1)
+        })
+        .method("fail", |_, ()| async move {
+            Err::<(), _>(JsonRpcError::new(SERVER_ERROR, "failed"))
+        });
+    assert_eq!(server.methods(), ["add", "count", "echo", "fail"]);
+
+    let metrics = server.metrics();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let (addr, server) = server
+        .bind(&SocketAddr::from(([127, 0, 0, 1], 0)), async move {
+            shutdown_rx.await.ok();
+        })
+        .expect("bind");
+    let server = tokio::spawn(server);
+    let base = format!("http://{addr}");
+    let rpc = format!("{base}/rpc");
+
+    // auth
+    let body = request(1, "add", json!([1, 2])).to_string();
+    let (status, _, _) = send(Method::POST, &rpc, None, &body).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = send(Method::POST, &rpc, Some("wrong"), &body).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // typed params and results
+    let resp = call(&rpc, request(1, "add", json!([1, 2]))).await;
+    assert_eq!(resp["id"], 1);
+    assert_eq!(resp["result"], 3);
+    let resp = call(&rpc, request(2, "echo", json!([]))).await;
+    assert_eq!(resp["result"], Value::Null);
+    let resp = call(&rpc, request(3, "echo", json!(["x"]))).await;
+    assert_eq!(resp["result"], "x");
+    let resp = call(&rpc, request(4, "add", json!(["a", 2]))).await;
+    assert_eq!(error_code(&resp), INVALID_PARAMS as i64);
+    let resp = call(&rpc, request(5, "add", json!([1, 2, 3]))).await;
+    assert_eq!(error_code(&resp), INVALID_PARAMS as i64);
+    let resp = call(&rpc, request(6, "unknown", json!([]))).await;
+    assert_eq!(error_code(&resp), METHOD_NOT_FOUND as i64);
+    let resp = call(&rpc, request(7, "fail", json!([]))).await;
+    assert_eq!(error_code(&resp), SERVER_ERROR as i64);
+    assert_eq!(resp["error"]["message"], "failed");
+
+    // readiness
+    let (status, _, _) = send(Method::GET, &format!("{base}/ready"), None, "").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    let resp = call(
+        &rpc,
+        json!({ "jsonrpc": "2.0", "id": 8, "method": "count" }),
+    )
+    .await;
+    assert_eq!(resp["result"], 1);
+    let (status, _, _) = send(Method::GET, &format!("{base}/ready"), None, "").await;
+    assert_eq!(status, StatusCode::OK);
+
+    // batches
+    let resp = call(
+        &rpc,
+        json!([
+            request(10, "add", json!([2, 3])),
+            request(11, "unknown", json!([])),
+            request(12, "count", json!([])),
+            { "jsonrpc": "2.0", "id": 13 },
+        ]),
+    )
+    .await;
+    let resp = resp.as_array().expect("batch response");
+    assert_eq!(resp.len(), 4);
+    assert_eq!(resp[0]["id"], 10);
+    assert_eq!(resp[0]["result"], 5);
+    assert_eq!(error_code(&resp[1]), METHOD_NOT_FOUND as i64);
+    assert_eq!(resp[2]["result"], 2);
+    assert_eq!(resp[3]["id"], 13);
+    assert_eq!(error_code(&resp[3]), INVALID_REQUEST as i64);
+    let resp = call(&rpc, json!([])).await;
+    assert_eq!(error_code(&resp), INVALID_REQUEST as i64);
+    let (_, _, body) = send(Method::POST, &rpc, Some(TOKEN), "{").await;
+    let resp: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(error_code(&resp), PARSE_ERROR as i64);
+
+    // body limit
+    let body = request(20, "echo", json!(["x".repeat(2048)])).to_string();
+    let (status, _, _) = send(Method::POST, &rpc, Some(TOKEN), &body).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // routes, health and CORS
+    let (status, headers, body) = send(Method::GET, &format!("{base}/custom"), None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "custom");
+    assert_eq!(headers["access-control-allow-origin"], "*");
+    let (status, _, _) = send(Method::GET, &format!("{base}/health"), None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, headers, _) = send(Method::OPTIONS, &rpc, None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        headers["access-control-allow-methods"],
+        "post, get, options"
+    );
+    let (status, _, _) = send(Method::GET, &format!("{base}/unknown"), None, "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // metrics of the registered methods
+    assert_eq!(metrics.get("add").calls, 4);
+    assert_eq!(metrics.get("add").errors, 2);
+    assert_eq!(metrics.get("fail").errors, 1);
+    assert_eq!(metrics.get("unknown").calls, 0);
+    let (status, headers, body) = send(Method::GET, &format!("{base}/metrics"), None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    assert!(body.contains("rpc_calls_total{method=\"count\"} 2\n"));
+    assert!(body.contains("rpc_errors_total{method=\"add\"} 2\n"));
+
+    shutdown_tx.send(()).unwrap();
+    server.await.unwrap().expect("server");
+    assert_eq!(counter.load(Ordering::SeqCst), 2);
+}
+
+/// A prover that computes the proofs on the second request.
+fn fake_prover(requests: Arc<AtomicU64>) -> JsonRpcServer<Arc<AtomicU64>> {
+    JsonRpcServer::new(requests)
+        .info("proverd", "0.1.0")
+        .method(
+            "proof",
+            |requests: Arc<AtomicU64>, (options,): (ProofRequestOptions,)| async move {
+                if requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return Ok(None);
+                }
+                let mut proofs = Proofs {
+                    gas: options.block,
+                    ..Default::default()
+                };
+                proofs.circuit.label = options.circuit;
+                Ok::<_, JsonRpcError>(Some(proofs))
+            },
+        )
+        .method(
+            "circuit_config",
+            |_, (options,): (ProofRequestOptions,)| async move {
+                Ok::<_, JsonRpcError>(CircuitConfig {
+                    block_gas_limit: options.block as usize,
+                    ..Default::default()
+                })
+            },
+        )
+        .method("info", |_, ()| async move {
+            Ok::<_, JsonRpcError>(NodeInformation {
+                id: "a".to_string(),
+                tasks: Vec::new(),
+                pk_cache: Default::default(),
+            })
+        })
+        .method("status", |_, ()| async move {
+            Ok::<_, JsonRpcError>(NodeStatus {
+                id: "a".to_string(),
+                task: None,
+                obtained: false,
+            })
+        })
+        .method(
+            "flush",
+            |_, (options,): (FlushRequestOptions,)| async move {
+                Ok::<_, JsonRpcError>(options.pending)
+            },
+        )
+        .describe("proof", "Enqueues a proof task", &["options"])
+}
+
+#[tokio::test]
+async fn server_prover_client_and_openrpc() {
+    let server = fake_prover(Arc::new(AtomicU64::new(0)));
+    let doc = server.openrpc();
+    let (addr, server) = server
+        .bind(
+            &SocketAddr::from(([127, 0, 0, 1], 0)),
+            std::future::pending(),
+        )
+        .expect("bind");
+    tokio::spawn(server);
+    let client = ProverClient::new(format!("http://{addr}").parse().unwrap());
+
+    let options = ProofRequestOptions {
+        circuit: "super".to_string(),
+        block: 7,
+        ..Default::default()
+    };
+    assert!(client.proof(&options).await.expect("proof").is_none());
+    let proofs = client.proof(&options).await.expect("proof").unwrap();
+    assert_eq!(proofs.gas, 7);
+    assert_eq!(proofs.circuit.label, "super");
+    let config = client
+        .circuit_config(&options)
+        .await
+        .expect("circuit_config");
+    assert_eq!(config.block_gas_limit, 7);
+    assert_eq!(client.info().await.expect("info").id, "a");
+    assert!(client.status().await.expect("status").task.is_none());
+    let flush = FlushRequestOptions {
+        pending: true,
+        ..Default::default()
+    };
+    assert!(client.flush(&flush).await.expect("flush"));
+    // not served by this prover
+    let err = client.flush_all().await.unwrap_err();
+    assert_eq!(err.code(), Some(METHOD_NOT_FOUND));
+
+    // the document is served with `rpc.discover`
+    let served = call(
+        &format!("http://{addr}"),
+        request(1, "rpc.discover", json!([])),
+    )
+    .await;
+    assert_eq!(served["result"], doc);
+    assert_eq!(doc["openrpc"], "1.2.6");
+    assert_eq!(doc["info"]["title"], "proverd");
+    let methods = doc["methods"].as_array().unwrap();
+    let names: Vec<&str> = methods
+        .iter()
+        .map(|m| m["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        ["circuit_config", "flush", "info", "proof", "status"]
+    );
+
+    let proof = &methods[3];
+    assert_eq!(proof["summary"], "Enqueues a proof task");
+    assert_eq!(proof["params"][0]["name"], "options");
+    assert_eq!(proof["params"][0]["required"], true);
+    let schema = &proof["params"][0]["schema"];
+    assert_eq!(schema["title"], "ProofRequestOptions");
+    assert_eq!(schema["properties"]["block"]["type"], "integer");
+    assert_eq!(
+        schema["properties"]["param"]["type"],
+        json!(["string", "null"])
+    );
+    assert_eq!(
+        schema["properties"]["block"]["description"],
+        "the block number"
+    );
+    let required = schema["required"].as_array().unwrap();
+    assert!(required.contains(&json!("circuit")));
+    assert!(!required.contains(&json!("param")));
+    assert!(!required.contains(&json!("mock")));
+    let result = &proof["result"]["schema"]["oneOf"][0];
+    assert_eq!(result["title"], "Proofs");
+    assert_eq!(
+        result["properties"]["circuit"]["properties"]["proof"]["pattern"],
+        "^0x[0-9a-fA-F]*$"
+    );
+    assert_eq!(methods[1]["params"][0]["name"], "param0");
+    assert!(methods[2]["params"].as_array().unwrap().is_empty());
+    assert_eq!(methods[2]["result"]["schema"]["title"], "NodeInformation");
+}
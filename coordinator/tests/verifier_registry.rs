@@ -0,0 +1,98 @@
+use coordinator::config::VerifierRegistrySource;
+use coordinator::error::Error;
+use coordinator::verifier_registry::{config_digest, verifier_code, VerifierRegistry};
+use ethers_core::types::Address;
+use std::fs;
+use zkevm_common::prover::CircuitConfig;
+
+fn circuit_config(block_gas_limit: usize) -> CircuitConfig {
+    CircuitConfig {
+        block_gas_limit,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn verifier_registry_lookup() {
+    let dir = std::env::temp_dir().join(format!("verifier-registry-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let config = circuit_config(63_000);
+    let other_config = circuit_config(300_000);
+    let exact = Address::from_low_u64_be(1);
+    let any = Address::from_low_u64_be(2);
+    let entries = [
+        serde_json::json!({ "label": "super-63000", "config": config, "address": exact }),
+        serde_json::json!({ "label": "super-63000-a", "address": any }),
+    ];
+    for (i, entry) in entries.iter().enumerate() {
+        fs::write(dir.join(format!("verifier-{i}.json")), entry.to_string()).unwrap();
+    }
+
+    let source: VerifierRegistrySource = dir.to_str().unwrap().parse().unwrap();
+    let registry = VerifierRegistry::load(Some(&source), false).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(registry.lookup("super-63000", &config), Ok(exact));
+    assert_eq!(registry.lookup("super-63000-a", &other_config), Ok(any));
+    assert!(registry.lookup("super-63000", &other_config).is_err());
+    assert!(registry.lookup("pi-63000", &config).is_err());
+
+    assert_eq!(config_digest(&config), config_digest(&config.clone()));
+    assert_ne!(config_digest(&config), config_digest(&other_config));
+}
+
+#[test]
+fn verifier_registry_source() {
+    let addr = "0x936a70c0b28532aa22240dce21f89a8399d6ac62";
+    assert_eq!(
+        addr.parse::<VerifierRegistrySource>(),
+        Ok(VerifierRegistrySource::Contract(addr.parse().unwrap()))
+    );
+    assert!("0x1234".parse::<VerifierRegistrySource>().is_err());
+    assert!(
+        VerifierRegistry::load(Some(&"/nonexistent/verifiers.json".parse().unwrap()), true)
+            .is_err()
+    );
+    assert!(VerifierRegistry::load(None, false).is_err());
+    assert!(matches!(
+        VerifierRegistry::load(None, true),
+        Ok(VerifierRegistry::Label)
+    ));
+}
+
+#[test]
+fn verifier_code_by_label_and_address() {
+    let dir = std::env::temp_dir().join(format!("verifier-code-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let addr = Address::from_low_u64_be(1);
+    let agg_addr = Address::from_low_u64_be(2);
+    // `super-63000` is a prefix of the aggregation label, the other files are not runtime code
+    let files = [
+        (format!("verifier-super-63000-a-{agg_addr:?}"), "0xaa"),
+        (format!("verifier-super-63000-{agg_addr:?}"), "0xbb"),
+        (format!("verifier-super-63000-{addr:?}.yul"), "object"),
+        (format!("verifier-super-63000-{addr:?}.json"), "{}"),
+        (format!("verifier-super-63000-{addr:?}"), "cc\n"),
+    ];
+    for (name, code) in files {
+        fs::write(dir.join(name), code).unwrap();
+    }
+
+    assert_eq!(
+        verifier_code(&dir, "super-63000", addr).unwrap().to_vec(),
+        [0xcc]
+    );
+    assert_eq!(
+        verifier_code(&dir, "super-63000-a", agg_addr)
+            .unwrap()
+            .to_vec(),
+        [0xaa]
+    );
+    assert!(matches!(
+        verifier_code(&dir, "super-63000-a", addr),
+        Err(Error::Io(_))
+    ));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -26,7 +26,7 @@ async fn worst_case_smod() {
         )
         .await
         .expect("tx_hash");
-    shared_state.mine().await;
+    shared_state.mine().await.expect("mine");
     let receipt = wait_for_tx_no_panic!(tx_hash, &shared_state.config.lock().await.l2_rpc_url);
     assert_eq!(receipt.gas_used.expect("gas_used"), block_gas_limit);
     finalize_chain!(shared_state);
@@ -49,7 +49,7 @@ async fn worst_case_mload() {
         )
         .await
         .expect("tx_hash");
-    shared_state.mine().await;
+    shared_state.mine().await.expect("mine");
     let receipt = wait_for_tx_no_panic!(tx_hash, &shared_state.config.lock().await.l2_rpc_url);
     assert_eq!(receipt.gas_used.expect("gas_used"), block_gas_limit);
     finalize_chain!(shared_state);
@@ -72,7 +72,7 @@ async fn worst_case_keccak_0_32() {
         )
         .await
         .expect("tx_hash");
-    shared_state.mine().await;
+    shared_state.mine().await.expect("mine");
     let receipt = wait_for_tx_no_panic!(tx_hash, &shared_state.config.lock().await.l2_rpc_url);
     assert_eq!(receipt.gas_used.expect("gas_used"), block_gas_limit);
     finalize_chain!(shared_state);
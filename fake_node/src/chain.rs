@@ -0,0 +1,290 @@
+use ethers_core::types::{
+    Address, Block, Bloom, Bytes, Log, Transaction, TransactionReceipt, H256, H64, U256, U64,
+};
+use ethers_core::utils::keccak256;
+use ethers_core::utils::rlp;
+use ethers_core::utils::rlp::RlpStream;
+use std::collections::HashMap;
+
+/// A block of the in-memory chain.
+#[derive(Clone, Debug)]
+pub struct FakeBlock {
+    pub number: U64,
+    pub hash: H256,
+    pub parent_hash: H256,
+    pub timestamp: U256,
+    pub state_root: H256,
+    pub transactions: Vec<H256>,
+    pub logs: Vec<Log>,
+}
+
+impl FakeBlock {
+    fn new(parent: Option<&FakeBlock>, timestamp: U256, transactions: Vec<H256>) -> Self {
+        let (number, parent_hash, parent_root) = match parent {
+            Some(parent) => (parent.number + 1, parent.hash, parent.state_root),
+            None => (U64::zero(), H256::zero(), H256::zero()),
+        };
+        let mut state = parent_root.as_bytes().to_vec();
+        transactions
+            .iter()
+            .for_each(|tx| state.extend_from_slice(tx.as_bytes()));
+        let mut block = Self {
+            number,
+            hash: H256::zero(),
+            parent_hash,
+            timestamp,
+            state_root: H256::from(keccak256(state)),
+            transactions,
+            logs: Vec::new(),
+        };
+        block.hash = H256::from(keccak256(block.header_rlp()));
+
+        block
+    }
+
+    /// A legacy header with the fields not tracked by the fake node set to zero,
+    /// the block hash is the keccak256 hash of it.
+    pub fn header_rlp(&self) -> Bytes {
+        let mut stream = RlpStream::new_list(15);
+        stream.append(&self.parent_hash);
+        // uncles hash
+        stream.append(&H256::zero());
+        // coinbase
+        stream.append(&Address::zero());
+        stream.append(&self.state_root);
+        // transactions and receipts root
+        stream.append(&H256::zero());
+        stream.append(&H256::zero());
+        stream.append(&Bloom::zero());
+        // difficulty
+        stream.append(&U256::zero());
+        stream.append(&self.number);
+        // gas limit and gas used
+        stream.append(&U256::zero());
+        stream.append(&U256::zero());
+        stream.append(&self.timestamp);
+        // extra data
+        stream.append(&Vec::<u8>::new().as_slice());
+        // mix hash and nonce
+        stream.append(&H256::zero());
+        stream.append(&H64::zero());
+
+        stream.out().to_vec().into()
+    }
+
+    pub fn header(&self) -> serde_json::Value {
+        serde_json::json!({
+            "parentHash": self.parent_hash,
+            "hash": self.hash,
+            "number": self.number,
+            "stateRoot": self.state_root,
+            "timestamp": self.timestamp,
+        })
+    }
+}
+
+/// An event for `FakeNode::mine`. Each event is emitted by its own transaction
+/// with `input` as calldata.
+#[derive(Clone, Debug, Default)]
+pub struct FakeEvent {
+    pub address: Address,
+    pub topics: Vec<H256>,
+    pub data: Bytes,
+    pub input: Bytes,
+}
+
+/// The state of the in-memory chain.
+#[derive(Debug)]
+pub struct Chain {
+    pub chain_id: u64,
+    /// Include transactions in a new block as soon as they are received,
+    /// otherwise they are kept in `txpool` until a block is sealed.
+    pub auto_mine: bool,
+    pub blocks: HashMap<H256, FakeBlock>,
+    /// block hashes of the canonical chain, indexed by block number
+    pub canonical: Vec<H256>,
+    pub transactions: HashMap<H256, Transaction>,
+    pub receipts: HashMap<H256, TransactionReceipt>,
+    /// raw transactions, in the order they were received
+    pub sent_transactions: Vec<Bytes>,
+    pub txpool: Vec<Bytes>,
+    /// raw transactions of sealed blocks by transaction hash
+    pub raw_transactions: HashMap<H256, Bytes>,
+    /// blocks built with `engine_forkchoiceUpdatedV1`, indexed by payload id
+    pub payloads: Vec<H256>,
+    pub balances: HashMap<Address, U256>,
+    pub nonces: HashMap<Address, U256>,
+}
+
+impl Chain {
+    pub fn new(chain_id: u64, auto_mine: bool) -> Self {
+        let genesis = FakeBlock::new(None, U256::zero(), Vec::new());
+        Self {
+            chain_id,
+            auto_mine,
+            canonical: vec![genesis.hash],
+            blocks: HashMap::from([(genesis.hash, genesis)]),
+            transactions: HashMap::new(),
+            receipts: HashMap::new(),
+            sent_transactions: Vec::new(),
+            txpool: Vec::new(),
+            raw_transactions: HashMap::new(),
+            payloads: Vec::new(),
+            balances: HashMap::new(),
+            nonces: HashMap::new(),
+        }
+    }
+
+    pub fn head(&self) -> &FakeBlock {
+        &self.blocks[self.canonical.last().unwrap()]
+    }
+
+    pub fn block_by_number(&self, number: U64) -> Option<&FakeBlock> {
+        self.canonical
+            .get(number.as_usize())
+            .map(|hash| &self.blocks[hash])
+    }
+
+    /// Resolves a block tag or number, `None` if the block is unknown.
+    pub fn block_by_tag(&self, tag: &str) -> Option<&FakeBlock> {
+        match tag {
+            "latest" | "pending" | "safe" | "finalized" => Some(self.head()),
+            "earliest" => self.block_by_number(U64::zero()),
+            tag => {
+                self.block_by_number(U64::from_str_radix(tag.trim_start_matches("0x"), 16).ok()?)
+            }
+        }
+    }
+
+    /// Creates a new block on top of `parent` with `raw_txs`.
+    /// The block only becomes canonical with `set_head`.
+    pub fn seal_block(&mut self, parent: H256, timestamp: U256, raw_txs: Vec<Bytes>) -> H256 {
+        let parent = self.blocks.get(&parent).cloned();
+        let hashes = raw_txs
+            .iter()
+            .map(|raw| H256::from(keccak256(raw)))
+            .collect();
+        let block = FakeBlock::new(parent.as_ref(), timestamp, hashes);
+
+        for (i, raw) in raw_txs.iter().enumerate() {
+            let mut tx: Transaction = rlp::decode(raw).unwrap_or_default();
+            tx.from = tx.recover_from().unwrap_or_default();
+            tx.hash = block.transactions[i];
+            tx.block_hash = Some(block.hash);
+            tx.block_number = Some(block.number);
+            tx.transaction_index = Some(i.into());
+
+            let receipt = TransactionReceipt {
+                transaction_hash: tx.hash,
+                transaction_index: i.into(),
+                block_hash: Some(block.hash),
+                block_number: Some(block.number),
+                from: tx.from,
+                to: tx.to,
+                gas_used: Some(tx.gas),
+                cumulative_gas_used: tx.gas,
+                status: Some(1.into()),
+                ..Default::default()
+            };
+
+            self.receipts.insert(tx.hash, receipt);
+            self.raw_transactions.insert(tx.hash, raw.clone());
+            self.transactions.insert(tx.hash, tx);
+        }
+
+        let hash = block.hash;
+        self.blocks.insert(hash, block);
+
+        hash
+    }
+
+    /// Makes `hash` the head of the canonical chain, returns `false` if the block is unknown.
+    pub fn set_head(&mut self, hash: H256) -> bool {
+        let mut canonical = Vec::new();
+        let mut cursor = hash;
+        loop {
+            let block = match self.blocks.get(&cursor) {
+                Some(block) => block,
+                None => return false,
+            };
+            canonical.push(block.hash);
+            if block.number.is_zero() {
+                break;
+            }
+            cursor = block.parent_hash;
+        }
+        canonical.reverse();
+
+        for tx_hash in &self.blocks[&hash].transactions {
+            let tx = &self.transactions[tx_hash];
+            let nonce = self.nonces.entry(tx.from).or_default();
+            *nonce = (*nonce).max(tx.nonce + 1);
+        }
+        self.canonical = canonical;
+
+        true
+    }
+
+    /// Mines a block with `raw_txs` on top of the head and emits `events` in it.
+    pub fn mine(&mut self, raw_txs: Vec<Bytes>, events: Vec<FakeEvent>) -> H256 {
+        let head = self.head();
+        let (parent, timestamp) = (head.hash, head.timestamp + 1);
+        let hash = self.seal_block(parent, timestamp, raw_txs);
+        self.set_head(hash);
+
+        let block = self.blocks.get_mut(&hash).unwrap();
+        for (i, event) in events.into_iter().enumerate() {
+            let mut data = event.input.to_vec();
+            data.extend_from_slice(hash.as_bytes());
+            data.extend_from_slice(&i.to_be_bytes());
+            let tx = Transaction {
+                hash: H256::from(keccak256(data)),
+                block_hash: Some(hash),
+                block_number: Some(block.number),
+                to: Some(event.address),
+                input: event.input,
+                ..Default::default()
+            };
+            block.logs.push(Log {
+                address: event.address,
+                topics: event.topics,
+                data: event.data,
+                block_hash: Some(hash),
+                block_number: Some(block.number),
+                transaction_hash: Some(tx.hash),
+                log_index: Some(block.logs.len().into()),
+                ..Default::default()
+            });
+            self.transactions.insert(tx.hash, tx);
+        }
+
+        hash
+    }
+
+    pub fn block_json(&self, block: &FakeBlock, full: bool) -> serde_json::Value {
+        let transactions: Vec<serde_json::Value> = block
+            .transactions
+            .iter()
+            .map(|hash| match full {
+                true => serde_json::to_value(&self.transactions[hash]).unwrap(),
+                false => serde_json::to_value(hash).unwrap(),
+            })
+            .collect();
+        let mut json = serde_json::to_value(Block::<H256> {
+            hash: Some(block.hash),
+            parent_hash: block.parent_hash,
+            author: Some(Address::zero()),
+            number: Some(block.number),
+            timestamp: block.timestamp,
+            state_root: block.state_root,
+            logs_bloom: Some(Bloom::zero()),
+            mix_hash: Some(H256::zero()),
+            nonce: Some(H64::zero()),
+            ..Default::default()
+        })
+        .unwrap();
+        json["transactions"] = transactions.into();
+
+        json
+    }
+}
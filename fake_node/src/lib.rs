@@ -0,0 +1,599 @@
+//! An in-memory json-rpc node that implements the subset of the L1/L2 node api used by the
+//! coordinator. Any method can be scripted with `FakeNode::on`.
+
+mod chain;
+
+pub use chain::*;
+
+use ethers_core::types::{Address, Bytes, Log, Transaction, H256, U256, U64};
+use ethers_core::utils::keccak256;
+use ethers_core::utils::rlp;
+use hmac::{Hmac, Mac};
+use hyper::body::Buf;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, Uri};
+use serde_json::Value;
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+use zkevm_common::json_rpc::{
+    JsonRpcError, JsonRpcRequest, JsonRpcResponse, JsonRpcResponseError, PARSE_ERROR,
+};
+
+/// Returns `None` to fall through to the default implementation of the method.
+pub type Handler = Arc<dyn Fn(&[Value]) -> Option<Result<Value, String>> + Send + Sync>;
+
+#[derive(Default)]
+struct NodeState {
+    chain: Option<Chain>,
+    /// required for `engine_*` methods if set
+    jwt_secret: Option<Vec<u8>>,
+    handlers: HashMap<String, Vec<Handler>>,
+    /// the number of requests to answer with `503 Service Unavailable`
+    failing_requests: usize,
+    requests: Vec<(String, Vec<Value>)>,
+}
+
+pub struct FakeNode {
+    pub uri: Uri,
+    state: Arc<Mutex<NodeState>>,
+    server: JoinHandle<()>,
+}
+
+impl Drop for FakeNode {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+impl FakeNode {
+    /// Starts a node with a genesis block on a random local port.
+    /// If `auto_mine` is set, transactions are mined as soon as they are received,
+    /// like an L1 node with instant sealing. Otherwise they are kept in the transaction pool
+    /// until a block is sealed with `miner_sealBlock` or the Engine API, like the L2 node.
+    pub async fn start(chain_id: u64, auto_mine: bool) -> Self {
+        let state = Arc::new(Mutex::new(NodeState {
+            chain: Some(Chain::new(chain_id, auto_mine)),
+            ..Default::default()
+        }));
+
+        let ctx = state.clone();
+        let service = make_service_fn(move |_| {
+            let ctx = ctx.clone();
+            let service = service_fn(move |req| handle_request(ctx.clone(), req));
+
+            async move { Ok::<_, hyper::Error>(service) }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(service);
+        let uri: Uri = format!("http://{}", server.local_addr()).parse().unwrap();
+        let server = tokio::spawn(async move {
+            server.await.expect("server should be serving");
+        });
+
+        Self { uri, state, server }
+    }
+
+    /// Adds `handler` for `method`. Handlers are consulted in reverse order of
+    /// registration, before the default implementation.
+    pub fn on<F>(&self, method: &str, handler: F)
+    where
+        F: Fn(&[Value]) -> Option<Result<Value, String>> + Send + Sync + 'static,
+    {
+        self.state
+            .lock()
+            .unwrap()
+            .handlers
+            .entry(method.to_string())
+            .or_default()
+            .push(Arc::new(handler));
+    }
+
+    /// Requires a JSON Web Token signed with `secret` for the `engine_*` methods,
+    /// like the authenticated Engine API endpoint of an execution client.
+    pub fn require_jwt(&self, secret: Vec<u8>) {
+        self.state.lock().unwrap().jwt_secret = Some(secret);
+    }
+
+    /// Answers the next `n` requests with an empty `503 Service Unavailable` response,
+    /// like a proxy without a healthy node.
+    pub fn fail_requests(&self, n: usize) {
+        self.state.lock().unwrap().failing_requests = n;
+    }
+
+    /// Runs `f` with exclusive access to the chain.
+    pub fn with_chain<R>(&self, f: impl FnOnce(&mut Chain) -> R) -> R {
+        f(self.state.lock().unwrap().chain.as_mut().unwrap())
+    }
+
+    /// Mines a block on top of the head that emits `events`.
+    pub fn mine(&self, events: Vec<FakeEvent>) -> H256 {
+        self.with_chain(|chain| chain.mine(Vec::new(), events))
+    }
+
+    pub fn head(&self) -> FakeBlock {
+        self.with_chain(|chain| chain.head().clone())
+    }
+
+    pub fn set_balance(&self, address: Address, balance: U256) {
+        self.with_chain(|chain| chain.balances.insert(address, balance));
+    }
+
+    /// Raw transactions received via `eth_sendRawTransaction`.
+    pub fn sent_transactions(&self) -> Vec<Bytes> {
+        self.with_chain(|chain| chain.sent_transactions.clone())
+    }
+
+    /// Method and params of all requests received so far.
+    pub fn requests(&self) -> Vec<(String, Vec<Value>)> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Number of requests received so far for `method`.
+    pub fn count(&self, method: &str) -> usize {
+        self.state
+            .lock()
+            .unwrap()
+            .requests
+            .iter()
+            .filter(|(m, _)| m == method)
+            .count()
+    }
+}
+
+async fn handle_request(
+    state: Arc<Mutex<NodeState>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, hyper::Error> {
+    let token = req
+        .headers()
+        .get(hyper::header::AUTHORIZATION)
+        .and_then(|val| val.to_str().ok())
+        .and_then(|val| val.strip_prefix("Bearer "))
+        .map(|val| val.to_string());
+    let body = hyper::body::aggregate(req.into_body()).await?;
+    {
+        let mut state = state.lock().unwrap();
+        if state.failing_requests > 0 {
+            state.failing_requests -= 1;
+            let mut resp = Response::default();
+            *resp.status_mut() = hyper::StatusCode::SERVICE_UNAVAILABLE;
+            return Ok(resp);
+        }
+    }
+
+    // a batch is an array of requests
+    let parse =
+        |req| serde_json::from_value::<JsonRpcRequest<Value>>(req).map_err(|e| e.to_string());
+    let (batch, reqs) = match serde_json::from_reader::<_, Value>(body.reader()) {
+        Ok(Value::Array(reqs)) => (true, reqs.into_iter().map(parse).collect()),
+        Ok(req) => (false, vec![parse(req)]),
+        Err(err) => (false, vec![Err(err.to_string())]),
+    };
+
+    let secret = state.lock().unwrap().jwt_secret.clone();
+    if let Some(secret) = secret {
+        let authenticated = reqs.iter().flatten().all(|req| {
+            !req.method.starts_with("engine_")
+                || verify_jwt(&secret, token.as_deref().unwrap_or_default())
+        });
+        if !authenticated {
+            let mut resp = Response::new(Body::from("invalid token"));
+            *resp.status_mut() = hyper::StatusCode::UNAUTHORIZED;
+            return Ok(resp);
+        }
+    }
+
+    let mut responses: Vec<Value> = reqs
+        .into_iter()
+        .map(|req| {
+            let resp = match req {
+                Err(err) => serde_json::to_value(JsonRpcResponseError {
+                    jsonrpc: "2.0".to_string(),
+                    id: Value::Null,
+                    error: JsonRpcError::new(PARSE_ERROR, err),
+                }),
+                Ok(req) => match handle_method(&state, &req.method, &params(req.params)) {
+                    Ok(result) => serde_json::to_value(JsonRpcResponse {
+                        jsonrpc: "2.0".to_string(),
+                        id: req.id,
+                        result: Some(result),
+                    }),
+                    Err(err) => serde_json::to_value(JsonRpcResponseError {
+                        jsonrpc: "2.0".to_string(),
+                        id: req.id,
+                        error: err.into(),
+                    }),
+                },
+            };
+            resp.unwrap()
+        })
+        .collect();
+    let payload = match batch {
+        true => Value::Array(responses),
+        false => responses.remove(0),
+    };
+
+    Ok(Response::new(Body::from(payload.to_string())))
+}
+
+/// Checks the HS256 signature and that the issued-at claim is within 60 seconds of now.
+fn verify_jwt(secret: &[u8], token: &str) -> bool {
+    let (message, signature) = match token.rsplit_once('.') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let claims = message
+        .split_once('.')
+        .and_then(|(_, claims)| base64::decode_config(claims, base64::URL_SAFE_NO_PAD).ok())
+        .and_then(|claims| serde_json::from_slice::<Value>(&claims).ok());
+    let iat = match claims.as_ref().and_then(|claims| claims["iat"].as_u64()) {
+        Some(iat) => iat,
+        None => return false,
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+    mac.update(message.as_bytes());
+    let expected = base64::encode_config(mac.finalize().into_bytes(), base64::URL_SAFE_NO_PAD);
+
+    expected == signature && now.abs_diff(iat) <= 60
+}
+
+/// Positional params, `null` is treated as no params.
+fn params(params: Value) -> Vec<Value> {
+    match params {
+        Value::Array(params) => params,
+        Value::Null => Vec::new(),
+        param => vec![param],
+    }
+}
+
+fn handle_method(
+    state: &Mutex<NodeState>,
+    method: &str,
+    params: &[Value],
+) -> Result<Value, String> {
+    let handlers = {
+        let mut state = state.lock().unwrap();
+        state.requests.push((method.to_string(), params.to_vec()));
+        state.handlers.get(method).cloned().unwrap_or_default()
+    };
+    // handlers are invoked without holding the lock, they may call into the node
+    for handler in handlers.iter().rev() {
+        if let Some(res) = handler(params) {
+            return res;
+        }
+    }
+
+    let mut state = state.lock().unwrap();
+    let chain = state.chain.as_mut().unwrap();
+
+    default_method(chain, method, params)
+}
+
+fn param<T: serde::de::DeserializeOwned>(params: &[Value], i: usize) -> Result<T, String> {
+    serde_json::from_value(params.get(i).cloned().unwrap_or_default())
+        .map_err(|e| format!("invalid param {i}: {e}"))
+}
+
+fn to_value<T: serde::Serialize>(val: T) -> Result<Value, String> {
+    serde_json::to_value(val).map_err(|e| e.to_string())
+}
+
+/// Resolves the block tag or number at `params[i]`.
+fn block_param(chain: &Chain, params: &[Value], i: usize) -> Result<FakeBlock, String> {
+    let tag = match params.get(i) {
+        Some(Value::Number(n)) => format!("{:#x}", n.as_u64().unwrap_or_default()),
+        Some(Value::String(s)) => s.clone(),
+        _ => "latest".to_string(),
+    };
+    chain
+        .block_by_tag(&tag)
+        .cloned()
+        .ok_or_else(|| format!("block not found: {tag}"))
+}
+
+fn default_method(chain: &mut Chain, method: &str, params: &[Value]) -> Result<Value, String> {
+    match method {
+        "eth_chainId" | "net_version" => to_value(U64::from(chain.chain_id)),
+        "web3_clientVersion" => to_value("FakeNode"),
+        "eth_blockNumber" => to_value(chain.head().number),
+        "eth_gasPrice" => to_value(U256::from(1_000_000_000u64)),
+        "eth_estimateGas" => to_value(U256::from(1_000_000u64)),
+        "eth_createAccessList" => Ok(serde_json::json!({
+            "accessList": [],
+            "gasUsed": U256::from(1_000_000u64),
+        })),
+        // geth returns 32 zero bytes for most of the calls in question
+        "eth_call" => to_value(Bytes::from(vec![0u8; 32])),
+        "eth_getBalance" => {
+            let address: Address = param(params, 0)?;
+            to_value(chain.balances.get(&address).cloned().unwrap_or_default())
+        }
+        "eth_getTransactionCount" => {
+            let address: Address = param(params, 0)?;
+            to_value(chain.nonces.get(&address).cloned().unwrap_or_default())
+        }
+        "eth_getHeaderByNumber" => Ok(block_param(chain, params, 0)?.header()),
+        "eth_getHeaderByHash" => {
+            let hash: H256 = param(params, 0)?;
+            match chain.blocks.get(&hash) {
+                Some(block) => Ok(block.header()),
+                None => Err(format!("header not found: {hash:?}")),
+            }
+        }
+        "eth_getBlockByNumber" => {
+            let block = block_param(chain, params, 0)?;
+            let full: bool = param(params, 1).unwrap_or_default();
+            Ok(chain.block_json(&block, full))
+        }
+        "eth_getBlockByHash" => {
+            let hash: H256 = param(params, 0)?;
+            let full: bool = param(params, 1).unwrap_or_default();
+            match chain.blocks.get(&hash) {
+                Some(block) => Ok(chain.block_json(block, full)),
+                None => Ok(Value::Null),
+            }
+        }
+        "debug_getHeaderRlp" => to_value(block_param(chain, params, 0)?.header_rlp()),
+        "eth_getTransactionByHash" => {
+            let hash: H256 = param(params, 0)?;
+            to_value(chain.transactions.get(&hash))
+        }
+        "eth_getTransactionReceipt" => {
+            // only for transactions of the canonical chain
+            let hash: H256 = param(params, 0)?;
+            let receipt = chain.receipts.get(&hash).filter(|receipt| {
+                receipt
+                    .block_number
+                    .and_then(|number| chain.block_by_number(number))
+                    .map_or(false, |block| Some(block.hash) == receipt.block_hash)
+            });
+            to_value(receipt)
+        }
+        "eth_sendRawTransaction" => {
+            let raw: Bytes = param(params, 0)?;
+            let hash = H256::from(keccak256(&raw));
+            if chain.txpool.contains(&raw) {
+                return Err("already known".to_string());
+            }
+            chain.sent_transactions.push(raw.clone());
+            match chain.auto_mine {
+                true => {
+                    chain.mine(vec![raw], Vec::new());
+                }
+                false => chain.txpool.push(raw),
+            }
+            to_value(hash)
+        }
+        "eth_getLogs" => to_value(get_logs(
+            chain,
+            &params.get(0).cloned().unwrap_or_default(),
+        )?),
+        "eth_getProof" => {
+            let address: Address = param(params, 0)?;
+            let keys: Vec<H256> = param(params, 1).unwrap_or_default();
+            let storage_proof: Vec<Value> = keys
+                .iter()
+                .map(|key| serde_json::json!({ "key": key, "value": "0x0", "proof": ["0x80"] }))
+                .collect();
+            Ok(serde_json::json!({
+                "address": address,
+                "accountProof": ["0x80"],
+                "balance": chain.balances.get(&address).cloned().unwrap_or_default(),
+                "codeHash": H256::zero(),
+                "nonce": chain.nonces.get(&address).cloned().unwrap_or_default(),
+                "storageHash": H256::zero(),
+                "storageProof": storage_proof,
+            }))
+        }
+        "txpool_content" => {
+            let mut pending: BTreeMap<Address, BTreeMap<String, Transaction>> = BTreeMap::new();
+            for raw in &chain.txpool {
+                let mut tx: Transaction = rlp::decode(raw).map_err(|e| e.to_string())?;
+                tx.hash = H256::from(keccak256(raw));
+                tx.from = tx.recover_from().unwrap_or_default();
+                pending
+                    .entry(tx.from)
+                    .or_default()
+                    .insert(tx.nonce.to_string(), tx);
+            }
+            Ok(serde_json::json!({ "pending": pending, "queued": {} }))
+        }
+        "txpool_status" => Ok(serde_json::json!({
+            "pending": U64::from(chain.txpool.len()),
+            "queued": U64::zero(),
+        })),
+        "miner_init" => to_value(Address::zero()),
+        "miner_sealBlock" => {
+            #[derive(serde::Deserialize)]
+            struct SealBlockRequest {
+                parent: H256,
+                timestamp: U64,
+                transactions: Option<Vec<Bytes>>,
+            }
+            let req: SealBlockRequest = param(params, 0)?;
+            let raw_txs = match req.transactions {
+                Some(txs) => txs,
+                None => std::mem::take(&mut chain.txpool),
+            };
+            if !chain.blocks.contains_key(&req.parent) {
+                return Err(format!("unknown parent: {:?}", req.parent));
+            }
+            let hash = chain.seal_block(req.parent, req.timestamp.as_u64().into(), raw_txs);
+            let block = chain.blocks[&hash].clone();
+            Ok(chain.block_json(&block, true))
+        }
+        "miner_setHead" => {
+            let hash: H256 = param(params, 0)?;
+            to_value(chain.set_head(hash))
+        }
+        "engine_forkchoiceUpdatedV1" => {
+            #[derive(serde::Deserialize)]
+            struct ForkchoiceState {
+                #[serde(rename = "headBlockHash")]
+                head_block_hash: H256,
+            }
+            #[derive(serde::Deserialize)]
+            struct PayloadAttributes {
+                timestamp: U64,
+            }
+            let head = param::<ForkchoiceState>(params, 0)?.head_block_hash;
+            let attributes: Option<PayloadAttributes> = param(params, 1)?;
+            if !chain.set_head(head) {
+                return Ok(serde_json::json!({
+                    "payloadStatus": { "status": "SYNCING", "latestValidHash": null, "validationError": null },
+                    "payloadId": null,
+                }));
+            }
+            // unlike `miner_sealBlock`, transactions stay in the pool until they are canonical
+            let included: HashSet<H256> = chain
+                .canonical
+                .iter()
+                .flat_map(|hash| chain.blocks[hash].transactions.iter().cloned())
+                .collect();
+            chain
+                .txpool
+                .retain(|raw| !included.contains(&H256::from(keccak256(raw))));
+            let payload_id = attributes.map(|attributes| {
+                let raw_txs = chain.txpool.clone();
+                let hash = chain.seal_block(head, attributes.timestamp.as_u64().into(), raw_txs);
+                chain.payloads.push(hash);
+                Bytes::from((chain.payloads.len() as u64 - 1).to_be_bytes().to_vec())
+            });
+            Ok(serde_json::json!({
+                "payloadStatus": { "status": "VALID", "latestValidHash": head, "validationError": null },
+                "payloadId": payload_id,
+            }))
+        }
+        "engine_getPayloadV1" => {
+            let id: Bytes = param(params, 0)?;
+            let hash = <[u8; 8]>::try_from(id.as_ref())
+                .ok()
+                .and_then(|id| chain.payloads.get(u64::from_be_bytes(id) as usize))
+                .ok_or_else(|| format!("unknown payload: {id}"))?;
+            let block = &chain.blocks[hash];
+            let transactions: Vec<&Bytes> = block
+                .transactions
+                .iter()
+                .map(|hash| &chain.raw_transactions[hash])
+                .collect();
+            Ok(serde_json::json!({
+                "parentHash": block.parent_hash,
+                "feeRecipient": Address::zero(),
+                "stateRoot": block.state_root,
+                "receiptsRoot": H256::zero(),
+                "logsBloom": Bytes::from(vec![0u8; 256]),
+                "prevRandao": H256::zero(),
+                "blockNumber": block.number,
+                "gasLimit": U64::from(30_000_000u64),
+                "gasUsed": U64::zero(),
+                "timestamp": U64::from(block.timestamp.as_u64()),
+                "extraData": Bytes::default(),
+                "baseFeePerGas": U256::zero(),
+                "blockHash": block.hash,
+                "transactions": transactions,
+            }))
+        }
+        "engine_newPayloadV1" => {
+            #[derive(serde::Deserialize)]
+            struct ExecutionPayload {
+                #[serde(rename = "blockHash")]
+                block_hash: H256,
+            }
+            let hash = param::<ExecutionPayload>(params, 0)?.block_hash;
+            let status = match chain.blocks.contains_key(&hash) {
+                true => "VALID",
+                false => "SYNCING",
+            };
+            Ok(serde_json::json!({
+                "status": status,
+                "latestValidHash": hash,
+                "validationError": null,
+            }))
+        }
+        _ => Err(format!(
+            "the method {method} does not exist/is not available"
+        )),
+    }
+}
+
+/// Filters the logs of the canonical chain by `fromBlock` and `toBlock` or `blockHash`,
+/// `address` and `topics`.
+fn get_logs(chain: &Chain, filter: &Value) -> Result<Vec<Log>, String> {
+    // a value or a list of values, `null` matches anything
+    fn any_of<T: serde::de::DeserializeOwned + PartialEq>(
+        val: &Value,
+        key: &str,
+    ) -> Result<Option<Vec<T>>, String> {
+        let res = match val {
+            Value::Null => None,
+            Value::Array(_) => Some(serde_json::from_value(val.clone())),
+            _ => Some(serde_json::from_value(val.clone()).map(|v| vec![v])),
+        };
+        res.transpose().map_err(|e| format!("{key}: {e}"))
+    }
+
+    let head = chain.head().number;
+    let block_num = |key: &str| -> Result<U64, String> {
+        match &filter[key] {
+            Value::String(tag) => chain
+                .block_by_tag(tag)
+                .map(|block| block.number)
+                .or_else(|| U64::from_str_radix(tag.trim_start_matches("0x"), 16).ok())
+                .ok_or_else(|| format!("{key}: {tag}")),
+            _ => Ok(head),
+        }
+    };
+    let (from, to) = match &filter["blockHash"] {
+        Value::Null => (block_num("fromBlock")?, block_num("toBlock")?),
+        hash => {
+            let hash: H256 = serde_json::from_value(hash.clone()).map_err(|e| e.to_string())?;
+            let block = chain
+                .blocks
+                .get(&hash)
+                .ok_or_else(|| format!("unknown block: {hash:?}"))?;
+            (block.number, block.number)
+        }
+    };
+    let addresses: Option<Vec<Address>> = any_of(&filter["address"], "address")?;
+    let topics: Vec<Option<Vec<H256>>> = match &filter["topics"] {
+        Value::Array(topics) => topics
+            .iter()
+            .map(|topic| any_of(topic, "topics"))
+            .collect::<Result<_, _>>()?,
+        _ => Vec::new(),
+    };
+
+    let mut logs = Vec::new();
+    let mut number = from;
+    while number <= to {
+        if let Some(block) = chain.block_by_number(number) {
+            logs.extend(
+                block
+                    .logs
+                    .iter()
+                    .filter(|log| {
+                        addresses
+                            .as_ref()
+                            .map_or(true, |a| a.contains(&log.address))
+                    })
+                    .filter(|log| {
+                        topics.iter().enumerate().all(|(i, topic)| match topic {
+                            None => true,
+                            Some(topic) => log.topics.get(i).map_or(false, |t| topic.contains(t)),
+                        })
+                    })
+                    .cloned(),
+            );
+        }
+        number = number + 1;
+    }
+
+    Ok(logs)
+}
@@ -29,6 +29,7 @@ fn get_crate_version(pkg: &str) -> String {
 
 fn main() {
     let pkg_version = var("CARGO_PKG_VERSION").expect("CARGO_PKG_VERSION");
+    let circuits_version = get_crate_version("zkevm-circuits");
     let version = format!(
         "{} {} {}",
         pkg_version,
@@ -43,10 +44,14 @@ fn main() {
                 "--dirty"
             ]
         ),
-        get_crate_version("zkevm-circuits"),
+        circuits_version,
     );
     println!(
         "cargo:rustc-env=PROVER_VERSION={}",
         version.replace('\n', "")
     );
+    println!(
+        "cargo:rustc-env=CIRCUITS_VERSION={}",
+        circuits_version.replace('\n', "")
+    );
 }
@@ -0,0 +1,54 @@
+use clap::Parser;
+use env_logger::Env;
+use prover::key_cache::KeyCache;
+use prover::shared_state::SharedState;
+use prover::VERSION;
+use serde::Serialize;
+use std::path::PathBuf;
+
+#[derive(Parser, Serialize, Debug)]
+#[clap(version = VERSION, about)]
+/// This command generates the proving keys of the circuits for every circuit configuration
+/// and writes them to the key cache of the prover daemon.
+struct GenKeysConfig {
+    #[clap(long, env = "PROVERD_KEY_CACHE")]
+    /// Directory to write the proving keys to, existing keys are kept.
+    key_cache: PathBuf,
+    #[clap(
+        long,
+        env = "PROVERD_CIRCUITS",
+        value_delimiter = ',',
+        default_value = "super"
+    )]
+    /// The circuits to generate the proving keys for.
+    circuits: Vec<String>,
+    #[clap(long, env = "PROVERD_PARAMS_PATH")]
+    /// The params file or directory the prover uses, otherwise params are generated.
+    params_path: Option<String>,
+    #[clap(long, env = "PROVERD_AGGREGATION_KEYS")]
+    /// Also generate the keys of the aggregation circuits, this computes a proof of each circuit.
+    aggregation_keys: bool,
+    #[clap(long, env = "PROVERD_CONFIG_FILE")]
+    /// TOML, YAML or JSON file with values for any of the options, keyed by their long name.
+    /// Environment variables and command line arguments take precedence.
+    config_file: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+
+    let config: GenKeysConfig = zkevm_common::config::parse();
+
+    let key_cache = KeyCache::open(&config.key_cache).expect("key cache");
+    let state = SharedState::new(String::new(), None).with_key_cache(key_cache);
+    state
+        .prepare_keys(
+            &config.circuits,
+            &config.params_path,
+            false,
+            config.aggregation_keys,
+        )
+        .await
+        .expect("prepare_keys");
+}
@@ -1,37 +1,66 @@
+use clap::Parser;
 use env_logger::Env;
+use prover::key_cache::KeyCache;
 use prover::shared_state::SharedState;
-use std::env::var;
+use prover::VERSION;
+use serde::Serialize;
+use std::path::PathBuf;
 use zkevm_common::prover::*;
 
+#[derive(Parser, Serialize, Debug)]
+#[clap(version = VERSION, about)]
 /// This command generates and prints the proofs to stdout.
-/// Required environment variables:
-/// - PROVERD_BLOCK_NUM - the block number to generate the proof for
-/// - PROVERD_RPC_URL - a geth http rpc that supports the debug namespace
-/// - PROVERD_PARAMS_PATH - a path to a file generated with the gen_params tool
+struct ProverCmdConfig {
+    #[clap(long, env = "PROVERD_BLOCK_NUM", required_unless_present = "worker")]
+    /// The block number to generate the proof for.
+    block_num: Option<u64>,
+    #[clap(long, env = "PROVERD_RPC_URL", required_unless_present = "worker")]
+    /// A geth http rpc that supports the debug namespace.
+    rpc_url: Option<String>,
+    #[clap(long, env = "PROVERD_PARAMS_PATH", required_unless_present = "worker")]
+    /// A path to a file generated with the gen_params tool.
+    params_path: Option<String>,
+    #[clap(long)]
+    /// Reads `ProofRequestOptions` as JSON from stdin and writes the result as JSON to stdout
+    /// instead, used by prover_rpcd to compute proofs in a child process.
+    worker: bool,
+    #[clap(long, env = "PROVERD_KEY_CACHE")]
+    /// Directory to load and store the proving keys.
+    key_cache: Option<PathBuf>,
+    #[clap(long, env = "PROVERD_CONFIG_FILE")]
+    /// TOML, YAML or JSON file with values for any of the options, keyed by their long name.
+    /// Environment variables and command line arguments take precedence.
+    config_file: Option<PathBuf>,
+}
+
 #[tokio::main]
 async fn main() {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
-    let block_num: u64 = var("PROVERD_BLOCK_NUM")
-        .expect("PROVERD_BLOCK_NUM env var")
-        .parse()
-        .expect("Cannot parse PROVERD_BLOCK_NUM env var");
-    let rpc_url: String = var("PROVERD_RPC_URL")
-        .expect("PROVERD_RPC_URL env var")
-        .parse()
-        .expect("Cannot parse PROVERD_RPC_URL env var");
-    let params_path: String = var("PROVERD_PARAMS_PATH")
-        .expect("PROVERD_PARAMS_PATH env var")
-        .parse()
-        .expect("Cannot parse PROVERD_PARAMS_PATH env var");
-
-    let state = SharedState::new(String::new(), None);
+    let config: ProverCmdConfig = zkevm_common::config::parse();
+
+    let mut state = SharedState::new(String::new(), None);
+    if let Some(dir) = config.key_cache {
+        state = state.with_key_cache(KeyCache::open(dir).expect("key cache"));
+    }
+
+    if config.worker {
+        let request: ProofRequestOptions =
+            serde_json::from_reader(std::io::stdin()).expect("ProofRequestOptions");
+        state.get_or_enqueue(&request).await;
+        state.duty_cycle().await;
+        let result = state.get_or_enqueue(&request).await.expect("some");
+
+        serde_json::to_writer(std::io::stdout(), &result).expect("serialize and write");
+        return;
+    }
+
     let request = ProofRequestOptions {
         circuit: "super".to_string(),
-        block: block_num,
-        rpc: rpc_url,
+        block: config.block_num.unwrap(),
+        rpc: config.rpc_url.unwrap(),
         retry: false,
-        param: Some(params_path),
+        param: config.params_path,
         mock: false,
         aggregate: false,
         ..Default::default()
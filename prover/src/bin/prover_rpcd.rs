@@ -1,11 +1,18 @@
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
 use env_logger::Env;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::Duration;
 
+use prover::key_cache::KeyCache;
 use prover::server::serve;
 use prover::shared_state::SharedState;
+use prover::worker::WorkerConfig;
 use prover::VERSION;
+use zkevm_common::task_store::{Retention, TaskStore};
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Serialize, Debug)]
 #[clap(version = VERSION, about)]
 /// This command starts a http/json-rpc server and serves proof oriented methods.
 pub(crate) struct ProverdConfig {
@@ -16,26 +23,124 @@ pub(crate) struct ProverdConfig {
     #[clap(long, env = "PROVERD_LOOKUP")]
     /// A `HOSTNAME:PORT` conformant string that will be used for DNS service discovery of other nodes.
     lookup: Option<String>,
+    #[clap(long, env = "PROVERD_TASK_STORE")]
+    /// Directory to persist the tasks and their results in, they are reloaded on startup.
+    task_store: Option<PathBuf>,
+    #[clap(long, env = "PROVERD_MAX_COMPLETED_TASKS")]
+    /// The number of completed tasks to keep in the task store, the oldest are removed first.
+    max_completed_tasks: Option<usize>,
+    #[clap(long, env = "PROVERD_COMPLETED_TASK_TTL")]
+    /// Seconds to keep completed tasks in the task store.
+    completed_task_ttl: Option<u64>,
+    #[clap(long, env = "PROVERD_KEY_CACHE")]
+    /// Directory to store the generated proving keys in, they are loaded from there on first use.
+    key_cache: Option<PathBuf>,
+    #[clap(long, env = "PROVERD_PK_CACHE_BUDGET")]
+    /// Memory budget of the in-memory proving key cache in MiB, the least recently used keys
+    /// are evicted beyond it. Unlimited by default.
+    pk_cache_budget: Option<u64>,
+    #[clap(long, env = "PROVERD_PRELOAD_KEYS", value_delimiter = ',')]
+    /// Circuits to load or generate the proving keys for on startup, before taking tasks.
+    /// Ignored with `worker`, fill the key cache with gen_keys instead.
+    preload_keys: Vec<String>,
+    #[clap(long, env = "PROVERD_PARAMS_PATH")]
+    /// The params file or directory for `preload_keys`, otherwise params are generated.
+    params_path: Option<String>,
+    #[clap(long, env = "PROVERD_AGGREGATION_KEYS")]
+    /// Also preload the keys of the aggregation circuits for `preload_keys`.
+    aggregation_keys: bool,
+    #[clap(long, env = "PROVERD_WORKER")]
+    /// Path of the prover_cmd executable to compute each proof in a child process with,
+    /// crashes of the child are reported as task errors. Requires `key_cache`.
+    worker: Option<PathBuf>,
+    #[clap(long, env = "PROVERD_WORKER_MEMORY_LIMIT")]
+    /// Virtual memory limit of a worker process in MiB.
+    worker_memory_limit: Option<u64>,
+    #[clap(long, env = "PROVERD_WORKER_CPU_TIME_LIMIT")]
+    /// CPU time limit of a worker process in seconds.
+    worker_cpu_time_limit: Option<u64>,
+    #[clap(long, env = "PROVERD_WORKER_TIMEOUT")]
+    /// A worker process is killed after this many seconds.
+    worker_timeout: Option<u64>,
+    #[clap(long, env = "PROVERD_CONFIG_FILE")]
+    /// TOML, YAML or JSON file with values for any of the options, keyed by their long name.
+    /// Environment variables and command line arguments take precedence.
+    config_file: Option<PathBuf>,
 }
 
 #[tokio::main]
 async fn main() {
-    let config = ProverdConfig::parse();
+    let config: ProverdConfig = zkevm_common::config::parse();
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
-    let shared_state = SharedState::new(SharedState::random_worker_id(), config.lookup);
+    // workers do not share the keys in memory, each would generate them otherwise
+    if config.worker.is_some() && config.key_cache.is_none() {
+        ProverdConfig::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--worker requires --key-cache",
+            )
+            .exit();
+    }
+
+    let mut shared_state = match config.task_store {
+        Some(dir) => {
+            let retention = Retention {
+                max_completed: config.max_completed_tasks,
+                max_age: config.completed_task_ttl.map(Duration::from_secs),
+            };
+            let task_store = TaskStore::open(dir, retention).expect("task store");
+            SharedState::with_task_store(SharedState::random_worker_id(), config.lookup, task_store)
+                .expect("task store")
+        }
+        None => SharedState::new(SharedState::random_worker_id(), config.lookup),
+    };
+    if let Some(budget) = config.pk_cache_budget {
+        shared_state = shared_state.with_pk_cache_budget(budget << 20);
+    }
+    let worker_mode = config.worker.is_some();
+    if let Some(program) = config.worker {
+        let key_cache = config.key_cache.as_ref().expect("key cache");
+        let args = vec!["--key-cache".to_string(), key_cache.display().to_string()];
+        shared_state = shared_state.with_worker(WorkerConfig {
+            program,
+            args,
+            memory_limit: config.worker_memory_limit.map(|mib| mib << 20),
+            cpu_time_limit: config.worker_cpu_time_limit,
+            timeout: config.worker_timeout.map(Duration::from_secs),
+        });
+    }
+    if let Some(dir) = config.key_cache {
+        shared_state = shared_state.with_key_cache(KeyCache::open(dir).expect("key cache"));
+    }
     {
         // start the http server
         let h1 = serve(&shared_state, &config.bind);
 
         // starts the duty cycle loop
         let ctx = shared_state.clone();
+        let (mut preload_keys, params_path, aggregation_keys) = (
+            config.preload_keys,
+            config.params_path,
+            config.aggregation_keys,
+        );
+        if worker_mode && !preload_keys.is_empty() {
+            // the workers load the keys from the key cache, not from this process
+            log::warn!("preload_keys is ignored with worker, use gen_keys to fill the key cache");
+            preload_keys.clear();
+        }
         // use a dedicated runtime for mixed async / heavy (blocking) compute
         let rt = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
             .build()
             .unwrap();
         let h2 = rt.spawn(async move {
+            if let Err(err) = ctx
+                .prepare_keys(&preload_keys, &params_path, true, aggregation_keys)
+                .await
+            {
+                panic!("preloading proving keys failed: {err}");
+            }
             loop {
                 let ctx = ctx.clone();
                 // enclose this call to catch panics which may
@@ -0,0 +1,134 @@
+use crate::Fr;
+use crate::ProverKey;
+use crate::ProverParams;
+use crate::CIRCUITS_VERSION;
+use halo2_proofs::plonk::{Circuit, ConstraintSystem};
+use halo2_proofs::poly::commitment::Params;
+use halo2_proofs::SerdeFormat;
+use std::fmt::Write;
+use std::fs;
+use std::hash::Hasher;
+use std::io;
+use std::path::{Path, PathBuf};
+use zkevm_common::hash::{fnv1a, Fnv1a};
+use zkevm_common::prover::CircuitConfig;
+
+/// Marks proving key files, followed by the length and FNV-1a checksum of the key.
+const MAGIC: &[u8; 8] = b"ZKEVMPK1";
+const HEADER_LEN: usize = MAGIC.len() + 16;
+
+/// A directory of proving keys, `<name>.pk` with an integrity header and
+/// `<name>.vk` with the bare verifying key for external verifiers.
+pub struct KeyCache {
+    dir: PathBuf,
+}
+
+impl KeyCache {
+    /// Opens the cache in `dir`, creating the directory if needed.
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The name of the key of the circuit `name` for `params` and `config`, stable across
+    /// restarts. `aggregation` selects the key of the aggregation circuit.
+    /// The name includes a digest of the constraint system of `C`, the type of `_circuit`,
+    /// and the zkevm-circuits version, keys of changed circuits are not reused.
+    pub fn key_name<C: Circuit<Fr>>(
+        name: &str,
+        _circuit: &C,
+        params: &ProverParams,
+        config: &CircuitConfig,
+        aggregation: bool,
+    ) -> String {
+        // the first generator and the g2 points identify the setup
+        let params_digest = fnv1a(
+            format!(
+                "{:?}{:?}{:?}",
+                params.get_g()[0],
+                params.g2(),
+                params.s_g2()
+            )
+            .as_bytes(),
+        );
+        let config_digest = fnv1a(&serde_json::to_vec(config).expect("CircuitConfig"));
+        let circuit_digest = {
+            let mut cs = ConstraintSystem::<Fr>::default();
+            C::configure(&mut cs);
+            let mut hasher = Fnv1a::default();
+            write!(hasher, "{}{:?}", CIRCUITS_VERSION, cs.pinned()).expect("Fnv1a");
+            hasher.finish()
+        };
+
+        format!(
+            "{}{}-k{}-{:016x}-{:016x}-{:016x}",
+            name,
+            if aggregation { "-agg" } else { "" },
+            params.k(),
+            params_digest,
+            config_digest,
+            circuit_digest
+        )
+    }
+
+    /// Reads the key `name`, `Ok(None)` if it is not cached.
+    /// Fails with `InvalidData` if the file does not pass the integrity check.
+    pub fn read<C: Circuit<Fr>>(&self, name: &str) -> io::Result<Option<ProverKey>> {
+        let data = match fs::read(self.path(name, "pk")) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let invalid =
+            |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{name}: {msg}"));
+        if data.len() < HEADER_LEN || &data[..MAGIC.len()] != MAGIC {
+            return Err(invalid("not a proving key file"));
+        }
+        let len = u64::from_le_bytes(data[8..16].try_into().unwrap());
+        let checksum = u64::from_le_bytes(data[16..24].try_into().unwrap());
+        let key = &data[HEADER_LEN..];
+        if key.len() as u64 != len {
+            return Err(invalid("truncated"));
+        }
+        if fnv1a(key) != checksum {
+            return Err(invalid("checksum mismatch"));
+        }
+
+        ProverKey::read::<_, C>(&mut &key[..], SerdeFormat::RawBytesUnchecked).map(Some)
+    }
+
+    /// Writes the proving and verifying key `name`, files are replaced atomically.
+    pub fn write(&self, name: &str, pk: &ProverKey) -> io::Result<()> {
+        let mut key = Vec::new();
+        pk.write(&mut key, SerdeFormat::RawBytesUnchecked)?;
+
+        let mut data = Vec::with_capacity(HEADER_LEN + key.len());
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&(key.len() as u64).to_le_bytes());
+        data.extend_from_slice(&fnv1a(&key).to_le_bytes());
+        data.extend_from_slice(&key);
+        drop(key);
+        self.replace(&self.path(name, "pk"), &data)?;
+
+        let mut vk = Vec::new();
+        pk.get_vk().write(&mut vk, SerdeFormat::RawBytes)?;
+        self.replace(&self.path(name, "vk"), &vk)
+    }
+
+    fn path(&self, name: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{name}.{extension}"))
+    }
+
+    fn replace(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, path)
+    }
+}
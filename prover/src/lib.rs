@@ -7,6 +7,8 @@ use halo2_proofs::poly::kzg::commitment::KZGCommitmentScheme;
 use halo2_proofs::poly::kzg::commitment::ParamsKZG;
 
 pub const VERSION: &str = env!("PROVER_VERSION");
+/// The zkevm-circuits dependency, part of the proving key names.
+pub const CIRCUITS_VERSION: &str = env!("CIRCUITS_VERSION");
 
 pub type ProverParams = ParamsKZG<Bn256>;
 pub type ProverCommitmentScheme = KZGCommitmentScheme<Bn256>;
@@ -15,6 +17,9 @@ pub type ProverKey = ProvingKey<G1Affine>;
 pub mod circuit_autogen;
 pub mod circuit_witness;
 pub mod circuits;
+pub mod key_cache;
+pub mod pk_cache;
 pub mod server;
 pub mod shared_state;
 pub mod utils;
+pub mod worker;
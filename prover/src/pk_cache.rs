@@ -0,0 +1,179 @@
+use crate::Fr;
+use crate::ProverKey;
+use halo2_proofs::plonk::{Circuit, ConstraintSystem};
+use std::collections::HashMap;
+use std::sync::Arc;
+use zkevm_common::prover::{PkCacheEntry, PkCacheStats};
+
+struct Entry<T> {
+    value: Arc<T>,
+    size: u64,
+    last_used: u64,
+}
+
+/// In-memory cache of proving keys with an optional memory budget in bytes.
+/// Exceeding the budget evicts the least recently used keys, except keys in use
+/// by a proof computation, i.e. with references outside of the cache.
+pub struct PkCache<T = ProverKey> {
+    budget: Option<u64>,
+    entries: HashMap<String, Entry<T>>,
+    size: u64,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl<T> PkCache<T> {
+    pub fn new(budget: Option<u64>) -> Self {
+        Self {
+            budget,
+            entries: HashMap::new(),
+            size: 0,
+            clock: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<Arc<T>> {
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                self.hits += 1;
+                entry.last_used = self.clock;
+                Some(entry.value.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Inserts `value` taking `size` bytes and evicts other keys to stay within the budget.
+    /// Returns the evicted keys.
+    pub fn insert(&mut self, key: &str, value: Arc<T>, size: u64) -> Vec<String> {
+        self.clock += 1;
+        self.remove(key);
+        self.entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                size,
+                last_used: self.clock,
+            },
+        );
+        self.size += size;
+
+        self.evict(Some(key), 0)
+    }
+
+    /// Evicts keys to make room for a key of `size` bytes within the budget, before it is
+    /// loaded or generated. Returns the evicted keys.
+    pub fn reserve(&mut self, size: u64) -> Vec<String> {
+        self.evict(None, size)
+    }
+
+    /// Evicts the least recently used keys, except `keep` and keys in use,
+    /// until `additional` bytes fit into the budget.
+    fn evict(&mut self, keep: Option<&str>, additional: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        let budget = match self.budget {
+            Some(budget) => budget,
+            None => return evicted,
+        };
+        while self.size + additional > budget {
+            let lru = self
+                .entries
+                .iter()
+                .filter(|(name, entry)| {
+                    Some(name.as_str()) != keep && Arc::strong_count(&entry.value) == 1
+                })
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(name, _)| name.clone());
+            match lru {
+                Some(name) => {
+                    self.remove(&name);
+                    self.evictions += 1;
+                    log::info!("ProvingKey: evicted key={}", name);
+                    evicted.push(name);
+                }
+                None => {
+                    log::warn!(
+                        "ProvingKey: cache exceeds its budget of {} bytes with {} bytes in use",
+                        budget,
+                        self.size + additional
+                    );
+                    break;
+                }
+            }
+        }
+
+        evicted
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Arc<T>> {
+        let entry = self.entries.remove(key)?;
+        self.size -= entry.size;
+        Some(entry.value)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.size = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> PkCacheStats {
+        let mut keys: Vec<PkCacheEntry> = self
+            .entries
+            .iter()
+            .map(|(name, entry)| PkCacheEntry {
+                name: name.clone(),
+                size: entry.size,
+                in_use: Arc::strong_count(&entry.value) > 1,
+            })
+            .collect();
+        keys.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        PkCacheStats {
+            budget: self.budget,
+            size: self.size,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            keys,
+        }
+    }
+}
+
+/// Estimates the memory of the proving key of `C` for `2^k` rows from the number of its
+/// polynomials, without generating or serializing the key.
+/// Selectors count as fixed columns, keygen turns them into fewer of these.
+pub fn pk_size<C: Circuit<Fr>>(k: u32) -> u64 {
+    let mut cs = ConstraintSystem::<Fr>::default();
+    C::configure(&mut cs);
+
+    let n = 1u64 << k;
+    // the extended domain of the quotient polynomial
+    let extended_n = n
+        * (cs.degree() as u64)
+            .saturating_sub(1)
+            .max(1)
+            .next_power_of_two();
+    let columns =
+        (cs.num_fixed_columns() + cs.num_selectors() + cs.permutation().get_columns().len()) as u64;
+    // values, coefficients and extended evaluations of each column and l0, l_last, l_active_row
+    let scalars = columns * (2 * n + extended_n) + 3 * extended_n;
+
+    scalars * std::mem::size_of::<Fr>() as u64
+}
@@ -1,15 +1,9 @@
 use crate::circuit_witness::CircuitWitness;
 use crate::shared_state::SharedState;
-use hyper::body::Buf;
-use hyper::body::HttpBody;
-use hyper::header::HeaderValue;
-use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::{Method, Response, StatusCode};
 use zkevm_common::json_rpc::JsonRpcError;
-use zkevm_common::json_rpc::JsonRpcRequest;
-use zkevm_common::json_rpc::JsonRpcResponse;
-use zkevm_common::json_rpc::JsonRpcResponseError;
 use zkevm_common::prover::*;
+use zkevm_common::server::JsonRpcServer;
 
 /// Starts the proverd json-rpc server.
 /// Note: the server may not immediately listening after returning the
@@ -18,266 +12,166 @@ pub fn serve(ctx: &SharedState, addr: &str) -> tokio::task::JoinHandle<()> {
     let addr = addr
         .parse::<std::net::SocketAddr>()
         .expect("valid socket address");
-    let ctx = ctx.clone();
+    let server = rpc_server(ctx);
     tokio::spawn(async move {
-        let service = make_service_fn(move |_| {
-            let ctx = ctx.clone();
-            let service = service_fn(move |req| handle_request(ctx.clone(), req));
-
-            async move { Ok::<_, hyper::Error>(service) }
-        });
-        let server = Server::bind(&addr).serve(service);
-        log::info!("Listening on http://{}", addr);
-        server.await.expect("server should be serving");
+        server
+            .serve(&addr, std::future::pending())
+            .await
+            .expect("server should be serving");
     })
 }
 
-/// sets default headers for CORS requests
-fn set_headers(headers: &mut hyper::HeaderMap, extended: bool) {
-    headers.insert("content-type", HeaderValue::from_static("application/json"));
-    headers.insert("access-control-allow-origin", HeaderValue::from_static("*"));
-
-    if extended {
-        headers.insert(
-            "access-control-allow-methods",
-            HeaderValue::from_static("post, get, options"),
-        );
-        headers.insert(
-            "access-control-allow-headers",
-            HeaderValue::from_static("origin, content-type, accept, x-requested-with"),
-        );
-        headers.insert("access-control-max-age", HeaderValue::from_static("300"));
-    }
-}
-
-async fn handle_request(
-    shared_state: SharedState,
-    req: Request<Body>,
-) -> Result<Response<Body>, hyper::Error> {
-    {
-        // limits the request size
-        const MAX_BODY_SIZE: u64 = 1 << 20;
-        let response_content_length = match req.body().size_hint().upper() {
-            Some(v) => v,
-            None => MAX_BODY_SIZE + 1,
-        };
-
-        if response_content_length > MAX_BODY_SIZE {
-            let mut resp = Response::new(Body::from("request too large"));
-            *resp.status_mut() = StatusCode::BAD_REQUEST;
-            return Ok(resp);
-        }
-    }
-
-    match (req.method(), req.uri().path()) {
-        (&Method::GET, "/health") => {
-            // nothing to report yet - healthy by default
-            let mut resp = Response::default();
-            set_headers(resp.headers_mut(), false);
-            Ok(resp)
-        }
-
+/// The proverd json-rpc methods and the `/status` endpoint.
+pub fn rpc_server(ctx: &SharedState) -> JsonRpcServer<SharedState> {
+    JsonRpcServer::new(ctx.clone())
+        .info("proverd", env!("CARGO_PKG_VERSION"))
         // returns http 200 if busy else 204.
         // can be used programmatically for e.g. shutting down the instance if no workis being
         // done.
-        (&Method::GET, "/status") => {
-            let rw = shared_state.rw.lock().await;
-            let is_busy = rw.pending.is_some() || rw.tasks.iter().any(|e| e.result.is_none());
-            drop(rw);
-
-            let mut resp = Response::default();
-            *resp.status_mut() = match is_busy {
-                false => StatusCode::NO_CONTENT,
-                true => StatusCode::OK,
-            };
-            set_headers(resp.headers_mut(), false);
-            Ok(resp)
-        }
-
-        // json-rpc
-        (&Method::POST, "/") => {
-            let body_bytes = hyper::body::aggregate(req.into_body())
-                .await
-                .unwrap()
-                .reader();
-            let json_req: Result<JsonRpcRequest<Vec<serde_json::Value>>, serde_json::Error> =
-                serde_json::from_reader(body_bytes);
-
-            if let Err(err) = json_req {
-                let payload = serde_json::to_vec(&JsonRpcResponseError {
-                    jsonrpc: "2.0".to_string(),
-                    id: 0.into(),
-                    error: JsonRpcError {
-                        // parser error
-                        code: -32700,
-                        message: err.to_string(),
-                    },
-                })
-                .unwrap();
-                let mut resp = Response::new(Body::from(payload));
-                set_headers(resp.headers_mut(), false);
-                return Ok(resp);
-            }
-
-            let json_req = json_req.unwrap();
-            let result: Result<serde_json::Value, String> =
-                handle_method(json_req.method.as_str(), &json_req.params, &shared_state).await;
-            let payload = match result {
-                Err(err) => {
-                    serde_json::to_vec(&JsonRpcResponseError {
-                        jsonrpc: "2.0".to_string(),
-                        id: json_req.id,
-                        error: JsonRpcError {
-                            // internal server error
-                            code: -32000,
-                            message: err,
-                        },
-                    })
-                }
-                Ok(val) => serde_json::to_vec(&JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id: json_req.id,
-                    result: Some(val),
-                }),
-            };
-            let mut resp = Response::new(Body::from(payload.unwrap()));
-            set_headers(resp.headers_mut(), false);
-            Ok(resp)
-        }
-
-        // serve CORS headers
-        (&Method::OPTIONS, "/") => {
-            let mut resp = Response::default();
-            set_headers(resp.headers_mut(), true);
-            Ok(resp)
-        }
-
-        // everything else
-        _ => {
-            let mut not_found = Response::default();
-            *not_found.status_mut() = StatusCode::NOT_FOUND;
-            Ok(not_found)
-        }
-    }
-}
-
-async fn handle_method(
-    method: &str,
-    params: &[serde_json::Value],
-    shared_state: &SharedState,
-) -> Result<serde_json::Value, String> {
-    match method {
+        .route(
+            Method::GET,
+            "/status",
+            |shared_state: SharedState, _req| async move {
+                let rw = shared_state.rw.lock().await;
+                let is_busy = rw.pending.is_some() || rw.tasks.iter().any(|e| e.result.is_none());
+                drop(rw);
+
+                let mut resp = Response::default();
+                *resp.status_mut() = match is_busy {
+                    false => StatusCode::NO_CONTENT,
+                    true => StatusCode::OK,
+                };
+                resp
+            },
+        )
         // enqueues a task for computating proof for any given block
-        "proof" => {
-            let options = params.get(0).ok_or("expected struct ProofRequestOptions")?;
-            let options: ProofRequestOptions =
-                serde_json::from_value(options.to_owned()).map_err(|e| e.to_string())?;
-
-            shared_state
-                .get_or_enqueue(&options)
-                .await
-                .map(|result| serde_json::to_value(result?).map_err(|e| e.to_string()))
-                .unwrap_or_else(|| Ok(serde_json::Value::Null))
-        }
-
-        "circuit_config" => {
-            let options = params.get(0).ok_or("expected struct ProofRequestOptions")?;
-            let options: ProofRequestOptions =
-                serde_json::from_value(options.to_owned()).map_err(|e| e.to_string())?;
-
-            let witness = CircuitWitness::from_rpc(&options.block, &options.rpc)
-                .await
-                .map_err(|e| e.to_string())?;
-
-            let circuit_config =
-                crate::match_circuit_params!(witness.gas_used(), CIRCUIT_CONFIG, {
-                    return Err(format!(
-                        "No circuit parameters found for block with gas={}",
-                        witness.gas_used()
-                    ));
-                });
-
-            Ok(serde_json::to_value(circuit_config).unwrap())
-        }
-
-        // TODO: Add the abilitity to abort the current task.
-
+        .method(
+            "proof",
+            |shared_state: SharedState, (options,): (ProofRequestOptions,)| async move {
+                match shared_state.get_or_enqueue(&options).await {
+                    Some(result) => Ok(ProofResponse::Completed(result?)),
+                    None => Ok(ProofResponse::Pending(
+                        shared_state.pending_task(&options).await,
+                    )),
+                }
+            },
+        )
+        .method(
+            "circuit_config",
+            |_, (options,): (ProofRequestOptions,)| async move {
+                let witness = CircuitWitness::from_rpc(&options.block, &options.rpc)
+                    .await
+                    .map_err(|e| e.to_string())?;
+
+                let circuit_config =
+                    crate::match_circuit_params!(witness.gas_used(), CIRCUIT_CONFIG, {
+                        return Err(format!(
+                            "No circuit parameters found for block with gas={}",
+                            witness.gas_used()
+                        )
+                        .into());
+                    });
+
+                Ok(circuit_config)
+            },
+        )
+        .method(
+            "cancel",
+            |shared_state: SharedState, (options,): (ProofRequestOptions,)| async move {
+                Ok::<_, JsonRpcError>(shared_state.cancel(&options).await)
+            },
+        )
+        .method("abort", |shared_state: SharedState, ()| async move {
+            Ok::<_, JsonRpcError>(shared_state.abort().await)
+        })
+        .method(
+            "invalidate",
+            |shared_state: SharedState, (options,): (ProofRequestOptions,)| async move {
+                Ok::<_, JsonRpcError>(shared_state.invalidate(&options).await)
+            },
+        )
         // returns `NodeInformation`
         // used internally for p2p communication
-        "info" => Ok(serde_json::to_value(shared_state.get_node_information().await).unwrap()),
-
+        .method("info", |shared_state: SharedState, ()| async move {
+            Ok::<_, JsonRpcError>(shared_state.get_node_information().await)
+        })
         // returns `NodeStatus`
         // used internally for p2p communication
-        "status" => {
-            let mut pending_task = None;
+        .method("status", |shared_state: SharedState, ()| async move {
             let rw = shared_state.rw.lock().await;
 
-            if let Some(val) = &rw.pending {
-                pending_task = Some(val.clone());
-            }
-
-            let ret = NodeStatus {
+            Ok::<_, JsonRpcError>(NodeStatus {
                 id: shared_state.ro.node_id.clone(),
-                task: pending_task,
+                task: rw.pending.clone(),
                 obtained: rw.obtained,
-            };
-            drop(rw);
-
-            Ok(serde_json::to_value(ret).unwrap())
-        }
-
+            })
+        })
         // Note: this only flushes `this` instance and not any other nodes.
-        "flush" => {
-            #[derive(serde::Deserialize)]
-            struct FlushRequestOptions {
-                cache: bool,
-                pending: bool,
-                completed: bool,
-            }
-
-            let options = params.get(0).ok_or("expected struct FlushRequestOptions")?;
-            let options: FlushRequestOptions =
-                serde_json::from_value(options.to_owned()).map_err(|e| e.to_string())?;
-            let mut rw_state = shared_state.rw.lock().await;
-
-            if options.cache {
-                rw_state.pk_cache.clear();
-            }
-            if options.pending {
-                rw_state.tasks.retain(|e| e.result.is_some());
-            }
-            if options.completed {
-                rw_state.tasks.retain(|e| e.result.is_none());
-            }
-
-            Ok(serde_json::Value::Bool(true))
-        }
-
+        .method(
+            "flush",
+            |shared_state: SharedState, (options,): (FlushRequestOptions,)| async move {
+                if options.cache {
+                    shared_state.rw.lock().await.pk_cache.clear();
+                }
+                shared_state
+                    .flush_tasks(options.pending, options.completed)
+                    .await;
+
+                Ok::<_, JsonRpcError>(true)
+            },
+        )
+        .describe(
+            "proof",
+            "Enqueues a proof task, returns the proofs once computed and its queue state before",
+            &["options"],
+        )
+        .describe(
+            "circuit_config",
+            "Returns the circuit parameters for the block",
+            &["options"],
+        )
+        .describe(
+            "info",
+            "Returns the node id, its tasks and proving key cache statistics",
+            &[],
+        )
+        .describe("status", "Returns the task the node works on", &[])
+        .describe(
+            "cancel",
+            "Cancels a task on this node and its peers, returns false if it is not pending",
+            &["options"],
+        )
+        .describe(
+            "abort",
+            "Cancels the task this node works on and returns it",
+            &[],
+        )
+        .describe(
+            "invalidate",
+            "Drops the result of a task on this node and its peers and enqueues it again",
+            &["options"],
+        )
+        .describe(
+            "flush",
+            "Removes the selected tasks from this node only",
+            &["options"],
+        )
         // TODO: remove these obsolete methods later.
         // the following methods can be used to programmatically
         // prune the `tasks` from the list.
-        "flushAll" => {
-            shared_state.rw.lock().await.tasks.clear();
-            Ok(serde_json::Value::Bool(true))
-        }
-        "flushPending" => {
-            shared_state
-                .rw
-                .lock()
-                .await
-                .tasks
-                .retain(|e| e.result.is_some());
-            Ok(serde_json::Value::Bool(true))
-        }
-        "flushCompleted" => {
-            shared_state
-                .rw
-                .lock()
-                .await
-                .tasks
-                .retain(|e| e.result.is_none());
-            Ok(serde_json::Value::Bool(true))
-        }
-        _ => Err("this method is not available".to_string()),
-    }
+        .method("flushAll", |shared_state: SharedState, ()| async move {
+            shared_state.flush_tasks(true, true).await;
+            Ok::<_, JsonRpcError>(true)
+        })
+        .method("flushPending", |shared_state: SharedState, ()| async move {
+            shared_state.flush_tasks(true, false).await;
+            Ok::<_, JsonRpcError>(true)
+        })
+        .method(
+            "flushCompleted",
+            |shared_state: SharedState, ()| async move {
+                shared_state.flush_tasks(false, true).await;
+                Ok::<_, JsonRpcError>(true)
+            },
+        )
 }
@@ -1,9 +1,12 @@
 use crate::circuit_witness::CircuitWitness;
 use crate::circuits::*;
+use crate::key_cache::KeyCache;
+use crate::pk_cache::{pk_size, PkCache};
 use crate::utils::collect_instance;
 use crate::utils::fixed_rng;
 use crate::utils::gen_num_instance;
 use crate::utils::gen_proof;
+use crate::worker::WorkerConfig;
 use crate::Fr;
 use crate::G1Affine;
 use crate::ProverKey;
@@ -17,7 +20,6 @@ use halo2_proofs::SerdeFormat;
 use hyper::Uri;
 use rand::{thread_rng, Rng};
 use snark_verifier::system::halo2::transcript::evm::EvmTranscript;
-use std::collections::HashMap;
 use std::fmt::Write;
 use std::fs::File;
 use std::io::Write as IoWrite;
@@ -31,8 +33,19 @@ use zkevm_circuits::root_circuit::Config as PlonkConfig;
 use zkevm_circuits::root_circuit::PoseidonTranscript;
 use zkevm_circuits::root_circuit::RootCircuit;
 use zkevm_circuits::util::SubCircuit;
-use zkevm_common::json_rpc::jsonrpc_request_client;
+use zkevm_common::json_rpc::{JsonRpcClient, RetryPolicy};
 use zkevm_common::prover::*;
+use zkevm_common::task_store::TaskStore;
+
+/// Client for the peer node at `uri`.
+fn peer_client(uri: Uri) -> ProverClient {
+    ProverClient::with_client(
+        JsonRpcClient::builder(uri)
+            .timeout(5000)
+            .retry(RetryPolicy::NONE)
+            .build(),
+    )
+}
 
 fn get_param_path(path: &String, k: usize) -> PathBuf {
     // try to automatically choose a file if the path is a folder.
@@ -43,17 +56,15 @@ fn get_param_path(path: &String, k: usize) -> PathBuf {
     }
 }
 
-fn get_or_gen_param(task_options: &ProofRequestOptions, k: usize) -> (Arc<ProverParams>, String) {
+fn get_or_gen_param(task_options: &ProofRequestOptions, k: usize) -> Arc<ProverParams> {
     match &task_options.param {
         Some(v) => {
             let path = get_param_path(v, k);
             let file = File::open(&path).expect("couldn't open params");
-            let params = Arc::new(
+            Arc::new(
                 ProverParams::read(&mut std::io::BufReader::new(file))
                     .expect("Failed to read params"),
-            );
-
-            (params, path.to_str().unwrap().into())
+            )
         }
         None => {
             let param = ProverParams::setup(k as u32, fixed_rng());
@@ -65,8 +76,7 @@ fn get_or_gen_param(task_options: &ProofRequestOptions, k: usize) -> (Arc<Prover
                     )
                     .unwrap();
             }
-            let param = Arc::new(param);
-            (param, format!("{k}"))
+            Arc::new(param)
         }
     }
 }
@@ -104,13 +114,17 @@ async fn compute_proof<C: Circuit<Fr> + Clone + SubCircuit<Fr>>(
         prover.verify_par().expect("MockProver::verify_par");
         circuit_proof.aux.mock = Instant::now().duration_since(time_started).as_millis() as u32;
     } else {
-        let (param, param_path) = get_or_gen_param(task_options, circuit_config.min_k);
+        shared_state.check_cancelled(task_options).await?;
+        let param = get_or_gen_param(task_options, circuit_config.min_k);
         circuit_proof.k = param.k() as u8;
         // generate and cache the prover key
         let pk = {
-            let cache_key = format!(
-                "{}{}{:?}",
-                &task_options.circuit, &param_path, &circuit_config
+            let cache_key = KeyCache::key_name(
+                &task_options.circuit,
+                &circuit,
+                &param,
+                &circuit_config,
+                false,
             );
             shared_state
                 .gen_pk(&cache_key, &param, &circuit, &mut circuit_proof.aux)
@@ -118,6 +132,7 @@ async fn compute_proof<C: Circuit<Fr> + Clone + SubCircuit<Fr>>(
                 .map_err(|e| e.to_string())?
         };
 
+        shared_state.check_cancelled(task_options).await?;
         let circuit_instance = circuit.instance();
         circuit_proof.instance = collect_instance(&circuit_instance);
 
@@ -145,6 +160,7 @@ async fn compute_proof<C: Circuit<Fr> + Clone + SubCircuit<Fr>>(
             }
 
             // aggregate the circuit proof
+            shared_state.check_cancelled(task_options).await?;
             let protocol = {
                 let time_started = Instant::now();
                 let v = compile(
@@ -157,8 +173,7 @@ async fn compute_proof<C: Circuit<Fr> + Clone + SubCircuit<Fr>>(
                 v
             };
 
-            let (agg_params, agg_param_path) =
-                get_or_gen_param(task_options, circuit_config.min_k_aggregation);
+            let agg_params = get_or_gen_param(task_options, circuit_config.min_k_aggregation);
             aggregation_proof.k = agg_params.k() as u8;
 
             let agg_circuit = {
@@ -176,9 +191,12 @@ async fn compute_proof<C: Circuit<Fr> + Clone + SubCircuit<Fr>>(
             };
 
             let agg_pk = {
-                let cache_key = format!(
-                    "{}{}{:?}ag",
-                    &task_options.circuit, &agg_param_path, &circuit_config
+                let cache_key = KeyCache::key_name(
+                    &task_options.circuit,
+                    &agg_circuit,
+                    &agg_params,
+                    &circuit_config,
+                    true,
                 );
                 shared_state
                     .gen_pk(
@@ -260,6 +278,28 @@ macro_rules! compute_proof_wrapper {
     }};
 }
 
+macro_rules! prepare_key_wrapper {
+    ($shared_state:expr, $circuit_name:expr, $param:expr, $keep:expr, $aggregate:expr, $witness:expr, $CIRCUIT:ident) => {{
+        let circuit = $CIRCUIT::<
+            { CIRCUIT_CONFIG.max_txs },
+            { CIRCUIT_CONFIG.max_calldata },
+            { CIRCUIT_CONFIG.max_rws },
+            { CIRCUIT_CONFIG.max_copy_rows },
+            _,
+        >(&$witness, fixed_rng())?;
+        $shared_state
+            .prepare_key(
+                $circuit_name,
+                $param,
+                $keep,
+                $aggregate,
+                CIRCUIT_CONFIG,
+                &circuit,
+            )
+            .await?
+    }};
+}
+
 #[derive(Clone)]
 pub struct RoState {
     // a unique identifier
@@ -267,11 +307,17 @@ pub struct RoState {
     // a `HOSTNAME:PORT` conformant string that will be used for DNS service discovery of other
     // nodes
     pub node_lookup: Option<String>,
+    // persists `RwState.tasks` if set
+    pub task_store: Option<Arc<TaskStore>>,
+    // stores the proving keys of `RwState.pk_cache` on disk if set
+    pub key_cache: Option<Arc<KeyCache>>,
+    // computes the proofs in child processes if set
+    pub worker: Option<WorkerConfig>,
 }
 
 pub struct RwState {
     pub tasks: Vec<ProofRequest>,
-    pub pk_cache: HashMap<String, Arc<ProverKey>>,
+    pub pk_cache: PkCache,
     /// The current active task this instance wants to obtain or is working on.
     pub pending: Option<ProofRequestOptions>,
     /// `true` if this instance started working on `pending`
@@ -290,20 +336,258 @@ impl SharedState {
             ro: RoState {
                 node_id,
                 node_lookup,
+                task_store: None,
+                key_cache: None,
+                worker: None,
             },
             rw: Arc::new(Mutex::new(RwState {
                 tasks: Vec::new(),
-                pk_cache: HashMap::new(),
+                pk_cache: PkCache::new(None),
                 pending: None,
                 obtained: false,
             })),
         }
     }
 
+    /// Like `new` but persists the tasks in `task_store` and starts with the tasks
+    /// stored there, minus the completed ones exceeding its retention policy.
+    pub fn with_task_store(
+        node_id: String,
+        node_lookup: Option<String>,
+        task_store: TaskStore,
+    ) -> Result<SharedState, String> {
+        let mut tasks = task_store.load().map_err(|e| e.to_string())?;
+        let pruned = task_store.prune().map_err(|e| e.to_string())?;
+        tasks.retain(|task| !pruned.contains(&task.options));
+        log::info!(
+            "task store: loaded {} tasks from {}",
+            tasks.len(),
+            task_store.dir().display()
+        );
+
+        let mut state = Self::new(node_id, node_lookup);
+        state.ro.task_store = Some(Arc::new(task_store));
+        Arc::get_mut(&mut state.rw).unwrap().get_mut().tasks = tasks;
+
+        Ok(state)
+    }
+
+    /// Stores the proving keys in `key_cache` and loads them from there.
+    pub fn with_key_cache(mut self, key_cache: KeyCache) -> SharedState {
+        self.ro.key_cache = Some(Arc::new(key_cache));
+        self
+    }
+
+    /// Computes the proofs in child processes started with `worker`.
+    pub fn with_worker(mut self, worker: WorkerConfig) -> SharedState {
+        self.ro.worker = Some(worker);
+        self
+    }
+
+    /// Limits the memory used by cached proving keys to `budget` bytes.
+    pub fn with_pk_cache_budget(mut self, budget: u64) -> SharedState {
+        Arc::get_mut(&mut self.rw)
+            .expect("SharedState not shared yet")
+            .get_mut()
+            .pk_cache = PkCache::new(Some(budget));
+        self
+    }
+
+    /// Loads or generates the proving keys of `circuits` for every circuit configuration,
+    /// using the params in `param` like `ProofRequestOptions.param`.
+    /// New keys are written to the key cache, `keep` also retains all keys in memory.
+    /// `aggregate` also prepares the keys of the aggregation circuits, these depend on a
+    /// circuit proof, one is computed for each circuit.
+    pub async fn prepare_keys(
+        &self,
+        circuits: &[String],
+        param: &Option<String>,
+        keep: bool,
+        aggregate: bool,
+    ) -> Result<(), String> {
+        if circuits.is_empty() {
+            return Ok(());
+        }
+
+        let mut gas_used = 0;
+        loop {
+            crate::match_circuit_params!(
+                gas_used,
+                {
+                    let witness = CircuitWitness::dummy(CIRCUIT_CONFIG)?;
+                    for circuit in circuits {
+                        match circuit.as_str() {
+                            "pi" => prepare_key_wrapper!(
+                                self,
+                                circuit,
+                                param,
+                                keep,
+                                aggregate,
+                                witness,
+                                gen_pi_circuit
+                            ),
+                            "super" => prepare_key_wrapper!(
+                                self,
+                                circuit,
+                                param,
+                                keep,
+                                aggregate,
+                                witness,
+                                gen_super_circuit
+                            ),
+                            "evm" => prepare_key_wrapper!(
+                                self,
+                                circuit,
+                                param,
+                                keep,
+                                aggregate,
+                                witness,
+                                gen_evm_circuit
+                            ),
+                            "state" => prepare_key_wrapper!(
+                                self,
+                                circuit,
+                                param,
+                                keep,
+                                aggregate,
+                                witness,
+                                gen_state_circuit
+                            ),
+                            "tx" => prepare_key_wrapper!(
+                                self,
+                                circuit,
+                                param,
+                                keep,
+                                aggregate,
+                                witness,
+                                gen_tx_circuit
+                            ),
+                            "bytecode" => prepare_key_wrapper!(
+                                self,
+                                circuit,
+                                param,
+                                keep,
+                                aggregate,
+                                witness,
+                                gen_bytecode_circuit
+                            ),
+                            "copy" => prepare_key_wrapper!(
+                                self,
+                                circuit,
+                                param,
+                                keep,
+                                aggregate,
+                                witness,
+                                gen_copy_circuit
+                            ),
+                            "exp" => prepare_key_wrapper!(
+                                self,
+                                circuit,
+                                param,
+                                keep,
+                                aggregate,
+                                witness,
+                                gen_exp_circuit
+                            ),
+                            "keccak" => prepare_key_wrapper!(
+                                self,
+                                circuit,
+                                param,
+                                keep,
+                                aggregate,
+                                witness,
+                                gen_keccak_circuit
+                            ),
+                            _ => return Err(format!("unknown circuit: {circuit}")),
+                        }
+                    }
+                    // continue with the next configuration
+                    gas_used = CIRCUIT_CONFIG.block_gas_limit + 1;
+                },
+                {
+                    break;
+                }
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn prepare_key<C: Circuit<Fr> + Clone + SubCircuit<Fr>>(
+        &self,
+        circuit_name: &str,
+        param: &Option<String>,
+        keep: bool,
+        aggregate: bool,
+        circuit_config: CircuitConfig,
+        circuit: &C,
+    ) -> Result<(), String> {
+        let task_options = ProofRequestOptions {
+            param: param.clone(),
+            ..Default::default()
+        };
+        let mut aux = ProofResultInstrumentation::default();
+        let param = get_or_gen_param(&task_options, circuit_config.min_k);
+        let cache_key = KeyCache::key_name(circuit_name, circuit, &param, &circuit_config, false);
+        let pk = self
+            .gen_pk(&cache_key, &param, circuit, &mut aux)
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut cache_keys = vec![cache_key];
+
+        if aggregate {
+            // like `compute_proof`, the aggregation circuit verifies a proof of the circuit
+            let circuit_instance = circuit.instance();
+            let proof = gen_proof::<_, _, PoseidonTranscript<_, _>, PoseidonTranscript<_, _>, _>(
+                &param,
+                &pk,
+                circuit.clone(),
+                circuit_instance.clone(),
+                fixed_rng(),
+                false,
+                false,
+                &mut aux,
+            );
+            let protocol = compile(
+                param.as_ref(),
+                pk.get_vk(),
+                PlonkConfig::kzg().with_num_instance(gen_num_instance(&circuit_instance)),
+            );
+            let agg_params = get_or_gen_param(&task_options, circuit_config.min_k_aggregation);
+            let agg_circuit = RootCircuit::new(
+                &agg_params,
+                &protocol,
+                Value::known(&circuit_instance),
+                Value::known(&proof),
+            )
+            .expect("RootCircuit::new");
+            let agg_key = KeyCache::key_name(
+                circuit_name,
+                &agg_circuit,
+                &agg_params,
+                &circuit_config,
+                true,
+            );
+            self.gen_pk(&agg_key, &agg_params, &agg_circuit, &mut aux)
+                .await
+                .map_err(|e| e.to_string())?;
+            cache_keys.push(agg_key);
+        }
+
+        if !keep {
+            let mut rw = self.rw.lock().await;
+            for cache_key in &cache_keys {
+                rw.pk_cache.remove(cache_key);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Will return the result or error of the task if it's completed.
     /// Otherwise enqueues the task and returns `None`.
     /// `retry_if_error` enqueues the task again if it returned with an error
-    /// before.
+    /// before, except if it was cancelled, see `invalidate`.
     pub async fn get_or_enqueue(
         &self,
         options: &ProofRequestOptions,
@@ -313,15 +597,17 @@ impl SharedState {
         // task already pending or completed?
         let task = rw.tasks.iter_mut().find(|e| e.options == *options);
 
-        if task.is_some() {
+        let changed = if task.is_some() {
             let mut task = task.unwrap();
 
             if task.result.is_some() {
-                if options.retry && task.result.as_ref().unwrap().is_err() {
+                let retryable = matches!(&task.result, Some(Err(err)) if err != TASK_CANCELLED);
+                if options.retry && retryable {
                     log::debug!("retrying: {:#?}", task);
                     // will be a candidate in `duty_cycle` again
                     task.result = None;
                     task.edition += 1;
+                    task.clone()
                 } else {
                     log::debug!("completed: {:#?}", task);
                     return task.result.clone();
@@ -336,10 +622,14 @@ impl SharedState {
                 options: options.clone(),
                 result: None,
                 edition: 0,
+                enqueued_at: unix_time(),
             };
             log::debug!("enqueue: {:#?}", task);
-            rw.tasks.push(task);
-        }
+            rw.tasks.push(task.clone());
+            task
+        };
+        drop(rw);
+        self.store_tasks(vec![changed]).await;
 
         None
     }
@@ -355,16 +645,15 @@ impl SharedState {
             return;
         }
 
+        self.prune_tasks().await;
         let rw = self.rw.lock().await;
         if rw.pending.is_some() || rw.obtained {
             // already computing
             return;
         }
-        // find a pending task
-        let tasks: Vec<ProofRequestOptions> = rw
-            .tasks
-            .iter()
-            .filter(|&e| e.result.is_none())
+        // the pending tasks, most urgent first
+        let tasks: Vec<ProofRequestOptions> = schedule(&rw.tasks, unix_time())
+            .into_iter()
             .map(|e| e.options.clone())
             .collect();
         drop(rw);
@@ -410,8 +699,7 @@ impl SharedState {
 
         // Note: this catches any panics for the task itself but will not help in the
         // situation when the process get itself OOM killed, stack overflows etc.
-        // This could be avoided by spawning a subprocess for the proof computation
-        // instead.
+        // A worker process isolates this node from these, see `RoState.worker`.
 
         // spawn a task to catch panics
         let task_result: Result<Result<Proofs, String>, tokio::task::JoinError> = {
@@ -419,10 +707,18 @@ impl SharedState {
             let self_copy = self.clone();
 
             tokio::spawn(async move {
+                if let Some(worker) = &self_copy.ro.worker {
+                    return worker
+                        .run_until(&task_options_copy, self_copy.cancelled(&task_options_copy))
+                        .await
+                        .unwrap_or_else(|err| Err(err.to_string()));
+                }
+
                 let witness =
                     CircuitWitness::from_rpc(&task_options_copy.block, &task_options_copy.rpc)
                         .await
                         .map_err(|e| e.to_string())?;
+                self_copy.check_cancelled(&task_options_copy).await?;
 
                 let (config, circuit_proof, aggregation_proof) = crate::match_circuit_params!(
                     witness.gas_used(),
@@ -536,7 +832,7 @@ impl SharedState {
             Ok(val) => val,
         };
 
-        {
+        let completed = {
             // done, update the queue
             log::info!("task_result: {:#?}", task_result);
 
@@ -547,9 +843,15 @@ impl SharedState {
             // insert task result
             let task = rw.tasks.iter_mut().find(|e| e.options == task_options);
             if let Some(task) = task {
+                if task.result.is_some() {
+                    // cancelled in the meantime, keep that
+                    log::info!("task already has a result, ignoring {:#?}", task_options);
+                    return;
+                }
                 // found our task, update result
                 task.result = Some(task_result);
                 task.edition += 1;
+                task.clone()
             } else {
                 // task was already removed in the meantime,
                 // assume it's obsolete and forget about it
@@ -557,6 +859,109 @@ impl SharedState {
                     "task was already removed, ignoring result {:#?}",
                     task_options
                 );
+                return;
+            }
+        };
+        self.store_tasks(vec![completed]).await;
+    }
+
+    /// The queue position and estimated start of the pending task with `options`.
+    pub async fn pending_task(&self, options: &ProofRequestOptions) -> PendingTask {
+        let rw = self.rw.lock().await;
+        let running = rw.pending.as_ref().filter(|_| rw.obtained);
+        pending_task(&rw.tasks, running, options, unix_time())
+    }
+
+    /// Marks the pending task with `options` as cancelled. Peers merge this like any result
+    /// and a node working on the task stops at the next phase, see `check_cancelled`.
+    /// Returns `false` if the task is unknown or already completed.
+    pub async fn cancel(&self, options: &ProofRequestOptions) -> bool {
+        let mut rw = self.rw.lock().await;
+        let cancelled = match rw.tasks.iter_mut().find(|e| e.options == *options) {
+            Some(task) if task.result.is_none() => {
+                log::info!("cancel: {:#?}", task.options);
+                task.result = Some(Err(TASK_CANCELLED.to_string()));
+                task.edition += 1;
+                task.clone()
+            }
+            _ => return false,
+        };
+        drop(rw);
+        self.store_tasks(vec![cancelled]).await;
+
+        true
+    }
+
+    /// Drops the result of the task with `options` and enqueues it again, e.g. because its
+    /// proofs did not verify or to re-submit a cancelled task. Peers merge this like any result.
+    /// Returns `false` if the task is unknown or still pending.
+    pub async fn invalidate(&self, options: &ProofRequestOptions) -> bool {
+        let mut rw = self.rw.lock().await;
+        let invalidated = match rw.tasks.iter_mut().find(|e| e.options == *options) {
+            Some(task) if task.result.is_some() => {
+                log::info!("invalidate: {:#?}", task.options);
+                task.result = None;
+                task.edition += 1;
+                task.clone()
+            }
+            _ => return false,
+        };
+        drop(rw);
+        self.store_tasks(vec![invalidated]).await;
+
+        true
+    }
+
+    /// Cancels the task this node works on and returns it, if any.
+    pub async fn abort(&self) -> Option<ProofRequestOptions> {
+        let options = self.rw.lock().await.pending.clone()?;
+        self.cancel(&options).await.then_some(options)
+    }
+
+    /// Fails with `TASK_CANCELLED` if the task with `options` is no longer pending,
+    /// because it was cancelled on this node or a peer or removed.
+    /// Called between the phases of a proof computation.
+    pub async fn check_cancelled(&self, options: &ProofRequestOptions) -> Result<(), String> {
+        let rw = self.rw.lock().await;
+        match rw.tasks.iter().find(|e| e.options == *options) {
+            Some(task) if task.result.is_none() => Ok(()),
+            _ => Err(TASK_CANCELLED.to_string()),
+        }
+    }
+
+    /// Completes once `check_cancelled` fails.
+    async fn cancelled(&self, options: &ProofRequestOptions) {
+        while self.check_cancelled(options).await.is_ok() {
+            tokio::time::sleep(std::time::Duration::from_millis(1000)).await;
+        }
+    }
+
+    /// Removes the pending and/or completed tasks, also from the task store.
+    pub async fn flush_tasks(&self, pending: bool, completed: bool) {
+        let removed = {
+            let mut rw = self.rw.lock().await;
+            let (removed, kept): (Vec<ProofRequest>, Vec<ProofRequest>) =
+                std::mem::take(&mut rw.tasks)
+                    .into_iter()
+                    .partition(|task| match task.result {
+                        None => pending,
+                        Some(_) => completed,
+                    });
+            rw.tasks = kept;
+            removed
+        };
+
+        if let Some(task_store) = self.ro.task_store.clone() {
+            let res = tokio::task::spawn_blocking(move || {
+                for task in removed {
+                    if let Err(err) = task_store.remove(&task.options) {
+                        log::error!("task store: {}", err);
+                    }
+                }
+            })
+            .await;
+            if let Err(err) = res {
+                log::error!("task store: {}", err);
             }
         }
     }
@@ -564,9 +969,11 @@ impl SharedState {
     /// Returns `node_id` and `tasks` for this instance.
     /// Normally used for the rpc api.
     pub async fn get_node_information(&self) -> NodeInformation {
+        let rw = self.rw.lock().await;
         NodeInformation {
             id: self.ro.node_id.clone(),
-            tasks: self.rw.lock().await.tasks.clone(),
+            tasks: rw.tasks.clone(),
+            pk_cache: rw.pk_cache.stats(),
         }
     }
 
@@ -583,7 +990,6 @@ impl SharedState {
             return Ok(true);
         }
 
-        let hyper_client = hyper::Client::new();
         let addrs_iter = self
             .ro
             .node_lookup
@@ -594,9 +1000,7 @@ impl SharedState {
 
         for addr in addrs_iter {
             let uri = Uri::try_from(format!("http://{addr}")).map_err(|e| e.to_string())?;
-            let peer: NodeInformation =
-                jsonrpc_request_client(5000, &hyper_client, &uri, "info", serde_json::json!([]))
-                    .await?;
+            let peer = peer_client(uri).info().await?;
 
             if peer.id == self.ro.node_id {
                 log::debug!("{} skipping self({})", LOG_TAG, peer.id);
@@ -610,11 +1014,8 @@ impl SharedState {
         Ok(true)
     }
 
-    // TODO: can this be pre-generated to a file?
-    // related
-    // https://github.com/zcash/halo2/issues/443
-    // https://github.com/zcash/halo2/issues/449
-    /// Compute or retrieve a proving key from cache.
+    /// Retrieves a proving key from the memory or disk cache, otherwise computes it
+    /// and stores it in both.
     async fn gen_pk<C: Circuit<Fr>>(
         &self,
         cache_key: &str,
@@ -622,48 +1023,75 @@ impl SharedState {
         circuit: &C,
         aux: &mut ProofResultInstrumentation,
     ) -> Result<Arc<ProverKey>, Box<dyn std::error::Error>> {
-        let mut rw = self.rw.lock().await;
-        if !rw.pk_cache.contains_key(cache_key) {
-            // drop, potentially long running
-            drop(rw);
+        let size = pk_size::<C>(param.k());
+        {
+            let mut rw = self.rw.lock().await;
+            if let Some(pk) = rw.pk_cache.get(cache_key) {
+                return Ok(pk);
+            }
+            // make room before the key is loaded or generated
+            rw.pk_cache.reserve(size);
+        }
 
-            let vk = {
-                let time_started = Instant::now();
-                let vk = keygen_vk(param.as_ref(), circuit)?;
-                aux.vk = Instant::now().duration_since(time_started).as_millis() as u32;
-                vk
-            };
-            let pk = {
-                let time_started = Instant::now();
-                let pk = keygen_pk(param.as_ref(), vk, circuit)?;
-                aux.pk = Instant::now().duration_since(time_started).as_millis() as u32;
+        // potentially long running, without holding the lock
+        let cached = self.ro.key_cache.as_ref().and_then(|key_cache| {
+            key_cache.read::<C>(cache_key).unwrap_or_else(|err| {
+                log::warn!("ProvingKey: ignoring cached key: {}", err);
+                None
+            })
+        });
+        let pk = match cached {
+            Some(pk) => {
+                log::info!("ProvingKey: loaded key={}", cache_key);
                 pk
-            };
-            if std::env::var("PROVERD_DUMP").is_ok() {
-                pk.write(
-                    &mut File::create(cache_key).unwrap(),
-                    SerdeFormat::RawBytesUnchecked,
-                )
-                .unwrap();
             }
+            None => {
+                let vk = {
+                    let time_started = Instant::now();
+                    let vk = keygen_vk(param.as_ref(), circuit)?;
+                    aux.vk = Instant::now().duration_since(time_started).as_millis() as u32;
+                    vk
+                };
+                let pk = {
+                    let time_started = Instant::now();
+                    let pk = keygen_pk(param.as_ref(), vk, circuit)?;
+                    aux.pk = Instant::now().duration_since(time_started).as_millis() as u32;
+                    pk
+                };
+                if let Some(key_cache) = &self.ro.key_cache {
+                    if let Err(err) = key_cache.write(cache_key, &pk) {
+                        log::error!("ProvingKey: writing key={} failed: {}", cache_key, err);
+                    }
+                }
+                log::info!("ProvingKey: generated key={}", cache_key);
+                pk
+            }
+        };
+        let pk = Arc::new(pk);
 
-            let pk = Arc::new(pk);
-
-            // acquire lock and update
-            rw = self.rw.lock().await;
-            rw.pk_cache.insert(cache_key.to_string(), pk);
-
-            log::info!("ProvingKey: generated and cached key={}", cache_key);
-        }
+        // acquire lock and update
+        self.rw
+            .lock()
+            .await
+            .pk_cache
+            .insert(cache_key, pk.clone(), size);
+        log::info!("ProvingKey: cached key={} size={}", cache_key, size);
 
-        Ok(rw.pk_cache.get(cache_key).unwrap().clone())
+        Ok(pk)
     }
 
     async fn merge_tasks(&self, node_info: &NodeInformation) {
         const LOG_TAG: &str = "merge_tasks:";
         let mut rw = self.rw.lock().await;
+        let mut changed = Vec::new();
 
         for peer_task in &node_info.tasks {
+            if let Some(task_store) = &self.ro.task_store {
+                if task_store.is_pruned(peer_task) {
+                    log::debug!("{} pruned {:#?}", LOG_TAG, peer_task.options);
+                    continue;
+                }
+            }
             let maybe_task = rw.tasks.iter_mut().find(|e| e.options == peer_task.options);
 
             if let Some(existent_task) = maybe_task {
@@ -676,13 +1104,60 @@ impl SharedState {
                 // update result, edition
                 existent_task.edition = peer_task.edition;
                 existent_task.result = peer_task.result.clone();
+                changed.push(existent_task.clone());
                 log::debug!("{} updated {:#?}", LOG_TAG, existent_task);
             } else {
-                // copy task
-                rw.tasks.push(peer_task.clone());
-                log::debug!("{} new task {:#?}", LOG_TAG, peer_task);
+                // copy task, peers of an earlier version do not send `enqueued_at`
+                let mut task = peer_task.clone();
+                if task.enqueued_at == 0 {
+                    task.enqueued_at = unix_time();
+                }
+                changed.push(task.clone());
+                log::debug!("{} new task {:#?}", LOG_TAG, task);
+                rw.tasks.push(task);
             }
         }
+        drop(rw);
+
+        self.store_tasks(changed).await;
+    }
+
+    /// Persists `tasks` if there is a task store, errors are only logged.
+    /// Call it without holding the lock of `self.rw`, the IO runs on a blocking thread.
+    async fn store_tasks(&self, tasks: Vec<ProofRequest>) {
+        let task_store = match &self.ro.task_store {
+            Some(task_store) if !tasks.is_empty() => task_store.clone(),
+            _ => return,
+        };
+        let res = tokio::task::spawn_blocking(move || {
+            for task in tasks {
+                if let Err(err) = task_store.save(&task) {
+                    log::error!("task store: {}", err);
+                }
+            }
+        })
+        .await;
+        if let Err(err) = res {
+            log::error!("task store: {}", err);
+        }
+    }
+
+    /// Drops the completed tasks exceeding the retention policy of the task store.
+    async fn prune_tasks(&self) {
+        let task_store = match &self.ro.task_store {
+            Some(task_store) => task_store.clone(),
+            None => return,
+        };
+        match tokio::task::spawn_blocking(move || task_store.prune()).await {
+            Ok(Ok(pruned)) => self
+                .rw
+                .lock()
+                .await
+                .tasks
+                .retain(|task| !pruned.contains(&task.options)),
+            Ok(Err(err)) => log::error!("task store: {}", err),
+            Err(err) => log::error!("task store: {}", err),
+        }
     }
 
     /// Tries to obtain `self.rw.pending` by querying all other peers
@@ -707,7 +1182,6 @@ impl SharedState {
         }
 
         // resolve all other nodes for this service
-        let hyper_client = hyper::Client::new();
         let addrs_iter = self
             .ro
             .node_lookup
@@ -717,9 +1191,7 @@ impl SharedState {
             .map_err(|e| e.to_string())?;
         for addr in addrs_iter {
             let uri = Uri::try_from(format!("http://{addr}")).map_err(|e| e.to_string())?;
-            let peer: NodeStatus =
-                jsonrpc_request_client(5000, &hyper_client, &uri, "status", serde_json::json!([]))
-                    .await?;
+            let peer = peer_client(uri).status().await?;
 
             if peer.id == self.ro.node_id {
                 log::debug!("{} skipping self({})", LOG_TAG, peer.id);
@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use zkevm_common::prover::{ProofRequestOptions, Proofs, TASK_CANCELLED};
+
+/// The number of trailing stderr lines of a worker included in errors.
+const STDERR_TAIL: usize = 16;
+
+/// Runs proof computations in child processes with `prover_cmd --worker`,
+/// which reads the `ProofRequestOptions` as JSON from stdin and writes
+/// the `Result<Proofs, String>` as JSON to stdout.
+#[derive(Clone, Debug, Default)]
+pub struct WorkerConfig {
+    /// The `prover_cmd` executable.
+    pub program: PathBuf,
+    /// Arguments passed after `--worker`, e.g. `--key-cache`.
+    pub args: Vec<String>,
+    /// Virtual memory limit in bytes.
+    pub memory_limit: Option<u64>,
+    /// CPU time limit in seconds.
+    pub cpu_time_limit: Option<u64>,
+    /// The worker is killed after this time.
+    pub timeout: Option<Duration>,
+}
+
+/// Why a worker did not return a result.
+#[derive(Debug)]
+pub enum WorkerError {
+    /// The worker could not be started.
+    Spawn(String),
+    /// The worker exited without a valid result.
+    Protocol(String),
+    /// An allocation failed, with the end of its stderr.
+    OutOfMemory(String),
+    /// Killed with SIGKILL, by the OOM killer or an operator, with the end of its stderr.
+    Killed(String),
+    /// Exceeded `WorkerConfig::cpu_time_limit`.
+    CpuTimeLimit(u64),
+    /// Exceeded `WorkerConfig::timeout`.
+    Timeout(Duration),
+    /// Exited with a signal or an unexpected status, with the end of its stderr.
+    Crashed(String, String),
+    /// Killed because the task was cancelled.
+    Cancelled,
+}
+
+impl fmt::Display for WorkerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Spawn(err) => write!(f, "worker: spawn failed: {err}"),
+            Self::Protocol(err) => write!(f, "worker: invalid result: {err}"),
+            Self::OutOfMemory(stderr) => write!(f, "worker: out of memory\n{stderr}"),
+            Self::Killed(stderr) => write!(f, "worker: killed (possibly out of memory)\n{stderr}"),
+            Self::CpuTimeLimit(secs) => write!(f, "worker: exceeded the cpu time limit of {secs}s"),
+            Self::Timeout(timeout) => write!(f, "worker: timed out after {timeout:?}"),
+            Self::Crashed(status, stderr) => write!(f, "worker: crashed with {status}\n{stderr}"),
+            Self::Cancelled => write!(f, "{TASK_CANCELLED}"),
+        }
+    }
+}
+
+impl std::error::Error for WorkerError {}
+
+impl WorkerConfig {
+    /// Computes the proofs for `options` in a new worker process.
+    /// Errors of the proof computation itself are returned as `Ok(Err(..))`.
+    pub async fn run(
+        &self,
+        options: &ProofRequestOptions,
+    ) -> Result<Result<Proofs, String>, WorkerError> {
+        self.run_until(options, std::future::pending()).await
+    }
+
+    /// Like `run` but kills the worker once `cancel` completes.
+    pub async fn run_until(
+        &self,
+        options: &ProofRequestOptions,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<Result<Proofs, String>, WorkerError> {
+        let mut child = self
+            .command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| WorkerError::Spawn(e.to_string()))?;
+
+        let request = serde_json::to_vec(options).expect("ProofRequestOptions");
+        let mut stdin = child.stdin.take().unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+
+        // forward the logs of the worker and keep the last lines for errors
+        let stderr = tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            let mut tail = VecDeque::with_capacity(STDERR_TAIL);
+            while let Ok(Some(line)) = lines.next_line().await {
+                eprintln!("{line}");
+                if tail.len() == STDERR_TAIL {
+                    tail.pop_front();
+                }
+                tail.push_back(line);
+            }
+            Vec::from(tail).join("\n")
+        });
+
+        let exchange = async {
+            // a worker may exit before reading its request, the exit status tells why
+            let _ = stdin.write_all(&request).await;
+            drop(stdin);
+            let mut output = Vec::new();
+            let _ = stdout.read_to_end(&mut output).await;
+            (output, child.wait().await)
+        };
+        let limited = async {
+            match self.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, exchange)
+                    .await
+                    .map_err(|_| WorkerError::Timeout(timeout)),
+                None => Ok(exchange.await),
+            }
+        };
+        let res = tokio::select! {
+            res = limited => res,
+            _ = cancel => Err(WorkerError::Cancelled),
+        };
+        let (output, status) = match res {
+            Ok(res) => res,
+            Err(err) => {
+                let _ = child.kill().await;
+                return Err(err);
+            }
+        };
+        let status = status.map_err(|e| WorkerError::Protocol(e.to_string()))?;
+        let stderr = stderr.await.unwrap_or_default();
+
+        if status.success() {
+            return serde_json::from_slice(&output)
+                .map_err(|e| WorkerError::Protocol(e.to_string()));
+        }
+
+        // a failed allocation aborts, SIGKILL comes from the OOM killer but also from operators
+        const SIGABRT: i32 = 6;
+        const SIGKILL: i32 = 9;
+        const SIGXCPU: i32 = 24;
+        match status.signal() {
+            Some(SIGKILL) => Err(WorkerError::Killed(stderr)),
+            Some(SIGABRT) if stderr.contains("memory allocation of") => {
+                Err(WorkerError::OutOfMemory(stderr))
+            }
+            Some(SIGXCPU) if self.cpu_time_limit.is_some() => {
+                Err(WorkerError::CpuTimeLimit(self.cpu_time_limit.unwrap()))
+            }
+            _ => Err(WorkerError::Crashed(status.to_string(), stderr)),
+        }
+    }
+
+    /// Applies the resource limits with `ulimit` in a shell that executes the worker.
+    fn command(&self) -> Command {
+        let mut limits = String::new();
+        if let Some(bytes) = self.memory_limit {
+            limits += &format!("ulimit -v {} && ", bytes / 1024);
+        }
+        if let Some(secs) = self.cpu_time_limit {
+            limits += &format!("ulimit -t {secs} && ");
+        }
+
+        let mut cmd = if limits.is_empty() {
+            Command::new(&self.program)
+        } else {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c")
+                .arg(format!("{limits}exec \"$@\""))
+                .arg("sh")
+                .arg(&self.program);
+            cmd
+        };
+        cmd.arg("--worker").args(&self.args);
+
+        cmd
+    }
+}
@@ -0,0 +1,71 @@
+use prover::pk_cache::PkCache;
+use std::sync::Arc;
+
+#[test]
+fn pk_cache_evicts_least_recently_used() {
+    let mut cache = PkCache::<u8>::new(Some(100));
+    assert!(cache.insert("a", Arc::new(1), 40).is_empty());
+    assert!(cache.insert("b", Arc::new(2), 40).is_empty());
+    // `a` becomes the most recently used
+    assert_eq!(cache.get("a").as_deref(), Some(&1));
+    assert_eq!(cache.insert("c", Arc::new(3), 40), ["b"]);
+    assert!(cache.get("b").is_none());
+
+    let stats = cache.stats();
+    assert_eq!(stats.size, 80);
+    assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 1, 1));
+    let keys: Vec<&str> = stats.keys.iter().map(|key| key.name.as_str()).collect();
+    assert_eq!(keys, ["a", "c"]);
+
+    // replacing a key accounts the new size only
+    assert!(cache.insert("c", Arc::new(4), 20).is_empty());
+    assert_eq!(cache.stats().size, 60);
+    cache.clear();
+    assert!(cache.is_empty());
+    assert_eq!(cache.stats().size, 0);
+}
+
+#[test]
+fn pk_cache_keeps_keys_in_use() {
+    let mut cache = PkCache::<u8>::new(Some(100));
+    cache.insert("a", Arc::new(1), 60);
+    let in_use = cache.get("a").unwrap();
+    assert!(cache.stats().keys[0].in_use);
+
+    // over budget, but `a` is in use and `b` was just inserted
+    assert!(cache.insert("b", Arc::new(2), 60).is_empty());
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.stats().size, 120);
+
+    drop(in_use);
+    assert_eq!(cache.insert("c", Arc::new(3), 10), ["a"]);
+    assert_eq!(cache.stats().size, 70);
+
+    // unlimited without a budget
+    let mut cache = PkCache::<u8>::new(None);
+    for (i, key) in ["a", "b", "c"].into_iter().enumerate() {
+        assert!(cache
+            .insert(key, Arc::new(i as u8), u64::MAX / 4)
+            .is_empty());
+    }
+    assert_eq!(cache.len(), 3);
+}
+
+#[test]
+fn pk_cache_reserves_before_insert() {
+    let mut cache = PkCache::<u8>::new(Some(100));
+    cache.insert("a", Arc::new(1), 40);
+    cache.insert("b", Arc::new(2), 40);
+
+    // room for 50 bytes takes evicting `a`
+    assert_eq!(cache.reserve(50), ["a"]);
+    assert_eq!(cache.stats().size, 40);
+    assert!(cache.insert("c", Arc::new(3), 50).is_empty());
+    assert!(cache.reserve(10).is_empty());
+
+    let in_use = cache.get("b").unwrap();
+    // `c` is evicted, `b` is in use and stays
+    assert_eq!(cache.reserve(70), ["c"]);
+    assert_eq!(cache.len(), 1);
+    drop(in_use);
+}
@@ -69,3 +69,32 @@ async fn proverd_simple_signaling() {
     // check again
     assert!(node_a.get_or_enqueue(&proof_b).await.is_some());
 }
+
+#[tokio::test]
+async fn proverd_cancelled_tasks_stay_cancelled() {
+    init_logger();
+
+    let node = SharedState::new("c".to_string(), None);
+    let proof = ProofRequestOptions {
+        circuit: "super".to_string(),
+        block: 3,
+        retry: true,
+        rpc: "http://localhost:1111".to_string(),
+        ..Default::default()
+    };
+
+    assert!(node.get_or_enqueue(&proof).await.is_none());
+    assert!(node.cancel(&proof).await);
+    // already completed
+    assert!(!node.cancel(&proof).await);
+
+    // `retry` does not reset a cancelled task
+    let result = node.get_or_enqueue(&proof).await;
+    assert_eq!(result.unwrap().unwrap_err(), TASK_CANCELLED);
+    assert!(node.check_cancelled(&proof).await.is_err());
+
+    // re-submit
+    assert!(node.invalidate(&proof).await);
+    assert!(node.get_or_enqueue(&proof).await.is_none());
+    assert!(node.check_cancelled(&proof).await.is_ok());
+}
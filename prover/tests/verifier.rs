@@ -62,6 +62,20 @@ impl Verifier {
 
         self
     }
+
+    /// Writes the verifier registry entry for the coordinator (`COORDINATOR_VERIFIER_REGISTRY`).
+    fn write_registry_entry(&mut self) -> &Self {
+        self.build();
+        let file_name = format!("verifier-{}-{:?}.json", self.label, self.address);
+        let entry = serde_json::json!({
+            "label": self.label,
+            "config": self.config,
+            "address": self.address,
+        });
+        write_bytes(&file_name, entry.to_string().as_bytes());
+
+        self
+    }
 }
 
 fn write_bytes(name: &str, vec: &[u8]) {
@@ -129,6 +143,7 @@ macro_rules! gen_match {
                         )
                         .into();
                         data.write_yul();
+                        data.write_registry_entry();
 
                         if var("ONLY_EVM").is_ok() {
                             log::info!("returning early");
@@ -166,6 +181,7 @@ macro_rules! gen_match {
                 )
                 .into();
                 data.write_yul();
+                data.write_registry_entry();
             },
             {
                 panic!("no circuit parameters found");
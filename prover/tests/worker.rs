@@ -0,0 +1,84 @@
+use prover::worker::{WorkerConfig, WorkerError};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::time::Duration;
+use zkevm_common::prover::{ProofRequestOptions, TASK_CANCELLED};
+
+/// Writes a shell script that stands in for `prover_cmd --worker`.
+fn worker(name: &str, script: &str) -> WorkerConfig {
+    let path = std::env::temp_dir().join(format!("worker-{}-{}", name, std::process::id()));
+    std::fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    WorkerConfig {
+        program: path,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn worker_results_and_failures() {
+    let options = ProofRequestOptions {
+        circuit: "super".to_string(),
+        block: 1,
+        ..Default::default()
+    };
+
+    // the request arrives on stdin, errors of the computation are results
+    let config = worker(
+        "result",
+        r#"test "$1" = --worker || exit 1; grep -q '"block":1' && echo '{"Err":"no block"}'"#,
+    );
+    assert_eq!(config.run(&options).await.unwrap().unwrap_err(), "no block");
+
+    let config = worker("garbage", "echo garbage");
+    let err = config.run(&options).await.unwrap_err();
+    assert!(matches!(err, WorkerError::Protocol(_)), "{err}");
+
+    let config = worker("exit", "echo failing >&2; exit 3");
+    match config.run(&options).await.unwrap_err() {
+        WorkerError::Crashed(status, stderr) => {
+            assert!(status.contains('3'), "{status}");
+            assert_eq!(stderr, "failing");
+        }
+        err => panic!("unexpected {err}"),
+    }
+
+    let config = worker("killed", "kill -9 $$");
+    let err = config.run(&options).await.unwrap_err();
+    assert!(matches!(err, WorkerError::Killed(_)), "{err}");
+    assert!(err
+        .to_string()
+        .starts_with("worker: killed (possibly out of memory)"));
+
+    let config = worker(
+        "oom",
+        "echo 'memory allocation of 64 bytes failed' >&2; kill -6 $$",
+    );
+    let err = config.run(&options).await.unwrap_err();
+    assert!(matches!(err, WorkerError::OutOfMemory(_)), "{err}");
+
+    let mut config = worker("timeout", "sleep 10");
+    config.timeout = Some(Duration::from_millis(200));
+    let err = config.run(&options).await.unwrap_err();
+    assert!(matches!(err, WorkerError::Timeout(_)), "{err}");
+
+    let config = worker("cancel", "sleep 10");
+    let cancel = tokio::time::sleep(Duration::from_millis(200));
+    let err = config.run_until(&options, cancel).await.unwrap_err();
+    assert!(matches!(err, WorkerError::Cancelled), "{err}");
+    assert_eq!(err.to_string(), TASK_CANCELLED);
+
+    // the limits apply to the worker
+    let mut config = worker("limits", r#"echo "{\"Err\":\"$(ulimit -t)\"}""#);
+    config.cpu_time_limit = Some(7);
+    config.memory_limit = Some(1 << 30);
+    assert_eq!(config.run(&options).await.unwrap().unwrap_err(), "7");
+
+    let config = WorkerConfig {
+        program: PathBuf::from("/nonexistent/prover_cmd"),
+        ..Default::default()
+    };
+    let err = config.run(&options).await.unwrap_err();
+    assert!(matches!(err, WorkerError::Spawn(_)), "{err}");
+}